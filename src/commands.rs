@@ -1,13 +1,194 @@
 use anyhow::Result;
 use log::info;
+use niri_ipc::{Action, WorkspaceReferenceArg};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::Mutex;
 
-use crate::config::Config;
-use crate::ipc::IpcRequest;
+use crate::config::{self, Config};
+use crate::ipc::{ErrorKind, IpcError, IpcRequest, WaitCondition, WindowAction};
 use crate::niri::NiriIpc;
-use crate::plugins::PluginManager;
+use crate::plugins::window_utils::{self, WindowMatcher, WindowMatcherCache};
+use crate::plugins::{PluginManager, PluginState};
+
+/// A window as returned by `WindowList`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowInfo {
+    pub id: u64,
+    pub app_id: Option<String>,
+    pub title: String,
+    /// Workspace name, or idx if unnamed
+    pub workspace: Option<String>,
+    pub floating: bool,
+}
+
+/// A workspace as returned by `WorkspaceList`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceInfo {
+    pub id: u64,
+    pub idx: u8,
+    pub name: Option<String>,
+    pub output: Option<String>,
+    pub focused: bool,
+    pub windows: usize,
+}
+
+/// Whether niri answers on its socket, as reported by `piri status`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NiriStatus {
+    pub connected: bool,
+    pub version: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Daemon overview returned by `Status`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub version: String,
+    pub uptime_secs: u64,
+    pub config_path: PathBuf,
+    pub profile: Option<String>,
+    /// Unix time of the last successful config reload, if any
+    pub last_reload: Option<u64>,
+    pub plugins: Vec<PluginState>,
+    pub scratchpads: usize,
+    pub singletons: usize,
+    pub niri: NiriStatus,
+}
+
+/// Daemon version returned by `Version`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionInfo {
+    pub version: String,
+    /// Names of the running plugins
+    pub plugins: Vec<String>,
+}
+
+/// Daemon health returned by `Ping`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PingInfo {
+    pub uptime_secs: u64,
+    /// Times `piri restart` executed the daemon again, which keeps its PID
+    #[serde(default)]
+    pub restarts: u32,
+    /// Time niri took to answer a request, in milliseconds
+    pub niri_ms: Option<f64>,
+    pub niri_error: Option<String>,
+}
+
+/// Wait until `condition` holds. The handler is only locked to check the current state, so
+/// that other requests are handled meanwhile
+pub async fn wait_for(
+    handler: &Arc<Mutex<CommandHandler>>,
+    condition: &WaitCondition,
+    timeout: std::time::Duration,
+) -> Result<()> {
+    // Subscribed before checking, so that a change in between isn't missed
+    let mut events = crate::events::subscribe();
+    let matcher_cache = WindowMatcherCache::new();
+    let matcher = match condition {
+        WaitCondition::Window { app_id, title } => WindowMatcher::new(
+            app_id.clone().map(|p| vec![p]),
+            title.clone().map(|p| vec![p]),
+        ),
+        WaitCondition::Scratchpad { .. } => WindowMatcher::new(None, None),
+    };
+
+    let satisfied = {
+        let handler = handler.lock().await;
+        match condition {
+            WaitCondition::Scratchpad { name, visible } => {
+                let status = handler.plugin_manager.lock().await.plugin_status("scratchpads").await;
+                let Some(status) = status else {
+                    anyhow::bail!(IpcError::new(
+                        ErrorKind::PluginDisabled,
+                        "Scratchpads plugin is not running"
+                    ));
+                };
+                status[name.as_str()]["visible"].as_bool() == Some(*visible)
+            }
+            WaitCondition::Window { .. } => {
+                let mut found = false;
+                for window in handler.niri.get_windows().await? {
+                    if matcher_cache
+                        .matches(window.app_id.as_ref(), Some(&window.title), &matcher)
+                        .await?
+                    {
+                        found = true;
+                        break;
+                    }
+                }
+                found
+            }
+        }
+    };
+    if satisfied {
+        return Ok(());
+    }
+
+    let wait = async {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => {
+                    anyhow::bail!("Daemon is shutting down")
+                }
+            };
+            let matched = match condition {
+                WaitCondition::Scratchpad { name, visible } => {
+                    let expected = if *visible {
+                        "ScratchpadShown"
+                    } else {
+                        "ScratchpadHidden"
+                    };
+                    event[expected]["name"].as_str() == Some(name.as_str())
+                }
+                WaitCondition::Window { .. } => {
+                    let window = &event["WindowOpenedOrChanged"]["window"];
+                    let app_id = window["app_id"].as_str().map(str::to_string);
+                    let title = window["title"].as_str().map(str::to_string);
+                    !window.is_null()
+                        && matcher_cache.matches(app_id.as_ref(), title.as_ref(), &matcher).await?
+                }
+            };
+            if matched {
+                return Ok(());
+            }
+        }
+    };
+    match tokio::time::timeout(timeout, wait).await {
+        Ok(result) => result,
+        Err(_) => anyhow::bail!(IpcError::new(
+            ErrorKind::Timeout,
+            format!(
+                "Timed out after {:?} waiting for {}",
+                timeout,
+                describe(condition)
+            )
+        )),
+    }
+}
+
+fn describe(condition: &WaitCondition) -> String {
+    match condition {
+        WaitCondition::Scratchpad { name, visible } => format!(
+            "scratchpad '{}' to be {}",
+            name,
+            if *visible { "visible" } else { "hidden" }
+        ),
+        WaitCondition::Window { app_id, title } => match (app_id, title) {
+            (Some(app_id), Some(title)) => {
+                format!("a window with app_id '{}' or title '{}'", app_id, title)
+            }
+            (Some(app_id), None) => format!("a window with app_id '{}'", app_id),
+            (None, Some(title)) => format!("a window with title '{}'", title),
+            (None, None) => "a window".to_string(),
+        },
+    }
+}
 
 /// Command handler for processing different commands
 pub struct CommandHandler {
@@ -15,6 +196,12 @@ pub struct CommandHandler {
     config_path: PathBuf,
     niri: NiriIpc,
     plugin_manager: Arc<Mutex<PluginManager>>,
+    /// Compiled patterns of `piri window` commands
+    matcher_cache: WindowMatcherCache,
+    /// Active config profile, applied on every reload
+    profile: Option<String>,
+    started: Instant,
+    last_reload: Option<SystemTime>,
 }
 
 impl CommandHandler {
@@ -29,6 +216,10 @@ impl CommandHandler {
             config_path,
             niri,
             plugin_manager,
+            matcher_cache: WindowMatcherCache::new(),
+            profile: None,
+            started: Instant::now(),
+            last_reload: None,
         }
     }
 
@@ -45,6 +236,22 @@ impl CommandHandler {
         }
     }
 
+    /// Handle IPC query (a request that returns data) through plugins
+    pub async fn handle_ipc_query_through_plugins(
+        &mut self,
+        request: &IpcRequest,
+    ) -> Option<Result<serde_json::Value>> {
+        let mut pm = self.plugin_manager.lock().await;
+        pm.handle_ipc_query(request).await.transpose()
+    }
+
+    /// Subscribe to combined plugin status updates (None if the bar plugin is disabled)
+    pub async fn subscribe_status(
+        &self,
+    ) -> Option<tokio::sync::watch::Receiver<serde_json::Value>> {
+        self.plugin_manager.lock().await.subscribe_status().await
+    }
+
     /// Set plugin manager (called by daemon after initialization)
     pub fn set_plugin_manager(&mut self, plugin_manager: Arc<Mutex<PluginManager>>) {
         self.plugin_manager = plugin_manager;
@@ -65,15 +272,296 @@ impl CommandHandler {
         &self.config_path
     }
 
+    /// List all windows, ordered by workspace
+    pub async fn list_windows(&self) -> Result<Vec<WindowInfo>> {
+        let windows = self.niri.get_windows().await?;
+        let workspaces = self.niri.get_workspaces().await?;
+
+        let mut listed: Vec<(Option<u64>, WindowInfo)> = windows
+            .into_iter()
+            .map(|window| {
+                let workspace =
+                    window.workspace_id.and_then(|id| workspaces.iter().find(|ws| ws.id == id));
+                (
+                    workspace.map(|ws| ws.id),
+                    WindowInfo {
+                        id: window.id,
+                        app_id: window.app_id,
+                        title: window.title,
+                        workspace: workspace
+                            .map(|ws| ws.name.clone().unwrap_or_else(|| ws.idx.to_string())),
+                        floating: window.floating,
+                    },
+                )
+            })
+            .collect();
+        listed.sort_by_key(|(workspace_id, window)| (*workspace_id, window.id));
+        Ok(listed.into_iter().map(|(_, window)| window).collect())
+    }
+
+    /// Focus a window, moving it to the focused workspace first if `bring` is set
+    pub async fn focus_window(&self, window_id: u64, bring: bool) -> Result<()> {
+        if !self.niri.get_windows().await?.iter().any(|w| w.id == window_id) {
+            anyhow::bail!(IpcError::not_found(format!(
+                "Window {} not found",
+                window_id
+            )));
+        }
+        if bring {
+            self.niri.move_window_to_focused_workspace(window_id).await?;
+        }
+        self.niri.focus_window(window_id).await
+    }
+
+    /// List all workspaces, ordered by output and index
+    pub async fn list_workspaces(&self) -> Result<Vec<WorkspaceInfo>> {
+        let windows = self.niri.get_windows().await?;
+        let mut workspaces: Vec<WorkspaceInfo> = self
+            .niri
+            .get_workspaces()
+            .await?
+            .into_iter()
+            .map(|ws| WorkspaceInfo {
+                id: ws.id,
+                idx: ws.idx,
+                windows: windows.iter().filter(|w| w.workspace_id == Some(ws.id)).count(),
+                name: ws.name,
+                output: ws.output,
+                focused: ws.is_focused,
+            })
+            .collect();
+        workspaces.sort_by(|a, b| (&a.output, a.idx).cmp(&(&b.output, b.idx)));
+        Ok(workspaces)
+    }
+
+    /// Focus a workspace by name, index or a fuzzy name match
+    pub async fn focus_workspace(&self, query: &str) -> Result<()> {
+        let workspaces = self.niri.get_workspaces().await?;
+        let workspace = window_utils::resolve_workspace(query, &workspaces)?
+            .ok_or_else(|| IpcError::not_found(format!("No workspace matches '{}'", query)))?;
+        info!(
+            "Focusing workspace {}",
+            workspace.name.clone().unwrap_or_else(|| workspace.idx.to_string())
+        );
+        self.niri
+            .send_action(Action::FocusWorkspace {
+                reference: WorkspaceReferenceArg::Id(workspace.id),
+            })
+            .await
+    }
+
+    /// Focus, move or close the first window matching the app_id or title regex (either
+    /// may match, like in the config), or all of them. Windows on the focused workspace
+    /// come first, then the oldest
+    pub async fn window_action(
+        &self,
+        action: &WindowAction,
+        app_id: Option<String>,
+        title: Option<String>,
+        all: bool,
+    ) -> Result<()> {
+        let patterns = match (&app_id, &title) {
+            (Some(app_id), Some(title)) => format!("app_id '{}' or title '{}'", app_id, title),
+            (Some(app_id), None) => format!("app_id '{}'", app_id),
+            (None, Some(title)) => format!("title '{}'", title),
+            (None, None) => {
+                anyhow::bail!("An app_id or title pattern is required to match windows")
+            }
+        };
+        let matcher = WindowMatcher::new(app_id.map(|p| vec![p]), title.map(|p| vec![p]));
+
+        let workspaces = self.niri.get_workspaces().await?;
+        let focused_workspace = workspaces.iter().find(|ws| ws.is_focused).map(|ws| ws.id);
+        let mut matched = Vec::new();
+        for window in self.niri.get_windows().await? {
+            if self
+                .matcher_cache
+                .matches(window.app_id.as_ref(), Some(&window.title), &matcher)
+                .await?
+            {
+                matched.push(window);
+            }
+        }
+        if matched.is_empty() {
+            anyhow::bail!(IpcError::not_found(format!(
+                "No window matches {}",
+                patterns
+            )));
+        }
+        matched.sort_by_key(|w| (w.workspace_id != focused_workspace, w.id));
+        // Focusing several windows only leaves the last one focused
+        if !all || matches!(action, WindowAction::Focus) {
+            matched.truncate(1);
+        }
+
+        for window in matched {
+            info!("{:?} window {} ({:?})", action, window.id, window.app_id);
+            match action {
+                WindowAction::Focus => self.niri.focus_window(window.id).await?,
+                WindowAction::Move { workspace } => {
+                    let workspace = window_utils::resolve_workspace(workspace, &workspaces)?
+                        .ok_or_else(|| {
+                            IpcError::not_found(format!("No workspace matches '{}'", workspace))
+                        })?;
+                    self.niri.move_window_to_workspace_id(window.id, workspace.id).await?
+                }
+                WindowAction::Close => {
+                    self.niri
+                        .send_action(Action::CloseWindow {
+                            id: Some(window.id),
+                        })
+                        .await?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn version(&self) -> VersionInfo {
+        let plugins = self.plugin_manager.lock().await.plugin_states();
+        VersionInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            plugins: plugins.into_iter().filter(|p| p.enabled).map(|p| p.name).collect(),
+        }
+    }
+
+    /// Uptime, and how long niri takes to answer
+    pub async fn ping(&self) -> PingInfo {
+        let start = Instant::now();
+        let (niri_ms, niri_error) = match self.niri.send_request(niri_ipc::Request::Version).await {
+            Ok(_) => (Some(start.elapsed().as_secs_f64() * 1000.0), None),
+            Err(e) => (None, Some(format!("{:#}", e))),
+        };
+        PingInfo {
+            uptime_secs: self.started.elapsed().as_secs(),
+            restarts: crate::daemon::restarts(),
+            niri_ms,
+            niri_error,
+        }
+    }
+
+    /// Overview of the daemon, its plugins and the niri connection
+    pub async fn status(&self) -> DaemonStatus {
+        let niri = match self.niri.send_request(niri_ipc::Request::Version).await {
+            Ok(niri_ipc::Response::Version(version)) => NiriStatus {
+                connected: true,
+                version: Some(version),
+                error: None,
+            },
+            Ok(_) => NiriStatus {
+                connected: true,
+                version: None,
+                error: None,
+            },
+            Err(e) => NiriStatus {
+                connected: false,
+                version: None,
+                error: Some(e.to_string()),
+            },
+        };
+        DaemonStatus {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            uptime_secs: self.started.elapsed().as_secs(),
+            config_path: self.config_path.clone(),
+            profile: self.profile.clone(),
+            last_reload: self
+                .last_reload
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+            plugins: self.plugin_manager.lock().await.plugin_states(),
+            scratchpads: self.config.scratchpads.len(),
+            singletons: self.config.singleton.len(),
+            niri,
+        }
+    }
+
+    /// Switch to a config profile (None: no profile) and reload the config file with it
+    /// merged in, like `piri reload`. Does nothing if the profile is already active
+    pub async fn set_profile(&mut self, profile: Option<String>) -> Result<()> {
+        if profile == self.profile {
+            return Ok(());
+        }
+        info!("Switching config profile to {:?}", profile);
+        // Check that the profile applies before switching to it
+        Config::load_with_profile(&self.config_path, profile.as_deref())?;
+        self.profile = profile;
+        self.reload(None).await
+    }
+
+    /// Reload the config file and reinitialize plugins, or only the given plugin. Other
+    /// plugins keep their runtime state either way, but a single plugin reload doesn't
+    /// pass the new config to them
+    pub async fn reload(&mut self, plugin: Option<&str>) -> Result<()> {
+        let config_path = self.config_path.clone();
+        self.reload_config(&config_path).await?;
+        let mut pm = self.plugin_manager.lock().await;
+        match plugin {
+            Some(name) => {
+                info!("Reloading plugin {}", name);
+                pm.reload_plugin(name, self.niri.clone(), &self.config).await?;
+            }
+            None => {
+                self.niri.update_socket_path(self.config.niri.socket_path.clone());
+                pm.reload(self.niri.clone(), &self.config).await?;
+            }
+        }
+        crate::events::publish("ConfigReloaded", serde_json::json!({ "plugin": plugin }));
+        Ok(())
+    }
+
+    /// Value in the running config at a dotted key path
+    pub fn config_get(&self, path: &str) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(
+            self.config.redacted().get_value(path)?,
+        )?)
+    }
+
+    /// The whole running config, with defaults filled in and the entries plugins created at
+    /// runtime. The TCP token is redacted, here and in `config_get`
+    pub async fn config_show(&self) -> Result<serde_json::Value> {
+        let mut config = serde_json::to_value(toml::Value::try_from(self.config.redacted())?)?;
+        let runtime = self.plugin_manager.lock().await.runtime_config().await;
+        crate::utils::merge_json(&mut config, runtime);
+        Ok(config)
+    }
+
+    /// Change a value in the running config and reinitialize plugins with it. With `write`,
+    /// the value is also saved to the config file (which then gets hot-reloaded)
+    pub async fn config_set(&mut self, path: &str, value: &str, write: bool) -> Result<()> {
+        let value = config::parse_value(value);
+        let new_config = self.config.with_value(path, value.clone())?;
+        if write {
+            config::write_value(&self.config_path, path, &value)?;
+        }
+        info!("Setting config {} = {}", path, value);
+        self.config = new_config;
+        if let Err(e) = crate::logging::apply(&self.config.piri.log) {
+            log::warn!("{:#}", e);
+        }
+        crate::plugins::window_utils::set_command_shell(self.config.piri.command_shell);
+
+        self.niri.update_socket_path(self.config.niri.socket_path.clone());
+        let mut pm = self.plugin_manager.lock().await;
+        pm.reload(self.niri.clone(), &self.config).await
+    }
+
     /// Reload configuration from file (used by hot-reload)
     pub async fn reload_config(&mut self, config_path: &PathBuf) -> Result<()> {
         info!("Reloading configuration from {:?}", config_path);
 
-        let new_config = Config::load(config_path)?;
+        let new_config = Config::load_with_profile(config_path, self.profile.as_deref())?;
         info!("Configuration reloaded successfully");
+        for notice in new_config.deprecations(config_path) {
+            log::warn!("{} (`piri config migrate` rewrites it)", notice);
+        }
 
         // Update config
         self.config = new_config;
+        if let Err(e) = crate::logging::apply(&self.config.piri.log) {
+            log::warn!("{:#}", e);
+        }
+        crate::plugins::window_utils::set_command_shell(self.config.piri.command_shell);
+        self.last_reload = Some(SystemTime::now());
 
         // Note: Plugins will use the updated config on next request
         // Existing scratchpads will continue to work with old config
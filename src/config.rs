@@ -1,10 +1,12 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use crate::plugins::empty::EmptyPluginConfig;
+use crate::ipc::IpcError;
 
 /// Direction from which the scratchpad appears
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -16,28 +18,41 @@ pub enum Direction {
 }
 
 impl Direction {
-    /// Convert string to Direction
-    pub fn from_str(s: &str) -> Result<Self> {
+    /// The names directions are written as
+    pub const NAMES: &[&str] = &["fromTop", "fromBottom", "fromLeft", "fromRight"];
+
+    /// Convert Direction to string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::FromTop => "fromTop",
+            Direction::FromBottom => "fromBottom",
+            Direction::FromLeft => "fromLeft",
+            Direction::FromRight => "fromRight",
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
         match s {
             "fromTop" => Ok(Direction::FromTop),
             "fromBottom" => Ok(Direction::FromBottom),
             "fromLeft" => Ok(Direction::FromLeft),
             "fromRight" => Ok(Direction::FromRight),
             _ => anyhow::bail!(
-                "Invalid direction: {}. Must be one of: fromTop, fromBottom, fromLeft, fromRight",
-                s
+                "Invalid direction: {}. Must be one of: {}",
+                s,
+                Direction::NAMES.join(", ")
             ),
         }
     }
+}
 
-    /// Convert Direction to string
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            Direction::FromTop => "fromTop",
-            Direction::FromBottom => "fromBottom",
-            Direction::FromLeft => "fromLeft",
-            Direction::FromRight => "fromRight",
-        }
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
     }
 }
 
@@ -56,12 +71,340 @@ impl<'de> Deserialize<'de> for Direction {
         D: Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        Direction::from_str(&s).map_err(serde::de::Error::custom)
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Width and height of a scratchpad in percent of the output, written as "75% 60%"
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Size {
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Size {
+    /// Width and height as fractions of the output
+    pub fn ratios(&self) -> (f64, f64) {
+        (self.width / 100.0, self.height / 100.0)
+    }
+}
+
+impl FromStr for Size {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        let [width, height] = parts.as_slice() else {
+            anyhow::bail!("Size must be in format 'width% height%', got: {}", s);
+        };
+        Ok(Size {
+            width: parse_percent(width, "Width")?,
+            height: parse_percent(height, "Height")?,
+        })
+    }
+}
+
+/// One side of a size, e.g. "75%"
+fn parse_percent(value: &str, side: &str) -> Result<f64> {
+    let percent = value
+        .strip_suffix('%')
+        .ok_or_else(|| anyhow::anyhow!("{} must end with %, got: {}", side, value))?
+        .parse::<f64>()
+        .with_context(|| format!("Failed to parse {} '{}'", side.to_lowercase(), value))?;
+    if !(percent > 0.0 && percent <= 100.0) {
+        anyhow::bail!("{} must be above 0% and at most 100%, got: {}", side, value);
+    }
+    Ok(percent)
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}% {}%", self.width, self.height)
+    }
+}
+
+impl Serialize for Size {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Size {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(|e| serde::de::Error::custom(format!("{:#}", e)))
+    }
+}
+
+/// A length of time, written with units: "150ms", "2s", "5m", "1h" or combined as "1m30s".
+/// A plain number is the unit the setting used before, seconds unless noted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timespan(pub std::time::Duration);
+
+impl Timespan {
+    pub const fn from_secs(secs: u64) -> Self {
+        Timespan(std::time::Duration::from_secs(secs))
+    }
+}
+
+impl FromStr for Timespan {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow::anyhow!(
+                "Invalid duration: '{}' (expected a number with a unit: ms, s, m or h, e.g. \"150ms\" or \"1m30s\")",
+                s
+            )
+        };
+        let mut rest = s.trim();
+        if rest.is_empty() {
+            return Err(invalid());
+        }
+        let mut total = std::time::Duration::ZERO;
+        while !rest.is_empty() {
+            let number_end =
+                rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+            let unit_end = rest[number_end..]
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .map_or(rest.len(), |end| number_end + end);
+            let number: f64 = rest[..number_end].parse().map_err(|_| invalid())?;
+            let scale = match &rest[number_end..unit_end] {
+                "ms" => 0.001,
+                "s" => 1.0,
+                "m" => 60.0,
+                "h" => 3600.0,
+                // "0" needs no unit
+                "" if number == 0.0 && unit_end == rest.len() => 0.0,
+                _ => return Err(invalid()),
+            };
+            total +=
+                std::time::Duration::try_from_secs_f64(number * scale).map_err(|_| invalid())?;
+            rest = rest[unit_end..].trim_start();
+        }
+        Ok(Timespan(total))
+    }
+}
+
+impl fmt::Display for Timespan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0.as_secs();
+        if self.0.subsec_nanos() == 0 {
+            if secs == 0 {
+                return write!(f, "0s");
+            }
+            // "1h", "1m30s" and the like
+            for (value, unit) in [(secs / 3600, "h"), (secs / 60 % 60, "m"), (secs % 60, "s")] {
+                if value > 0 {
+                    write!(f, "{}{}", value, unit)?;
+                }
+            }
+            Ok(())
+        } else if self.0.as_nanos().is_multiple_of(1_000_000) {
+            write!(f, "{}ms", self.0.as_millis())
+        } else {
+            write!(f, "{}s", self.0.as_secs_f64())
+        }
+    }
+}
+
+impl Serialize for Timespan {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+/// Reads a duration string, or a plain number in `unit`
+struct TimespanVisitor {
+    unit: std::time::Duration,
+}
+
+impl<'de> serde::de::Visitor<'de> for TimespanVisitor {
+    type Value = Timespan;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a duration such as \"150ms\" or \"2s\"")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Timespan, E> {
+        value.parse().map_err(|e| E::custom(format!("{:#}", e)))
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Timespan, E> {
+        let value = u32::try_from(value).map_err(|_| E::custom("duration is too long"))?;
+        Ok(Timespan(self.unit * value))
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<Timespan, E> {
+        match u64::try_from(value) {
+            Ok(value) => self.visit_u64(value),
+            Err(_) => Err(E::custom("duration can't be negative")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Timespan {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(TimespanVisitor {
+            unit: std::time::Duration::from_secs(1),
+        })
+    }
+}
+
+/// A timeout or interval, which has to be above zero; plain numbers are seconds
+fn deserialize_timeout<'de, D>(deserializer: D) -> Result<Timespan, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    positive(Timespan::deserialize(deserializer)?)
+}
+
+/// Like `deserialize_timeout`, for settings whose plain numbers are milliseconds
+fn deserialize_timeout_millis<'de, D>(deserializer: D) -> Result<Timespan, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    positive(deserializer.deserialize_any(TimespanVisitor {
+        unit: std::time::Duration::from_millis(1),
+    })?)
+}
+
+/// Subcommands of `piri singleton`, which would be read instead of a singleton's name
+const SINGLETON_SUBCOMMANDS: [&str; 2] = ["list", "toggle"];
+
+fn deserialize_singletons<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, SingletonConfig>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+    let singletons = HashMap::<String, SingletonConfig>::deserialize(deserializer)?;
+    match SINGLETON_SUBCOMMANDS.iter().find(|name| singletons.contains_key(**name)) {
+        Some(name) => Err(D::Error::custom(format!(
+            "a singleton can't be named '{}', `piri singleton {}` runs that subcommand",
+            name, name
+        ))),
+        None => Ok(singletons),
+    }
+}
+
+fn positive<E: serde::de::Error>(timespan: Timespan) -> Result<Timespan, E> {
+    if timespan.0.is_zero() {
+        return Err(E::custom("duration must be above 0"));
+    }
+    Ok(timespan)
+}
+
+/// A size in bytes, written as a plain number or with a K, M or G suffix (powers of 1024),
+/// e.g. "10M"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+const BYTE_UNITS: &[(&str, u64)] = &[("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)];
+
+impl FromStr for ByteSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow::anyhow!(
+                "Invalid size: '{}' (expected a number of bytes, optionally with a K, M or G \
+                 suffix, e.g. \"10M\")",
+                s
+            )
+        };
+        let trimmed = s.trim();
+        let split = trimmed.find(|c: char| !c.is_ascii_digit()).unwrap_or(trimmed.len());
+        let (number, unit) = trimmed.split_at(split);
+        let number: u64 = number.parse().map_err(|_| invalid())?;
+        // "10M", "10MB" and "10MiB" are the same
+        let unit = unit.trim().to_uppercase();
+        let unit = unit.strip_suffix("IB").or_else(|| unit.strip_suffix('B')).unwrap_or(&unit);
+        let multiplier = match unit {
+            "" => 1,
+            unit => BYTE_UNITS
+                .iter()
+                .find(|(name, _)| *name == unit)
+                .map(|(_, multiplier)| *multiplier)
+                .ok_or_else(invalid)?,
+        };
+        number.checked_mul(multiplier).map(ByteSize).ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, multiplier) in BYTE_UNITS {
+            if self.0 > 0 && self.0.is_multiple_of(*multiplier) {
+                return write!(f, "{}{}", self.0 / multiplier, name);
+            }
+        }
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for ByteSize {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+/// Reads a size string, or a plain number of bytes
+struct ByteSizeVisitor;
+
+impl<'de> serde::de::Visitor<'de> for ByteSizeVisitor {
+    type Value = ByteSize;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a size such as \"10M\" or a number of bytes")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<ByteSize, E> {
+        value.parse().map_err(|e| E::custom(format!("{:#}", e)))
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<ByteSize, E> {
+        Ok(ByteSize(value))
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<ByteSize, E> {
+        u64::try_from(value)
+            .map(ByteSize)
+            .map_err(|_| E::custom("size can't be negative"))
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ByteSizeVisitor)
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
+    /// Further config files merged over this one, in order (`~/.config/niri/piri.d/*.toml`)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec_list")]
+    pub include: Vec<String>,
     #[serde(default)]
     pub niri: NiriConfig,
     #[serde(default)]
@@ -70,7 +413,7 @@ pub struct Config {
     pub scratchpads: HashMap<String, ScratchpadConfig>,
     #[serde(default)]
     pub empty: HashMap<String, EmptyWorkspaceConfig>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "deserialize_singletons")]
     pub singleton: HashMap<String, SingletonConfig>,
     #[serde(default)]
     pub window_rule: Vec<WindowRuleConfig>,
@@ -78,15 +421,48 @@ pub struct Config {
     pub window_order: HashMap<String, u32>,
     #[serde(default)]
     pub swallow: Vec<crate::plugins::swallow::SwallowRule>,
+    #[serde(default)]
+    pub idle_inhibit: Vec<IdleInhibitRule>,
+    #[serde(default)]
+    pub hooks: Vec<HookRule>,
+    #[serde(default)]
+    pub auto_fullscreen: Vec<AutoFullscreenRule>,
+    #[serde(default)]
+    pub autostart: Vec<AutostartEntry>,
+    #[serde(default)]
+    pub title_watch: Vec<TitleWatchRule>,
+    #[serde(default)]
+    pub column_width: Vec<ColumnWidthRule>,
+    #[serde(default)]
+    pub schedule: Vec<ScheduleEntry>,
+    #[serde(default)]
+    pub present: HashMap<String, PresentConfig>,
+    /// Workspace name -> outputs it belongs on, in order of preference
+    #[serde(default, deserialize_with = "deserialize_string_or_vec_map")]
+    pub workspace_pin: HashMap<String, Vec<String>>,
+    /// Named partial configs merged over the rest of the file while the profile is active
+    #[serde(default)]
+    pub profiles: HashMap<String, toml::Table>,
+    /// Hostname pattern (`*` and `?` wildcards) -> partial config merged over the rest of
+    /// the file on the machines it matches
+    #[serde(default)]
+    pub host: HashMap<String, toml::Table>,
+    /// Custom subcommands: name -> the piri command line it stands for
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+    /// Output name -> settings for the windows and workspaces on it, over the global ones
+    #[serde(default)]
+    pub outputs: HashMap<String, OutputConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct WindowOrderSection {
     #[serde(default = "default_enable_event_listener")]
     pub enable_event_listener: bool,
     #[serde(default = "default_window_order_weight")]
     pub default_weight: u32,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "deserialize_string_or_vec_list")]
     pub workspaces: Vec<String>,
 }
 
@@ -101,6 +477,7 @@ impl Default for WindowOrderSection {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct SwallowSection {
     #[serde(default)]
     pub rules: Vec<crate::plugins::swallow::SwallowRule>,
@@ -110,73 +487,1134 @@ pub struct SwallowSection {
     pub exclude: Option<crate::plugins::swallow::SwallowExclude>,
 }
 
-fn default_true() -> bool {
-    true
+/// `[piri.exclude]`: windows that swallow, window_rule, window_order and autofill leave
+/// alone, such as screenshot tools and launchers
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExcludeSection {
+    /// Regex pattern(s) of app_ids
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub app_id: Option<Vec<String>>,
+    /// Regex pattern(s) of titles
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub title: Option<Vec<String>>,
 }
 
-impl Default for SwallowSection {
+/// `[piri.log]`: how much the daemon logs, applied at startup and on every reload.
+/// `RUST_LOG` takes precedence over it, and `--debug` over `level`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogSection {
+    /// Level of the modules without a filter of their own (default: info)
+    #[serde(default)]
+    pub level: Option<LogLevel>,
+    /// Levels of single modules, e.g. "piri::plugins::swallow=trace"
+    #[serde(default)]
+    pub filters: Vec<LogFilter>,
+    /// "text" (default) or "json" for one JSON object per line
+    #[serde(default)]
+    pub format: Option<LogFormat>,
+    /// File the daemon also writes its log to, e.g. "~/.local/state/piri/piri.log"
+    #[serde(default)]
+    pub file: Option<String>,
+    /// Size at which the log file is renamed to `<file>.1` and a new one started
+    #[serde(default = "default_log_max_size")]
+    pub max_size: ByteSize,
+    /// Number of rotated log files kept, `<file>.1` being the most recent
+    #[serde(default = "default_log_keep")]
+    pub keep: usize,
+}
+
+fn default_log_max_size() -> ByteSize {
+    ByteSize(10 << 20)
+}
+
+fn default_log_keep() -> usize {
+    3
+}
+
+impl Default for LogSection {
     fn default() -> Self {
         Self {
-            rules: Vec::new(),
-            use_pid_matching: default_true(),
-            exclude: None,
+            level: None,
+            filters: Vec::new(),
+            format: None,
+            file: None,
+            max_size: default_log_max_size(),
+            keep: default_log_keep(),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct NiriConfig {
-    /// Path to niri socket (default: $XDG_RUNTIME_DIR/niri or /tmp/niri)
-    pub socket_path: Option<String>,
+impl LogSection {
+    /// Configured log file with `~` and environment variables expanded
+    pub fn file(&self) -> Option<PathBuf> {
+        self.file.as_ref().map(|file| {
+            shellexpand::full(file)
+                .map(|p| PathBuf::from(p.as_ref()))
+                .unwrap_or_else(|_| PathBuf::from(file))
+        })
+    }
 }
 
-impl Default for NiriConfig {
-    fn default() -> Self {
-        Self { socket_path: None }
+/// A log level: off, error, warn, info, debug or trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogLevel(pub log::LevelFilter);
+
+impl LogLevel {
+    pub const NAMES: &'static [&'static str] = &["off", "error", "warn", "info", "debug", "trace"];
+}
+
+impl FromStr for LogLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        s.parse().map(LogLevel).map_err(|_| {
+            anyhow::anyhow!(
+                "Invalid log level: {} (expected off, error, warn, info, debug or trace)",
+                s
+            )
+        })
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PiriConfig {
-    #[serde(default)]
-    pub scratchpad: ScratchpadDefaults,
-    #[serde(default)]
-    pub plugins: PluginsConfig,
-    #[serde(default)]
-    pub window_order: WindowOrderSection,
-    #[serde(default)]
-    pub swallow: SwallowSection,
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.as_str().to_lowercase())
+    }
 }
 
-impl Default for PiriConfig {
-    fn default() -> Self {
-        Self {
-            scratchpad: ScratchpadDefaults::default(),
-            plugins: PluginsConfig::default(),
-            window_order: WindowOrderSection::default(),
-            swallow: SwallowSection::default(),
+impl Serialize for LogLevel {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for LogLevel {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(|e| serde::de::Error::custom(format!("{:#}", e)))
+    }
+}
+
+/// The level of one module and its submodules, written as "module=level". A module on its
+/// own logs everything, as in `RUST_LOG`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFilter {
+    pub module: String,
+    pub level: LogLevel,
+}
+
+impl FromStr for LogFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (module, level) = match s.split_once('=') {
+            Some((module, level)) => (module.trim(), level.trim().parse()?),
+            None => (s.trim(), LogLevel(log::LevelFilter::Trace)),
+        };
+        if module.is_empty() || module.contains(char::is_whitespace) {
+            anyhow::bail!("Log filter must be in format 'module=level', got: {}", s);
         }
+        Ok(LogFilter {
+            module: module.to_string(),
+            level,
+        })
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PluginsConfig {
-    #[serde(default)]
-    pub scratchpads: Option<bool>,
+impl fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.module, self.level)
+    }
+}
+
+impl Serialize for LogFilter {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for LogFilter {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(|e| serde::de::Error::custom(format!("{:#}", e)))
+    }
+}
+
+/// How log lines are written, `[piri.log] format` or `--log-format`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// `[timestamp LEVEL module] message`, as env_logger writes them
+    #[default]
+    Text,
+    /// A JSON object per line with timestamp, level, module and message fields
+    Json,
+}
+
+impl LogFormat {
+    /// The names formats are written as
+    pub const NAMES: &[&str] = &["text", "json"];
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => anyhow::bail!(
+                "Invalid log format: {}. Must be one of: {}",
+                s,
+                LogFormat::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for SwallowSection {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            use_pid_matching: default_true(),
+            exclude: None,
+        }
+    }
+}
+
+/// How autofill aligns columns after a window closes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AutofillAlign {
+    /// Align the last column to the right edge
+    #[default]
+    Right,
+    /// Keep the focused column centered
+    Center,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutofillSection {
+    /// Alignment mode: "right" (default) or "center"
+    #[serde(default)]
+    pub align: AutofillAlign,
+    /// Equalize column widths on the workspace after a window closes
+    #[serde(default)]
+    pub balance_columns: bool,
+    /// Only act when the workspace has at least this many columns
+    #[serde(default)]
+    pub min_columns: usize,
+    /// Workspaces (name or idx) to align (empty = all workspaces)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec_list")]
+    pub workspaces: Vec<String>,
+    /// Outputs to align (empty = all outputs)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec_list")]
+    pub outputs: Vec<String>,
+    /// Regex pattern(s) of app_ids that never trigger alignment
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub exclude_app_id: Option<Vec<String>>,
+    /// Keep a master column on the left and stack every other window in one column
+    #[serde(default)]
+    pub master_stack: bool,
+    /// Width of the master column in percent of the output (default: 60)
+    #[serde(default = "default_master_width")]
+    pub master_width: f64,
+    /// Regex pattern(s) of app_ids that take the master column (default: newest window)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub master_app_id: Option<Vec<String>>,
+    /// Center the largest floating window once the last tiled window has closed
+    #[serde(default)]
+    pub center_floating: bool,
+}
+
+fn default_master_width() -> f64 {
+    60.0
+}
+
+impl Default for AutofillSection {
+    fn default() -> Self {
+        Self {
+            align: AutofillAlign::default(),
+            balance_columns: false,
+            min_columns: 0,
+            workspaces: Vec::new(),
+            outputs: Vec::new(),
+            exclude_app_id: None,
+            master_stack: false,
+            master_width: default_master_width(),
+            master_app_id: None,
+            center_floating: false,
+        }
+    }
+}
+
+/// Which windows focus history navigation considers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FocusScope {
+    /// All windows on all workspaces
+    #[default]
+    Global,
+    /// Only windows on the focused workspace
+    Workspace,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FocusHistorySection {
+    /// Default scope for `piri focus` commands: "global" (default) or "workspace"
+    #[serde(default)]
+    pub scope: FocusScope,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkspaceNamesSection {
+    /// app_id (exact or regex) -> label or icon used in workspace names.
+    /// Apps without a label use their app_id
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    /// Name format. Placeholders: {idx}, {apps} (default: "{idx}: {apps}")
+    #[serde(default = "default_workspace_name_format")]
+    pub format: String,
+    /// Maximum number of apps in a name, most windows first (default: 2)
+    #[serde(default = "default_workspace_name_max_apps")]
+    pub max_apps: usize,
+    /// Separator between app labels (default: " ")
+    #[serde(default = "default_workspace_name_separator")]
+    pub separator: String,
+}
+
+fn default_workspace_name_format() -> String {
+    "{idx}: {apps}".to_string()
+}
+
+fn default_workspace_name_max_apps() -> usize {
+    2
+}
+
+fn default_workspace_name_separator() -> String {
+    " ".to_string()
+}
+
+impl Default for WorkspaceNamesSection {
+    fn default() -> Self {
+        Self {
+            labels: HashMap::new(),
+            format: default_workspace_name_format(),
+            max_apps: default_workspace_name_max_apps(),
+            separator: default_workspace_name_separator(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdleInhibitSection {
+    /// Command that keeps the session from going idle while it runs; it is
+    /// started when a rule matches and terminated when none does
+    #[serde(default = "default_idle_inhibit_command")]
+    pub command: CommandLine,
+}
+
+fn default_idle_inhibit_command() -> CommandLine {
+    CommandLine::from("systemd-inhibit --what=idle --who=piri --why='Window rule' sleep infinity")
+}
+
+impl Default for IdleInhibitSection {
+    fn default() -> Self {
+        Self {
+            command: default_idle_inhibit_command(),
+        }
+    }
+}
+
+/// When an idle inhibit rule applies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InhibitWhen {
+    /// A matching window is open
+    #[default]
+    Exists,
+    /// A matching window is focused
+    Focused,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdleInhibitRule {
+    /// Regex pattern(s) to match app_id (optional, can be a string or list of strings)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub app_id: Option<Vec<String>>,
+    /// Regex pattern(s) to match title (optional, can be a string or list of strings)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub title: Option<Vec<String>>,
+    /// "exists" (default) or "focused"
+    #[serde(default)]
+    pub when: InhibitWhen,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutostartEntry {
+    /// Command to launch
+    pub command: CommandLine,
+    /// Regex pattern(s) of the app_id the command's window has; without it the command is
+    /// launched without waiting for a window
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub app_id: Option<Vec<String>>,
+    /// Workspace name or idx to put the window on
+    #[serde(default)]
+    pub workspace: Option<String>,
+    /// Output to put the window on (its active workspace, or `workspace` on that output)
+    #[serde(default)]
+    pub output: Option<String>,
+    /// How long to wait for the window (default: "10s")
+    #[serde(
+        default = "default_autostart_timeout",
+        deserialize_with = "deserialize_timeout"
+    )]
+    pub timeout: Timespan,
+}
+
+fn default_autostart_timeout() -> Timespan {
+    Timespan::from_secs(10)
+}
+
+/// What an auto fullscreen rule does to matching windows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FullscreenMode {
+    /// Fullscreen the window
+    #[default]
+    Fullscreen,
+    /// Maximize the window's column
+    Maximize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutoFullscreenRule {
+    /// Regex pattern(s) to match app_id (optional, can be a string or list of strings)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub app_id: Option<Vec<String>>,
+    /// Regex pattern(s) to match title (optional, can be a string or list of strings)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub title: Option<Vec<String>>,
+    /// Workspace name(s) or idx(es) the rule applies on (optional, default: all)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub workspace: Option<Vec<String>>,
+    /// Output name(s) the rule applies on (optional, default: all)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub output: Option<Vec<String>>,
+    /// "fullscreen" (default) or "maximize"
+    #[serde(default)]
+    pub mode: FullscreenMode,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookRule {
+    /// niri event type, e.g. "WindowOpenedOrChanged" or "WorkspaceActivated"
+    pub event: String,
+    /// Regex pattern(s) the event's window app_id must match (optional, string or list)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub match_app_id: Option<Vec<String>>,
+    /// Regex pattern(s) the event's window title must match (optional, string or list)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub match_title: Option<Vec<String>>,
+    /// Command; {field} placeholders are replaced by the event's fields (shell-quoted in a string)
+    pub exec: CommandLine,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnWidthRule {
+    /// Regex pattern(s) to match app_id (can be a string or list of strings)
+    #[serde(deserialize_with = "deserialize_string_or_vec")]
+    pub app_id: Option<Vec<String>>,
+    /// Width preset ("one-third", "half", "two-thirds"), proportion ("40%") or pixels ("800")
+    pub width: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleEntry {
+    /// When to run, as a cron expression in local time (e.g., "55 9 * * 1-5")
+    pub cron: String,
+    /// Config profile to switch to ("": the base config)
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Command to run, e.g. a layout command
+    #[serde(default)]
+    pub exec: Option<CommandLine>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PresentConfig {
+    /// Regex pattern(s) to match app_id of the windows to present
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub app_id: Option<Vec<String>>,
+    /// Regex pattern(s) to match title of the windows to present
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub title: Option<Vec<String>>,
+    /// Named workspace the windows are moved to (default: "present")
+    #[serde(default = "default_present_workspace")]
+    pub workspace: String,
+    /// Plugins suspended while presenting (default: window_order, autofill)
+    #[serde(
+        default = "default_present_suspend",
+        deserialize_with = "deserialize_string_or_vec_list"
+    )]
+    pub suspend: Vec<String>,
+}
+
+fn default_present_workspace() -> String {
+    "present".to_string()
+}
+
+fn default_present_suspend() -> Vec<String> {
+    vec!["window_order".to_string(), "autofill".to_string()]
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TitleWatchRule {
+    /// Regex pattern(s) of app_ids to watch (optional, default: all windows)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub app_id: Option<Vec<String>>,
+    /// Regex the title has to start matching for the command to run
+    pub title: String,
+    /// Command; {id}, {app_id} and {title} are replaced by the window's (shell-quoted in a string)
+    pub exec: CommandLine,
+    /// Minimum time between two runs of this rule (default: 0)
+    #[serde(default)]
+    pub cooldown: Timespan,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WallpaperSection {
+    /// Command that sets the wallpaper of an output.
+    /// Placeholders (substituted shell-quoted in a string): {image}, {output}, {workspace}
+    #[serde(default = "default_wallpaper_command")]
+    pub command: CommandLine,
+    /// Image used when neither the workspace nor the output has one
+    #[serde(default)]
+    pub default: Option<String>,
+    /// Workspace -> image. Keys are "OUTPUT:workspace", a workspace name or an idx
+    #[serde(default)]
+    pub workspaces: HashMap<String, String>,
+    /// Output -> image
+    #[serde(default)]
+    pub outputs: HashMap<String, String>,
+}
+
+fn default_wallpaper_command() -> CommandLine {
+    CommandLine::from("swww img --outputs {output} {image}")
+}
+
+impl Default for WallpaperSection {
+    fn default() -> Self {
+        Self {
+            command: default_wallpaper_command(),
+            default: None,
+            workspaces: HashMap::new(),
+            outputs: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkspaceLifecycleSection {
+    /// Names given to workspaces that get a window, in order
+    #[serde(default, deserialize_with = "deserialize_string_or_vec_list")]
+    pub names: Vec<String>,
+    /// Name format used once `names` are used up, {n} is the lowest free number (default: "ws{n}")
+    #[serde(default = "default_workspace_lifecycle_format")]
+    pub format: String,
+    /// Unname workspaces once they are empty so niri removes them (default: true)
+    #[serde(default = "default_true")]
+    pub remove_empty: bool,
+    /// Only manage workspaces on these outputs (default: all)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub outputs: Option<Vec<String>>,
+}
+
+fn default_workspace_lifecycle_format() -> String {
+    "ws{n}".to_string()
+}
+
+impl Default for WorkspaceLifecycleSection {
+    fn default() -> Self {
+        Self {
+            names: Vec::new(),
+            format: default_workspace_lifecycle_format(),
+            remove_empty: true,
+            outputs: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HotplugSection {
+    /// Outputs that workspaces of a disconnected output are moved to, in order of preference
+    #[serde(default, deserialize_with = "deserialize_string_or_vec_list")]
+    pub fallback_outputs: Vec<String>,
+    /// Command to run when an output is connected, {output} is replaced by its name
+    #[serde(default)]
+    pub on_added: Option<CommandLine>,
+    /// Command to run when an output is disconnected, {output} is replaced by its name
+    #[serde(default)]
+    pub on_removed: Option<CommandLine>,
+    /// Reposition hidden scratchpads after outputs change (default: false)
+    #[serde(default)]
+    pub rehome_scratchpads: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FloatingGeometrySection {
+    /// Regex pattern(s) of app_ids to remember (optional, default: every floating window)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub app_id: Option<Vec<String>>,
+    /// Title regex patterns whose windows get their own geometry per app
+    #[serde(default, deserialize_with = "deserialize_string_or_vec_list")]
+    pub titles: Vec<String>,
+    /// Restore the position as well as the size (default: true)
+    #[serde(default = "default_true")]
+    pub restore_position: bool,
+}
+
+impl Default for FloatingGeometrySection {
+    fn default() -> Self {
+        Self {
+            app_id: None,
+            titles: Vec::new(),
+            restore_position: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GamingSection {
+    /// Regex pattern(s) of game app_ids (string or list)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub app_id: Option<Vec<String>>,
+    /// Plugins suspended while a game is fullscreen (default: window_order, autofill, swallow)
+    #[serde(
+        default = "default_gaming_suspend",
+        deserialize_with = "deserialize_string_or_vec_list"
+    )]
+    pub suspend: Vec<String>,
+    /// Command to run when a game goes fullscreen
+    #[serde(default)]
+    pub on_start: Option<CommandLine>,
+    /// Command to run when no game is fullscreen anymore
+    #[serde(default)]
+    pub on_stop: Option<CommandLine>,
+}
+
+fn default_gaming_suspend() -> Vec<String> {
+    vec![
+        "window_order".to_string(),
+        "autofill".to_string(),
+        "swallow".to_string(),
+    ]
+}
+
+impl Default for GamingSection {
+    fn default() -> Self {
+        Self {
+            app_id: None,
+            suspend: default_gaming_suspend(),
+            on_start: None,
+            on_stop: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PowerSection {
+    /// Profile (`[profiles.<name>]`) to switch to on battery (default: none)
+    #[serde(default)]
+    pub battery_profile: Option<String>,
+    /// Profile to switch to on AC (default: none, i.e. the base config)
+    #[serde(default)]
+    pub ac_profile: Option<String>,
+    /// Command to run when switching to battery
+    #[serde(default)]
+    pub on_battery: Option<CommandLine>,
+    /// Command to run when switching to AC
+    #[serde(default)]
+    pub on_ac: Option<CommandLine>,
+    /// Time between power supply checks (default: "10s")
+    #[serde(
+        default = "default_power_poll_interval",
+        deserialize_with = "deserialize_timeout"
+    )]
+    pub poll_interval: Timespan,
+}
+
+fn default_power_poll_interval() -> Timespan {
+    Timespan::from_secs(10)
+}
+
+impl Default for PowerSection {
+    fn default() -> Self {
+        Self {
+            battery_profile: None,
+            ac_profile: None,
+            on_battery: None,
+            on_ac: None,
+            poll_interval: default_power_poll_interval(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkspaceOsdSection {
+    /// Notification text. Placeholders: {name}, {idx}, {output}, {windows}
+    /// (default: "Workspace {name} ({windows} windows)")
+    #[serde(default = "default_workspace_osd_format")]
+    pub format: String,
+    /// Command to run instead of showing a notification, same placeholders (shell-quoted)
+    #[serde(default)]
+    pub command: Option<CommandLine>,
+    /// How long the notification shows, plain numbers are milliseconds (default: "1s")
+    #[serde(
+        default = "default_workspace_osd_timeout",
+        deserialize_with = "deserialize_timeout_millis"
+    )]
+    pub timeout: Timespan,
+    /// Per-output overrides, by output name
+    #[serde(default)]
+    pub outputs: HashMap<String, WorkspaceOsdOutput>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkspaceOsdOutput {
+    /// Show the OSD for workspaces on this output (default: true)
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Notification text for this output
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Command for this output
+    #[serde(default)]
+    pub command: Option<CommandLine>,
+}
+
+fn default_workspace_osd_format() -> String {
+    "Workspace {name} ({windows} windows)".to_string()
+}
+
+fn default_workspace_osd_timeout() -> Timespan {
+    Timespan::from_secs(1)
+}
+
+impl Default for WorkspaceOsdSection {
+    fn default() -> Self {
+        Self {
+            format: default_workspace_osd_format(),
+            command: None,
+            timeout: default_workspace_osd_timeout(),
+            outputs: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GroupsSection {
+    /// Named workspace that hidden group members are parked on; it has to be declared
+    /// in the niri config (default: "piri-groups")
+    #[serde(default = "default_park_workspace")]
+    pub park_workspace: String,
+}
+
+fn default_park_workspace() -> String {
+    "piri-groups".to_string()
+}
+
+impl Default for GroupsSection {
+    fn default() -> Self {
+        Self {
+            park_workspace: default_park_workspace(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DndSection {
+    /// Plugins suspended while do-not-disturb is on (default: workspace_osd, title_watch)
+    #[serde(
+        default = "default_dnd_suspend",
+        deserialize_with = "deserialize_string_or_vec_list"
+    )]
+    pub suspend: Vec<String>,
+    /// Command to run when do-not-disturb is turned on
+    #[serde(default)]
+    pub on_enter: Option<CommandLine>,
+    /// Command to run when do-not-disturb is turned off
+    #[serde(default)]
+    pub on_exit: Option<CommandLine>,
+}
+
+fn default_dnd_suspend() -> Vec<String> {
+    vec!["workspace_osd".to_string(), "title_watch".to_string()]
+}
+
+impl Default for DndSection {
+    fn default() -> Self {
+        Self {
+            suspend: default_dnd_suspend(),
+            on_enter: None,
+            on_exit: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StashSection {
+    /// Named workspace that stashed windows are moved to; it has to be declared in the
+    /// niri config (default: "piri-stash")
+    #[serde(default = "default_stash_workspace")]
+    pub workspace: String,
+}
+
+fn default_stash_workspace() -> String {
+    "piri-stash".to_string()
+}
+
+impl Default for StashSection {
+    fn default() -> Self {
+        Self {
+            workspace: default_stash_workspace(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SocketSection {
+    /// IPC socket path, or `@name` for an abstract socket (default:
+    /// $XDG_RUNTIME_DIR/piri-$WAYLAND_DISPLAY.sock, in /tmp without XDG_RUNTIME_DIR)
+    #[serde(default)]
+    pub path: Option<String>,
+    /// File mode of the socket, e.g. 0o660 (default: as created, subject to the umask)
+    #[serde(default)]
+    pub mode: Option<u32>,
+    /// Group owning the socket, by name or ID
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+impl SocketSection {
+    /// Configured socket path with `~` and environment variables expanded
+    pub fn path(&self) -> Option<PathBuf> {
+        self.path.as_ref().map(|path| {
+            shellexpand::full(path)
+                .map(|p| PathBuf::from(p.as_ref()))
+                .unwrap_or_else(|_| PathBuf::from(path))
+        })
+    }
+}
+
+/// TCP listener speaking the socket's protocol, for clients in containers or behind SSH
+/// port forwarding
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TcpSection {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Address to listen on (default: 127.0.0.1:7777)
+    #[serde(default = "default_tcp_address")]
+    pub address: String,
+    /// Token clients must present before any request; the listener isn't started without one
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+fn default_tcp_address() -> String {
+    "127.0.0.1:7777".to_string()
+}
+
+impl Default for TcpSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: default_tcp_address(),
+            token: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyboardLayoutSection {
+    /// Layout for windows that haven't had one yet, by name as listed by
+    /// `niri msg keyboard-layouts` (default: keep the current layout)
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BarSection {
+    /// Format of the `text` field printed by `piri bar`.
+    /// Placeholders: {workspace}, {output}, {scratchpads}, {singletons}
+    #[serde(default = "default_bar_format")]
+    pub format: String,
+}
+
+fn default_bar_format() -> String {
+    "{workspace}".to_string()
+}
+
+impl Default for BarSection {
+    fn default() -> Self {
+        Self {
+            format: default_bar_format(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SessionSection {
+    /// app_id -> command used to relaunch windows of that app on restore
+    #[serde(default)]
+    pub commands: HashMap<String, CommandLine>,
+    /// How long to wait for each relaunched window to appear (default: "10s")
+    #[serde(
+        default = "default_launch_timeout",
+        deserialize_with = "deserialize_timeout"
+    )]
+    pub launch_timeout: Timespan,
+}
+
+fn default_launch_timeout() -> Timespan {
+    Timespan::from_secs(10)
+}
+
+impl Default for SessionSection {
+    fn default() -> Self {
+        Self {
+            commands: HashMap::new(),
+            launch_timeout: default_launch_timeout(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NiriConfig {
+    /// Path to niri socket (default: $XDG_RUNTIME_DIR/niri or /tmp/niri)
+    pub socket_path: Option<String>,
+}
+
+impl Default for NiriConfig {
+    fn default() -> Self {
+        Self { socket_path: None }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PiriConfig {
+    #[serde(default)]
+    pub scratchpad: ScratchpadDefaults,
+    #[serde(default)]
+    pub plugins: PluginsConfig,
+    #[serde(default)]
+    pub window_order: WindowOrderSection,
+    #[serde(default)]
+    pub swallow: SwallowSection,
+    #[serde(default)]
+    pub autofill: AutofillSection,
+    #[serde(default)]
+    pub focus_history: FocusHistorySection,
+    #[serde(default)]
+    pub session: SessionSection,
+    #[serde(default)]
+    pub bar: BarSection,
+    #[serde(default)]
+    pub workspace_names: WorkspaceNamesSection,
+    #[serde(default)]
+    pub idle_inhibit: IdleInhibitSection,
+    #[serde(default)]
+    pub wallpaper: WallpaperSection,
+    #[serde(default)]
+    pub hotplug: HotplugSection,
+    #[serde(default)]
+    pub workspace_lifecycle: WorkspaceLifecycleSection,
+    #[serde(default)]
+    pub groups: GroupsSection,
+    #[serde(default)]
+    pub floating_geometry: FloatingGeometrySection,
+    #[serde(default)]
+    pub gaming: GamingSection,
+    #[serde(default)]
+    pub power: PowerSection,
+    #[serde(default)]
+    pub workspace_osd: WorkspaceOsdSection,
+    #[serde(default)]
+    pub stash: StashSection,
+    #[serde(default)]
+    pub dnd: DndSection,
+    #[serde(default)]
+    pub keyboard_layout: KeyboardLayoutSection,
+    #[serde(default)]
+    pub socket: SocketSection,
+    #[serde(default)]
+    pub tcp: TcpSection,
+    /// Reload the config file when it changes on disk (default: true)
+    #[serde(default = "default_true")]
+    pub auto_reload: bool,
+    #[serde(default)]
+    pub exclude: ExcludeSection,
+    #[serde(default)]
+    pub log: LogSection,
+    /// How string commands are run: "sh" (default), "bash" or "none" to split them into
+    /// words and run them without a shell
+    #[serde(default)]
+    pub command_shell: CommandShell,
+}
+
+impl Default for PiriConfig {
+    fn default() -> Self {
+        Self {
+            scratchpad: ScratchpadDefaults::default(),
+            plugins: PluginsConfig::default(),
+            window_order: WindowOrderSection::default(),
+            swallow: SwallowSection::default(),
+            autofill: AutofillSection::default(),
+            focus_history: FocusHistorySection::default(),
+            session: SessionSection::default(),
+            bar: BarSection::default(),
+            workspace_names: WorkspaceNamesSection::default(),
+            idle_inhibit: IdleInhibitSection::default(),
+            wallpaper: WallpaperSection::default(),
+            hotplug: HotplugSection::default(),
+            workspace_lifecycle: WorkspaceLifecycleSection::default(),
+            groups: GroupsSection::default(),
+            floating_geometry: FloatingGeometrySection::default(),
+            gaming: GamingSection::default(),
+            power: PowerSection::default(),
+            workspace_osd: WorkspaceOsdSection::default(),
+            stash: StashSection::default(),
+            dnd: DndSection::default(),
+            keyboard_layout: KeyboardLayoutSection::default(),
+            socket: SocketSection::default(),
+            tcp: TcpSection::default(),
+            auto_reload: default_true(),
+            exclude: ExcludeSection::default(),
+            log: LogSection::default(),
+            command_shell: CommandShell::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginsConfig {
+    #[serde(default)]
+    pub scratchpads: Option<bool>,
+    #[serde(default)]
+    pub empty: Option<bool>,
+    #[serde(default)]
+    pub window_rule: Option<bool>,
+    #[serde(default)]
+    pub autofill: Option<bool>,
+    #[serde(default)]
+    pub singleton: Option<bool>,
+    #[serde(default)]
+    pub window_order: Option<bool>,
+    #[serde(default)]
+    pub swallow: Option<bool>,
+    #[serde(default)]
+    pub focus_history: Option<bool>,
+    #[serde(default)]
+    pub marks: Option<bool>,
+    #[serde(default)]
+    pub session: Option<bool>,
+    #[serde(default)]
+    pub bar: Option<bool>,
+    #[serde(default)]
+    pub workspace_names: Option<bool>,
+    #[serde(default)]
+    pub idle_inhibit: Option<bool>,
+    #[serde(default)]
+    pub wallpaper: Option<bool>,
+    #[serde(default)]
+    pub hotplug: Option<bool>,
+    #[serde(default)]
+    pub workspace_lifecycle: Option<bool>,
+    #[serde(default)]
+    pub groups: Option<bool>,
+    #[serde(default)]
+    pub hooks: Option<bool>,
+    #[serde(default)]
+    pub floating_geometry: Option<bool>,
+    #[serde(default)]
+    pub auto_fullscreen: Option<bool>,
+    #[serde(default)]
+    pub gaming: Option<bool>,
+    #[serde(default)]
+    pub power: Option<bool>,
+    #[serde(default)]
+    pub autostart: Option<bool>,
+    #[serde(default)]
+    pub columns: Option<bool>,
+    #[serde(default)]
+    pub workspace_pin: Option<bool>,
+    #[serde(default)]
+    pub workspace_osd: Option<bool>,
+    #[serde(default)]
+    pub title_watch: Option<bool>,
+    #[serde(default)]
+    pub stash: Option<bool>,
     #[serde(default)]
-    pub empty: Option<bool>,
+    pub dnd: Option<bool>,
     #[serde(default)]
-    pub window_rule: Option<bool>,
+    pub column_width: Option<bool>,
     #[serde(default)]
-    pub autofill: Option<bool>,
+    pub schedule: Option<bool>,
     #[serde(default)]
-    pub singleton: Option<bool>,
+    pub present: Option<bool>,
     #[serde(default)]
-    pub window_order: Option<bool>,
+    pub keyboard_layout: Option<bool>,
     #[serde(default)]
-    pub swallow: Option<bool>,
-    #[serde(rename = "empty_config", default)]
-    pub empty_config: Option<EmptyPluginConfig>,
+    pub sendto: Option<bool>,
 }
 
 impl Default for PluginsConfig {
@@ -189,26 +1627,110 @@ impl Default for PluginsConfig {
             singleton: None,
             window_order: None,
             swallow: None,
-            empty_config: None,
+            focus_history: None,
+            marks: None,
+            session: None,
+            bar: None,
+            workspace_names: None,
+            idle_inhibit: None,
+            wallpaper: None,
+            hotplug: None,
+            workspace_lifecycle: None,
+            groups: None,
+            hooks: None,
+            floating_geometry: None,
+            auto_fullscreen: None,
+            gaming: None,
+            power: None,
+            autostart: None,
+            columns: None,
+            workspace_pin: None,
+            workspace_osd: None,
+            title_watch: None,
+            stash: None,
+            dnd: None,
+            column_width: None,
+            schedule: None,
+            present: None,
+            keyboard_layout: None,
+            sendto: None,
         }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct EmptyWorkspaceConfig {
-    /// Command to execute when switching to this empty workspace
-    pub command: String,
+    /// Command(s) to execute when switching to this empty workspace, in order
+    #[serde(deserialize_with = "deserialize_commands")]
+    pub command: Vec<CommandLine>,
+}
+
+/// `[outputs."<name>"]`: what differs for the windows and workspaces on one output
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Plugins switched on or off on this output; unset ones follow `[piri.plugins]`
+    #[serde(default)]
+    pub plugins: PluginsConfig,
+    /// Scratchpad defaults while this output is focused
+    #[serde(default)]
+    pub scratchpad: ScratchpadOverrides,
+    /// Empty workspace rules, taking precedence over `[empty.<workspace>]`
+    #[serde(default)]
+    pub empty: HashMap<String, EmptyWorkspaceConfig>,
+}
+
+/// `[piri.scratchpad]` settings that differ on one output
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScratchpadOverrides {
+    #[serde(default)]
+    pub default_size: Option<Size>,
+    #[serde(default)]
+    pub default_margin: Option<u32>,
+    #[serde(default)]
+    pub move_to_workspace: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct SingletonConfig {
     /// Command to execute the application (can include environment variables and arguments)
-    pub command: String,
-    /// Optional app_id pattern to match windows (if not specified, extracted from command)
-    pub app_id: Option<String>,
+    pub command: CommandLine,
+    /// Regex pattern(s) to match app_id (if neither app_id nor title is set, extracted from command)
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub app_id: Option<Vec<String>>,
+    /// Regex pattern(s) to match title
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub title: Option<Vec<String>>,
     /// Optional command to execute after the window is created (only executed when window is newly created)
     #[serde(default)]
-    pub on_created_command: Option<String>,
+    pub on_created_command: Option<CommandLine>,
+    /// Bring an existing window to the focused workspace instead of switching to it
+    #[serde(default)]
+    pub summon: bool,
+    /// Hide the window when toggled while it is focused, instead of doing nothing
+    #[serde(default)]
+    pub hide_on_refocus: bool,
+    /// Workspace that hidden windows are moved to (default: where they were summoned from)
+    #[serde(default)]
+    pub stash_workspace: Option<String>,
+    /// Workspace (name or idx) to move the window to when it is launched for the first time
+    #[serde(default)]
+    pub workspace: Option<String>,
+    /// Extra environment variables for the launched application
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Working directory for the launched application (supports ~ and $VAR)
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Arguments appended to `command` only when launching a new instance
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    pub args_when_new: Option<Vec<String>>,
+    /// Command to run if no window appears after launching `command`
+    #[serde(default)]
+    pub fallback_command: Option<CommandLine>,
 }
 
 /// Helper type to deserialize String or Vec<String>
@@ -230,6 +1752,7 @@ impl StringOrVec {
 
 /// Window rule configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct WindowRuleConfig {
     /// Regex pattern(s) to match app_id (optional, can be a string or list of strings)
     #[serde(default, deserialize_with = "deserialize_string_or_vec")]
@@ -240,7 +1763,7 @@ pub struct WindowRuleConfig {
     /// Workspace to move matching windows to (name or idx, optional if focus_command is specified)
     pub open_on_workspace: Option<String>,
     /// Command to execute when a matching window is focused (optional)
-    pub focus_command: Option<String>,
+    pub focus_command: Option<CommandLine>,
     /// If true, focus_command will only execute on the first focus (default: false)
     #[serde(default)]
     pub focus_command_once: bool,
@@ -258,11 +1781,35 @@ where
     Ok(opt.map(|sov| sov.into_vec()))
 }
 
+/// Deserialize a String or Vec<String> into a Vec<String>, for lists that have a default
+pub(crate) fn deserialize_string_or_vec_list<'de, D>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::Deserialize;
+    Ok(StringOrVec::deserialize(deserializer)?.into_vec())
+}
+
+/// Deserialize a map whose values are a String or Vec<String>
+pub(crate) fn deserialize_string_or_vec_map<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::Deserialize;
+    let map: HashMap<String, StringOrVec> = HashMap::deserialize(deserializer)?;
+    Ok(map.into_iter().map(|(key, sov)| (key, sov.into_vec())).collect())
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ScratchpadDefaults {
     /// Default size for dynamically added scratchpads (e.g., "40% 60%")
     #[serde(default = "default_size")]
-    pub default_size: String,
+    pub default_size: Size,
     /// Default margin for dynamically added scratchpads (pixels)
     #[serde(default = "default_margin")]
     pub default_margin: u32,
@@ -271,8 +1818,11 @@ pub struct ScratchpadDefaults {
     pub move_to_workspace: Option<String>,
 }
 
-fn default_size() -> String {
-    "75% 60%".to_string()
+fn default_size() -> Size {
+    Size {
+        width: 75.0,
+        height: 60.0,
+    }
 }
 
 fn default_margin() -> u32 {
@@ -289,16 +1839,181 @@ impl Default for ScratchpadDefaults {
     }
 }
 
+/// How string commands are run, `[piri] command_shell`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommandShell {
+    /// `sh -c`
+    #[default]
+    Sh,
+    /// `bash -c`
+    Bash,
+    /// No shell: the command is split into words, honoring quotes, and run directly
+    None,
+}
+
+/// A command to run: a string goes through `command_shell`, a list is the program and its
+/// arguments, run without a shell
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum CommandLine {
+    Shell(String),
+    Exec(Vec<String>),
+}
+
+impl CommandLine {
+    /// Fill in placeholders with `replace(text, quote)`, which should shell-quote the values
+    /// it inserts when `quote` is set. Arguments of a list get them as they are
+    pub fn expand(&self, replace: impl Fn(&str, bool) -> String) -> CommandLine {
+        match self {
+            CommandLine::Shell(command) => CommandLine::Shell(replace(command, true)),
+            CommandLine::Exec(args) => {
+                CommandLine::Exec(args.iter().map(|arg| replace(arg, false)).collect())
+            }
+        }
+    }
+
+    /// The same command with more arguments, each quoted when the command is run by a shell
+    pub fn with_args(&self, extra: &[String]) -> CommandLine {
+        match self {
+            CommandLine::Shell(command) => CommandLine::Shell(
+                std::iter::once(command.clone())
+                    .chain(extra.iter().map(|arg| crate::utils::shell_quote(arg)))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            CommandLine::Exec(args) => {
+                CommandLine::Exec(args.iter().chain(extra).cloned().collect())
+            }
+        }
+    }
+
+    /// The program that is run, e.g. to guess the app_id of its window
+    pub fn program(&self) -> Option<&str> {
+        match self {
+            CommandLine::Shell(command) => command.split_whitespace().next(),
+            CommandLine::Exec(args) => args.first().map(String::as_str),
+        }
+    }
+}
+
+impl Default for CommandLine {
+    fn default() -> Self {
+        CommandLine::Shell(String::new())
+    }
+}
+
+impl From<&str> for CommandLine {
+    fn from(command: &str) -> Self {
+        CommandLine::Shell(command.to_string())
+    }
+}
+
+impl fmt::Display for CommandLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandLine::Shell(command) => write!(f, "{}", command),
+            CommandLine::Exec(args) => {
+                let words: Vec<String> = args
+                    .iter()
+                    .map(|arg| {
+                        let plain = !arg.is_empty()
+                            && arg.chars().all(|c| c.is_alphanumeric() || "-_./=:,+%@".contains(c));
+                        if plain {
+                            arg.clone()
+                        } else {
+                            crate::utils::shell_quote(arg)
+                        }
+                    })
+                    .collect();
+                write!(f, "{}", words.join(" "))
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CommandLine {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct CommandVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for CommandVisitor {
+            type Value = CommandLine;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a command string or a list of the program and its arguments")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, command: &str) -> Result<CommandLine, E> {
+                Ok(CommandLine::Shell(command.to_string()))
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<CommandLine, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let mut args = Vec::new();
+                while let Some(arg) = seq.next_element::<String>()? {
+                    args.push(arg);
+                }
+                if args.is_empty() {
+                    return Err(serde::de::Error::invalid_length(0, &self));
+                }
+                Ok(CommandLine::Exec(args))
+            }
+        }
+
+        deserializer.deserialize_any(CommandVisitor)
+    }
+}
+
+/// Deserialize one command or a list of them; a list may mix strings and argument lists
+pub(crate) fn deserialize_commands<'de, D>(deserializer: D) -> Result<Vec<CommandLine>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct CommandsVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for CommandsVisitor {
+        type Value = Vec<CommandLine>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a command or a list of commands")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, command: &str) -> Result<Self::Value, E> {
+            Ok(vec![CommandLine::Shell(command.to_string())])
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: serde::de::SeqAccess<'de>,
+        {
+            let mut commands = Vec::new();
+            while let Some(command) = seq.next_element::<CommandLine>()? {
+                commands.push(command);
+            }
+            Ok(commands)
+        }
+    }
+
+    deserializer.deserialize_any(CommandsVisitor)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ScratchpadConfig {
     /// Direction from which the scratchpad appears
     pub direction: Direction,
     /// Command to execute the application (can include environment variables and arguments)
-    pub command: String,
-    /// Explicit app_id to match windows (required)
-    pub app_id: String,
+    pub command: CommandLine,
+    /// Regex pattern(s) of the app_id to match windows (required, a string or list)
+    #[serde(deserialize_with = "deserialize_string_or_vec_list")]
+    pub app_id: Vec<String>,
     /// Size of the scratchpad (e.g., "75% 60%")
-    pub size: String,
+    pub size: Size,
     /// Margin from the edge in pixels
     pub margin: u32,
     /// If true, swallow the scratchpad window to the focused window when shown
@@ -306,37 +2021,455 @@ pub struct ScratchpadConfig {
     pub swallow_to_focus: bool,
 }
 
-impl ScratchpadConfig {
-    /// Parse size string (e.g., "75% 60%") into width and height percentages
-    pub fn parse_size(&self) -> Result<(f64, f64)> {
-        let parts: Vec<&str> = self.size.split_whitespace().collect();
-        if parts.len() != 2 {
+/// Merge `overlay` into `base`: tables are merged key by key, anything else is replaced
+fn merge_tables(base: &mut toml::Table, overlay: &toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_tables(base_table, overlay_table);
+            }
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Merge an included file into `base`: like [`merge_tables`], except that arrays (e.g. of
+/// `[[swallow]]` rules) are appended to instead of replaced
+fn merge_included(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_included(base_table, overlay_table);
+            }
+            (Some(toml::Value::Array(base_array)), toml::Value::Array(overlay_array)) => {
+                base_array.extend(overlay_array);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Expand `${NAME}` and `${NAME:-default}` in all strings of a config table, `$${` is
+/// kept as `${`. Other uses of `$`, like the end of a regex, are left alone, and so are unset
+/// variables without a default and shell expansions like `${f%.*}`, for the shell running a
+/// command to expand
+fn expand_env_vars(table: &mut toml::Table, path: &str) -> Result<()> {
+    for (key, value) in table.iter_mut() {
+        let path = if path.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", path, key)
+        };
+        expand_env_vars_in(value, &path)?;
+    }
+    Ok(())
+}
+
+fn expand_env_vars_in(value: &mut toml::Value, path: &str) -> Result<()> {
+    match value {
+        toml::Value::String(text) if text.contains("${") => {
+            *text = expand_env(text).with_context(|| format!("Invalid value of '{}'", path))?;
+        }
+        toml::Value::Array(values) => {
+            for value in values {
+                expand_env_vars_in(value, path)?;
+            }
+        }
+        toml::Value::Table(table) => expand_env_vars(table, path)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+fn expand_env(text: &str) -> Result<String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            expanded.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end =
+                after.find('}').with_context(|| format!("Unterminated '${{' in '{}'", text))?;
+            let (name, default) = match after[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&after[..end], None),
+            };
+            match (std::env::var(name), default) {
+                _ if !is_env_name(name) => expanded.push_str(&rest[..end + 3]),
+                (Ok(value), Some(default)) if value.is_empty() => expanded.push_str(default),
+                (Ok(value), _) => expanded.push_str(&value),
+                (Err(_), Some(default)) => expanded.push_str(default),
+                (Err(_), None) => expanded.push_str(&rest[..end + 3]),
+            }
+            rest = &after[end + 1..];
+        } else {
+            expanded.push('$');
+            rest = &rest[1..];
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Whether `name` can be an environment variable: letters, digits and `_`, not starting with
+/// a digit
+fn is_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Regex matching the whole of a text against a pattern with `*` and `?` wildcards
+fn wildcard_regex(pattern: &str) -> Result<regex::Regex> {
+    let mut regex = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Ok(regex::Regex::new(&regex)?)
+}
+
+/// An `include` entry: a path whose file name may contain `*` and `?` wildcards
+#[derive(Debug, Clone)]
+pub struct IncludePattern {
+    dir: PathBuf,
+    name: regex::Regex,
+    literal: Option<PathBuf>,
+}
+
+impl IncludePattern {
+    /// Parse an entry, with `~` and environment variables expanded and relative paths
+    /// taken from the directory of the config file
+    fn parse(pattern: &str, config_path: &Path) -> Result<Self> {
+        let expanded =
+            shellexpand::full(pattern).with_context(|| format!("Invalid include '{}'", pattern))?;
+        let mut path = PathBuf::from(expanded.as_ref());
+        if path.is_relative() {
+            path = config_path.parent().unwrap_or(Path::new(".")).join(path);
+        }
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .with_context(|| format!("Include '{}' does not name a file", pattern))?
+            .to_string();
+        let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        if dir.to_string_lossy().contains(['*', '?']) {
             anyhow::bail!(
-                "Size must be in format 'width% height%', got: {}",
-                self.size
+                "Include '{}': wildcards are only supported in the file name",
+                pattern
             );
         }
 
-        let width = parts[0]
-            .strip_suffix('%')
-            .ok_or_else(|| anyhow::anyhow!("Width must end with %, got: {}", parts[0]))?
-            .parse::<f64>()
-            .context("Failed to parse width")?;
+        let literal = (!name.contains(['*', '?'])).then(|| path.clone());
+        Ok(Self {
+            dir,
+            name: wildcard_regex(&name)?,
+            literal,
+        })
+    }
+
+    /// Directory the matching files are in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        path.parent() == Some(self.dir.as_path())
+            && path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| self.name.is_match(name))
+    }
+
+    /// Matching files sorted by name. A path without wildcards has to exist
+    fn files(&self) -> Result<Vec<PathBuf>> {
+        if let Some(path) = &self.literal {
+            if !path.is_file() {
+                anyhow::bail!("Included config file {:?} does not exist", path);
+            }
+            return Ok(vec![path.clone()]);
+        }
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Ok(Vec::new());
+        };
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && self.matches(path))
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// Parse a value given on the command line: a TOML value (`true`, `50`, `"text"`,
+/// `["a", "b"]`), or else the text itself as a string
+pub fn parse_value(raw: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+/// Split a dotted key path (`piri.scratchpad.default_margin`) into its keys
+fn split_path(path: &str) -> Result<Vec<&str>> {
+    let keys: Vec<&str> = path.split('.').collect();
+    if keys.iter().any(|key| key.is_empty()) {
+        anyhow::bail!("Invalid config key '{}'", path);
+    }
+    Ok(keys)
+}
+
+/// The example config shipped with piri, covering every plugin
+const EXAMPLE_CONFIG: &str = include_str!("../config.example.toml");
+
+const MINIMAL_TEMPLATE: &str = r#"# Piri Configuration File
+# Everything is commented out, so the defaults apply. `piri config init --force` writes
+# a template with all options, `piri check` tells whether the file is valid
+
+# Plugins are disabled unless enabled here
+[piri.plugins]
+# scratchpads = true
+# singleton = true
+# window_rule = true
+# autofill = true
+# swallow = true
+
+# [scratchpads.term]
+# direction = "fromRight"
+# command = "foot --app-id scratch-term"
+# app_id = "scratch-term"
+# size = "40% 60%"
+# margin = 50
+
+# [singleton.browser]
+# command = "firefox"
+
+# [[window_rule]]
+# app_id = "^firefox$"
+# open_on_workspace = "2"
+"#;
+
+/// A config file to start from, with every setting commented out so that the defaults
+/// apply: the example config with all options, or a short one with the common ones
+pub fn template(minimal: bool) -> String {
+    if minimal {
+        return MINIMAL_TEMPLATE.to_string();
+    }
+    let mut template = String::from(
+        "# Piri Configuration File\n\
+         # Everything is commented out, so the defaults apply. Uncomment what you need and\n\
+         # check the result with `piri check`\n",
+    );
+    // Skip the example's own header
+    for line in EXAMPLE_CONFIG.lines().skip(2) {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            template.push_str(line);
+        } else {
+            template.push_str("# ");
+            template.push_str(line);
+        }
+        template.push('\n');
+    }
+    template
+}
+
+/// Whether a config file is written in KDL rather than TOML, going by its extension
+pub fn is_kdl(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "kdl")
+}
+
+/// Read a config file, with the deprecated settings of a TOML file rewritten in the current
+/// format
+fn read_config(path: &Path) -> Result<String> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {:?}", path))?;
+    if is_kdl(path) {
+        return Ok(content);
+    }
+    let migration = crate::migrate::migrate(&content)
+        .with_context(|| format!("Failed to parse config file: {:?}", path))?;
+    Ok(migration.content)
+}
+
+/// Content of a config file in either format, as a TOML table
+fn parse_table(path: &Path, content: &str) -> Result<toml::Table> {
+    if is_kdl(path) {
+        let schema = toml::Table::try_from(Config::default())?;
+        crate::kdl::to_table(content, &schema)
+    } else {
+        Ok(toml::from_str(content)?)
+    }
+}
+
+/// Add the closest known key to an "unknown field" error, which is usually a typo of it
+fn with_suggestion(error: toml::de::Error) -> anyhow::Error {
+    match closest_key(error.message()) {
+        Some(key) => anyhow::anyhow!("{}Did you mean `{}`?", error, key),
+        None => error.into(),
+    }
+}
+
+/// For "unknown field `x`, expected one of `a`, `b`", the expected key closest to `x`, if
+/// it's close enough to be what was meant
+fn closest_key(message: &str) -> Option<String> {
+    let rest = message.strip_prefix("unknown field `")?;
+    let (unknown, expected) = rest.split_once('`')?;
+    let expected = expected.split_once("expected")?.1;
+    expected
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .map(|key| (edit_distance(unknown, key), key))
+        .filter(|(distance, key)| *distance <= (key.len() / 3).max(2))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, key)| key.to_string())
+}
+
+/// Levenshtein distance between two keys
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substituted = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
 
-        let height = parts[1]
-            .strip_suffix('%')
-            .ok_or_else(|| anyhow::anyhow!("Height must end with %, got: {}", parts[1]))?
-            .parse::<f64>()
-            .context("Failed to parse height")?;
+/// Set the value at a dotted key path in a config file, keeping its comments and layout
+pub fn write_value(file: &Path, path: &str, value: &toml::Value) -> Result<()> {
+    if is_kdl(file) {
+        anyhow::bail!("Saving values is only supported for TOML config files");
+    }
+    let content = fs::read_to_string(file)
+        .with_context(|| format!("Failed to read config file: {:?}", file))?;
+    let mut document: toml_edit::DocumentMut = content
+        .parse()
+        .with_context(|| format!("Failed to parse config file: {:?}", file))?;
+    let new_value: toml_edit::Value = value
+        .to_string()
+        .parse()
+        .with_context(|| format!("Failed to convert value for '{}'", path))?;
 
-        Ok((width / 100.0, height / 100.0))
+    let keys = split_path(path)?;
+    let (last, parents) = keys.split_last().context("Empty config key")?;
+    let mut table = document.as_table_mut() as &mut dyn toml_edit::TableLike;
+    for key in parents {
+        let item = table.entry(key).or_insert(toml_edit::table());
+        table = item
+            .as_table_like_mut()
+            .with_context(|| format!("'{}' in '{}' is not a table", key, path))?;
     }
+    match table.get_mut(last).and_then(|item| item.as_value_mut()) {
+        // Keep the comments around the old value
+        Some(old) => {
+            let decor = old.decor().clone();
+            *old = new_value;
+            *old.decor_mut() = decor;
+        }
+        None => {
+            table.insert(last, toml_edit::value(new_value));
+        }
+    }
+    fs::write(file, document.to_string())
+        .with_context(|| format!("Failed to write config file: {:?}", file))
 }
 
 impl Config {
+    /// The value at a dotted key path, e.g. `piri.scratchpad.default_margin`
+    pub fn get_value(&self, path: &str) -> Result<toml::Value> {
+        let mut value = toml::Value::try_from(self).context("Failed to serialize config")?;
+        for key in split_path(path)? {
+            value = match value {
+                toml::Value::Table(mut table) => table.remove(key),
+                _ => None,
+            }
+            .with_context(|| IpcError::not_found(format!("Config key '{}' not found", path)))?;
+        }
+        Ok(value)
+    }
+
+    /// A copy of the config with secrets, i.e. the TCP token, replaced by `<redacted>`, for
+    /// showing it to clients
+    pub fn redacted(&self) -> Config {
+        const REDACTED: &str = "<redacted>";
+        let mut config = self.clone();
+        if config.piri.tcp.token.is_some() {
+            config.piri.tcp.token = Some(REDACTED.to_string());
+        }
+        for overlay in config.profiles.values_mut().chain(config.host.values_mut()) {
+            let token = overlay
+                .get_mut("piri")
+                .and_then(|piri| piri.get_mut("tcp"))
+                .and_then(|tcp| tcp.get_mut("token"));
+            if let Some(token) = token {
+                *token = toml::Value::String(REDACTED.to_string());
+            }
+        }
+        config
+    }
+
+    /// A copy of the config with the value at a dotted key path replaced
+    pub fn with_value(&self, path: &str, value: toml::Value) -> Result<Config> {
+        let mut root = toml::Table::try_from(self).context("Failed to serialize config")?;
+        let keys = split_path(path)?;
+        let (last, parents) = keys.split_last().context("Empty config key")?;
+        let mut table = &mut root;
+        for key in parents {
+            table = table
+                .entry(key.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .with_context(|| format!("'{}' in '{}' is not a table", key, path))?;
+        }
+        table.insert(last.to_string(), value);
+
+        toml::Value::Table(root).try_into().map_err(|e: toml::de::Error| {
+            if e.message().starts_with("unknown field") {
+                anyhow::anyhow!(IpcError::not_found(format!(
+                    "Unknown config key '{}': {}",
+                    path,
+                    with_suggestion(e)
+                )))
+            } else {
+                anyhow::anyhow!("Invalid value for '{}': {}", path, e)
+            }
+        })
+    }
+
+    /// The `include` entries, resolved against the config file at `config_path`
+    pub fn include_patterns(&self, config_path: &Path) -> Result<Vec<IncludePattern>> {
+        self.include
+            .iter()
+            .map(|pattern| IncludePattern::parse(pattern, config_path))
+            .collect()
+    }
+
     /// Load configuration from file
     /// This is the only method that should be used to load config
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::load_with_profile(path, None)
+    }
+
+    /// Load configuration from file with a profile's overlay (`[profiles.<name>]`) applied
+    pub fn load_with_profile<P: AsRef<Path>>(path: P, profile: Option<&str>) -> Result<Self> {
         let path = path.as_ref();
 
         // Create default config if file doesn't exist
@@ -345,33 +2478,207 @@ impl Config {
             if let Some(parent) = path.parent() {
                 fs::create_dir_all(parent).context("Failed to create config directory")?;
             }
-            let toml = toml::to_string_pretty(&default_config)
-                .context("Failed to serialize default config")?;
-            fs::write(path, toml).context("Failed to write default config")?;
+            // Only TOML has a template to start from
+            let content = if is_kdl(path) {
+                String::new()
+            } else {
+                template(false)
+            };
+            fs::write(path, content).context("Failed to write default config")?;
             return Ok(default_config);
         }
 
-        let content = fs::read_to_string(path)
-            .with_context(|| format!("Failed to read config file: {:?}", path))?;
+        let content = read_config(path)?;
 
-        let config: Config = toml::from_str(&content)
+        let mut table = parse_table(path, &content)
             .with_context(|| format!("Failed to parse config file: {:?}", path))?;
+        // TOML is parsed as a whole first, for errors pointing at the line at fault
+        let config: Config = if is_kdl(path) {
+            toml::Value::Table(table.clone()).try_into()
+        } else {
+            toml::from_str(&content)
+        }
+        .map_err(with_suggestion)
+        .with_context(|| format!("Failed to parse config file: {:?}", path))?;
+
+        for pattern in config.include_patterns(path)? {
+            for file in pattern.files()? {
+                let content = read_config(&file)?;
+                let mut included = parse_table(&file, &content)
+                    .with_context(|| format!("Failed to parse config file: {:?}", file))?;
+                // Only the main file includes others
+                included.remove("include");
+                merge_included(&mut table, included);
+            }
+        }
+        expand_env_vars(&mut table, "")
+            .with_context(|| format!("Failed to load config file: {:?}", path))?;
+        let mut config: Config = toml::Value::Table(table.clone())
+            .try_into()
+            .map_err(with_suggestion)
+            .with_context(|| format!("Failed to load config file: {:?}", path))?;
+
+        let hosts = config.matching_hosts(crate::utils::hostname().as_deref())?;
+        if !hosts.is_empty() {
+            for pattern in &hosts {
+                merge_tables(&mut table, &config.host[pattern]);
+            }
+            config = toml::Value::Table(table.clone())
+                .try_into()
+                .map_err(with_suggestion)
+                .with_context(|| format!("Failed to apply [host.\"{}\"]", hosts.join("\", \"")))?;
+        }
+
+        let Some(profile) = profile else {
+            return Ok(config);
+        };
+        let overlay = config.profiles.get(profile).with_context(|| {
+            IpcError::not_found(format!(
+                "Profile '{}' is not defined in {:?}",
+                profile, path
+            ))
+        })?;
+        merge_tables(&mut table, overlay);
+        toml::Value::Table(table)
+            .try_into()
+            .map_err(with_suggestion)
+            .with_context(|| format!("Failed to apply profile '{}'", profile))
+    }
+
+    /// Patterns of the `[host."..."]` sections that match `hostname`, in the order they are
+    /// applied: alphabetically, so that later ones override earlier ones
+    fn matching_hosts(&self, hostname: Option<&str>) -> Result<Vec<String>> {
+        let Some(hostname) = hostname else {
+            return Ok(Vec::new());
+        };
+        let mut hosts = Vec::new();
+        for pattern in self.host.keys() {
+            if wildcard_regex(pattern)?.is_match(hostname) {
+                hosts.push(pattern.clone());
+            }
+        }
+        hosts.sort();
+        Ok(hosts)
+    }
+
+    /// Problems with the `[host."..."]` sections that don't match this machine, which only
+    /// show on the machines they are meant for
+    pub fn host_warnings(&self) -> Vec<String> {
+        let Ok(toml::Value::Table(base)) = toml::Value::try_from(self) else {
+            return Vec::new();
+        };
+        let applied = self.matching_hosts(crate::utils::hostname().as_deref()).unwrap_or_default();
+        let mut patterns: Vec<&String> =
+            self.host.keys().filter(|pattern| !applied.contains(pattern)).collect();
+        patterns.sort();
+        patterns
+            .into_iter()
+            .filter_map(|pattern| {
+                let mut table = base.clone();
+                merge_tables(&mut table, &self.host[pattern]);
+                toml::Value::Table(table).try_into::<Config>().err().map(|e| {
+                    format!(
+                        "[host.\"{}\"] does not apply: {:#}",
+                        pattern,
+                        with_suggestion(e)
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Problems with the profiles of the config file at `path`, which only show when a
+    /// profile is switched to
+    pub fn profile_warnings(&self, path: &Path) -> Vec<String> {
+        let mut names: Vec<&String> = self.profiles.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .filter_map(|name| {
+                Self::load_with_profile(path, Some(name))
+                    .err()
+                    .map(|e| format!("Profile '{}' does not apply: {:#}", name, e))
+            })
+            .collect()
+    }
+
+    /// Whether a plugin handles the windows and workspaces of an output
+    pub fn plugin_enabled_on(&self, name: &str, output: &str) -> bool {
+        self.outputs
+            .get(output)
+            .and_then(|output| output.plugins.get(name))
+            .unwrap_or_else(|| self.piri.plugins.is_enabled(name))
+    }
+
+    /// Whether a plugin runs at all, i.e. is enabled globally or on some output
+    pub fn plugin_runs(&self, name: &str) -> bool {
+        self.piri.plugins.is_enabled(name)
+            || self.outputs.values().any(|output| output.plugins.get(name) == Some(true))
+    }
 
-        Ok(config)
+    /// The deprecated settings in the config file and the files it includes, each saying
+    /// what replaces it
+    pub fn deprecations(&self, path: &Path) -> Vec<String> {
+        let mut files = vec![path.to_path_buf()];
+        for pattern in self.include_patterns(path).unwrap_or_default() {
+            files.extend(pattern.files().unwrap_or_default());
+        }
+        files
+            .into_iter()
+            .filter(|file| !is_kdl(file))
+            .filter_map(|file| {
+                let content = fs::read_to_string(&file).ok()?;
+                let notices = crate::migrate::migrate(&content).ok()?.notices;
+                Some(notices.into_iter().map(move |notice| format!("{:?}: {}", file, notice)))
+            })
+            .flatten()
+            .collect()
     }
 }
 
 impl PluginsConfig {
     pub fn is_enabled(&self, name: &str) -> bool {
+        self.get(name).unwrap_or(false)
+    }
+
+    /// Whether a plugin is switched on or off, if the config says so
+    pub fn get(&self, name: &str) -> Option<bool> {
         match name {
-            "scratchpads" => self.scratchpads.unwrap_or(false),
-            "empty" => self.empty.unwrap_or(false),
-            "window_rule" => self.window_rule.unwrap_or(false),
-            "autofill" => self.autofill.unwrap_or(false),
-            "singleton" => self.singleton.unwrap_or(false),
-            "window_order" => self.window_order.unwrap_or(false),
-            "swallow" => self.swallow.unwrap_or(false),
-            _ => false,
+            "scratchpads" => self.scratchpads,
+            "empty" => self.empty,
+            "window_rule" => self.window_rule,
+            "autofill" => self.autofill,
+            "singleton" => self.singleton,
+            "window_order" => self.window_order,
+            "swallow" => self.swallow,
+            "focus_history" => self.focus_history,
+            "marks" => self.marks,
+            "session" => self.session,
+            "bar" => self.bar,
+            "workspace_names" => self.workspace_names,
+            "idle_inhibit" => self.idle_inhibit,
+            "wallpaper" => self.wallpaper,
+            "hotplug" => self.hotplug,
+            "workspace_lifecycle" => self.workspace_lifecycle,
+            "groups" => self.groups,
+            "hooks" => self.hooks,
+            "floating_geometry" => self.floating_geometry,
+            "auto_fullscreen" => self.auto_fullscreen,
+            "gaming" => self.gaming,
+            "power" => self.power,
+            "autostart" => self.autostart,
+            "columns" => self.columns,
+            "workspace_pin" => self.workspace_pin,
+            "workspace_osd" => self.workspace_osd,
+            "title_watch" => self.title_watch,
+            "stash" => self.stash,
+            "dnd" => self.dnd,
+            "column_width" => self.column_width,
+            "schedule" => self.schedule,
+            "present" => self.present,
+            "keyboard_layout" => self.keyboard_layout,
+            "sendto" => self.sendto,
+            _ => None,
         }
     }
 }
@@ -387,6 +2694,7 @@ fn default_window_order_weight() -> u32 {
 impl Default for Config {
     fn default() -> Self {
         Self {
+            include: Vec::new(),
             niri: NiriConfig::default(),
             piri: PiriConfig::default(),
             scratchpads: HashMap::new(),
@@ -395,54 +2703,142 @@ impl Default for Config {
             window_rule: Vec::new(),
             window_order: HashMap::new(),
             swallow: Vec::new(),
+            idle_inhibit: Vec::new(),
+            hooks: Vec::new(),
+            auto_fullscreen: Vec::new(),
+            autostart: Vec::new(),
+            title_watch: Vec::new(),
+            column_width: Vec::new(),
+            schedule: Vec::new(),
+            present: HashMap::new(),
+            workspace_pin: HashMap::new(),
+            profiles: HashMap::new(),
+            host: HashMap::new(),
+            aliases: HashMap::new(),
+            outputs: HashMap::new(),
         }
     }
 }
 
-// Helper to convert TOML table to ScratchpadConfig
-impl TryFrom<toml::Table> for ScratchpadConfig {
-    type Error = anyhow::Error;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    fn try_from(table: toml::Table) -> Result<Self> {
-        let direction = table
-            .get("direction")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Missing 'direction' field"))
-            .and_then(|s| Direction::from_str(s))?;
-
-        let command = table
-            .get("command")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Missing 'command' field"))?
-            .to_string();
+    #[test]
+    fn expand_env_replaces_set_variables() {
+        std::env::set_var("PIRI_TEST_TERMINAL", "kitty");
+        assert_eq!(
+            expand_env("${PIRI_TEST_TERMINAL} -e top").unwrap(),
+            "kitty -e top"
+        );
+    }
 
-        let size = table
-            .get("size")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Missing 'size' field"))?
-            .to_string();
+    #[test]
+    fn expand_env_uses_default_when_unset_or_empty() {
+        std::env::remove_var("PIRI_TEST_UNSET");
+        std::env::set_var("PIRI_TEST_EMPTY", "");
+        assert_eq!(expand_env("${PIRI_TEST_UNSET:-foot}").unwrap(), "foot");
+        assert_eq!(expand_env("${PIRI_TEST_EMPTY:-foot}").unwrap(), "foot");
+        assert_eq!(expand_env("${PIRI_TEST_UNSET:-}x").unwrap(), "x");
+    }
 
-        let margin = table
-            .get("margin")
-            .and_then(|v| v.as_integer())
-            .ok_or_else(|| anyhow::anyhow!("Missing 'margin' field"))? as u32;
+    #[test]
+    fn expand_env_keeps_escaped_dollar_brace() {
+        std::env::set_var("PIRI_TEST_ESCAPED", "no");
+        assert_eq!(
+            expand_env("$${PIRI_TEST_ESCAPED} costs $5").unwrap(),
+            "${PIRI_TEST_ESCAPED} costs $5"
+        );
+    }
 
-        let app_id = table
-            .get("app_id")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Missing 'app_id' field"))?
-            .to_string();
+    #[test]
+    fn expand_env_leaves_unset_variables_and_shell_expansions() {
+        std::env::remove_var("PIRI_TEST_UNSET_TOO");
+        assert_eq!(
+            expand_env("sh -c 'for f in a b; do echo ${f}; done'").unwrap(),
+            "sh -c 'for f in a b; do echo ${f}; done'"
+        );
+        assert_eq!(
+            expand_env("${PIRI_TEST_UNSET_TOO} ${f%.*} ${#list[@]}").unwrap(),
+            "${PIRI_TEST_UNSET_TOO} ${f%.*} ${#list[@]}"
+        );
+        assert_eq!(expand_env("^foot$").unwrap(), "^foot$");
+    }
 
-        let swallow_to_focus =
-            table.get("swallow_to_focus").and_then(|v| v.as_bool()).unwrap_or(false);
+    #[test]
+    fn expand_env_rejects_unterminated_variable() {
+        assert!(expand_env("${HOME").is_err());
+        assert!(expand_env("echo ${").is_err());
+    }
 
-        Ok(ScratchpadConfig {
-            direction,
+    #[test]
+    fn with_args_quotes_each_argument_for_the_shell() {
+        let args = [
+            "--title".to_string(),
+            "my notes".to_string(),
+            "it's".to_string(),
+        ];
+        let command = CommandLine::Shell("kitty --single-instance".to_string()).with_args(&args);
+        assert_eq!(
             command,
-            app_id,
-            size,
-            margin,
-            swallow_to_focus,
-        })
+            CommandLine::Shell(
+                "kitty --single-instance '--title' 'my notes' 'it'\\''s'".to_string()
+            )
+        );
+        let command = CommandLine::Exec(vec!["kitty".to_string()]).with_args(&args);
+        assert_eq!(
+            command,
+            CommandLine::Exec(vec![
+                "kitty".to_string(),
+                "--title".to_string(),
+                "my notes".to_string(),
+                "it's".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn singletons_cant_be_named_like_subcommands() {
+        let config = "[singleton.notes]\ncommand = \"kitty\"\n";
+        assert!(toml::from_str::<Config>(config).is_ok());
+        for name in SINGLETON_SUBCOMMANDS {
+            let config = format!("[singleton.{}]\ncommand = \"kitty\"\n", name);
+            let error = toml::from_str::<Config>(&config).unwrap_err().to_string();
+            assert!(error.contains("can't be named"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn redacted_hides_the_tcp_token() {
+        let config: Config = toml::from_str(
+            "[piri.tcp]\ntoken = \"secret\"\n[profiles.work.piri.tcp]\ntoken = \"other\"\n",
+        )
+        .unwrap();
+        let redacted = config.redacted();
+        assert_eq!(redacted.piri.tcp.token.as_deref(), Some("<redacted>"));
+        assert_eq!(
+            redacted.profiles["work"]["piri"]["tcp"]["token"].as_str(),
+            Some("<redacted>")
+        );
+        assert!(!toml::to_string(&redacted).unwrap().contains("secret"));
+        assert_eq!(Config::default().redacted().piri.tcp.token, None);
+    }
+
+    #[test]
+    fn with_value_reports_unknown_keys_as_not_found() {
+        use crate::ipc::ErrorKind;
+        let config = Config::default();
+        let value = toml::Value::Integer(5);
+        assert!(config.with_value("piri.scratchpad.default_margin", value.clone()).is_ok());
+        let error = config.with_value("piri.scratchpad.default_margn", value.clone()).unwrap_err();
+        assert_eq!(ErrorKind::of(&error), ErrorKind::NotFound);
+        assert!(error.to_string().contains("Did you mean `default_margin`?"));
+        let error = config
+            .with_value(
+                "piri.scratchpad.default_margin",
+                toml::Value::String("x".into()),
+            )
+            .unwrap_err();
+        assert_eq!(ErrorKind::of(&error), ErrorKind::Other);
     }
 }
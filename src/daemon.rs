@@ -1,49 +1,248 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use log::{error, info, warn};
 use notify::{RecursiveMode, Watcher};
-use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
 use tokio::signal;
 use tokio::sync::Mutex;
 
 use crate::commands::CommandHandler;
-use crate::ipc::{handle_request, IpcServer};
+use crate::config::IncludePattern;
+use crate::ipc::{handle_request, handle_tcp_request, IpcServer};
 use crate::niri::NiriIpc;
-use crate::plugins::PluginManager;
-use crate::utils::send_notification;
-use niri_ipc::Event;
+use crate::plugins::{NiriMessage, PluginManager};
+use crate::utils::{load_state, save_state, send_notification, state_dir};
+use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::mpsc;
 
-/// Start a config file watcher that triggers reload on change
-async fn start_config_watcher(
-    handler: Arc<Mutex<CommandHandler>>,
-    plugin_manager: Arc<Mutex<PluginManager>>,
-    niri: NiriIpc,
-) -> Result<()> {
-    let (tx, mut rx) = mpsc::channel(1);
-    let config_path = {
+/// State file (in the state directory) the plugin state is handed over in by `piri restart`
+const HANDOFF_FILE: &str = "restart.json";
+
+/// Set by `piri restart`, for the daemon to execute itself again once stopped
+static RESTART: AtomicBool = AtomicBool::new(false);
+
+/// Times this process executed itself again, handed over with the plugin state
+static RESTARTS: AtomicU32 = AtomicU32::new(0);
+
+/// Plugin state left by a daemon for the one it executes
+#[derive(Debug, Serialize, Deserialize)]
+struct Handoff {
+    /// Executing keeps the PID, so a file with another one is left over from a failed restart
+    pid: u32,
+    restarts: u32,
+    plugins: BTreeMap<String, serde_json::Value>,
+}
+
+/// Have the daemon execute itself again, handing over the plugin state, when it stops
+pub fn request_restart() {
+    RESTART.store(true, Ordering::Relaxed);
+}
+
+/// Times `piri restart` executed the daemon again, for clients to tell the new daemon from
+/// the old one
+pub fn restarts() -> u32 {
+    RESTARTS.load(Ordering::Relaxed)
+}
+
+/// The state handed over by the daemon this process was before `piri restart`, if any
+fn take_handoff() -> Option<Handoff> {
+    let handoff: Option<Handoff> = match load_state(HANDOFF_FILE) {
+        Ok(handoff) => handoff,
+        Err(e) => {
+            warn!(
+                "Failed to read the state left by the previous daemon: {:#}",
+                e
+            );
+            None
+        }
+    };
+    let _ = std::fs::remove_file(state_dir().join(HANDOFF_FILE));
+    handoff.filter(|handoff| handoff.pid == std::process::id())
+}
+
+/// Replace the process with the piri binary, with the same arguments. Only returns on error
+fn reexec() -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to find the piri binary")?;
+    // After an upgrade the running binary was replaced, the new one is at the same path
+    let exe = match exe.to_str().and_then(|path| path.strip_suffix(" (deleted)")) {
+        Some(path) => PathBuf::from(path),
+        None => exe,
+    };
+    info!("Executing {:?}", exe);
+    // Already detached, forking again would change the PID the state is handed over with
+    let mut args = std::env::args_os().filter(|arg| arg != "--detach");
+    let mut command = std::process::Command::new(&exe);
+    if let Some(arg0) = args.next() {
+        command.arg0(arg0);
+    }
+    let e = command.args(args).exec();
+    Err(e).with_context(|| format!("Failed to execute {:?}", exe))
+}
+
+/// Write end of the pipe the process that ran `piri daemon --detach` waits on, until the
+/// daemon is ready or failed to start
+static READINESS: StdMutex<Option<File>> = StdMutex::new(None);
+
+/// The daemon `piri daemon --detach` started, once its socket accepts commands
+pub struct Detached {
+    pub pid: u32,
+    /// niri wasn't up yet, the plugins start once it is
+    pub waiting_for_niri: bool,
+}
+
+/// Fork the daemon off into a session of its own, with its standard streams on /dev/null,
+/// and wait until its socket accepts commands. Returns the daemon in the process that ran the
+/// command, and None in the daemon. Must be called before the tokio runtime starts any thread
+pub fn detach() -> Result<Option<Detached>> {
+    let mut fds = [0; 2];
+    // Close-on-exec, so that the commands the plugins run don't hold the pipe open
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to create a pipe");
+    }
+    let (mut reader, writer) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error()).context("Failed to fork"),
+        0 => {
+            drop(reader);
+            unsafe {
+                // Without a controlling terminal, closing the terminal doesn't hang it up
+                libc::setsid();
+                // Not being the session leader, the daemon can't acquire one again
+                match libc::fork() {
+                    -1 => libc::_exit(1),
+                    0 => {}
+                    _ => libc::_exit(0),
+                }
+            }
+            let null = OpenOptions::new().read(true).write(true).open("/dev/null")?;
+            for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+                unsafe { libc::dup2(null.as_raw_fd(), fd) };
+            }
+            *READINESS.lock().unwrap() = Some(writer);
+            Ok(None)
+        }
+        child => {
+            drop(writer);
+            unsafe { libc::waitpid(child, std::ptr::null_mut(), 0) };
+            // The daemon writes its PID once its socket accepts commands, followed by
+            // "waiting" if niri isn't up yet, or the error it stopped with. The pipe is
+            // closed if it dies before either
+            let mut answer = String::new();
+            reader.read_to_string(&mut answer)?;
+            let mut words = answer.split_whitespace();
+            match words.next().map(str::parse::<u32>) {
+                Some(Ok(pid)) => Ok(Some(Detached {
+                    pid,
+                    waiting_for_niri: words.next() == Some("waiting"),
+                })),
+                None => anyhow::bail!(
+                    "The daemon stopped before it was ready. Its output goes to /dev/null, set \
+                     `file` in [piri.log] to keep its log"
+                ),
+                Some(Err(_)) => anyhow::bail!("Failed to start daemon: {}", answer.trim()),
+            }
+        }
+    }
+}
+
+/// Tell the process that ran `piri daemon --detach`, if any, that the daemon's socket
+/// accepts commands
+fn notify_ready(waiting_for_niri: bool) {
+    if let Some(mut writer) = READINESS.lock().unwrap().take() {
+        let waiting = if waiting_for_niri { " waiting" } else { "" };
+        let _ = writeln!(writer, "{}{}", std::process::id(), waiting);
+    }
+}
+
+/// Tell the process that ran `piri daemon --detach`, if any, why the daemon didn't start
+pub fn notify_failed(e: &anyhow::Error) {
+    if let Some(mut writer) = READINESS.lock().unwrap().take() {
+        let _ = write!(writer, "{:#}", e);
+    }
+}
+
+/// Directory a file is in, watched instead of the file itself: editors often save by writing
+/// a new file and renaming it over the old one, which a watch on the file would not survive
+fn watch_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Watch the directories of the config file and of the files it includes, skipping those
+/// already watched or missing
+fn watch_config_dirs(
+    watcher: &mut impl Watcher,
+    watched: &mut HashSet<PathBuf>,
+    config_path: &Path,
+    includes: &[IncludePattern],
+) {
+    let dirs = std::iter::once(watch_dir(config_path))
+        .chain(includes.iter().map(|pattern| pattern.dir().to_path_buf()));
+    for dir in dirs {
+        if watched.contains(&dir) || !dir.is_dir() {
+            continue;
+        }
+        match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+            Ok(()) => {
+                watched.insert(dir);
+            }
+            Err(e) => warn!("Failed to watch {:?} for config changes: {}", dir, e),
+        }
+    }
+}
+
+/// Start a config file watcher that reloads like `piri reload` when the config file or a
+/// file it includes changes, unless `[piri] auto_reload` is turned off
+async fn start_config_watcher(handler: Arc<Mutex<CommandHandler>>) -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<Vec<PathBuf>>();
+    let (config_path, mut includes) = {
         let h = handler.lock().await;
-        h.config_path().clone()
+        let includes = h.config().include_patterns(h.config_path()).unwrap_or_default();
+        (h.config_path().clone(), includes)
     };
 
     let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
         if let Ok(event) = res {
-            if event.kind.is_modify() {
-                let _ = tx.blocking_send(());
+            if event.kind.is_modify() || event.kind.is_create() || event.kind.is_remove() {
+                let _ = tx.send(event.paths);
             }
         }
     })?;
-
-    watcher.watch(&config_path, RecursiveMode::NonRecursive)?;
+    let mut watched = HashSet::new();
+    watch_config_dirs(&mut watcher, &mut watched, &config_path, &includes);
+    if watched.is_empty() {
+        anyhow::bail!("Cannot watch the directory of {:?}", config_path);
+    }
 
     // Spawn a task to handle reload signals with debounce
     tokio::spawn(async move {
-        // Keep watcher alive
-        let _watcher = watcher;
+        let config_dir = watch_dir(&config_path);
+        let is_config_file = |path: &Path, includes: &[IncludePattern]| {
+            (path.parent() == Some(config_dir.as_path())
+                && path.file_name() == config_path.file_name())
+                || includes.iter().any(|pattern| pattern.matches(path))
+        };
 
         loop {
-            // Wait for first event
-            if rx.recv().await.is_none() {
-                break;
+            // Wait for the first change to a config file
+            loop {
+                match rx.recv().await {
+                    Some(paths) if paths.iter().any(|path| is_config_file(path, &includes)) => {
+                        break
+                    }
+                    Some(_) => {}
+                    None => return,
+                }
             }
 
             // Debounce: wait for 300ms, if new events arrive, reset the timer
@@ -66,26 +265,24 @@ async fn start_config_watcher(
                 }
             }
 
-            info!("Config file modified, reloading...");
-
             let mut h = handler.lock().await;
-            let path = h.config_path().clone();
-            if let Err(e) = h.reload_config(&path).await {
-                error!("Failed to auto-reload config: {}", e);
-                send_notification("piri", &format!("Auto-reload failed: {}", e));
+            // Checked against the running config, so that turning it back on in the file
+            // takes effect with the next manual reload
+            if !h.config().piri.auto_reload {
+                continue;
+            }
+            info!("Config file modified, reloading...");
+            if let Err(e) = h.reload(None).await {
+                error!("Failed to auto-reload config: {:#}", e);
+                send_notification("piri", &format!("Auto-reload failed: {:#}", e));
             } else {
-                let config = h.config().clone();
-                // Update existing NiriIpc instance in case socket_path changed
-                niri.update_socket_path(config.niri.socket_path.clone());
-
-                let mut pm = plugin_manager.lock().await;
-                if let Err(e) = pm.init(niri.clone(), &config).await {
-                    error!("Failed to reinitialize plugins after auto-reload: {}", e);
-                    send_notification("piri", &format!("Plugin reinit failed: {}", e));
-                } else {
-                    info!("Config auto-reloaded successfully");
-                    send_notification("piri", "Configuration hot-reloaded successfully");
-                }
+                info!("Config auto-reloaded successfully");
+                send_notification("piri", "Configuration hot-reloaded successfully");
+            }
+            // The includes may have changed with the reload
+            if let Ok(patterns) = h.config().include_patterns(&config_path) {
+                includes = patterns;
+                watch_config_dirs(&mut watcher, &mut watched, &config_path, &includes);
             }
         }
     });
@@ -93,21 +290,61 @@ async fn start_config_watcher(
     Ok(())
 }
 
+/// Next TCP connection, or never without a TCP listener
+async fn accept_tcp(tcp: Option<&(TcpListener, String)>) -> std::io::Result<TcpStream> {
+    match tcp {
+        Some((listener, _)) => listener.accept().await.map(|(stream, _)| stream),
+        None => std::future::pending().await,
+    }
+}
+
+/// Handle a connection to the socket in a task of its own, so that requests are handled
+/// concurrently
+fn spawn_ipc_request(
+    stream: tokio::net::UnixStream,
+    handler: &Arc<Mutex<CommandHandler>>,
+    shutdown: &Arc<tokio::sync::Notify>,
+) {
+    let handler = handler.clone();
+    let shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        if let Err(e) = handle_request(stream, handler, Some(shutdown)).await {
+            log::error!("Error handling IPC request: {}", e);
+        }
+    });
+}
+
+/// Handle a TCP connection in a task of its own
+fn spawn_tcp_request(
+    stream: TcpStream,
+    tcp: Option<&(TcpListener, String)>,
+    handler: &Arc<Mutex<CommandHandler>>,
+    shutdown: &Arc<tokio::sync::Notify>,
+) {
+    let handler = handler.clone();
+    let shutdown = shutdown.clone();
+    let token = tcp.map(|(_, token)| token.clone()).unwrap_or_default();
+    tokio::spawn(async move {
+        if let Err(e) = handle_tcp_request(stream, &token, handler, Some(shutdown)).await {
+            log::error!("Error handling TCP request: {}", e);
+        }
+    });
+}
+
 /// Run daemon main loop (internal function)
 async fn run_daemon_loop(
     ipc_server: IpcServer,
+    tcp: Option<(TcpListener, String)>,
     handler: Arc<Mutex<CommandHandler>>,
+    shutdown: Arc<tokio::sync::Notify>,
     plugin_manager: Arc<Mutex<PluginManager>>,
-    mut event_rx: mpsc::UnboundedReceiver<Event>,
+    mut event_rx: mpsc::UnboundedReceiver<NiriMessage>,
     niri: NiriIpc,
 ) -> Result<()> {
-    // Shared shutdown flag
-    let shutdown = Arc::new(tokio::sync::Notify::new());
-    let shutdown_clone = shutdown.clone();
-
     // Setup signal handlers
     let mut sigterm = signal::unix::signal(signal::unix::SignalKind::terminate())?;
     let mut sigint = signal::unix::signal(signal::unix::SignalKind::interrupt())?;
+    let mut sigusr1 = signal::unix::signal(signal::unix::SignalKind::user_defined1())?;
 
     // Main daemon loop with unified event distribution
     loop {
@@ -124,9 +361,16 @@ async fn run_daemon_loop(
                 info!("Received shutdown request via IPC, shutting down...");
                 break;
             }
+            _ = sigusr1.recv() => {
+                match crate::logging::toggle_debug() {
+                    Ok(true) => info!("Received SIGUSR1, debug logging on"),
+                    Ok(false) => info!("Received SIGUSR1, back to the configured log levels"),
+                    Err(e) => warn!("{:#}", e),
+                }
+            }
             event_result = event_rx.recv() => {
                 match event_result {
-                    Some(event) => {
+                    Some(NiriMessage::Event(event)) => {
                         let pm = plugin_manager.clone();
                         let niri_clone = niri.clone();
                         tokio::spawn(async move {
@@ -134,6 +378,11 @@ async fn run_daemon_loop(
                             pm.distribute_event(&event, &niri_clone).await;
                         });
                     }
+                    // Handled before the loop goes on, so that the plugins have dropped
+                    // the old IDs when the first events of the new stream reach them
+                    Some(NiriMessage::Reconnected) => {
+                        plugin_manager.lock().await.niri_reconnected(&niri).await;
+                    }
                     None => {
                         // Channel closed, event listener stopped
                         warn!("Event channel closed, stopping daemon");
@@ -143,22 +392,31 @@ async fn run_daemon_loop(
             }
             stream_result = ipc_server.accept() => {
                 match stream_result {
-                    Ok(stream) => {
-                        let handler_clone = handler.clone();
-                        let shutdown_flag = shutdown_clone.clone();
-                        // Spawn request handling to avoid blocking the main loop
-                        // This allows concurrent request handling
-                        tokio::spawn(async move {
-                            if let Err(e) = handle_request(stream, handler_clone, Some(shutdown_flag)).await {
-                                log::error!("Error handling IPC request: {}", e);
-                            }
-                        });
-                    }
+                    Ok(stream) => spawn_ipc_request(stream, &handler, &shutdown),
                     Err(e) => {
                         log::error!("Error accepting IPC connection: {}", e);
                     }
                 }
             }
+            stream_result = accept_tcp(tcp.as_ref()) => {
+                match stream_result {
+                    Ok(stream) => spawn_tcp_request(stream, tcp.as_ref(), &handler, &shutdown),
+                    Err(e) => {
+                        log::error!("Error accepting TCP connection: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    if RESTART.load(Ordering::Relaxed) {
+        let handoff = Handoff {
+            pid: std::process::id(),
+            restarts: restarts() + 1,
+            plugins: plugin_manager.lock().await.export_states().await,
+        };
+        if let Err(e) = save_state(HANDOFF_FILE, &handoff) {
+            warn!("Failed to save plugin state, restarting without it: {}", e);
         }
     }
 
@@ -169,9 +427,14 @@ async fn run_daemon_loop(
 }
 
 /// Run daemon (internal function, can be called with or without daemonizing)
-async fn run_daemon(mut handler: CommandHandler) -> Result<()> {
+async fn run_daemon(handler: CommandHandler) -> Result<()> {
     info!("Creating IPC server...");
 
+    let socket = handler.config().piri.socket.clone();
+    if let Some(path) = socket.path() {
+        crate::ipc::set_socket_path(path);
+    }
+
     // Create IPC server
     // If this fails, error will be visible on stderr (which is still open in daemon mode)
     let ipc_server = match IpcServer::new(None).await {
@@ -185,15 +448,86 @@ async fn run_daemon(mut handler: CommandHandler) -> Result<()> {
         }
     };
 
+    if let Err(e) = ipc_server.set_permissions(&socket) {
+        ipc_server.cleanup();
+        return Err(e);
+    }
+
+    // The socket keeps working if the TCP listener can't be set up
+    let tcp = match crate::ipc::bind_tcp(&handler.config().piri.tcp).await {
+        Ok(tcp) => tcp,
+        Err(e) => {
+            warn!("Not listening on TCP: {:#}", e);
+            None
+        }
+    };
+
+    let niri = handler.niri().clone();
+    let config = handler.config().clone();
+    let handler = Arc::new(Mutex::new(handler));
+    let shutdown = Arc::new(tokio::sync::Notify::new());
+
+    // Plugins query niri as soon as they start, so wait for it if piri was started first.
+    // Meanwhile requests about the daemon itself are answered, the others fail as niri
+    // being unreachable
+    let mut sigterm = signal::unix::signal(signal::unix::SignalKind::terminate())?;
+    let mut sigint = signal::unix::signal(signal::unix::SignalKind::interrupt())?;
+    notify_ready(niri.send_request(niri_ipc::Request::Version).await.is_err());
+    {
+        let ready = niri.wait_until_ready();
+        tokio::pin!(ready);
+        loop {
+            tokio::select! {
+                _ = &mut ready => break,
+                _ = sigterm.recv() => {
+                    ipc_server.cleanup();
+                    info!("Received SIGTERM before niri was up, daemon stopped");
+                    return Ok(());
+                }
+                _ = sigint.recv() => {
+                    ipc_server.cleanup();
+                    info!("Received SIGINT before niri was up, daemon stopped");
+                    return Ok(());
+                }
+                _ = shutdown.notified() => {
+                    ipc_server.cleanup();
+                    info!("Received shutdown request via IPC before niri was up, daemon stopped");
+                    return Ok(());
+                }
+                stream_result = ipc_server.accept() => match stream_result {
+                    Ok(stream) => spawn_ipc_request(stream, &handler, &shutdown),
+                    Err(e) => log::error!("Error accepting IPC connection: {}", e),
+                },
+                stream_result = accept_tcp(tcp.as_ref()) => match stream_result {
+                    Ok(stream) => spawn_tcp_request(stream, tcp.as_ref(), &handler, &shutdown),
+                    Err(e) => log::error!("Error accepting TCP connection: {}", e),
+                },
+            }
+        }
+    }
+
+    // After `piri restart` the plugins take the hidden windows over instead
+    let handoff = take_handoff();
+    if handoff.is_some() {
+        if let Err(e) = crate::journal::resume() {
+            warn!("Failed to read hidden windows: {:#}", e);
+        }
+    } else if let Err(e) = crate::journal::recover(&niri).await {
+        warn!("Failed to bring back hidden windows: {:#}", e);
+    }
+
     info!("Initializing plugins...");
 
     // Initialize plugin manager
-    let config = handler.config().clone();
-    let niri = handler.niri().clone();
     let mut plugin_manager = PluginManager::new();
     if let Err(e) = plugin_manager.init(niri.clone(), &config).await {
         warn!("Failed to initialize plugins: {}", e);
     }
+    if let Some(handoff) = handoff {
+        info!("Restarted, restoring the state of the previous daemon");
+        RESTARTS.store(handoff.restarts, Ordering::Relaxed);
+        plugin_manager.import_states(handoff.plugins).await;
+    }
 
     // Start unified event listener
     let event_rx = match plugin_manager.start_event_listener(niri.clone()).await {
@@ -206,15 +540,11 @@ async fn run_daemon(mut handler: CommandHandler) -> Result<()> {
 
     // Share plugin manager with handler
     let plugin_manager = Arc::new(Mutex::new(plugin_manager));
-    handler.set_plugin_manager(plugin_manager.clone());
-
-    // Wrap handler in Arc<Mutex<>> early to share with config watcher
-    let handler = Arc::new(Mutex::new(handler));
+    handler.lock().await.set_plugin_manager(plugin_manager.clone());
+    crate::ipc::set_niri_ready();
 
     // Start config watcher for hot-reload
-    if let Err(e) =
-        start_config_watcher(handler.clone(), plugin_manager.clone(), niri.clone()).await
-    {
+    if let Err(e) = start_config_watcher(handler.clone()).await {
         warn!("Failed to start config watcher: {}", e);
     }
 
@@ -225,7 +555,16 @@ async fn run_daemon(mut handler: CommandHandler) -> Result<()> {
     // This ensures the name is set even if tokio changed it
     // set_process_name("piri");
 
-    run_daemon_loop(ipc_server, handler, plugin_manager, event_rx, niri).await
+    run_daemon_loop(
+        ipc_server,
+        tcp,
+        handler,
+        shutdown,
+        plugin_manager,
+        event_rx,
+        niri,
+    )
+    .await
 }
 
 /// Run daemon
@@ -233,5 +572,9 @@ pub async fn run(handler: CommandHandler) -> Result<()> {
     // set_process_name("piri");
     info!("Starting piri daemon");
 
-    run_daemon(handler).await
+    run_daemon(handler).await?;
+    if RESTART.load(Ordering::Relaxed) {
+        reexec()?;
+    }
+    Ok(())
 }
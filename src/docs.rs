@@ -0,0 +1,108 @@
+use anyhow::{Context, Result};
+use clap::{Arg, Command};
+use std::io::Write;
+use std::path::Path;
+
+/// Write a man page for piri and one for every subcommand (`piri-scratchpads-toggle.1`, ...)
+pub fn write_man_pages(cmd: Command, out_dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(out_dir)
+        .with_context(|| format!("Failed to create directory {:?}", out_dir))?;
+    clap_mangen::generate_to(cmd, out_dir)
+        .with_context(|| format!("Failed to write man pages to {:?}", out_dir))
+}
+
+/// Write the reference of all commands as one markdown document
+pub fn write_markdown(mut cmd: Command, out: &mut impl Write) -> Result<()> {
+    // Fills in the full names and usages of the subcommands
+    cmd.build();
+    write_command(&mut cmd, 1, out)?;
+    Ok(())
+}
+
+fn write_command(cmd: &mut Command, depth: usize, out: &mut impl Write) -> std::io::Result<()> {
+    let name = cmd.get_bin_name().unwrap_or(cmd.get_name()).to_string();
+    writeln!(out, "{} `{}`\n", "#".repeat(depth.min(6)), name)?;
+    if let Some(about) = cmd.get_long_about().or(cmd.get_about()) {
+        writeln!(out, "{}\n", about)?;
+    }
+    // Commands with both arguments and subcommands have one usage line each
+    let usage = cmd.render_usage().to_string();
+    let usage: Vec<&str> =
+        usage.trim_start_matches("Usage: ").lines().map(|line| line.trim()).collect();
+    writeln!(out, "```\n{}\n```\n", usage.join("\n"))?;
+
+    // Global options are only listed for the top-level command
+    let is_root = depth == 1;
+    let arguments: Vec<&Arg> = cmd
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set() && (is_root || !arg.is_global_set()))
+        .filter(|arg| !matches!(arg.get_id().as_str(), "help" | "version"))
+        .collect();
+    if !arguments.is_empty() {
+        writeln!(out, "| Argument | Description |\n|---|---|")?;
+        for arg in arguments {
+            writeln!(out, "| `{}` | {} |", arg_name(arg), arg_description(arg))?;
+        }
+        writeln!(out)?;
+    }
+
+    for sub in cmd.get_subcommands_mut() {
+        if sub.get_name() != "help" && !sub.is_hide_set() {
+            write_command(sub, depth + 1, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// `<NAME>` for positionals, `-s, --long <VALUE>` for options
+fn arg_name(arg: &Arg) -> String {
+    let value = match arg.get_value_names() {
+        Some(names) => names.iter().map(|n| format!("<{}>", n)).collect::<Vec<_>>().join(" "),
+        None => format!("<{}>", arg.get_id().as_str().to_uppercase()),
+    };
+    if arg.is_positional() {
+        return value;
+    }
+    let mut flags = Vec::new();
+    if let Some(short) = arg.get_short() {
+        flags.push(format!("-{}", short));
+    }
+    if let Some(long) = arg.get_long() {
+        flags.push(format!("--{}", long));
+    }
+    let flags = flags.join(", ");
+    if arg.get_action().takes_values() {
+        format!("{} {}", flags, value)
+    } else {
+        flags
+    }
+}
+
+/// Help text, with possible and default values
+fn arg_description(arg: &Arg) -> String {
+    let mut description = arg.get_help().map(|help| help.to_string()).unwrap_or_default();
+    if !description.is_empty() && !description.ends_with(['.', ']']) {
+        description.push('.');
+    }
+    let values: Vec<String> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| format!("`{}`", value.get_name()))
+        .collect();
+    // Flags have the possible values true and false
+    if !values.is_empty() && arg.get_action().takes_values() {
+        description.push_str(&format!(" Values: {}.", values.join(", ")));
+    }
+    let defaults: Vec<String> = arg
+        .get_default_values()
+        .iter()
+        .map(|value| format!("`{}`", value.to_string_lossy()))
+        .collect();
+    if !defaults.is_empty() && arg.get_action().takes_values() && !description.contains("[default:")
+    {
+        description.push_str(&format!(" Default: {}.", defaults.join(", ")));
+    }
+    // Keep each argument on one table row
+    description.trim().replace('\n', " ").replace('|', "\\|")
+}
@@ -0,0 +1,42 @@
+use std::sync::OnceLock;
+use tokio::sync::broadcast;
+
+/// Events waiting for slow `piri events` clients before they start missing some
+const EVENT_BUFFER: usize = 256;
+
+/// Merged stream of niri events and piri's own events, for `piri events`
+static EVENTS: OnceLock<broadcast::Sender<serde_json::Value>> = OnceLock::new();
+
+fn sender() -> &'static broadcast::Sender<serde_json::Value> {
+    EVENTS.get_or_init(|| broadcast::channel(EVENT_BUFFER).0)
+}
+
+pub fn subscribe() -> broadcast::Receiver<serde_json::Value> {
+    sender().subscribe()
+}
+
+/// Whether anybody listens, to skip building events nobody will see
+pub fn has_subscribers() -> bool {
+    sender().receiver_count() > 0
+}
+
+/// Publish a niri event
+pub fn publish_niri(event: &niri_ipc::Event) {
+    if !has_subscribers() {
+        return;
+    }
+    match serde_json::to_value(event) {
+        Ok(value) => {
+            let _ = sender().send(value);
+        }
+        Err(e) => log::debug!("Failed to serialize event {:?}: {}", event, e),
+    }
+}
+
+/// Publish a piri event, shaped like niri's: `{"<name>": <payload>}`
+pub fn publish(name: &str, payload: serde_json::Value) {
+    if !has_subscribers() {
+        return;
+    }
+    let _ = sender().send(serde_json::json!({ name: payload }));
+}
@@ -1,8 +1,17 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{UnixListener, UnixStream};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio::sync::broadcast;
+
+use crate::config::{Config, Direction, FocusScope, SocketSection, TcpSection};
 
 /// IPC message types for communication between client and daemon
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,37 +21,555 @@ pub enum IpcRequest {
     },
     ScratchpadAdd {
         name: String,
-        direction: String,
+        direction: Direction,
         swallow_to_focus: bool,
     },
+    /// Reposition hidden scratchpads for the current outputs (sent by the hotplug plugin)
+    ScratchpadRehome,
+    /// Hide every visible scratchpad, leaving focus alone (sent by the present plugin)
+    ScratchpadHideAll,
     SingletonToggle {
         name: String,
     },
+    SingletonList,
     WindowOrderToggle,
+    AutofillToggle,
+    /// Focus the previously focused window
+    FocusLast {
+        scope: Option<FocusScope>,
+    },
+    /// Step back (older) through the focus history
+    FocusPrev {
+        scope: Option<FocusScope>,
+    },
+    /// Step forward (newer) through the focus history
+    FocusNext {
+        scope: Option<FocusScope>,
+    },
+    MarkSet {
+        name: String,
+    },
+    MarkGoto {
+        name: String,
+    },
+    MarkList,
+    SessionSave {
+        name: Option<String>,
+    },
+    SessionRestore {
+        name: Option<String>,
+    },
+    /// Add the focused window to a group, showing it in place of the group's visible window
+    GroupAdd {
+        name: String,
+    },
+    /// Take the focused window out of its group
+    GroupRemove,
+    /// Show the next window of a group (default: the focused window's group)
+    GroupNext {
+        name: Option<String>,
+    },
+    /// Show the previous window of a group (default: the focused window's group)
+    GroupPrev {
+        name: Option<String>,
+    },
+    GroupList,
+    /// Rotate the windows of the focused column by one (down, or up with `backward`)
+    ColumnRotate {
+        backward: bool,
+    },
+    /// Swap the focused window with the master (leftmost) column
+    ColumnSwapMaster,
+    /// Reverse the column order of the focused workspace
+    ColumnReverse,
+    /// Stop keeping a pinned workspace on its output, optionally moving it to `output`
+    WorkspacePinOverride {
+        workspace: String,
+        output: Option<String>,
+    },
+    /// Keep an overridden workspace on its pinned output again (None: all of them)
+    WorkspacePinRestore {
+        workspace: Option<String>,
+    },
+    /// Move the focused window to the stash workspace
+    Stash,
+    /// Bring back a stashed window (None: the most recently stashed one), to the focused
+    /// workspace if `here` is set
+    Unstash {
+        window_id: Option<u64>,
+        here: bool,
+    },
+    StashList,
+    /// Turn do-not-disturb mode on or off (None: toggle)
+    DndSet {
+        enabled: Option<bool>,
+    },
+    /// Whether do-not-disturb mode is on
+    DndStatus,
+    /// Move the focused window to a workspace (or output, if `output` is set or no
+    /// workspace matches), focusing it there if `follow` is set
+    SendTo {
+        target: String,
+        output: bool,
+        follow: bool,
+    },
+    /// Start presenting: move the windows of a presentation to its workspace
+    PresentStart {
+        name: String,
+    },
+    /// Stop presenting and put the windows back where they were
+    PresentStop,
+    /// Overview of the daemon (uptime, config, plugins, niri connection)
+    Status,
+    /// Daemon version and enabled plugins, for clients to check that they match
+    Version,
+    /// Counters and latencies recorded since the daemon started
+    Metrics,
+    /// List all windows (for pickers)
+    WindowList,
+    /// Focus a window, moving it to the focused workspace first if `bring` is set
+    WindowFocus {
+        id: u64,
+        bring: bool,
+    },
+    /// List all workspaces (for pickers)
+    WorkspaceList,
+    /// Focus a workspace by name, index or a fuzzy name match
+    WorkspaceFocus {
+        query: String,
+    },
+    /// Act on the first window (or all windows, with `all`) matching the app_id or title
+    /// regex
+    WindowAction {
+        action: WindowAction,
+        app_id: Option<String>,
+        title: Option<String>,
+        all: bool,
+    },
+    /// Value in the running config at a dotted key path
+    ConfigGet {
+        path: String,
+    },
+    /// The whole running config, including scratchpads added at runtime
+    ConfigShow,
+    /// Change a value in the running config, and in the config file with `write`
+    ConfigSet {
+        path: String,
+        value: String,
+        write: bool,
+    },
+    /// Reload the config file and reinitialize all plugins
+    Reload,
+    /// Reload the config file and apply it to a single plugin
+    ReloadPlugin {
+        name: String,
+    },
+    /// Switch the active config profile (None: base config only)
+    ProfileSet {
+        name: Option<String>,
+    },
+    /// Stop (or resume) passing events to plugins (sent by the gaming, dnd and present plugins).
+    /// A plugin is resumed once every owner that suspended it has resumed it
+    PluginSuspend {
+        owner: String,
+        plugins: Vec<String>,
+        suspend: bool,
+    },
+    /// Keep the connection open and receive a `Data` response on every status change
+    BarSubscribe,
+    /// Keep the connection open and receive a `Data` response for every niri or piri event,
+    /// or only for the events with one of the given names
+    EventsSubscribe {
+        #[serde(default)]
+        names: Vec<String>,
+    },
+    /// Recent daemon log lines; with `follow`, keep the connection open and receive new
+    /// lines as they are logged
+    Logs {
+        follow: bool,
+    },
+    /// Answer once the condition holds, or with a timeout error after `timeout_ms`
+    Wait {
+        condition: WaitCondition,
+        timeout_ms: u64,
+    },
+    /// First request on a TCP connection, with the token from `[piri.tcp]`
+    Auth {
+        token: String,
+    },
+    /// Keep the connection open after the `Success` response and handle the requests sent
+    /// over it, one response each, until the client closes it
+    Session,
     Ping,
+    /// Log at `level` from now on, or at the configured levels again with None
+    SetLogLevel {
+        level: Option<crate::config::LogLevel>,
+    },
     Shutdown,
+    /// Stop and execute the daemon binary again, the plugins keeping their state
+    Restart,
+}
+
+impl IpcRequest {
+    /// Whether the request is about the daemon itself, and so is handled while it waits for
+    /// niri before starting its plugins
+    pub fn works_before_niri(&self) -> bool {
+        matches!(
+            self,
+            IpcRequest::Ping
+                | IpcRequest::Status
+                | IpcRequest::Version
+                | IpcRequest::Metrics
+                | IpcRequest::Logs { .. }
+                | IpcRequest::SetLogLevel { .. }
+                | IpcRequest::Session
+                | IpcRequest::Shutdown
+        )
+    }
+
+    /// Name of the request, e.g. "ScratchpadToggle"
+    pub fn name(&self) -> String {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::String(name)) => name,
+            Ok(serde_json::Value::Object(map)) => map.keys().next().cloned().unwrap_or_default(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// What `WindowAction` does with the matched windows
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum WindowAction {
+    Focus,
+    /// Move to a workspace, resolved like `piri sendto` does
+    Move {
+        workspace: String,
+    },
+    Close,
+}
+
+/// What `Wait` waits for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum WaitCondition {
+    Scratchpad {
+        name: String,
+        visible: bool,
+    },
+    /// A window matching either regex
+    Window {
+        app_id: Option<String>,
+        title: Option<String>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum IpcResponse {
     Success,
-    Error(String),
-    Pong,
+    Error(IpcError),
+    Pong(crate::commands::PingInfo),
+    /// Result of a query request
+    Data(serde_json::Value),
+}
+
+/// What made a request fail, so that scripts can tell failures apart by the exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Other,
+    DaemonNotRunning,
+    PluginDisabled,
+    /// No scratchpad, singleton, mark, window, ... with the given name
+    NotFound,
+    NiriUnreachable,
+    Timeout,
+}
+
+impl ErrorKind {
+    /// Exit code of the CLI; 2 is left to clap for usage errors
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::DaemonNotRunning => 3,
+            ErrorKind::PluginDisabled => 4,
+            ErrorKind::NotFound => 5,
+            ErrorKind::NiriUnreachable => 6,
+            ErrorKind::Timeout => 7,
+        }
+    }
+
+    /// What the user can do about it, shown by the CLI below the error
+    pub fn hint(self) -> Option<&'static str> {
+        match self {
+            ErrorKind::DaemonNotRunning => Some("Start the daemon with `piri daemon`"),
+            ErrorKind::NiriUnreachable => {
+                Some("Check that niri is running and NIRI_SOCKET is set for the daemon")
+            }
+            ErrorKind::Other
+            | ErrorKind::PluginDisabled
+            | ErrorKind::NotFound
+            | ErrorKind::Timeout => None,
+        }
+    }
+
+    /// The kind of the `IpcError` the error was created from or wrapped with, if any
+    pub fn of(error: &anyhow::Error) -> Self {
+        error.downcast_ref::<IpcError>().map_or(ErrorKind::Other, |e| e.kind)
+    }
+}
+
+/// An error with its kind; it keeps the kind when wrapped in an `anyhow::Error`, with or
+/// without context, and when sent to the client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "IpcErrorRepr")]
+pub struct IpcError {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// ID of the failed request, prefixing its lines in the daemon log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<u64>,
+}
+
+impl IpcError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            trace_id: None,
+        }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::NotFound, message)
+    }
+
+    /// Same error, with `prefix: ` in front of the message
+    pub fn prefixed(self, prefix: &str) -> Self {
+        Self {
+            message: format!("{}: {}", prefix, self.message),
+            ..self
+        }
+    }
+}
+
+impl std::fmt::Display for IpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for IpcError {}
+
+/// Daemons from before error kinds send errors as plain messages
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+enum IpcErrorRepr {
+    Message(String),
+    Full {
+        kind: ErrorKind,
+        message: String,
+        #[serde(default)]
+        trace_id: Option<u64>,
+    },
+}
+
+impl From<IpcErrorRepr> for IpcError {
+    fn from(repr: IpcErrorRepr) -> Self {
+        match repr {
+            IpcErrorRepr::Message(message) => Self::new(ErrorKind::Other, message),
+            IpcErrorRepr::Full {
+                kind,
+                message,
+                trace_id,
+            } => Self {
+                kind,
+                message,
+                trace_id,
+            },
+        }
+    }
+}
+
+impl From<String> for IpcError {
+    fn from(message: String) -> Self {
+        Self::new(ErrorKind::Other, message)
+    }
+}
+
+impl From<anyhow::Error> for IpcError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::new(ErrorKind::of(&error), error.to_string())
+    }
+}
+
+/// Error for a config that failed to load, with the causes that tell where the mistake is
+fn config_error(error: anyhow::Error) -> IpcError {
+    IpcError::new(ErrorKind::of(&error), format!("{:#}", error))
+}
+
+/// ID of the next request handled by the daemon
+static NEXT_TRACE_ID: AtomicU64 = AtomicU64::new(1);
+
+tokio::task_local! {
+    /// ID of the request being handled, for everything logged while handling it
+    static TRACE_ID: u64;
+}
+
+/// ID of the request whose handling is running on the current task, if any
+pub fn trace_id() -> Option<u64> {
+    TRACE_ID.try_with(|id| *id).ok()
+}
+
+/// Socket path from `[piri.socket]`, replacing the default one for this process
+static SOCKET_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use the configured socket path for the server and all clients of this process
+pub fn set_socket_path(path: PathBuf) {
+    let _ = SOCKET_PATH.set(path);
+}
+
+/// How long clients wait for the daemon, unless changed with `--timeout`
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Client timeout from `--timeout` or $PIRI_TIMEOUT
+static TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+/// Wait this long for the daemon in all clients of this process
+pub fn set_timeout(timeout: Duration) {
+    let _ = TIMEOUT.set(timeout);
+}
+
+/// Daemon address and token from `--address` and `PIRI_TOKEN`, used instead of the socket
+#[derive(Debug, Clone)]
+pub struct TcpEndpoint {
+    pub address: String,
+    pub token: String,
 }
 
-/// Get the default socket path for piri daemon
+static TCP_ENDPOINT: OnceLock<TcpEndpoint> = OnceLock::new();
+
+/// Connect all clients of this process to the daemon's TCP listener
+pub fn set_tcp_endpoint(endpoint: TcpEndpoint) {
+    let _ = TCP_ENDPOINT.set(endpoint);
+}
+
+/// Stream to a client or to the daemon, over the socket or TCP
+pub trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+/// Pause before retrying a refused connection, while a restarting daemon binds its socket
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Get the socket path for piri daemon: the configured one, or the default
 pub fn get_socket_path() -> PathBuf {
-    if let Ok(runtime_dir) = std::env::var("XDG_RUNTIME_DIR") {
-        PathBuf::from(runtime_dir).join("piri.sock")
-    } else {
-        PathBuf::from("/tmp/piri.sock")
+    if let Some(path) = SOCKET_PATH.get() {
+        return path.clone();
+    }
+    runtime_dir().join(default_socket_name())
+}
+
+fn runtime_dir() -> PathBuf {
+    std::env::var("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("/tmp"))
+}
+
+/// Pidfile of the daemon, next to its socket (in the runtime dir for abstract sockets)
+pub fn get_pid_path() -> PathBuf {
+    let socket_path = get_socket_path();
+    match abstract_name(&socket_path) {
+        Some(name) => runtime_dir().join(format!("{}.pid", String::from_utf8_lossy(name))),
+        None => socket_path.with_extension("pid"),
+    }
+}
+
+/// Remove the socket and pidfile left behind by a daemon that didn't shut down cleanly.
+/// Returns whether there was anything to remove
+pub fn remove_stale_files() -> bool {
+    let socket_path = get_socket_path();
+    let mut paths = vec![get_pid_path()];
+    if abstract_name(&socket_path).is_none() {
+        paths.push(socket_path);
+    }
+    let mut removed = false;
+    for path in paths {
+        if std::fs::remove_file(&path).is_ok() {
+            log::debug!("Removed stale {:?}", path);
+            removed = true;
+        }
+    }
+    removed
+}
+
+/// `piri-<display>.sock`, so that each niri session on the machine gets its own daemon.
+/// Without $WAYLAND_DISPLAY, a hash of $NIRI_SOCKET stands in for the display
+fn default_socket_name() -> String {
+    if let Ok(display) = std::env::var("WAYLAND_DISPLAY") {
+        // The display may also be given as an absolute path to the Wayland socket
+        if let Some(display) = Path::new(&display).file_name() {
+            return format!("piri-{}.sock", display.to_string_lossy());
+        }
+    }
+    if let Ok(niri_socket) = std::env::var("NIRI_SOCKET") {
+        // FNV-1a, stable across builds unlike the std hasher
+        let hash = niri_socket.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        return format!("piri-{:016x}.sock", hash);
+    }
+    "piri.sock".to_string()
+}
+
+/// Name of an abstract socket, which is given as `@name` instead of a path
+fn abstract_name(path: &Path) -> Option<&[u8]> {
+    path.as_os_str().as_bytes().strip_prefix(b"@")
+}
+
+fn abstract_address(name: &[u8]) -> std::io::Result<std::os::unix::net::SocketAddr> {
+    std::os::unix::net::SocketAddr::from_abstract_name(name)
+}
+
+/// Connect to a socket path or abstract socket
+async fn connect(path: &Path) -> std::io::Result<UnixStream> {
+    match abstract_name(path) {
+        Some(name) => {
+            let stream = std::os::unix::net::UnixStream::connect_addr(&abstract_address(name)?)?;
+            stream.set_nonblocking(true)?;
+            UnixStream::from_std(stream)
+        }
+        None => UnixStream::connect(path).await,
+    }
+}
+
+/// Group ID of a group given by name or ID
+fn resolve_group(group: &str) -> Result<u32> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+    let name = std::ffi::CString::new(group).context("Invalid group name")?;
+    let mut entry: libc::group = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    let ret = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            &mut entry,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    if ret != 0 || result.is_null() {
+        anyhow::bail!("Group '{}' not found", group);
     }
+    Ok(entry.gr_gid)
 }
 
 /// IPC server for daemon
 pub struct IpcServer {
     listener: UnixListener,
     socket_path: PathBuf,
+    pid_path: PathBuf,
 }
 
 impl IpcServer {
@@ -50,122 +577,400 @@ impl IpcServer {
     pub async fn new(socket_path: Option<PathBuf>) -> Result<Self> {
         let socket_path = socket_path.unwrap_or_else(get_socket_path);
 
-        // Remove existing socket if it exists
-        if socket_path.exists() {
-            std::fs::remove_file(&socket_path).context("Failed to remove existing socket")?;
-        }
+        let listener = if let Some(name) = abstract_name(&socket_path) {
+            // Abstract sockets go away with the last daemon, binding fails while one runs
+            let listener = std::os::unix::net::UnixListener::bind_addr(&abstract_address(name)?)
+                .with_context(|| format!("Failed to bind to abstract socket: {:?}", socket_path))?;
+            listener.set_nonblocking(true)?;
+            UnixListener::from_std(listener)?
+        } else {
+            // Remove existing socket if it exists
+            if socket_path.exists() {
+                std::fs::remove_file(&socket_path).context("Failed to remove existing socket")?;
+            }
 
-        // Create parent directory if needed
-        if let Some(parent) = socket_path.parent() {
-            std::fs::create_dir_all(parent).context("Failed to create socket directory")?;
-        }
+            // Create parent directory if needed
+            if let Some(parent) = socket_path.parent() {
+                std::fs::create_dir_all(parent).context("Failed to create socket directory")?;
+            }
 
-        let listener = UnixListener::bind(&socket_path)
-            .with_context(|| format!("Failed to bind to socket: {:?}", socket_path))?;
+            UnixListener::bind(&socket_path)
+                .with_context(|| format!("Failed to bind to socket: {:?}", socket_path))?
+        };
 
         log::info!("IPC server listening on {:?}", socket_path);
 
+        // For `piri stop --force`, when the socket doesn't answer
+        let pid_path = get_pid_path();
+        if let Err(e) = std::fs::write(&pid_path, format!("{}\n", std::process::id())) {
+            log::warn!("Failed to write pidfile {:?}: {}", pid_path, e);
+        }
+
         Ok(Self {
             listener,
             socket_path,
+            pid_path,
         })
     }
 
+    /// Apply the configured file mode and group to the socket, so that other users or
+    /// sandboxed clients can connect
+    pub fn set_permissions(&self, socket: &SocketSection) -> Result<()> {
+        if abstract_name(&self.socket_path).is_some() {
+            if socket.mode.is_some() || socket.group.is_some() {
+                log::warn!("Socket mode and group don't apply to abstract sockets, ignoring them");
+            }
+            return Ok(());
+        }
+        if let Some(ref group) = socket.group {
+            let gid = resolve_group(group)?;
+            std::os::unix::fs::chown(&self.socket_path, None, Some(gid))
+                .with_context(|| format!("Failed to change socket group to '{}'", group))?;
+        }
+        if let Some(mode) = socket.mode {
+            std::fs::set_permissions(&self.socket_path, std::fs::Permissions::from_mode(mode))
+                .with_context(|| format!("Failed to change socket mode to {:o}", mode))?;
+        }
+        Ok(())
+    }
+
     /// Accept a new connection
     pub async fn accept(&self) -> Result<UnixStream> {
         let (stream, _) = self.listener.accept().await.context("Failed to accept connection")?;
         Ok(stream)
     }
 
-    /// Clean up socket file on drop
+    /// Clean up socket file and pidfile on drop
     pub fn cleanup(&self) {
-        if self.socket_path.exists() {
+        if abstract_name(&self.socket_path).is_none() && self.socket_path.exists() {
             let _ = std::fs::remove_file(&self.socket_path);
         }
+        let _ = std::fs::remove_file(&self.pid_path);
     }
 }
 
 /// IPC client for subcommands
 pub struct IpcClient {
     socket_path: PathBuf,
+    tcp: Option<TcpEndpoint>,
+    timeout: Duration,
 }
 
 impl IpcClient {
     /// Create a new IPC client
     pub fn new(socket_path: Option<PathBuf>) -> Self {
         let socket_path = socket_path.unwrap_or_else(get_socket_path);
-        Self { socket_path }
+        Self {
+            socket_path,
+            tcp: TCP_ENDPOINT.get().cloned(),
+            timeout: TIMEOUT.get().copied().unwrap_or(DEFAULT_TIMEOUT),
+        }
+    }
+
+    /// How long the client waits for the daemon
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Same client, waiting `timeout` for the daemon
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
     }
 
     /// Send a request to the daemon and get a response
     pub async fn send_request(&self, request: IpcRequest) -> Result<IpcResponse> {
-        // Add timeout to prevent hanging
-        let connect_future = UnixStream::connect(&self.socket_path);
-        let mut stream = tokio::time::timeout(std::time::Duration::from_secs(5), connect_future)
+        let mut stream = self.connect_and_send(&request).await?;
+        Self::read_response(&mut stream, self.timeout).await
+    }
+
+    /// Send several requests over one connection; the daemon handles them in order and
+    /// answers with one response per request
+    pub async fn send_batch(&self, requests: &[IpcRequest]) -> Result<Vec<IpcResponse>> {
+        let mut stream = self.connect_and_send(requests).await?;
+        // Every request gets the time a single one would
+        let timeout = self.timeout * requests.len().max(1) as u32;
+        Self::read_response(&mut stream, timeout).await
+    }
+
+    /// Read a length-prefixed response
+    async fn read_response<T: serde::de::DeserializeOwned>(
+        stream: &mut impl Connection,
+        timeout: Duration,
+    ) -> Result<T> {
+        // Read response length
+        let response_len = tokio::time::timeout(timeout, stream.read_u32())
+            .await
+            .context(IpcError::new(
+                ErrorKind::Timeout,
+                "Timeout reading response length",
+            ))?
+            .context("Failed to read response length")?;
+
+        // Read response data
+        let mut response_bytes = vec![0u8; response_len as usize];
+        tokio::time::timeout(timeout, stream.read_exact(&mut response_bytes))
+            .await
+            .context(IpcError::new(
+                ErrorKind::Timeout,
+                "Timeout reading response data",
+            ))?
+            .context("Failed to read response data")?;
+
+        // Deserialize response
+        serde_json::from_slice(&response_bytes).context("Failed to deserialize response")
+    }
+
+    /// Open a connection that stays open for any number of requests
+    pub async fn session(&self) -> Result<IpcSession> {
+        let mut stream = self.connect_and_send(&IpcRequest::Session).await?;
+        match Self::read_response(&mut stream, self.timeout).await? {
+            IpcResponse::Success => Ok(IpcSession {
+                stream,
+                timeout: self.timeout,
+            }),
+            IpcResponse::Error(e) => anyhow::bail!(e.prefixed("Failed to open a session")),
+            response => anyhow::bail!("Unexpected response: {:?}", response),
+        }
+    }
+
+    /// Send a subscription request and call `on_response` for every response
+    /// until the daemon closes the connection
+    pub async fn subscribe<F>(&self, request: IpcRequest, mut on_response: F) -> Result<()>
+    where
+        F: FnMut(IpcResponse) -> Result<()>,
+    {
+        let mut stream = self.connect_and_send(&request).await?;
+
+        // No timeouts here: updates only arrive when something changes
+        loop {
+            let response_len = match stream.read_u32().await {
+                Ok(len) => len,
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e).context("Failed to read response length"),
+            };
+            let mut response_bytes = vec![0u8; response_len as usize];
+            stream
+                .read_exact(&mut response_bytes)
+                .await
+                .context("Failed to read response data")?;
+            let response: IpcResponse = serde_json::from_slice(&response_bytes)
+                .context("Failed to deserialize response")?;
+            on_response(response)?;
+        }
+    }
+
+    /// Connect to the daemon and send a request (or array of requests)
+    async fn connect_and_send<T: Serialize + ?Sized>(
+        &self,
+        request: &T,
+    ) -> Result<Box<dyn Connection>> {
+        let mut stream: Box<dyn Connection> = match &self.tcp {
+            Some(tcp) => Box::new(self.connect_tcp(tcp).await?),
+            None => Box::new(self.connect_socket().await?),
+        };
+        Self::write_request(&mut stream, request, self.timeout).await?;
+        Ok(stream)
+    }
+
+    /// Connect to the daemon's TCP listener and authenticate
+    async fn connect_tcp(&self, tcp: &TcpEndpoint) -> Result<TcpStream> {
+        let mut stream = tokio::time::timeout(self.timeout, TcpStream::connect(&tcp.address))
             .await
             .with_context(|| {
-                format!(
-                    "Connection timeout to daemon socket: {:?}",
-                    self.socket_path
+                IpcError::new(
+                    ErrorKind::Timeout,
+                    format!("Connection timeout to daemon at {}", tcp.address),
+                )
+            })?
+            .with_context(|| {
+                IpcError::new(
+                    ErrorKind::DaemonNotRunning,
+                    format!("Failed to connect to daemon at {}", tcp.address),
+                )
+            })?;
+        let auth = IpcRequest::Auth {
+            token: tcp.token.clone(),
+        };
+        Self::write_request(&mut stream, &auth, self.timeout).await?;
+        match Self::read_response(&mut stream, self.timeout).await? {
+            IpcResponse::Success => Ok(stream),
+            IpcResponse::Error(e) => anyhow::bail!(e.prefixed("Daemon refused the connection")),
+            response => anyhow::bail!("Unexpected response: {:?}", response),
+        }
+    }
+
+    async fn connect_socket(&self) -> Result<UnixStream> {
+        // Add timeout to prevent hanging
+        let connect = || tokio::time::timeout(self.timeout, connect(&self.socket_path));
+        let mut result = connect().await;
+        // A refused connection to an existing socket file: the daemon is restarting and
+        // about to bind a new socket
+        if let Ok(Err(ref e)) = result {
+            if e.kind() == std::io::ErrorKind::ConnectionRefused
+                && abstract_name(&self.socket_path).is_none()
+                && self.socket_path.exists()
+            {
+                log::debug!("Daemon refused the connection, retrying once");
+                tokio::time::sleep(RETRY_DELAY).await;
+                result = connect().await;
+            }
+        }
+        let stream = result
+            .with_context(|| {
+                IpcError::new(
+                    ErrorKind::Timeout,
+                    format!(
+                        "Connection timeout to daemon socket: {:?}",
+                        self.socket_path
+                    ),
                 )
             })?
             .with_context(|| {
-                format!(
-                    "Failed to connect to daemon socket: {:?}. Is the daemon running?",
-                    self.socket_path
+                IpcError::new(
+                    ErrorKind::DaemonNotRunning,
+                    format!("Failed to connect to daemon socket {:?}", self.socket_path),
                 )
             })?;
+        Ok(stream)
+    }
 
+    /// Write a length-prefixed request (or array of requests)
+    async fn write_request<T: Serialize + ?Sized>(
+        stream: &mut impl Connection,
+        request: &T,
+        timeout: Duration,
+    ) -> Result<()> {
         // Serialize request
         let request_json =
             serde_json::to_string(&request).context("Failed to serialize request")?;
 
         // Send request length and data
         let request_bytes = request_json.as_bytes();
-        tokio::time::timeout(
-            std::time::Duration::from_secs(5),
-            stream.write_u32(request_bytes.len() as u32),
-        )
-        .await
-        .context("Timeout writing request length")?
-        .context("Failed to write request length")?;
+        tokio::time::timeout(timeout, stream.write_u32(request_bytes.len() as u32))
+            .await
+            .context(IpcError::new(
+                ErrorKind::Timeout,
+                "Timeout writing request length",
+            ))?
+            .context("Failed to write request length")?;
 
-        tokio::time::timeout(
-            std::time::Duration::from_secs(5),
-            stream.write_all(request_bytes),
-        )
-        .await
-        .context("Timeout writing request data")?
-        .context("Failed to write request data")?;
+        tokio::time::timeout(timeout, stream.write_all(request_bytes))
+            .await
+            .context(IpcError::new(
+                ErrorKind::Timeout,
+                "Timeout writing request data",
+            ))?
+            .context("Failed to write request data")?;
+        Ok(())
+    }
+}
 
-        // Read response length
-        let response_len =
-            tokio::time::timeout(std::time::Duration::from_secs(5), stream.read_u32())
-                .await
-                .context("Timeout reading response length")?
-                .context("Failed to read response length")?;
+/// Connection to the daemon that handles requests until it is dropped
+pub struct IpcSession {
+    stream: Box<dyn Connection>,
+    timeout: Duration,
+}
 
-        // Read response data
-        let mut response_bytes = vec![0u8; response_len as usize];
-        tokio::time::timeout(
-            std::time::Duration::from_secs(5),
-            stream.read_exact(&mut response_bytes),
-        )
+impl IpcSession {
+    /// Send a request and wait for its response
+    pub async fn send_request(&mut self, request: &IpcRequest) -> Result<IpcResponse> {
+        IpcClient::write_request(&mut self.stream, request, self.timeout).await?;
+        IpcClient::read_response(&mut self.stream, self.timeout).await
+    }
+}
+
+/// Write a length-prefixed response (or array of responses) to the stream
+async fn write_response<T: Serialize>(stream: &mut impl Connection, response: &T) -> Result<()> {
+    let response_json = serde_json::to_string(response).context("Failed to serialize response")?;
+    let response_bytes = response_json.as_bytes();
+    stream
+        .write_u32(response_bytes.len() as u32)
+        .await
+        .context("Failed to write response length")?;
+    stream
+        .write_all(response_bytes)
         .await
-        .context("Timeout reading response data")?
-        .context("Failed to read response data")?;
+        .context("Failed to write response data")?;
+    Ok(())
+}
 
-        // Deserialize response
-        let response: IpcResponse =
-            serde_json::from_slice(&response_bytes).context("Failed to deserialize response")?;
+/// Stream status updates to a `piri bar` client until either side goes away
+async fn stream_status(
+    mut stream: impl Connection,
+    handler: &std::sync::Arc<tokio::sync::Mutex<crate::commands::CommandHandler>>,
+) -> Result<()> {
+    let (receiver, config) = {
+        let handler = handler.lock().await;
+        (handler.subscribe_status().await, handler.config().clone())
+    };
+    let Some(mut receiver) = receiver else {
+        let response = plugin_unavailable(&config, "bar", "Bar");
+        let _ = write_response(&mut stream, &response).await;
+        return Ok(());
+    };
+
+    loop {
+        let status = receiver.borrow_and_update().clone();
+        if !status.is_null() {
+            if let Err(e) = write_response(&mut stream, &IpcResponse::Data(status)).await {
+                log::debug!("Status subscriber disconnected: {}", e);
+                return Ok(());
+            }
+        }
+        if receiver.changed().await.is_err() {
+            // Bar plugin disabled or daemon shutting down
+            return Ok(());
+        }
+    }
+}
 
-        Ok(response)
+/// Stream events to a `piri events` client until it goes away; with `names`, only the events
+/// with those names
+async fn stream_events(mut stream: impl Connection, names: Vec<String>) -> Result<()> {
+    let mut receiver = crate::events::subscribe();
+    loop {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                log::warn!("Event subscriber too slow, skipped {} events", skipped);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        };
+        let name = event.as_object().and_then(|o| o.keys().next());
+        if !names.is_empty() && !name.is_some_and(|name| names.contains(name)) {
+            continue;
+        }
+        if let Err(e) = write_response(&mut stream, &IpcResponse::Data(event)).await {
+            log::debug!("Event subscriber disconnected: {}", e);
+            return Ok(());
+        }
+    }
+}
+
+/// Send the buffered log lines, then every new line, to a `piri logs --follow` client
+/// until it goes away
+async fn stream_logs(mut stream: impl Connection) -> Result<()> {
+    let (lines, mut receiver) = crate::log_buffer::subscribe();
+    let mut lines = serde_json::json!(lines);
+    loop {
+        if let Err(e) = write_response(&mut stream, &IpcResponse::Data(lines)).await {
+            log::debug!("Log subscriber disconnected: {}", e);
+            return Ok(());
+        }
+        lines = match receiver.recv().await {
+            Ok(line) => serde_json::json!([line]),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                serde_json::json!([format!("... {} lines skipped", skipped)])
+            }
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        };
     }
 }
 
 /// Helper function to send error response
-async fn send_error_response(stream: &mut UnixStream, error: &str) {
-    let response = IpcResponse::Error(error.to_string());
+async fn send_error_response(stream: &mut impl Connection, error: &str) {
+    let response = IpcResponse::Error(error.to_string().into());
     if let Ok(response_json) = serde_json::to_string(&response) {
         let response_bytes = response_json.as_bytes();
         let _ = stream.write_u32(response_bytes.len() as u32).await;
@@ -173,9 +978,56 @@ async fn send_error_response(stream: &mut UnixStream, error: &str) {
     }
 }
 
+/// Requests the daemon doesn't know usually come from a client of another version
+fn deserialize_error(what: &str, error: &serde_json::Error) -> String {
+    format!(
+        "Failed to deserialize {}: {}. Is the client the same version as the daemon ({})? See `piri version`",
+        what,
+        error,
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+/// Build the error response for a plugin request that no running plugin handled
+fn plugin_unavailable(config: &Config, plugin: &str, display_name: &str) -> IpcResponse {
+    if config.plugin_runs(plugin) {
+        IpcResponse::Error(IpcError::new(
+            ErrorKind::PluginDisabled,
+            format!(
+                "{} plugin is enabled but not running. If it crashed (see `piri status`), `piri reload` starts it again.",
+                display_name
+            ),
+        ))
+    } else {
+        IpcResponse::Error(IpcError::new(
+            ErrorKind::PluginDisabled,
+            format!(
+                "{} plugin is not enabled. Please enable it in the configuration file (piri.plugins.{} = true).",
+                display_name, plugin
+            ),
+        ))
+    }
+}
+
+/// Set once niri answered and the plugins started, see `IpcRequest::works_before_niri`
+static NIRI_READY: AtomicBool = AtomicBool::new(false);
+
+/// Handle every request from now on, the plugins having started
+pub fn set_niri_ready() {
+    NIRI_READY.store(true, Ordering::Relaxed);
+}
+
+/// The error requests get while the daemon waits for niri
+fn waiting_for_niri() -> IpcResponse {
+    IpcResponse::Error(IpcError::new(
+        ErrorKind::NiriUnreachable,
+        "The daemon is waiting for niri to start, its plugins aren't running yet",
+    ))
+}
+
 /// Handle an IPC request (used by daemon)
 pub async fn handle_request(
-    mut stream: UnixStream,
+    mut stream: impl Connection,
     handler: std::sync::Arc<tokio::sync::Mutex<crate::commands::CommandHandler>>,
     shutdown: Option<std::sync::Arc<tokio::sync::Notify>>,
 ) -> Result<()> {
@@ -196,74 +1048,43 @@ pub async fn handle_request(
         return Ok(());
     }
 
+    // An array of requests is a batch, answered with an array of responses
+    if request_bytes.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'[') {
+        return handle_batch(stream, &request_bytes, &handler, shutdown.as_ref()).await;
+    }
+
     // Deserialize request
     let request: IpcRequest = match serde_json::from_slice(&request_bytes) {
         Ok(req) => req,
         Err(e) => {
             log::error!("Failed to deserialize request: {}", e);
-            send_error_response(
-                &mut stream,
-                &format!("Failed to deserialize request: {}", e),
-            )
-            .await;
+            send_error_response(&mut stream, &deserialize_error("request", &e)).await;
             return Ok(());
         }
     };
 
-    // Handle request
-    let response = {
-        let mut handler = handler.lock().await;
-
-        // Try to handle through plugins first
-        if let Some(plugin_result) = handler.handle_ipc_request_through_plugins(&request).await {
-            match plugin_result {
-                Ok(()) => IpcResponse::Success,
-                Err(e) => {
-                    log::error!("Error handling request through plugins: {}", e);
-                    IpcResponse::Error(e.to_string())
-                }
-            }
-        } else {
-            // Fallback to direct handler methods for non-plugin requests
-            match request {
-                IpcRequest::Ping => IpcResponse::Pong,
-                IpcRequest::Shutdown => {
-                    // Notify the daemon loop to shutdown
-                    if let Some(ref shutdown) = shutdown {
-                        shutdown.notify_one();
-                    }
-                    IpcResponse::Success
-                }
-                IpcRequest::ScratchpadToggle { .. } | IpcRequest::ScratchpadAdd { .. } => {
-                    // Check if scratchpads plugin should be enabled but isn't
-                    let config = handler.config();
-                    if config.piri.plugins.is_enabled("scratchpads") {
-                        IpcResponse::Error("Scratchpads plugin is enabled but not initialized. Please restart the daemon.".to_string())
-                    } else {
-                        IpcResponse::Error("Scratchpads plugin is not enabled. Please enable it in the configuration file (piri.plugins.scratchpads = true).".to_string())
-                    }
-                }
-                IpcRequest::SingletonToggle { name: _ } => {
-                    // Check if singleton plugin should be enabled but isn't
-                    let config = handler.config();
-                    if config.piri.plugins.is_enabled("singleton") {
-                        IpcResponse::Error(format!("Singleton plugin is enabled but not initialized. Please restart the daemon."))
-                    } else {
-                        IpcResponse::Error(format!("Singleton plugin is not enabled. Please enable it in the configuration file (piri.plugins.singleton = true)."))
-                    }
-                }
-                IpcRequest::WindowOrderToggle => {
-                    // Check if window_order plugin should be enabled but isn't
-                    let config = handler.config();
-                    if config.piri.plugins.is_enabled("window_order") {
-                        IpcResponse::Error("WindowOrder plugin is enabled but not initialized. Please restart the daemon.".to_string())
-                    } else {
-                        IpcResponse::Error("WindowOrder plugin is not enabled. Please enable it in the configuration file (piri.plugins.window_order = true).".to_string())
-                    }
-                }
-            }
+    if !NIRI_READY.load(Ordering::Relaxed) && !request.works_before_niri() {
+        if let Err(e) = write_response(&mut stream, &waiting_for_niri()).await {
+            log::error!("Failed to write response: {}", e);
         }
-    };
+        return Ok(());
+    }
+
+    // Subscriptions keep the connection open and stream updates
+    if let IpcRequest::BarSubscribe = request {
+        return stream_status(stream, &handler).await;
+    }
+    if let IpcRequest::EventsSubscribe { names } = request {
+        return stream_events(stream, names).await;
+    }
+    if let IpcRequest::Logs { follow: true } = request {
+        return stream_logs(stream).await;
+    }
+    if let IpcRequest::Session = request {
+        return handle_session(stream, &handler, shutdown.as_ref()).await;
+    }
+
+    let response = dispatch_traced(request, &handler, shutdown.as_ref()).await;
 
     // Serialize response
     let response_json = match serde_json::to_string(&response) {
@@ -288,3 +1109,391 @@ pub async fn handle_request(
 
     Ok(())
 }
+
+/// Handle a batch of requests in order, each one after the previous finished
+async fn handle_batch(
+    mut stream: impl Connection,
+    request_bytes: &[u8],
+    handler: &std::sync::Arc<tokio::sync::Mutex<crate::commands::CommandHandler>>,
+    shutdown: Option<&std::sync::Arc<tokio::sync::Notify>>,
+) -> Result<()> {
+    let requests: Vec<IpcRequest> = match serde_json::from_slice(request_bytes) {
+        Ok(requests) => requests,
+        Err(e) => {
+            log::error!("Failed to deserialize batch: {}", e);
+            send_error_response(&mut stream, &deserialize_error("batch", &e)).await;
+            return Ok(());
+        }
+    };
+
+    let mut responses = Vec::with_capacity(requests.len());
+    for request in requests {
+        let response = match request {
+            IpcRequest::BarSubscribe
+            | IpcRequest::EventsSubscribe { .. }
+            | IpcRequest::Logs { follow: true }
+            | IpcRequest::Session => {
+                IpcResponse::Error("Subscriptions can't be part of a batch".to_string().into())
+            }
+            request => dispatch_traced(request, handler, shutdown).await,
+        };
+        responses.push(response);
+    }
+
+    if let Err(e) = write_response(&mut stream, &responses).await {
+        log::error!("Failed to write batch response: {}", e);
+    }
+    Ok(())
+}
+
+/// Listen on TCP if `[piri.tcp]` is enabled and has a token; returns the listener and the
+/// token clients must present
+pub async fn bind_tcp(tcp: &TcpSection) -> Result<Option<(TcpListener, String)>> {
+    if !tcp.enabled {
+        return Ok(None);
+    }
+    let Some(token) = tcp.token.clone().filter(|token| !token.is_empty()) else {
+        log::warn!("[piri.tcp] is enabled without a token, not listening on TCP");
+        return Ok(None);
+    };
+    let listener = TcpListener::bind(&tcp.address)
+        .await
+        .with_context(|| format!("Failed to listen on {}", tcp.address))?;
+    let address = listener.local_addr()?;
+    if address.ip().is_loopback() {
+        log::info!("Listening on TCP {}", address);
+    } else {
+        log::warn!(
+            "Listening on TCP {}, reachable from other machines with the token",
+            address
+        );
+    }
+    Ok(Some((listener, token)))
+}
+
+/// Handle a TCP connection: once its first request authenticated it with the token, it
+/// works like a socket connection
+pub async fn handle_tcp_request(
+    mut stream: TcpStream,
+    token: &str,
+    handler: std::sync::Arc<tokio::sync::Mutex<crate::commands::CommandHandler>>,
+    shutdown: Option<std::sync::Arc<tokio::sync::Notify>>,
+) -> Result<()> {
+    let peer = stream.peer_addr()?;
+    let read_auth = async {
+        let request_len = stream.read_u32().await?;
+        // Nothing is trusted before the token, don't let a stranger make us allocate much
+        anyhow::ensure!(request_len <= 4096, "Authentication request too long");
+        let mut request_bytes = vec![0u8; request_len as usize];
+        stream.read_exact(&mut request_bytes).await?;
+        Ok(serde_json::from_slice::<IpcRequest>(&request_bytes)?)
+    };
+    let authenticated = match tokio::time::timeout(DEFAULT_TIMEOUT, read_auth).await {
+        Ok(Ok(IpcRequest::Auth { token: given })) => token_matches(&given, token),
+        _ => false,
+    };
+    if !authenticated {
+        log::warn!("Rejected TCP connection from {}: invalid token", peer);
+        send_error_response(&mut stream, "Invalid token").await;
+        return Ok(());
+    }
+    log::debug!("Accepted TCP connection from {}", peer);
+    write_response(&mut stream, &IpcResponse::Success).await?;
+    handle_request(stream, handler, shutdown).await
+}
+
+/// Compare every byte, so that the time taken doesn't tell how much of the token was right
+fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Answer requests one at a time over the same connection, for `piri repl`
+async fn handle_session(
+    mut stream: impl Connection,
+    handler: &std::sync::Arc<tokio::sync::Mutex<crate::commands::CommandHandler>>,
+    shutdown: Option<&std::sync::Arc<tokio::sync::Notify>>,
+) -> Result<()> {
+    write_response(&mut stream, &IpcResponse::Success).await?;
+    loop {
+        let request_len = match stream.read_u32().await {
+            Ok(len) => len,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e).context("Failed to read request length"),
+        };
+        let mut request_bytes = vec![0u8; request_len as usize];
+        stream
+            .read_exact(&mut request_bytes)
+            .await
+            .context("Failed to read request data")?;
+
+        let response = match serde_json::from_slice(&request_bytes) {
+            Ok(
+                IpcRequest::BarSubscribe
+                | IpcRequest::EventsSubscribe { .. }
+                | IpcRequest::Logs { follow: true }
+                | IpcRequest::Session,
+            ) => IpcResponse::Error("Subscriptions can't be part of a session".to_string().into()),
+            Ok(request) => dispatch_traced(request, handler, shutdown).await,
+            Err(e) => IpcResponse::Error(deserialize_error("request", &e).into()),
+        };
+        write_response(&mut stream, &response).await?;
+    }
+}
+
+/// Handle a single request under a new trace ID, which prefixes the lines logged meanwhile and
+/// is sent back with errors
+async fn dispatch_traced(
+    request: IpcRequest,
+    handler: &std::sync::Arc<tokio::sync::Mutex<crate::commands::CommandHandler>>,
+    shutdown: Option<&std::sync::Arc<tokio::sync::Notify>>,
+) -> IpcResponse {
+    let id = NEXT_TRACE_ID.fetch_add(1, Ordering::Relaxed);
+    TRACE_ID
+        .scope(id, async {
+            log::debug!("Handling {:?}", request);
+            crate::metrics::count("piri_ipc_requests_total", &[("request", &request.name())]);
+            let response = match request {
+                request if !NIRI_READY.load(Ordering::Relaxed) && !request.works_before_niri() => {
+                    waiting_for_niri()
+                }
+                IpcRequest::Wait {
+                    condition,
+                    timeout_ms,
+                } => {
+                    let timeout = Duration::from_millis(timeout_ms);
+                    match crate::commands::wait_for(handler, &condition, timeout).await {
+                        Ok(()) => IpcResponse::Success,
+                        Err(e) => IpcResponse::Error(e.into()),
+                    }
+                }
+                request => dispatch(request, handler, shutdown).await,
+            };
+            match response {
+                IpcResponse::Error(mut e) => {
+                    e.trace_id = Some(id);
+                    IpcResponse::Error(e)
+                }
+                response => response,
+            }
+        })
+        .await
+}
+
+/// Handle a single request and build its response
+async fn dispatch(
+    request: IpcRequest,
+    handler: &std::sync::Arc<tokio::sync::Mutex<crate::commands::CommandHandler>>,
+    shutdown: Option<&std::sync::Arc<tokio::sync::Notify>>,
+) -> IpcResponse {
+    let mut handler = handler.lock().await;
+
+    // Try queries and requests through plugins first
+    if let Some(query_result) = handler.handle_ipc_query_through_plugins(&request).await {
+        match query_result {
+            Ok(data) => IpcResponse::Data(data),
+            Err(e) => {
+                log::error!("Error handling query through plugins: {}", e);
+                IpcResponse::Error(e.into())
+            }
+        }
+    } else if let Some(plugin_result) = handler.handle_ipc_request_through_plugins(&request).await {
+        match plugin_result {
+            Ok(()) => IpcResponse::Success,
+            Err(e) => {
+                log::error!("Error handling request through plugins: {}", e);
+                IpcResponse::Error(e.into())
+            }
+        }
+    } else {
+        // Fallback to direct handler methods for non-plugin requests
+        match request {
+            IpcRequest::Ping => IpcResponse::Pong(handler.ping().await),
+            IpcRequest::Shutdown => {
+                // Notify the daemon loop to shutdown
+                if let Some(shutdown) = shutdown {
+                    shutdown.notify_one();
+                }
+                IpcResponse::Success
+            }
+            IpcRequest::SetLogLevel { level } => {
+                let result = crate::logging::set_level(level.map(|level| level.0));
+                match level {
+                    Some(level) => log::info!("Log level set to {}", level),
+                    None => log::info!("Back to the configured log levels"),
+                }
+                match result {
+                    Ok(()) => IpcResponse::Success,
+                    Err(e) => IpcResponse::Error(e.into()),
+                }
+            }
+            IpcRequest::Restart => {
+                crate::daemon::request_restart();
+                if let Some(shutdown) = shutdown {
+                    shutdown.notify_one();
+                }
+                IpcResponse::Success
+            }
+            IpcRequest::Status => match serde_json::to_value(handler.status().await) {
+                Ok(data) => IpcResponse::Data(data),
+                Err(e) => IpcResponse::Error(e.to_string().into()),
+            },
+            IpcRequest::Version => match serde_json::to_value(handler.version().await) {
+                Ok(data) => IpcResponse::Data(data),
+                Err(e) => IpcResponse::Error(e.to_string().into()),
+            },
+            IpcRequest::Metrics => match serde_json::to_value(crate::metrics::snapshot()) {
+                Ok(data) => IpcResponse::Data(data),
+                Err(e) => IpcResponse::Error(e.to_string().into()),
+            },
+            IpcRequest::WindowList => match handler.list_windows().await {
+                Ok(windows) => match serde_json::to_value(windows) {
+                    Ok(data) => IpcResponse::Data(data),
+                    Err(e) => IpcResponse::Error(e.to_string().into()),
+                },
+                Err(e) => {
+                    log::error!("Failed to list windows: {}", e);
+                    IpcResponse::Error(e.into())
+                }
+            },
+            IpcRequest::WindowFocus { id, bring } => match handler.focus_window(id, bring).await {
+                Ok(()) => IpcResponse::Success,
+                Err(e) => {
+                    log::error!("Failed to focus window {}: {}", id, e);
+                    IpcResponse::Error(e.into())
+                }
+            },
+            IpcRequest::WorkspaceList => match handler.list_workspaces().await {
+                Ok(workspaces) => match serde_json::to_value(workspaces) {
+                    Ok(data) => IpcResponse::Data(data),
+                    Err(e) => IpcResponse::Error(e.to_string().into()),
+                },
+                Err(e) => {
+                    log::error!("Failed to list workspaces: {}", e);
+                    IpcResponse::Error(e.into())
+                }
+            },
+            IpcRequest::WorkspaceFocus { query } => match handler.focus_workspace(&query).await {
+                Ok(()) => IpcResponse::Success,
+                Err(e) => {
+                    log::error!("Failed to focus workspace {}: {}", query, e);
+                    IpcResponse::Error(e.into())
+                }
+            },
+            IpcRequest::WindowAction {
+                action,
+                app_id,
+                title,
+                all,
+            } => match handler.window_action(&action, app_id, title, all).await {
+                Ok(()) => IpcResponse::Success,
+                Err(e) => {
+                    log::error!("Failed to run window action {:?}: {}", action, e);
+                    IpcResponse::Error(e.into())
+                }
+            },
+            IpcRequest::Logs { .. } => {
+                IpcResponse::Data(serde_json::json!(crate::log_buffer::lines()))
+            }
+            IpcRequest::ConfigGet { path } => match handler.config_get(&path) {
+                Ok(data) => IpcResponse::Data(data),
+                Err(e) => IpcResponse::Error(e.into()),
+            },
+            IpcRequest::ConfigShow => match handler.config_show().await {
+                Ok(data) => IpcResponse::Data(data),
+                Err(e) => IpcResponse::Error(e.into()),
+            },
+            IpcRequest::ConfigSet { path, value, write } => {
+                match handler.config_set(&path, &value, write).await {
+                    Ok(()) => IpcResponse::Success,
+                    Err(e) => {
+                        log::error!("Failed to set config {}: {}", path, e);
+                        IpcResponse::Error(e.into())
+                    }
+                }
+            }
+            IpcRequest::Reload => match handler.reload(None).await {
+                Ok(()) => IpcResponse::Success,
+                Err(e) => {
+                    log::error!("Failed to reload config: {:#}", e);
+                    IpcResponse::Error(config_error(e))
+                }
+            },
+            IpcRequest::ReloadPlugin { name } => match handler.reload(Some(&name)).await {
+                Ok(()) => IpcResponse::Success,
+                Err(e) => {
+                    log::error!("Failed to reload plugin {}: {:#}", name, e);
+                    IpcResponse::Error(config_error(e))
+                }
+            },
+            IpcRequest::ProfileSet { name } => match handler.set_profile(name).await {
+                Ok(()) => IpcResponse::Success,
+                Err(e) => {
+                    log::error!("Failed to switch profile: {:#}", e);
+                    IpcResponse::Error(config_error(e))
+                }
+            },
+            // Plugin requests only get here if the plugin isn't running
+            IpcRequest::ScratchpadToggle { .. }
+            | IpcRequest::ScratchpadAdd { .. }
+            | IpcRequest::ScratchpadRehome
+            | IpcRequest::ScratchpadHideAll => {
+                plugin_unavailable(handler.config(), "scratchpads", "Scratchpads")
+            }
+            IpcRequest::SingletonToggle { .. } | IpcRequest::SingletonList => {
+                plugin_unavailable(handler.config(), "singleton", "Singleton")
+            }
+            IpcRequest::WindowOrderToggle => {
+                plugin_unavailable(handler.config(), "window_order", "WindowOrder")
+            }
+            IpcRequest::AutofillToggle => {
+                plugin_unavailable(handler.config(), "autofill", "Autofill")
+            }
+            IpcRequest::FocusLast { .. }
+            | IpcRequest::FocusPrev { .. }
+            | IpcRequest::FocusNext { .. } => {
+                plugin_unavailable(handler.config(), "focus_history", "FocusHistory")
+            }
+            IpcRequest::MarkSet { .. } | IpcRequest::MarkGoto { .. } | IpcRequest::MarkList => {
+                plugin_unavailable(handler.config(), "marks", "Marks")
+            }
+            IpcRequest::SessionSave { .. } | IpcRequest::SessionRestore { .. } => {
+                plugin_unavailable(handler.config(), "session", "Session")
+            }
+            IpcRequest::GroupAdd { .. }
+            | IpcRequest::GroupRemove
+            | IpcRequest::GroupNext { .. }
+            | IpcRequest::GroupPrev { .. }
+            | IpcRequest::GroupList => plugin_unavailable(handler.config(), "groups", "Groups"),
+            IpcRequest::ColumnRotate { .. }
+            | IpcRequest::ColumnSwapMaster
+            | IpcRequest::ColumnReverse => {
+                plugin_unavailable(handler.config(), "columns", "Columns")
+            }
+            IpcRequest::DndSet { .. } | IpcRequest::DndStatus => {
+                plugin_unavailable(handler.config(), "dnd", "Dnd")
+            }
+            IpcRequest::Stash | IpcRequest::Unstash { .. } | IpcRequest::StashList => {
+                plugin_unavailable(handler.config(), "stash", "Stash")
+            }
+            IpcRequest::SendTo { .. } => plugin_unavailable(handler.config(), "sendto", "SendTo"),
+            IpcRequest::PresentStart { .. } | IpcRequest::PresentStop => {
+                plugin_unavailable(handler.config(), "present", "Present")
+            }
+            IpcRequest::WorkspacePinOverride { .. } | IpcRequest::WorkspacePinRestore { .. } => {
+                plugin_unavailable(handler.config(), "workspace_pin", "WorkspacePin")
+            }
+            // Always handled by the plugin manager
+            IpcRequest::PluginSuspend { .. } => {
+                IpcResponse::Error("Plugin manager is not running".to_string().into())
+            }
+            // Handled before taking the handler lock
+            IpcRequest::BarSubscribe => plugin_unavailable(handler.config(), "bar", "Bar"),
+            IpcRequest::EventsSubscribe { .. } | IpcRequest::Session => IpcResponse::Success,
+            // Only checked as the first request on TCP connections
+            IpcRequest::Auth { .. } => IpcResponse::Success,
+            // Handled without holding the handler lock
+            IpcRequest::Wait { .. } => IpcResponse::Success,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::Result;
+use log::{info, warn};
+use niri_ipc::Action;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::niri::NiriIpc;
+use crate::utils::{load_state, save_state, send_notification};
+
+/// State file (in the state directory) of the windows piri keeps out of sight
+const JOURNAL_FILE: &str = "hidden_windows.json";
+
+/// A window moved off-screen or to a hidden workspace, which only the daemon knows how to
+/// bring back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    /// Plugin that hid it
+    plugin: String,
+    /// To tell the window apart from one that got its ID after niri restarted
+    app_id: Option<String>,
+    /// Workspace to bring it back to, if it still exists (default: the focused one)
+    workspace_id: Option<u64>,
+    /// Moved off-screen rather than to another workspace
+    off_screen: bool,
+}
+
+/// Window ID -> entry, written to the state file on every change
+static JOURNAL: Mutex<BTreeMap<u64, Entry>> = Mutex::new(BTreeMap::new());
+
+fn save(journal: &BTreeMap<u64, Entry>) {
+    if let Err(e) = save_state(JOURNAL_FILE, journal) {
+        warn!("Failed to save hidden windows: {}", e);
+    }
+}
+
+/// Note that `plugin` hid a window, so that it's brought back if the daemon stops before
+/// showing it again
+pub fn record(
+    plugin: &str,
+    window_id: u64,
+    app_id: Option<&str>,
+    workspace_id: Option<u64>,
+    off_screen: bool,
+) {
+    let mut journal = JOURNAL.lock().unwrap();
+    journal.insert(
+        window_id,
+        Entry {
+            plugin: plugin.to_string(),
+            app_id: app_id.map(str::to_string),
+            workspace_id,
+            off_screen,
+        },
+    );
+    save(&journal);
+}
+
+/// Note that a window was shown again or closed
+pub fn forget(window_id: u64) {
+    let mut journal = JOURNAL.lock().unwrap();
+    if journal.remove(&window_id).is_some() {
+        save(&journal);
+    }
+}
+
+/// Forget every window, after niri restarted and took them with it
+pub fn clear() {
+    let mut journal = JOURNAL.lock().unwrap();
+    if !journal.is_empty() {
+        journal.clear();
+        save(&journal);
+    }
+}
+
+/// Keep the windows the previous daemon hid hidden, as `piri restart` hands them over to the
+/// plugins of the new one
+pub fn resume() -> Result<()> {
+    let entries: BTreeMap<u64, Entry> = load_state(JOURNAL_FILE)?.unwrap_or_default();
+    *JOURNAL.lock().unwrap() = entries;
+    Ok(())
+}
+
+/// Bring back the windows a previous daemon left hidden, e.g. because it crashed, to the
+/// workspace they came from or the focused one. Called at startup, before the plugins
+pub async fn recover(niri: &NiriIpc) -> Result<()> {
+    let entries: BTreeMap<u64, Entry> = load_state(JOURNAL_FILE)?.unwrap_or_default();
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let windows = niri.get_windows().await?;
+    let workspaces = niri.get_workspaces().await?;
+    let focused = workspaces.iter().find(|ws| ws.is_focused).map(|ws| ws.id);
+
+    let mut restored = 0;
+    for (window_id, entry) in &entries {
+        if !windows.iter().any(|w| w.id == *window_id && w.app_id == entry.app_id) {
+            continue;
+        }
+        let target = entry
+            .workspace_id
+            .filter(|id| workspaces.iter().any(|ws| ws.id == *id))
+            .or(focused);
+        info!(
+            "Bringing back window {} (app_id={:?}), left hidden by {}",
+            window_id, entry.app_id, entry.plugin
+        );
+        match restore(niri, *window_id, target, entry.off_screen).await {
+            Ok(()) => restored += 1,
+            Err(e) => warn!("Failed to bring back window {}: {}", window_id, e),
+        }
+    }
+    save(&BTreeMap::new());
+
+    if restored > 0 {
+        send_notification(
+            "piri",
+            &format!(
+                "Brought back {} window(s) left hidden when piri stopped",
+                restored
+            ),
+        );
+    }
+    Ok(())
+}
+
+async fn restore(
+    niri: &NiriIpc,
+    window_id: u64,
+    workspace_id: Option<u64>,
+    off_screen: bool,
+) -> Result<()> {
+    if let Some(workspace_id) = workspace_id {
+        niri.move_window_to_workspace_id(window_id, workspace_id).await?;
+    }
+    if off_screen {
+        niri.send_action(Action::CenterWindow {
+            id: Some(window_id),
+        })
+        .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,633 @@
+use anyhow::{Context, Result};
+
+/// A parsed KDL node. Null values are dropped, TOML has no equivalent
+struct Node {
+    name: String,
+    /// Quoted names are kept as they are, bare ones get their dashes replaced
+    quoted: bool,
+    args: Vec<toml::Value>,
+    props: Vec<(String, toml::Value)>,
+    children: Option<Vec<Node>>,
+}
+
+/// Convert a KDL document to the TOML table the same config would be in TOML:
+///
+/// - `name value` sets a key, several values make an array and no value at all means
+///   `true` (`plugins { scratchpads; swallow }`)
+/// - `name { ... }` and `name key=value` are tables, `name "key" { ... }` is the entry
+///   `key` of the table `name` (`scratchpads "term" { ... }`)
+/// - bare names have `-` replaced by `_` (`use-pid-matching`), quoted names are kept
+///   (`"google-chrome" 10`)
+///
+/// `schema` is the default config as a table, which tells where arrays are expected, so
+/// that a single `swallow { ... }` still becomes a list of swallow rules
+pub fn to_table(content: &str, schema: &toml::Table) -> Result<toml::Table> {
+    let mut parser = Parser {
+        chars: content.chars().collect(),
+        pos: 0,
+    };
+    let nodes = parser.nodes(false).map_err(|e| {
+        let (line, column) = parser.location();
+        anyhow::anyhow!("KDL parse error at line {}, column {}: {}", line, column, e)
+    })?;
+    let mut table = toml::Table::new();
+    insert_nodes(&mut table, nodes, Some(schema))?;
+    Ok(table)
+}
+
+fn insert_nodes(
+    table: &mut toml::Table,
+    nodes: Vec<Node>,
+    schema: Option<&toml::Table>,
+) -> Result<()> {
+    for node in nodes {
+        let key = if node.quoted {
+            node.name.clone()
+        } else {
+            node.name.replace('-', "_")
+        };
+        let expected = schema.and_then(|schema| schema.get(&key));
+
+        if node.children.is_some() || !node.props.is_empty() {
+            let mut inner = toml::Table::new();
+            for (name, value) in node.props {
+                inner.insert(name.replace('-', "_"), value);
+            }
+            let keyed = match node.args.as_slice() {
+                [] => None,
+                [toml::Value::String(name)] => Some(name.clone()),
+                [value] => Some(value.to_string()),
+                _ => anyhow::bail!(
+                    "'{}' takes at most one argument before its block",
+                    node.name
+                ),
+            };
+            // Entries of a map, like scratchpads, have no defaults to look at
+            let inner_schema = match keyed {
+                Some(_) => None,
+                None => expected.and_then(|value| value.as_table()),
+            };
+            insert_nodes(&mut inner, node.children.unwrap_or_default(), inner_schema)?;
+
+            match keyed {
+                Some(name) => {
+                    let map = table
+                        .entry(key.clone())
+                        .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                        .as_table_mut()
+                        .with_context(|| format!("'{}' is given both as a value and a map", key))?;
+                    insert_value(map, name, toml::Value::Table(inner), None);
+                }
+                None => insert_value(table, key, toml::Value::Table(inner), expected),
+            }
+            continue;
+        }
+
+        let mut args = node.args;
+        let value = match args.len() {
+            0 => toml::Value::Boolean(true),
+            1 if !matches!(expected, Some(toml::Value::Array(_))) => args.remove(0),
+            _ => toml::Value::Array(args),
+        };
+        insert_value(table, key, value, expected);
+    }
+    Ok(())
+}
+
+/// Insert a node's value, appending to arrays and merging repeated tables
+fn insert_value(
+    table: &mut toml::Table,
+    key: String,
+    value: toml::Value,
+    expected: Option<&toml::Value>,
+) {
+    let is_array = matches!(expected, Some(toml::Value::Array(_)));
+    match (table.get_mut(&key), value) {
+        (Some(toml::Value::Array(items)), toml::Value::Array(values)) if is_array => {
+            items.extend(values)
+        }
+        (Some(toml::Value::Array(items)), value) if is_array => items.push(value),
+        (Some(toml::Value::Table(existing)), toml::Value::Table(values)) => {
+            for (name, value) in values {
+                insert_value(existing, name, value, None);
+            }
+        }
+        (_, toml::Value::Array(values)) => {
+            table.insert(key, toml::Value::Array(values));
+        }
+        (_, value) if is_array => {
+            table.insert(key, toml::Value::Array(vec![value]));
+        }
+        (_, value) => {
+            table.insert(key, value);
+        }
+    }
+}
+
+/// Characters that end a bare identifier or value
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || "\\/(){}<>;[]=,\"".contains(c)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    /// Line and column of the current position, 1-based
+    fn location(&self) -> (usize, usize) {
+        let before = &self.chars[..self.pos.min(self.chars.len())];
+        let line = before.iter().filter(|c| **c == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|c| **c != '\n').count() + 1;
+        (line, column)
+    }
+
+    /// Skip spaces, comments and escaped line breaks, and also line breaks with `newlines`
+    fn skip_whitespace(&mut self, newlines: bool) -> Result<()> {
+        while let Some(c) = self.peek() {
+            if c == '\n' || c == '\r' {
+                if !newlines {
+                    break;
+                }
+                self.pos += 1;
+            } else if c.is_whitespace() || c == '\u{feff}' {
+                self.pos += 1;
+            } else if self.starts_with("//") {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+            } else if self.starts_with("/*") {
+                self.skip_block_comment()?;
+            } else if c == '\\' {
+                // Line continuation
+                self.pos += 1;
+                self.skip_whitespace(false)?;
+                match self.peek() {
+                    Some('\r') => self.pos += if self.starts_with("\r\n") { 2 } else { 1 },
+                    Some('\n') => self.pos += 1,
+                    None => {}
+                    Some(_) => anyhow::bail!("expected a line break after '\\'"),
+                }
+            } else {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_block_comment(&mut self) -> Result<()> {
+        let mut depth = 0;
+        loop {
+            if self.starts_with("/*") {
+                depth += 1;
+                self.pos += 2;
+            } else if self.starts_with("*/") {
+                depth -= 1;
+                self.pos += 2;
+                if depth == 0 {
+                    return Ok(());
+                }
+            } else if self.peek().is_some() {
+                self.pos += 1;
+            } else {
+                anyhow::bail!("unterminated comment");
+            }
+        }
+    }
+
+    /// Nodes up to the end of the document, or up to and including the `}` of a block
+    fn nodes(&mut self, in_block: bool) -> Result<Vec<Node>> {
+        let mut nodes = Vec::new();
+        loop {
+            self.skip_whitespace(true)?;
+            match self.peek() {
+                None if in_block => anyhow::bail!("missing '}}'"),
+                None => return Ok(nodes),
+                Some('}') if in_block => {
+                    self.pos += 1;
+                    return Ok(nodes);
+                }
+                Some('}') => anyhow::bail!("unexpected '}}'"),
+                Some(';') => self.pos += 1,
+                Some(_) if self.starts_with("/-") => {
+                    self.pos += 2;
+                    self.skip_whitespace(true)?;
+                    self.node()?;
+                }
+                Some(_) => nodes.push(self.node()?),
+            }
+        }
+    }
+
+    fn node(&mut self) -> Result<Node> {
+        self.skip_type_annotation()?;
+        let (name, quoted) = match self.peek() {
+            Some('"') => (self.string()?, true),
+            _ if self.at_raw_string() => (self.raw_string()?, true),
+            _ => (self.bare()?, false),
+        };
+        let mut node = Node {
+            name,
+            quoted,
+            args: Vec::new(),
+            props: Vec::new(),
+            children: None,
+        };
+
+        loop {
+            self.skip_whitespace(false)?;
+            let mut discard = false;
+            if self.starts_with("/-") {
+                self.pos += 2;
+                self.skip_whitespace(false)?;
+                discard = true;
+            }
+            match self.peek() {
+                None | Some('}') => break,
+                Some('\n' | '\r' | ';') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some('{') => {
+                    self.pos += 1;
+                    let children = self.nodes(true)?;
+                    if !discard {
+                        node.children = Some(children);
+                    }
+                }
+                Some(_) => {
+                    let (name, value) = self.entry()?;
+                    match (discard, name, value) {
+                        (true, _, _) | (_, _, None) => {}
+                        (false, Some(name), Some(value)) => node.props.push((name, value)),
+                        (false, None, Some(value)) => node.args.push(value),
+                    }
+                }
+            }
+        }
+        Ok(node)
+    }
+
+    /// An argument or a `key=value` property
+    fn entry(&mut self) -> Result<(Option<String>, Option<toml::Value>)> {
+        self.skip_type_annotation()?;
+        let (token, quoted) = match self.peek() {
+            Some('"') => (self.string()?, true),
+            _ if self.at_raw_string() => (self.raw_string()?, true),
+            _ => (self.bare()?, false),
+        };
+        if self.peek() == Some('=') {
+            self.pos += 1;
+            self.skip_type_annotation()?;
+            let value = match self.peek() {
+                Some('"') => Some(toml::Value::String(self.string()?)),
+                _ if self.at_raw_string() => Some(toml::Value::String(self.raw_string()?)),
+                _ => {
+                    let bare = self.bare()?;
+                    keyword_or_number(&bare)
+                }
+            };
+            return Ok((Some(token), value));
+        }
+        if quoted {
+            return Ok((None, Some(toml::Value::String(token))));
+        }
+        Ok((None, keyword_or_number(&token)))
+    }
+
+    /// Skip a `(type)` annotation, which doesn't change how values are read here
+    fn skip_type_annotation(&mut self) -> Result<()> {
+        if self.peek() == Some('(') {
+            while self.peek().is_some_and(|c| c != ')') {
+                self.pos += 1;
+            }
+            if self.peek().is_none() {
+                anyhow::bail!("unterminated type annotation");
+            }
+            self.pos += 1;
+        }
+        Ok(())
+    }
+
+    fn bare(&mut self) -> Result<String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| !is_delimiter(c)) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            match self.peek() {
+                Some(c) => anyhow::bail!("unexpected '{}'", c),
+                None => anyhow::bail!("unexpected end of file"),
+            }
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.pos += 1;
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                None => anyhow::bail!("unterminated string"),
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(text);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    let escaped = self.peek().context("unterminated string")?;
+                    self.pos += 1;
+                    match escaped {
+                        'n' => text.push('\n'),
+                        'r' => text.push('\r'),
+                        't' => text.push('\t'),
+                        'b' => text.push('\u{8}'),
+                        'f' => text.push('\u{c}'),
+                        's' => text.push(' '),
+                        '"' | '\\' | '/' => text.push(escaped),
+                        'u' => text.push(self.unicode_escape()?),
+                        c if c.is_whitespace() => {
+                            // Escaped whitespace, including line breaks, is dropped
+                            while self.peek().is_some_and(char::is_whitespace) {
+                                self.pos += 1;
+                            }
+                        }
+                        c => anyhow::bail!("unknown escape '\\{}'", c),
+                    }
+                }
+                Some(c) => {
+                    text.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// `{1F600}` after `\u`
+    fn unicode_escape(&mut self) -> Result<char> {
+        if self.peek() != Some('{') {
+            anyhow::bail!("expected '{{' after '\\u'");
+        }
+        self.pos += 1;
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c != '}') {
+            self.pos += 1;
+        }
+        let hex: String = self.chars[start..self.pos].iter().collect();
+        self.pos += 1;
+        u32::from_str_radix(&hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .with_context(|| format!("invalid unicode escape '\\u{{{}}}'", hex))
+    }
+
+    /// Whether a raw string starts here, `r"..."` and `r#"..."#` in v1 or `#"..."#` in v2
+    fn at_raw_string(&self) -> bool {
+        let start = self.pos + usize::from(self.peek() == Some('r'));
+        let hashes = self.chars.iter().skip(start).take_while(|c| **c == '#').count();
+        (hashes > 0 || start > self.pos) && self.chars.get(start + hashes) == Some(&'"')
+    }
+
+    /// `r"..."`, `r#"..."#` or `#"..."#` with any number of `#`
+    fn raw_string(&mut self) -> Result<String> {
+        if self.peek() == Some('r') {
+            self.pos += 1;
+        }
+        let mut hashes = 0;
+        while self.peek() == Some('#') {
+            hashes += 1;
+            self.pos += 1;
+        }
+        if self.peek() != Some('"') {
+            anyhow::bail!("expected '\"' in raw string");
+        }
+        self.pos += 1;
+        let end = format!("\"{}", "#".repeat(hashes));
+        let start = self.pos;
+        while !self.starts_with(&end) {
+            if self.peek().is_none() {
+                anyhow::bail!("unterminated raw string");
+            }
+            self.pos += 1;
+        }
+        let text = self.chars[start..self.pos].iter().collect();
+        self.pos += end.chars().count();
+        Ok(text)
+    }
+}
+
+/// Value of a bare token: a keyword, a number, or else the text itself
+fn keyword_or_number(token: &str) -> Option<toml::Value> {
+    match token.trim_start_matches('#') {
+        "true" => return Some(toml::Value::Boolean(true)),
+        "false" => return Some(toml::Value::Boolean(false)),
+        "null" => return None,
+        _ => {}
+    }
+    match token {
+        "#inf" => return Some(toml::Value::Float(f64::INFINITY)),
+        "#-inf" => return Some(toml::Value::Float(f64::NEG_INFINITY)),
+        "#nan" => return Some(toml::Value::Float(f64::NAN)),
+        _ => {}
+    }
+    let digits = token.replace('_', "");
+    let (negative, unsigned) = match digits.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, digits.strip_prefix('+').unwrap_or(&digits)),
+    };
+    let radix = [("0x", 16), ("0o", 8), ("0b", 2)]
+        .into_iter()
+        .find_map(|(prefix, radix)| unsigned.strip_prefix(prefix).map(|rest| (rest, radix)));
+    let number = match radix {
+        Some((rest, radix)) => i64::from_str_radix(rest, radix).ok().map(toml::Value::Integer),
+        None => unsigned
+            .parse::<i64>()
+            .map(toml::Value::Integer)
+            .ok()
+            .or_else(|| unsigned.parse::<f64>().ok().map(toml::Value::Float))
+            .filter(|_| unsigned.starts_with(|c: char| c.is_ascii_digit())),
+    };
+    match number {
+        Some(toml::Value::Integer(n)) if negative => Some(toml::Value::Integer(-n)),
+        Some(toml::Value::Float(n)) if negative => Some(toml::Value::Float(-n)),
+        Some(number) => Some(number),
+        None => Some(toml::Value::String(token.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str, schema: &str) -> toml::Table {
+        to_table(content, &toml::from_str(schema).unwrap()).unwrap()
+    }
+
+    fn toml(content: &str) -> toml::Table {
+        toml::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn values_arrays_and_flags() {
+        let table = parse("a 1\nb \"x\" \"y\"\nc\nd 1 2; e", "");
+        assert_eq!(
+            table,
+            toml("a = 1\nb = [\"x\", \"y\"]\nc = true\nd = [1, 2]\ne = true")
+        );
+    }
+
+    #[test]
+    fn nested_sections() {
+        let table = parse(
+            "piri {\n    log {\n        level \"debug\"\n    }\n}\nplugins { scratchpads; swallow }",
+            "",
+        );
+        assert_eq!(
+            table,
+            toml("[piri.log]\nlevel = \"debug\"\n[plugins]\nscratchpads = true\nswallow = true")
+        );
+    }
+
+    #[test]
+    fn properties_are_tables() {
+        let table = parse("window gap=4 use-pid=#true", "");
+        assert_eq!(table, toml("[window]\ngap = 4\nuse_pid = true"));
+    }
+
+    #[test]
+    fn keyed_blocks_are_map_entries() {
+        let table = parse(
+            "scratchpads \"term\" { command \"kitty\" }\nscratchpads \"music\" size=\"40%\"",
+            "[scratchpads]",
+        );
+        assert_eq!(
+            table,
+            toml("[scratchpads.term]\ncommand = \"kitty\"\n[scratchpads.music]\nsize = \"40%\"")
+        );
+        assert!(to_table("a \"x\" \"y\" { }", &toml::Table::new()).is_err());
+        assert!(to_table("a 1\na \"x\" { }", &toml::Table::new()).is_err());
+    }
+
+    #[test]
+    fn repeated_nodes() {
+        let schema = "swallow = []\nexclude = []";
+        let table = parse(
+            "swallow { parent \"kitty\" }\nswallow { parent \"foot\" }",
+            schema,
+        );
+        assert_eq!(
+            table,
+            toml("[[swallow]]\nparent = \"kitty\"\n[[swallow]]\nparent = \"foot\"")
+        );
+        // A single node or value still makes an array where the defaults have one
+        let table = parse(
+            "swallow { parent \"kitty\" }\nexclude \"a\"\nexclude \"b\" \"c\"",
+            schema,
+        );
+        assert_eq!(
+            table,
+            toml("exclude = [\"a\", \"b\", \"c\"]\n[[swallow]]\nparent = \"kitty\"")
+        );
+        // Other repeated tables are merged, repeated values replaced
+        let table = parse("a { b 1 }\na { c 2 }\nd 1\nd 2", "");
+        assert_eq!(table, toml("d = 2\n[a]\nb = 1\nc = 2"));
+    }
+
+    #[test]
+    fn names() {
+        let table = parse(
+            "use-pid-matching #true\n\"google-chrome\" 10\nr\"raw-name\" 1",
+            "",
+        );
+        assert_eq!(
+            table,
+            toml("use_pid_matching = true\n\"google-chrome\" = 10\n\"raw-name\" = 1")
+        );
+    }
+
+    #[test]
+    fn keywords_v1_and_v2() {
+        let table = parse("a true\nb false\nc #true\nd #false\ne null\nf #null", "");
+        assert_eq!(
+            table,
+            toml("a = true\nb = false\nc = true\nd = false\ne = true\nf = true")
+        );
+        let table = parse("a #inf\nb #-inf\nc inf", "");
+        assert_eq!(table["a"].as_float(), Some(f64::INFINITY));
+        assert_eq!(table["b"].as_float(), Some(f64::NEG_INFINITY));
+        assert_eq!(table["c"].as_str(), Some("inf"));
+        assert!(parse("a #nan", "")["a"].as_float().unwrap().is_nan());
+    }
+
+    #[test]
+    fn numbers() {
+        let table = parse(
+            "a 10\nb -3\nc +4\nd 1_000\ne 1.5\nf -2.5e3\ng 0xff\nh 0o17\ni 0b101\nj -0x10\nk 1.0.0",
+            "",
+        );
+        assert_eq!(
+            table,
+            toml(
+                "a = 10\nb = -3\nc = 4\nd = 1000\ne = 1.5\nf = -2500.0\ng = 255\nh = 15\ni = 5\nj = -16\nk = \"1.0.0\""
+            )
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let table = parse(
+            r####"a "tab\there \"quoted\" \u{1F600}"
+b "line \
+   continued"
+c r"C:\path"
+d r#"say "hi""#
+e #"v2 "raw""#
+f ##"a "# b"##
+g (type)"annotated""####,
+            "",
+        );
+        assert_eq!(table["a"].as_str(), Some("tab\there \"quoted\" \u{1F600}"));
+        assert_eq!(table["b"].as_str(), Some("line continued"));
+        assert_eq!(table["c"].as_str(), Some("C:\\path"));
+        assert_eq!(table["d"].as_str(), Some("say \"hi\""));
+        assert_eq!(table["e"].as_str(), Some("v2 \"raw\""));
+        assert_eq!(table["f"].as_str(), Some("a \"# b"));
+        assert_eq!(table["g"].as_str(), Some("annotated"));
+    }
+
+    #[test]
+    fn comments_and_slashdash() {
+        let table = parse(
+            "// line\na 1 /* inline /* nested */ */ 2\n/-b 3\nc /-4 5 /-x=1\ne \\\n  6\nf { /-g 1; h 2 } /-{ i 3 }",
+            "",
+        );
+        assert_eq!(table, toml("a = [1, 2]\nc = 5\ne = 6\n[f]\nh = 2"));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |content| to_table(content, &toml::Table::new()).unwrap_err().to_string();
+        assert_eq!(
+            error("a {\n  b 1\n"),
+            "KDL parse error at line 3, column 1: missing '}'"
+        );
+        assert_eq!(
+            error("a 1 }"),
+            "KDL parse error at line 1, column 5: unexpected '}'"
+        );
+        assert!(error("a \"open").contains("unterminated string"));
+        assert!(error("a r#\"open\"").contains("unterminated raw string"));
+        assert!(error("a /* open").contains("unterminated comment"));
+        assert!(error("a \"\\q\"").contains("unknown escape"));
+        assert!(error("a \\ 1").contains("expected a line break"));
+    }
+}
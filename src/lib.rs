@@ -1,7 +1,15 @@
 pub mod commands;
 pub mod config;
 pub mod daemon;
+pub mod events;
 pub mod ipc;
+pub mod journal;
+pub mod kdl;
+pub mod log_buffer;
+pub mod logging;
+pub mod metrics;
+pub mod migrate;
 pub mod niri;
 pub mod plugins;
+pub mod scheduler;
 pub mod utils;
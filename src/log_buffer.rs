@@ -0,0 +1,79 @@
+use env_logger::fmt::Formatter;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+use tokio::sync::broadcast;
+
+/// Number of log lines kept for `piri logs`
+const CAPACITY: usize = 1000;
+
+/// The daemon's most recent log lines, and the lines logged since for `piri logs --follow`
+struct LogBuffer {
+    lines: Mutex<VecDeque<String>>,
+    tx: broadcast::Sender<String>,
+}
+
+static BUFFER: OnceLock<LogBuffer> = OnceLock::new();
+
+fn buffer() -> &'static LogBuffer {
+    BUFFER.get_or_init(|| LogBuffer {
+        lines: Mutex::new(VecDeque::with_capacity(CAPACITY)),
+        tx: broadcast::channel(CAPACITY).0,
+    })
+}
+
+fn push(line: String) {
+    let buffer = buffer();
+    let mut lines = buffer.lines.lock().unwrap();
+    if lines.len() == CAPACITY {
+        lines.pop_front();
+    }
+    // Sent while holding the lock so that subscribers see every line exactly once
+    let _ = buffer.tx.send(line.clone());
+    lines.push_back(line);
+}
+
+/// Keep a record in the buffer as a text line, whichever format the daemon writes
+pub fn keep(timestamp: &dyn fmt::Display, record: &log::Record) {
+    let trace = crate::ipc::trace_id().map(|id| format!("[#{}] ", id)).unwrap_or_default();
+    push(format!(
+        "[{} {:<5} {}] {}{}",
+        timestamp,
+        record.level(),
+        record.target(),
+        trace,
+        record.args()
+    ));
+}
+
+/// env_logger format that looks like the default one and also keeps the line in the buffer.
+/// Lines logged while handling an IPC request start with its trace ID
+pub fn format(buf: &mut Formatter, record: &log::Record) -> std::io::Result<()> {
+    let timestamp = buf.timestamp();
+    keep(&timestamp, record);
+    let trace = crate::ipc::trace_id().map(|id| format!("[#{}] ", id)).unwrap_or_default();
+    let style = buf.default_level_style(record.level());
+    writeln!(
+        buf,
+        "[{} {style}{:<5}{style:#} {}] {}{}",
+        timestamp,
+        record.level(),
+        record.target(),
+        trace,
+        record.args()
+    )
+}
+
+/// The buffered lines, oldest first
+pub fn lines() -> Vec<String> {
+    let lines = buffer().lines.lock().unwrap();
+    lines.iter().cloned().collect()
+}
+
+/// The buffered lines, and a receiver for the lines logged after them
+pub fn subscribe() -> (Vec<String>, broadcast::Receiver<String>) {
+    let buffer = buffer();
+    let lines = buffer.lines.lock().unwrap();
+    (lines.iter().cloned().collect(), buffer.tx.subscribe())
+}
@@ -0,0 +1,240 @@
+use anyhow::{Context, Result};
+use env_logger::fmt::Formatter;
+use env_logger::{Builder, Logger, Target, DEFAULT_FILTER_ENV, DEFAULT_WRITE_STYLE_ENV};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::json;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock, RwLock};
+
+use crate::config::{LogFormat, LogSection};
+
+/// How the process asked for logging at startup, kept to rebuild the logger with
+struct Settings {
+    /// `--debug` was given
+    debug: bool,
+    /// Keep recent lines for `piri logs`
+    buffered: bool,
+    /// `--log-format`, which takes precedence over the config file's
+    format: Option<LogFormat>,
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+static LOGGER: OnceLock<RwLock<Logger>> = OnceLock::new();
+/// `[piri.log]` as last applied, to rebuild the logger with when the level changes
+static SECTION: Mutex<Option<LogSection>> = Mutex::new(None);
+/// Level set at runtime with `piri log-level` or SIGUSR1, until reset
+static LEVEL: Mutex<Option<LevelFilter>> = Mutex::new(None);
+
+/// Forwards to the current env_logger, which `apply` swaps out
+struct ReloadableLogger;
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        LOGGER.get().is_some_and(|logger| logger.read().unwrap().enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if let Some(logger) = LOGGER.get() {
+            logger.read().unwrap().log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(logger) = LOGGER.get() {
+            logger.read().unwrap().flush();
+        }
+    }
+}
+
+/// `[piri.log] file`, renamed to `<file>.1` when it would grow past `max_size`, the older
+/// ones shifting up to `<file>.<keep>`
+struct LogFile {
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(path: PathBuf, section: &LogSection) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_size: section.max_size.0,
+            keep: section.keep,
+            file,
+            size,
+        })
+    }
+
+    /// `<file>.<n>`
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        // The oldest one is overwritten
+        for n in (1..self.keep).rev() {
+            let _ = fs::rename(self.rotated(n), self.rotated(n + 1));
+        }
+        if self.keep > 0 {
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Writes log lines to stderr, as without a log file, and to the log file
+struct Tee(LogFile);
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let _ = io::stderr().write_all(buf);
+        self.0.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+fn build(settings: &Settings, section: &LogSection, file: Option<LogFile>) -> Logger {
+    let mut builder = Builder::new();
+    if let Ok(style) = std::env::var(DEFAULT_WRITE_STYLE_ENV) {
+        builder.parse_write_style(&style);
+    }
+    match (*LEVEL.lock().unwrap(), std::env::var(DEFAULT_FILTER_ENV)) {
+        // Set at runtime, over every other level
+        (Some(level), _) => {
+            builder.filter_level(level);
+        }
+        // RUST_LOG replaces the config file's levels, as it does the default one
+        (None, Ok(filters)) => {
+            builder.parse_filters(&filters);
+        }
+        (None, Err(_)) => {
+            let level = match (settings.debug, section.level) {
+                (true, _) => LevelFilter::Debug,
+                (false, Some(level)) => level.0,
+                (false, None) => LevelFilter::Info,
+            };
+            builder.filter_level(level);
+            for filter in &section.filters {
+                builder.filter_module(&filter.module, filter.level.0);
+            }
+        }
+    }
+    match settings.format.or(section.format).unwrap_or_default() {
+        LogFormat::Json if settings.buffered => {
+            builder.format(|buf, record| {
+                crate::log_buffer::keep(&buf.timestamp(), record);
+                format_json(buf, record)
+            });
+        }
+        LogFormat::Json => {
+            builder.format(format_json);
+        }
+        LogFormat::Text if settings.buffered => {
+            builder.format(crate::log_buffer::format);
+        }
+        LogFormat::Text => {}
+    }
+    if let Some(file) = file {
+        builder.target(Target::Pipe(Box::new(Tee(file))));
+    }
+    builder.build()
+}
+
+/// One JSON object per line, for journald and log processors. Records logged while
+/// handling an IPC request also have its trace ID
+fn format_json(buf: &mut Formatter, record: &Record) -> std::io::Result<()> {
+    let mut line = json!({
+        "timestamp": buf.timestamp().to_string(),
+        "level": record.level().as_str(),
+        "module": record.target(),
+        "message": record.args().to_string(),
+    });
+    if let Some(id) = crate::ipc::trace_id() {
+        line["trace"] = json!(id);
+    }
+    writeln!(buf, "{}", line)
+}
+
+/// Install the logger. Until `apply` is called it logs at info level, or debug with `--debug`,
+/// unless `RUST_LOG` says otherwise
+pub fn init(debug: bool, buffered: bool, format: Option<LogFormat>) {
+    let settings = SETTINGS.get_or_init(|| Settings {
+        debug,
+        buffered,
+        format,
+    });
+    let logger = build(settings, &LogSection::default(), None);
+    log::set_max_level(logger.filter());
+    if LOGGER.set(RwLock::new(logger)).is_ok() {
+        let _ = log::set_logger(&ReloadableLogger);
+    }
+}
+
+/// Switch to the levels, format and log file of `[piri.log]`. A log file that can't be
+/// opened is an error, and the daemon then logs to stderr only
+pub fn apply(section: &LogSection) -> Result<()> {
+    let (Some(settings), Some(current)) = (SETTINGS.get(), LOGGER.get()) else {
+        return Ok(());
+    };
+    let file = section.file().map(|path| {
+        LogFile::open(path.clone(), section)
+            .with_context(|| format!("Failed to open log file {:?}", path))
+    });
+    let (file, result) = match file.transpose() {
+        Ok(file) => (file, Ok(())),
+        Err(e) => (None, Err(e)),
+    };
+    let logger = build(settings, section, file);
+    log::set_max_level(logger.filter());
+    *current.write().unwrap() = logger;
+    *SECTION.lock().unwrap() = Some(section.clone());
+    result
+}
+
+/// Log every module at `level` from now on, over `RUST_LOG`, `--debug` and `[piri.log]`, or
+/// go back to those with None. Lasts until the daemon stops, reloads included
+pub fn set_level(level: Option<LevelFilter>) -> Result<()> {
+    *LEVEL.lock().unwrap() = level;
+    let section = SECTION.lock().unwrap().clone().unwrap_or_default();
+    apply(&section)
+}
+
+/// Switch between debug logging and the usual levels, for SIGUSR1. Returns whether debug
+/// logging is now on
+pub fn toggle_debug() -> Result<bool> {
+    let debug = LEVEL.lock().unwrap().is_none();
+    set_level(debug.then_some(LevelFilter::Debug))?;
+    Ok(debug)
+}
@@ -1,21 +1,41 @@
-use anyhow::Result;
-use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use anyhow::{Context, Result};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use clap_complete::{generate, shells};
 use log::info;
-use std::io;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
 
 mod commands;
 mod config;
 mod daemon;
+mod docs;
+mod events;
 mod ipc;
+mod journal;
+mod kdl;
+mod log_buffer;
+mod logging;
+mod metrics;
+mod migrate;
 mod niri;
 mod plugins;
+mod repl;
+mod scheduler;
+mod simulate;
 mod utils;
 
-use commands::CommandHandler;
-use config::Config;
-use ipc::{IpcClient, IpcRequest, IpcResponse};
+use commands::{CommandHandler, DaemonStatus, PingInfo, VersionInfo, WindowInfo, WorkspaceInfo};
+use config::{Config, Direction, FocusScope, LogFormat, LogLevel};
+use ipc::{IpcClient, IpcRequest, IpcResponse, WindowAction};
+use plugins::groups::GroupInfo;
+use plugins::marks::MarkInfo;
+use plugins::singleton::SingletonStatus;
+use plugins::stash::StashedWindowInfo;
 use utils::send_notification;
 
 #[derive(Parser)]
@@ -32,12 +52,49 @@ struct Cli {
     /// Enable debug logging
     #[arg(short, long)]
     debug: bool,
+
+    /// Write log lines as text or as JSON objects, overriding [piri.log] format
+    #[arg(long, value_name = "FORMAT", value_parser = PossibleValuesParser::new(LogFormat::NAMES).try_map(|s| s.parse::<LogFormat>()))]
+    log_format: Option<LogFormat>,
+
+    /// Print results, returned data and errors as JSON on stdout
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Seconds to wait for the daemon to answer a command, and how long `piri wait` waits
+    /// [default: 5]
+    #[arg(long, global = true, env = "PIRI_TIMEOUT", value_name = "SECONDS", value_parser = parse_timeout)]
+    timeout: Option<Duration>,
+
+    /// Connect to the daemon's TCP listener at HOST:PORT instead of its socket, with the
+    /// token from PIRI_TOKEN
+    #[arg(long, global = true, env = "PIRI_ADDRESS", value_name = "HOST:PORT")]
+    address: Option<String>,
+}
+
+fn parse_timeout(value: &str) -> std::result::Result<Duration, String> {
+    // "5", "5s", "500ms" and "1m30s" all work
+    let timeout = match value.parse::<f64>() {
+        Ok(secs) => Duration::try_from_secs_f64(secs).map_err(|e| e.to_string())?,
+        Err(_) => value.parse::<config::Timespan>().map_err(|e| e.to_string())?.0,
+    };
+    if timeout.is_zero() {
+        return Err(format!("'{}' is not a positive duration", value));
+    }
+    Ok(timeout)
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Start piri as a daemon
-    Daemon,
+    Daemon {
+        /// Run in the background, in a session of its own, once its socket accepts commands
+        #[arg(long)]
+        detach: bool,
+        /// Run attached to the terminal, with the log on stderr (the default)
+        #[arg(long, conflicts_with = "detach")]
+        foreground: bool,
+    },
     /// Scratchpads management
     Scratchpads {
         /// Scratchpad name
@@ -47,12 +104,14 @@ enum Commands {
         action: ScratchpadAction,
     },
     /// Singleton management
+    #[command(subcommand_negates_reqs = true)]
     Singleton {
         /// Singleton name
-        name: String,
+        #[arg(required = true)]
+        name: Option<String>,
         /// Action to perform
         #[command(subcommand)]
-        action: SingletonAction,
+        action: Option<SingletonAction>,
     },
     /// Window order management
     WindowOrder {
@@ -60,14 +119,251 @@ enum Commands {
         #[command(subcommand)]
         action: WindowOrderAction,
     },
+    /// Autofill management
+    Autofill {
+        /// Action to perform
+        #[command(subcommand)]
+        action: AutofillAction,
+    },
+    /// Focus history (alt-tab style switching)
+    Focus {
+        /// Action to perform
+        #[command(subcommand)]
+        action: FocusAction,
+    },
+    /// Window marks (vim-style)
+    Mark {
+        /// Action to perform
+        #[command(subcommand)]
+        action: MarkAction,
+    },
+    /// Save and restore window layouts
+    Session {
+        /// Action to perform
+        #[command(subcommand)]
+        action: SessionAction,
+    },
+    /// Window groups, shown one at a time like tabs
+    Group {
+        /// Action to perform
+        #[command(subcommand)]
+        action: GroupAction,
+    },
+    /// Rotate, swap and reverse columns
+    Column {
+        /// Action to perform
+        #[command(subcommand)]
+        action: ColumnAction,
+    },
+    /// Temporary exceptions to workspace pins
+    Pin {
+        /// Action to perform
+        #[command(subcommand)]
+        action: PinAction,
+    },
+    /// Do-not-disturb mode
+    Dnd {
+        /// Action to perform
+        #[command(subcommand)]
+        action: DndAction,
+    },
+    /// Move the focused window to the stash workspace, emulating minimize
+    Stash {
+        #[command(subcommand)]
+        action: Option<StashAction>,
+    },
+    /// Bring back a stashed window to where it was stashed from
+    Unstash {
+        /// Window ID (default: the most recently stashed window)
+        window_id: Option<u64>,
+        /// Bring the window to the focused workspace instead
+        #[arg(long)]
+        here: bool,
+    },
+    /// Send the focused window to a workspace (name, index or part of a name) or output
+    Sendto {
+        /// Workspace or output
+        target: String,
+        /// Only match outputs
+        #[arg(long)]
+        output: bool,
+        /// Focus the window at its new place
+        #[arg(short, long)]
+        follow: bool,
+    },
+    /// Presentation mode: move a presentation's windows to a clean workspace
+    Present {
+        /// Presentation name, as configured in [present.<name>]
+        #[arg(required_unless_present = "stop")]
+        name: Option<String>,
+        /// Stop presenting and put the windows back
+        #[arg(long, conflicts_with = "name")]
+        stop: bool,
+    },
+    /// List windows for a picker (fuzzel/rofi/dmenu), or focus the picked one
+    Pick {
+        /// Read the picked line from stdin and focus its window
+        #[arg(long, conflicts_with = "bring")]
+        focus: bool,
+        /// Read the picked line from stdin and move its window to the focused workspace
+        #[arg(long)]
+        bring: bool,
+    },
+    /// Focus, move or close windows matched by app_id or title regex
+    Window {
+        #[command(subcommand)]
+        action: WindowCommand,
+    },
+    /// Switch workspaces by name, index or part of a name
+    Workspace {
+        #[command(subcommand)]
+        action: WorkspaceAction,
+    },
+    /// Print status as JSON lines for status bars (e.g. a waybar custom module)
+    Bar,
+    /// Print niri and piri events as they happen, one per line
+    Events {
+        /// Only print these events (e.g. ScratchpadShown WindowSwallowed)
+        names: Vec<String>,
+    },
+    /// Read or change the running daemon's config
+    Config {
+        /// Action to perform
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Switch between the `[profiles.<name>]` overlays of the config file
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+    /// Reload the config file and reinitialize plugins
+    Reload {
+        /// Only apply the config to this plugin, keeping the others as they are
+        #[arg(long, value_parser = PossibleValuesParser::new(plugins::PLUGIN_NAMES))]
+        plugin: Option<String>,
+    },
+    /// Print the daemon's recent log lines
+    Logs {
+        /// Keep printing new lines as they are logged
+        #[arg(short, long)]
+        follow: bool,
+    },
+    /// Change the daemon's log level until it stops, e.g. to capture debug lines of a bug
+    /// as it happens
+    LogLevel {
+        /// Level to log every module at, over RUST_LOG, --debug and [piri.log]
+        #[arg(
+            required_unless_present = "reset",
+            value_parser = PossibleValuesParser::new(LogLevel::NAMES).try_map(|s| s.parse::<LogLevel>())
+        )]
+        level: Option<LogLevel>,
+        /// Go back to the levels the daemon was started with
+        #[arg(long, conflicts_with = "level")]
+        reset: bool,
+    },
+    /// Show an overview of the daemon: uptime, config, plugins and niri connection
+    Status,
+    /// Run piri commands read from a file ("-" for stdin), one per line, over a single
+    /// connection
+    Batch {
+        /// File with one command per line, without the leading `piri`
+        file: PathBuf,
+    },
+    /// Block until a scratchpad is shown or hidden, or a matching window appears, for at
+    /// most --timeout
+    Wait(WaitArgs),
+    /// Check that the daemon and niri answer, and how fast
+    Ping {
+        /// Fail with exit code 7 when niri takes longer than this to answer
+        #[arg(long, value_name = "MS")]
+        max_ms: Option<f64>,
+    },
+    /// Type commands interactively, with completion and history, over a single connection
+    Repl,
+    /// Print the client's and the daemon's versions, warning if they differ
+    Version,
+    /// Print the daemon's counters and latencies in the Prometheus text format
+    Metrics,
     /// Stop the daemon
-    Stop,
+    Stop {
+        /// If the daemon doesn't answer, terminate the process from its pidfile and remove
+        /// the stale socket
+        #[arg(long)]
+        force: bool,
+    },
+    /// Restart the daemon, e.g. after upgrading piri, keeping the windows of scratchpads,
+    /// singletons, groups and the stash
+    Restart,
     /// Generate shell completion script
     Completion {
         /// Shell type
         #[arg(value_enum)]
         shell: Shell,
     },
+    /// Check the config file for mistakes without starting the daemon
+    Check {
+        /// Feed a synthetic event through the plugin rules and report which would fire,
+        /// e.g. 'open app_id=mpv parent=foot' (keys: app_id, title, parent, parent_title,
+        /// workspace, output; events: open, focus)
+        #[arg(long, value_name = "EVENT")]
+        simulate: Option<String>,
+    },
+    /// Generate man pages or a markdown reference of all commands
+    GenDocs {
+        #[command(subcommand)]
+        format: DocsFormat,
+    },
+}
+
+#[derive(Subcommand)]
+enum DocsFormat {
+    /// Write a man page for piri and each subcommand into a directory
+    Man {
+        /// Output directory
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Print a markdown reference of all commands
+    Markdown,
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = true)]
+struct WaitArgs {
+    /// Scratchpad to wait for
+    #[arg(long, conflicts_with_all = ["window_app_id", "window_title"])]
+    scratchpad: Option<String>,
+    /// State the scratchpad must reach
+    #[arg(long, value_enum, default_value = "visible", requires = "scratchpad")]
+    state: ScratchpadState,
+    /// Regex matched against the app_id of new windows
+    #[arg(long)]
+    window_app_id: Option<String>,
+    /// Regex matched against the title of new windows
+    #[arg(long)]
+    window_title: Option<String>,
+}
+
+impl WaitArgs {
+    fn condition(self) -> ipc::WaitCondition {
+        match self.scratchpad {
+            Some(name) => ipc::WaitCondition::Scratchpad {
+                name,
+                visible: matches!(self.state, ScratchpadState::Visible),
+            },
+            None => ipc::WaitCondition::Window {
+                app_id: self.window_app_id,
+                title: self.window_title,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ScratchpadState {
+    Visible,
+    Hidden,
 }
 
 #[derive(Subcommand)]
@@ -76,8 +372,9 @@ enum ScratchpadAction {
     Toggle,
     /// Add current focused window as scratchpad
     Add {
-        /// Direction from which the scratchpad appears (e.g., "fromTop", "fromBottom", "fromLeft", "fromRight")
-        direction: String,
+        /// Direction from which the scratchpad appears
+        #[arg(value_parser = PossibleValuesParser::new(Direction::NAMES).try_map(|s| s.parse::<Direction>()))]
+        direction: Direction,
         /// If true, swallow the scratchpad window to the focused window when shown
         #[arg(long)]
         swallow_to_focus: bool,
@@ -88,6 +385,8 @@ enum ScratchpadAction {
 enum SingletonAction {
     /// Toggle singleton (focus if exists, launch if not)
     Toggle,
+    /// List configured singletons and whether they are running
+    List,
 }
 
 #[derive(Subcommand)]
@@ -96,6 +395,265 @@ enum WindowOrderAction {
     Toggle,
 }
 
+#[derive(Subcommand)]
+enum AutofillAction {
+    /// Pause or resume autofill alignment
+    Toggle,
+}
+
+#[derive(Subcommand)]
+enum FocusAction {
+    /// Focus the previously focused window
+    Last(FocusScopeArgs),
+    /// Step back to older windows in the focus history
+    Prev(FocusScopeArgs),
+    /// Step forward to newer windows in the focus history
+    Next(FocusScopeArgs),
+}
+
+#[derive(Subcommand)]
+enum MarkAction {
+    /// Mark the focused window
+    Set {
+        /// Mark name (a single letter or digit)
+        name: String,
+    },
+    /// Focus the marked window, switching workspace if needed
+    Goto {
+        /// Mark name
+        name: String,
+    },
+    /// List marks and their windows
+    List,
+}
+
+#[derive(Subcommand)]
+enum ColumnAction {
+    /// Rotate the windows of the focused column by one, the bottom window wrapping to the top
+    Rotate {
+        /// Rotate up instead, the top window wrapping to the bottom
+        #[arg(long)]
+        backward: bool,
+    },
+    /// Swap the focused window with the master (leftmost) column
+    SwapMaster,
+    /// Reverse the column order of the focused workspace
+    Reverse,
+}
+
+#[derive(Subcommand)]
+enum PinAction {
+    /// Let a pinned workspace stay on any output until it is restored
+    Override {
+        /// Workspace name
+        workspace: String,
+        /// Move the workspace to this output right away
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Move an overridden workspace back to its pinned output
+    Restore {
+        /// Workspace name (default: all overridden workspaces)
+        workspace: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Write a commented config file to start from, at the path given by `--config`
+    Init {
+        /// Overwrite an existing file
+        #[arg(long)]
+        force: bool,
+        /// Only the common settings instead of all options of all plugins
+        #[arg(long)]
+        minimal: bool,
+    },
+    /// Rewrite deprecated settings of the config file in the current format, printing the
+    /// changes before saving them
+    Migrate {
+        /// Only print the changes
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print a config value, e.g. `piri.scratchpad.default_margin`
+    Get {
+        /// Dotted key path
+        path: String,
+    },
+    /// Print the whole running config, with defaults filled in and the scratchpads added
+    /// at runtime
+    Show,
+    /// Change a config value until the next reload; values are parsed as TOML, anything
+    /// else is taken as a string
+    Set {
+        /// Dotted key path
+        path: String,
+        /// New value
+        value: String,
+        /// Also save the value to the config file
+        #[arg(long)]
+        write: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProfileAction {
+    /// Apply a profile over the config file and reload
+    Set {
+        /// Profile name, as in `[profiles.<name>]`
+        name: String,
+    },
+    /// Go back to the config file without a profile
+    Clear,
+    /// Print the active profile
+    Get,
+}
+
+#[derive(Subcommand)]
+enum DndAction {
+    /// Turn do-not-disturb on if it is off, off otherwise
+    Toggle,
+    /// Turn do-not-disturb on
+    On,
+    /// Turn do-not-disturb off
+    Off,
+    /// Print whether do-not-disturb is on
+    Status,
+}
+
+#[derive(Subcommand)]
+enum StashAction {
+    /// List stashed windows, most recent first
+    List,
+}
+
+#[derive(Subcommand)]
+enum GroupAction {
+    /// Add the focused window to a group, creating the group if needed
+    Add {
+        /// Group name
+        name: String,
+    },
+    /// Take the focused window out of its group
+    Remove,
+    /// Show the next window of a group
+    Next {
+        /// Group name (default: the focused window's group)
+        name: Option<String>,
+    },
+    /// Show the previous window of a group
+    Prev {
+        /// Group name (default: the focused window's group)
+        name: Option<String>,
+    },
+    /// List groups and their windows
+    List,
+}
+
+#[derive(Subcommand)]
+enum SessionAction {
+    /// Save the current window layout
+    Save {
+        /// Session name (default: the unnamed session)
+        name: Option<String>,
+    },
+    /// Relaunch and arrange the windows of a saved session
+    Restore {
+        /// Session name (default: the unnamed session)
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum WorkspaceAction {
+    /// Focus a workspace: exact name, then index, then a case-insensitive name or part of it
+    Focus { query: String },
+    /// List workspaces, one name (or index) per line, for pickers
+    List,
+}
+
+#[derive(Subcommand)]
+enum WindowCommand {
+    /// Focus the first matching window, preferring the focused workspace
+    Focus {
+        #[command(flatten)]
+        matcher: WindowMatchArgs,
+    },
+    /// Move the first matching window to a workspace (name, index or part of a name)
+    Move {
+        workspace: String,
+        #[command(flatten)]
+        matcher: WindowMatchArgs,
+        /// Move all matching windows
+        #[arg(long)]
+        all: bool,
+    },
+    /// Close the first matching window
+    Close {
+        #[command(flatten)]
+        matcher: WindowMatchArgs,
+        /// Close all matching windows
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+impl WindowCommand {
+    fn into_request(self) -> IpcRequest {
+        let (action, matcher, all) = match self {
+            WindowCommand::Focus { matcher } => (WindowAction::Focus, matcher, false),
+            WindowCommand::Move {
+                workspace,
+                matcher,
+                all,
+            } => (WindowAction::Move { workspace }, matcher, all),
+            WindowCommand::Close { matcher, all } => (WindowAction::Close, matcher, all),
+        };
+        IpcRequest::WindowAction {
+            action,
+            app_id: matcher.app_id,
+            title: matcher.title,
+            all,
+        }
+    }
+}
+
+/// A window matches if either regex does
+#[derive(Args)]
+#[group(required = true, multiple = true)]
+struct WindowMatchArgs {
+    /// Regex matched against the app_id
+    #[arg(long)]
+    app_id: Option<String>,
+    /// Regex matched against the title
+    #[arg(long)]
+    title: Option<String>,
+}
+
+#[derive(Args)]
+struct FocusScopeArgs {
+    /// Only consider windows on the focused workspace
+    #[arg(long, conflicts_with = "global")]
+    workspace: bool,
+    /// Consider windows on all workspaces
+    #[arg(long)]
+    global: bool,
+}
+
+impl FocusScopeArgs {
+    /// Scope requested on the command line (None = use the configured default)
+    fn scope(&self) -> Option<FocusScope> {
+        if self.workspace {
+            Some(FocusScope::Workspace)
+        } else if self.global {
+            Some(FocusScope::Global)
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Clone, ValueEnum)]
 enum Shell {
     /// Bash completion script
@@ -128,36 +686,137 @@ fn main() -> Result<()> {
         original_hook(panic_info);
     }));
 
-    let rt = create_runtime();
-    let result = rt.block_on(async_main());
+    let cli = Cli::parse_from(expand_alias(std::env::args().collect()));
+    let json = cli.json;
 
-    // Shutdown the runtime to ensure all tasks are dropped
-    rt.shutdown_background();
+    let result = match cli.command {
+        // Forked before the runtime starts its threads
+        Commands::Daemon {
+            detach: true,
+            foreground: false,
+        } => match daemon::detach() {
+            Ok(Some(daemon)) => {
+                let waiting = if daemon.waiting_for_niri {
+                    ", waiting for niri"
+                } else {
+                    ""
+                };
+                handle_ipc_response(
+                    Ok(IpcResponse::Success),
+                    &format!("Daemon started (PID {}){}", daemon.pid, waiting),
+                    "",
+                    json,
+                )
+            }
+            Ok(None) => run(cli),
+            Err(e) => Err(e),
+        },
+        _ => run(cli),
+    };
 
     if let Err(e) = result {
-        eprintln!("Error in main: {}", e);
-        eprintln!("Error chain: {:?}", e);
-        std::process::exit(1);
+        daemon::notify_failed(&e);
+        let kind = ipc::ErrorKind::of(&e);
+        let trace_id = e.downcast_ref::<ipc::IpcError>().and_then(|e| e.trace_id);
+        if json {
+            println!(
+                "{}",
+                serde_json::json!({
+                    "success": false,
+                    "error": format!("{:#}", e),
+                    "kind": kind,
+                    "trace_id": trace_id,
+                })
+            );
+            std::process::exit(kind.exit_code());
+        }
+        // Errors with a known cause are expected, they don't need the whole chain
+        if kind == ipc::ErrorKind::Other {
+            eprintln!("Error in main: {}", e);
+            eprintln!("Error chain: {:?}", e);
+        } else {
+            eprintln!("Error: {:#}", e);
+        }
+        if let Some(hint) = kind.hint() {
+            eprintln!("Hint: {}", hint);
+        }
+        if let Some(id) = trace_id {
+            eprintln!(
+                "The daemon logged this request as #{} (see `piri logs`)",
+                id
+            );
+        }
+        std::process::exit(kind.exit_code());
     }
     Ok(())
 }
 
-async fn async_main() -> Result<()> {
-    let cli = Cli::parse();
+fn run(cli: Cli) -> Result<()> {
+    let rt = create_runtime();
+    let result = rt.block_on(async_main(cli));
+
+    // Shutdown the runtime to ensure all tasks are dropped
+    rt.shutdown_background();
+    result
+}
+
+async fn async_main(cli: Cli) -> Result<()> {
+    let json = cli.json;
+
+    // Initialize logger, keeping recent lines for `piri logs` in the daemon
+    logging::init(
+        cli.debug,
+        matches!(cli.command, Commands::Daemon { .. }),
+        cli.log_format,
+    );
 
-    // Initialize logger
-    let log_level = if cli.debug { "debug" } else { "info" };
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level)).init();
+    // Clients find the daemon through the socket path configured for it
+    if !matches!(
+        cli.command,
+        Commands::Daemon { .. }
+            | Commands::Check { .. }
+            | Commands::Completion { .. }
+            | Commands::GenDocs { .. }
+    ) {
+        let socket_path = load_config_if_exists(&cli.config)
+            .ok()
+            .and_then(|config| config.piri.socket.path());
+        if let Some(path) = socket_path {
+            ipc::set_socket_path(path);
+        }
+        if let Some(timeout) = cli.timeout {
+            ipc::set_timeout(timeout);
+        }
+        if let Some(address) = cli.address.clone() {
+            let token = std::env::var("PIRI_TOKEN").with_context(|| {
+                format!(
+                    "PIRI_TOKEN must be set to the [piri.tcp] token to connect to {}",
+                    address
+                )
+            })?;
+            ipc::set_tcp_endpoint(ipc::TcpEndpoint { address, token });
+        }
+    }
 
     match cli.command {
-        Commands::Daemon => {
+        Commands::Daemon { .. } => {
             // Only load config when starting daemon
-            let config_path = shellexpand::full(&cli.config)
-                .map(|s| PathBuf::from(s.as_ref()))
-                .unwrap_or_else(|_| PathBuf::from(&cli.config));
+            let config_path = expand_config_path(&cli.config);
 
             let config = Config::load(&config_path)?;
+            if let Err(e) = logging::apply(&config.piri.log) {
+                log::warn!("{:#}", e);
+            }
+            plugins::window_utils::set_command_shell(config.piri.command_shell);
             info!("Loaded configuration from {:?}", config_path);
+            for warning in
+                config.profile_warnings(&config_path).into_iter().chain(config.host_warnings())
+            {
+                log::warn!("{}", warning);
+            }
+            for notice in config.deprecations(&config_path) {
+                log::warn!("{} (`piri config migrate` rewrites it)", notice);
+            }
 
             let handler = CommandHandler::with_config_path(config, config_path);
 
@@ -179,6 +838,7 @@ async fn async_main() -> Result<()> {
                             .await,
                         &format!("Scratchpad '{}' toggled", name),
                         "Failed to toggle scratchpad",
+                        json,
                     )?;
                 }
                 ScratchpadAction::Add {
@@ -189,26 +849,34 @@ async fn async_main() -> Result<()> {
                         client
                             .send_request(IpcRequest::ScratchpadAdd {
                                 name: name.clone(),
-                                direction: direction.clone(),
+                                direction,
                                 swallow_to_focus,
                             })
                             .await,
                         &format!("Scratchpad '{}' added with direction '{}'", name, direction),
                         "Failed to add scratchpad",
+                        json,
                     )?;
                 }
             }
         }
         Commands::Singleton { name, action } => {
             let client = IpcClient::new(None);
-            match action {
-                SingletonAction::Toggle => {
+            match (action.unwrap_or(SingletonAction::Toggle), name) {
+                (SingletonAction::List, _) => {
+                    list_singletons(&client, &cli.config, json).await?;
+                }
+                (SingletonAction::Toggle, None) => {
+                    anyhow::bail!("Singleton name is required for toggle");
+                }
+                (SingletonAction::Toggle, Some(name)) => {
                     handle_ipc_response(
                         client
                             .send_request(IpcRequest::SingletonToggle { name: name.clone() })
                             .await,
                         &format!("Singleton '{}' toggled", name),
                         "Failed to toggle singleton",
+                        json,
                     )?;
                 }
             }
@@ -221,51 +889,1592 @@ async fn async_main() -> Result<()> {
                         client.send_request(IpcRequest::WindowOrderToggle).await,
                         "Window order toggled",
                         "Failed to toggle window order",
+                        json,
                     )?;
                 }
             }
         }
-        Commands::Stop => {
+        Commands::Autofill { action } => {
             let client = IpcClient::new(None);
+            match action {
+                AutofillAction::Toggle => {
+                    handle_ipc_response(
+                        client.send_request(IpcRequest::AutofillToggle).await,
+                        "Autofill toggled",
+                        "Failed to toggle autofill",
+                        json,
+                    )?;
+                }
+            }
+        }
+        Commands::Focus { action } => {
+            let client = IpcClient::new(None);
+            let request = match action {
+                FocusAction::Last(args) => IpcRequest::FocusLast {
+                    scope: args.scope(),
+                },
+                FocusAction::Prev(args) => IpcRequest::FocusPrev {
+                    scope: args.scope(),
+                },
+                FocusAction::Next(args) => IpcRequest::FocusNext {
+                    scope: args.scope(),
+                },
+            };
             handle_ipc_response(
-                client.send_request(IpcRequest::Shutdown).await,
-                "Daemon stopped",
-                "Failed to stop daemon",
+                client.send_request(request).await,
+                "Focus changed",
+                "Failed to change focus",
+                json,
             )?;
         }
-        Commands::Completion { shell } => {
-            let mut cmd = Cli::command();
-            match shell {
-                Shell::Bash => generate(shells::Bash, &mut cmd, "piri", &mut io::stdout()),
-                Shell::Zsh => generate(shells::Zsh, &mut cmd, "piri", &mut io::stdout()),
-                Shell::Fish => generate(shells::Fish, &mut cmd, "piri", &mut io::stdout()),
-                Shell::PowerShell => {
-                    generate(shells::PowerShell, &mut cmd, "piri", &mut io::stdout())
+        Commands::Mark { action } => {
+            let client = IpcClient::new(None);
+            match action {
+                MarkAction::Set { name } => {
+                    handle_ipc_response(
+                        client.send_request(IpcRequest::MarkSet { name: name.clone() }).await,
+                        &format!("Mark '{}' set", name),
+                        "Failed to set mark",
+                        json,
+                    )?;
+                }
+                MarkAction::Goto { name } => {
+                    handle_ipc_response(
+                        client.send_request(IpcRequest::MarkGoto { name: name.clone() }).await,
+                        &format!("Jumped to mark '{}'", name),
+                        "Failed to jump to mark",
+                        json,
+                    )?;
+                }
+                MarkAction::List => {
+                    let marks: Vec<MarkInfo> = match client
+                        .send_request(IpcRequest::MarkList)
+                        .await?
+                    {
+                        IpcResponse::Data(data) => serde_json::from_value(data)?,
+                        IpcResponse::Error(e) => anyhow::bail!(e.prefixed("Failed to list marks")),
+                        _ => anyhow::bail!("Unexpected response to mark list request"),
+                    };
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&marks)?);
+                    } else {
+                        for mark in marks {
+                            println!(
+                                "{}  {:<20}  {}  (window {}, workspace {})",
+                                mark.mark,
+                                mark.app_id.unwrap_or_default(),
+                                mark.title,
+                                mark.window_id,
+                                mark.workspace.unwrap_or_else(|| "-".to_string())
+                            );
+                        }
+                    }
                 }
-                Shell::Elvish => generate(shells::Elvish, &mut cmd, "piri", &mut io::stdout()),
             }
         }
-    }
-
-    Ok(())
-}
-
-fn handle_ipc_response(
-    result: Result<IpcResponse>,
-    success_msg: &str,
-    error_prefix: &str,
-) -> Result<()> {
-    match result {
-        Ok(IpcResponse::Success) => {
-            println!("{}", success_msg);
-            Ok(())
+        Commands::Session { action } => {
+            let client = IpcClient::new(None);
+            match action {
+                SessionAction::Save { name } => {
+                    handle_ipc_response(
+                        client.send_request(IpcRequest::SessionSave { name }).await,
+                        "Session saved",
+                        "Failed to save session",
+                        json,
+                    )?;
+                }
+                SessionAction::Restore { name } => {
+                    handle_ipc_response(
+                        client.send_request(IpcRequest::SessionRestore { name }).await,
+                        "Session restore started",
+                        "Failed to restore session",
+                        json,
+                    )?;
+                }
+            }
         }
-        Ok(IpcResponse::Error(e)) => {
-            send_notification("piri", &e);
-            anyhow::bail!("{}: {}", error_prefix, e);
+        Commands::Group { action } => {
+            let client = IpcClient::new(None);
+            match action {
+                GroupAction::Add { name } => {
+                    handle_ipc_response(
+                        client.send_request(IpcRequest::GroupAdd { name: name.clone() }).await,
+                        &format!("Window added to group '{}'", name),
+                        "Failed to add window to group",
+                        json,
+                    )?;
+                }
+                GroupAction::Remove => {
+                    handle_ipc_response(
+                        client.send_request(IpcRequest::GroupRemove).await,
+                        "Window removed from its group",
+                        "Failed to remove window from group",
+                        json,
+                    )?;
+                }
+                GroupAction::Next { name } => {
+                    handle_ipc_response(
+                        client.send_request(IpcRequest::GroupNext { name }).await,
+                        "Showing next window",
+                        "Failed to cycle group",
+                        json,
+                    )?;
+                }
+                GroupAction::Prev { name } => {
+                    handle_ipc_response(
+                        client.send_request(IpcRequest::GroupPrev { name }).await,
+                        "Showing previous window",
+                        "Failed to cycle group",
+                        json,
+                    )?;
+                }
+                GroupAction::List => {
+                    let groups: Vec<GroupInfo> = match client
+                        .send_request(IpcRequest::GroupList)
+                        .await?
+                    {
+                        IpcResponse::Data(data) => serde_json::from_value(data)?,
+                        IpcResponse::Error(e) => anyhow::bail!(e.prefixed("Failed to list groups")),
+                        _ => anyhow::bail!("Unexpected response to group list request"),
+                    };
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&groups)?);
+                    } else {
+                        for group in groups {
+                            println!("{}", group.name);
+                            for member in group.members {
+                                println!(
+                                    "  {} {:<20}  {}  (window {})",
+                                    if member.window_id == group.active {
+                                        "*"
+                                    } else {
+                                        " "
+                                    },
+                                    member.app_id.unwrap_or_default(),
+                                    member.title,
+                                    member.window_id
+                                );
+                            }
+                        }
+                    }
+                }
+            }
         }
-        Ok(IpcResponse::Pong) => {
-            println!("Pong");
+        Commands::Column { action } => {
+            let client = IpcClient::new(None);
+            let (request, success) = match action {
+                ColumnAction::Rotate { backward } => {
+                    (IpcRequest::ColumnRotate { backward }, "Column rotated")
+                }
+                ColumnAction::SwapMaster => (
+                    IpcRequest::ColumnSwapMaster,
+                    "Swapped with the master column",
+                ),
+                ColumnAction::Reverse => (IpcRequest::ColumnReverse, "Columns reversed"),
+            };
+            handle_ipc_response(
+                client.send_request(request).await,
+                success,
+                "Failed to rearrange columns",
+                json,
+            )?;
+        }
+        Commands::Pin { action } => {
+            let client = IpcClient::new(None);
+            match action {
+                PinAction::Override { workspace, output } => {
+                    handle_ipc_response(
+                        client
+                            .send_request(IpcRequest::WorkspacePinOverride {
+                                workspace: workspace.clone(),
+                                output,
+                            })
+                            .await,
+                        &format!("Pin of workspace '{}' overridden", workspace),
+                        "Failed to override pin",
+                        json,
+                    )?;
+                }
+                PinAction::Restore { workspace } => {
+                    handle_ipc_response(
+                        client.send_request(IpcRequest::WorkspacePinRestore { workspace }).await,
+                        "Pins restored",
+                        "Failed to restore pins",
+                        json,
+                    )?;
+                }
+            }
+        }
+        Commands::Dnd { action } => {
+            let client = IpcClient::new(None);
+            if let DndAction::Status = action {
+                let status = match client.send_request(IpcRequest::DndStatus).await? {
+                    IpcResponse::Data(data) => data,
+                    IpcResponse::Error(e) => {
+                        anyhow::bail!(e.prefixed("Failed to get do-not-disturb status"))
+                    }
+                    _ => anyhow::bail!("Unexpected response to do-not-disturb status request"),
+                };
+                if json {
+                    println!("{}", status);
+                } else {
+                    let enabled = status["enabled"].as_bool().unwrap_or(false);
+                    println!("{}", if enabled { "on" } else { "off" });
+                }
+            } else {
+                let enabled = match action {
+                    DndAction::On => Some(true),
+                    DndAction::Off => Some(false),
+                    _ => None,
+                };
+                handle_ipc_response(
+                    client.send_request(IpcRequest::DndSet { enabled }).await,
+                    "Do-not-disturb updated",
+                    "Failed to change do-not-disturb",
+                    json,
+                )?;
+            }
+        }
+        Commands::Stash { action } => {
+            let client = IpcClient::new(None);
+            match action {
+                None => {
+                    handle_ipc_response(
+                        client.send_request(IpcRequest::Stash).await,
+                        "Window stashed",
+                        "Failed to stash window",
+                        json,
+                    )?;
+                }
+                Some(StashAction::List) => {
+                    let windows: Vec<StashedWindowInfo> =
+                        match client.send_request(IpcRequest::StashList).await? {
+                            IpcResponse::Data(data) => serde_json::from_value(data)?,
+                            IpcResponse::Error(e) => {
+                                anyhow::bail!(e.prefixed("Failed to list stashed windows"))
+                            }
+                            _ => anyhow::bail!("Unexpected response to stash list request"),
+                        };
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&windows)?);
+                    } else {
+                        for window in windows {
+                            println!(
+                                "{}: {}  {}",
+                                window.window_id,
+                                window.app_id.unwrap_or_default(),
+                                window.title
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        Commands::Unstash { window_id, here } => {
+            let client = IpcClient::new(None);
+            handle_ipc_response(
+                client.send_request(IpcRequest::Unstash { window_id, here }).await,
+                "Window unstashed",
+                "Failed to unstash window",
+                json,
+            )?;
+        }
+        Commands::Sendto {
+            target,
+            output,
+            follow,
+        } => {
+            let client = IpcClient::new(None);
+            handle_ipc_response(
+                client
+                    .send_request(IpcRequest::SendTo {
+                        target,
+                        output,
+                        follow,
+                    })
+                    .await,
+                "Window sent",
+                "Failed to send window",
+                json,
+            )?;
+        }
+        Commands::Present { name, stop } => {
+            let client = IpcClient::new(None);
+            match name {
+                Some(name) if !stop => {
+                    handle_ipc_response(
+                        client.send_request(IpcRequest::PresentStart { name }).await,
+                        "Presentation started",
+                        "Failed to start presentation",
+                        json,
+                    )?;
+                }
+                _ => {
+                    handle_ipc_response(
+                        client.send_request(IpcRequest::PresentStop).await,
+                        "Presentation stopped",
+                        "Failed to stop presentation",
+                        json,
+                    )?;
+                }
+            }
+        }
+        Commands::Pick { focus, bring } => {
+            let client = IpcClient::new(None);
+            if focus || bring {
+                focus_picked(&client, bring, json).await?;
+            } else {
+                list_pickable(&client, json).await?;
+            }
+        }
+        Commands::Window { action } => {
+            let (success_msg, error_prefix) = match action {
+                WindowCommand::Focus { .. } => ("Window focused", "Failed to focus window"),
+                WindowCommand::Move { .. } => ("Windows moved", "Failed to move windows"),
+                WindowCommand::Close { .. } => ("Windows closed", "Failed to close windows"),
+            };
+            let client = IpcClient::new(None);
+            handle_ipc_response(
+                client.send_request(action.into_request()).await,
+                success_msg,
+                error_prefix,
+                json,
+            )?;
+        }
+        Commands::Workspace { action } => match action {
+            WorkspaceAction::Focus { query } => {
+                let client = IpcClient::new(None);
+                handle_ipc_response(
+                    client.send_request(IpcRequest::WorkspaceFocus { query }).await,
+                    "Workspace focused",
+                    "Failed to focus workspace",
+                    json,
+                )?;
+            }
+            WorkspaceAction::List => {
+                list_workspaces(&IpcClient::new(None), json).await?;
+            }
+        },
+        Commands::Bar => {
+            run_bar(&IpcClient::new(None)).await?;
+        }
+        Commands::Events { names } => {
+            run_events(&IpcClient::new(None), names, json).await?;
+        }
+        Commands::Config { action } => {
+            let client = IpcClient::new(None);
+            match action {
+                ConfigAction::Init { force, minimal } => {
+                    let path = write_config_template(&cli.config, force, minimal)?;
+                    if json {
+                        println!("{}", serde_json::json!({ "path": path }));
+                    } else {
+                        println!("Config written to {}", path.display());
+                    }
+                }
+                ConfigAction::Migrate { dry_run } => migrate_config(&cli.config, dry_run, json)?,
+                ConfigAction::Get { path } => {
+                    let value = match client.send_request(IpcRequest::ConfigGet { path }).await? {
+                        IpcResponse::Data(data) => data,
+                        IpcResponse::Error(e) => {
+                            anyhow::bail!(e.prefixed("Failed to get config value"))
+                        }
+                        _ => anyhow::bail!("Unexpected response to config get request"),
+                    };
+                    match value {
+                        _ if json => println!("{}", value),
+                        serde_json::Value::String(s) => println!("{}", s),
+                        serde_json::Value::Object(_) => {
+                            print!("{}", toml::to_string_pretty(&value)?)
+                        }
+                        _ => println!("{}", value),
+                    }
+                }
+                ConfigAction::Show => {
+                    let config = match client.send_request(IpcRequest::ConfigShow).await? {
+                        IpcResponse::Data(data) => data,
+                        IpcResponse::Error(e) => anyhow::bail!(e.prefixed("Failed to get config")),
+                        _ => anyhow::bail!("Unexpected response to config show request"),
+                    };
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&config)?);
+                    } else {
+                        print!("{}", toml::to_string_pretty(&config)?);
+                    }
+                }
+                ConfigAction::Set { path, value, write } => {
+                    handle_ipc_response(
+                        client
+                            .send_request(IpcRequest::ConfigSet {
+                                path: path.clone(),
+                                value,
+                                write,
+                            })
+                            .await,
+                        &format!("Config '{}' updated", path),
+                        "Failed to set config value",
+                        json,
+                    )?;
+                }
+            }
+        }
+        Commands::Profile { action } => {
+            let client = IpcClient::new(None);
+            match action {
+                ProfileAction::Set { name } => handle_ipc_response(
+                    client
+                        .send_request(IpcRequest::ProfileSet {
+                            name: Some(name.clone()),
+                        })
+                        .await,
+                    &format!("Switched to profile '{}'", name),
+                    "Failed to switch profile",
+                    json,
+                )?,
+                ProfileAction::Clear => handle_ipc_response(
+                    client.send_request(IpcRequest::ProfileSet { name: None }).await,
+                    "Switched back to the base config",
+                    "Failed to switch profile",
+                    json,
+                )?,
+                ProfileAction::Get => {
+                    let status: DaemonStatus = match client.send_request(IpcRequest::Status).await?
+                    {
+                        IpcResponse::Data(data) => serde_json::from_value(data)?,
+                        IpcResponse::Error(e) => {
+                            anyhow::bail!(e.prefixed("Failed to get daemon status"))
+                        }
+                        _ => anyhow::bail!("Unexpected response to status request"),
+                    };
+                    if json {
+                        println!("{}", serde_json::json!({ "profile": status.profile }));
+                    } else if let Some(profile) = status.profile {
+                        println!("{}", profile);
+                    }
+                }
+            }
+        }
+        Commands::Reload { plugin } => {
+            let client = IpcClient::new(None);
+            let (request, success) = match plugin {
+                Some(name) => (
+                    IpcRequest::ReloadPlugin { name: name.clone() },
+                    format!("Plugin '{}' reloaded", name),
+                ),
+                None => (IpcRequest::Reload, "Configuration reloaded".to_string()),
+            };
+            handle_ipc_response(
+                client.send_request(request).await,
+                &success,
+                "Failed to reload",
+                json,
+            )?;
+        }
+        Commands::Logs { follow } => {
+            print_logs(&IpcClient::new(None), follow, json).await?;
+        }
+        Commands::LogLevel { level, .. } => {
+            let client = IpcClient::new(None);
+            let message = match level {
+                Some(level) => format!("Logging at {} level", level),
+                None => "Logging at the configured levels".to_string(),
+            };
+            handle_ipc_response(
+                client.send_request(IpcRequest::SetLogLevel { level }).await,
+                &message,
+                "Failed to change the log level",
+                json,
+            )?;
+        }
+        Commands::Status => {
+            print_status(&IpcClient::new(None), json).await?;
+        }
+        Commands::Version => {
+            print_version(&IpcClient::new(None), json).await?;
+        }
+        Commands::Metrics => {
+            let client = IpcClient::new(None);
+            let samples: Vec<metrics::Sample> =
+                match client.send_request(IpcRequest::Metrics).await? {
+                    IpcResponse::Data(data) => serde_json::from_value(data)?,
+                    IpcResponse::Error(e) => anyhow::bail!(e.prefixed("Failed to get metrics")),
+                    _ => anyhow::bail!("Unexpected response to metrics request"),
+                };
+            if json {
+                println!("{}", serde_json::to_string_pretty(&samples)?);
+            } else {
+                print!("{}", metrics::render(&samples));
+            }
+        }
+        Commands::Wait(args) => {
+            let client = IpcClient::new(None);
+            let timeout = client.timeout();
+            let request = IpcRequest::Wait {
+                condition: args.condition(),
+                timeout_ms: timeout.as_millis() as u64,
+            };
+            // The daemon gives up first and tells what it was waiting for
+            let client = client.with_timeout(timeout + Duration::from_secs(1));
+            handle_ipc_response(
+                client.send_request(request).await,
+                "Done",
+                "Wait failed",
+                json,
+            )?;
+        }
+        Commands::Ping { max_ms } => {
+            ping(&IpcClient::new(None), max_ms, json).await?;
+        }
+        Commands::Batch { file } => {
+            run_batch(&IpcClient::new(None), &file, &cli.config, json).await?;
+        }
+        Commands::Repl => {
+            repl::run(&IpcClient::new(None), &cli.config, json).await?;
+        }
+        Commands::Stop { force } => {
+            let client = IpcClient::new(None);
+            match client.send_request(IpcRequest::Shutdown).await {
+                Err(e) if force => {
+                    log::debug!("Daemon didn't answer ({}), stopping it by PID", e);
+                    force_stop(json).await?;
+                }
+                result => {
+                    handle_ipc_response(result, "Daemon stopped", "Failed to stop daemon", json)?;
+                }
+            }
+        }
+        Commands::Restart => {
+            restart(&IpcClient::new(None), json).await?;
+        }
+        Commands::Completion { shell } => {
+            let mut cmd = cli_command(&cli.config);
+            match shell {
+                Shell::Bash => generate(shells::Bash, &mut cmd, "piri", &mut io::stdout()),
+                Shell::Zsh => generate(shells::Zsh, &mut cmd, "piri", &mut io::stdout()),
+                Shell::Fish => generate(shells::Fish, &mut cmd, "piri", &mut io::stdout()),
+                Shell::PowerShell => {
+                    generate(shells::PowerShell, &mut cmd, "piri", &mut io::stdout())
+                }
+                Shell::Elvish => generate(shells::Elvish, &mut cmd, "piri", &mut io::stdout()),
+            }
+        }
+        Commands::Check { ref simulate } => {
+            check_config(&cli.config, simulate.as_deref(), json).await?
+        }
+        Commands::GenDocs { format } => match format {
+            DocsFormat::Man { dir } => {
+                docs::write_man_pages(cli_command(&cli.config), &dir)?;
+                println!("Man pages written to {}", dir.display());
+            }
+            DocsFormat::Markdown => {
+                docs::write_markdown(cli_command(&cli.config), &mut io::stdout().lock())?;
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Print a JSON line for every status update. Keeps reconnecting while the daemon is
+/// unavailable so the bar module recovers after daemon restarts.
+async fn run_bar(client: &IpcClient) -> Result<()> {
+    loop {
+        let mut rejected = None;
+        let result = client
+            .subscribe(IpcRequest::BarSubscribe, |response| {
+                match response {
+                    IpcResponse::Data(status) => {
+                        let mut stdout = io::stdout().lock();
+                        writeln!(stdout, "{}", status)?;
+                        stdout.flush()?;
+                    }
+                    IpcResponse::Error(e) => rejected = Some(e),
+                    _ => {}
+                }
+                Ok(())
+            })
+            .await;
+
+        if let Some(e) = rejected {
+            anyhow::bail!(e.prefixed("Failed to subscribe to status"));
+        }
+        if let Err(e) = result {
+            log::debug!("Status stream unavailable: {}", e);
+        }
+
+        let written = writeln!(
+            io::stdout(),
+            r#"{{"text":"","tooltip":"piri daemon not running","class":["disconnected"]}}"#
+        );
+        match written {
+            // The bar closed our stdout, nothing left to do
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            result => result?,
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    }
+}
+
+/// Print events until the daemon goes away: `Name {payload}` per line, or the raw JSON
+async fn run_events(client: &IpcClient, names: Vec<String>, json: bool) -> Result<()> {
+    let mut rejected = None;
+    let result = client
+        .subscribe(IpcRequest::EventsSubscribe { names }, |response| {
+            match response {
+                IpcResponse::Data(event) => {
+                    let mut stdout = io::stdout().lock();
+                    match event.as_object().and_then(|o| o.iter().next()) {
+                        Some((name, payload)) if !json => writeln!(stdout, "{} {}", name, payload)?,
+                        _ => writeln!(stdout, "{}", event)?,
+                    }
+                    stdout.flush()?;
+                }
+                IpcResponse::Error(e) => rejected = Some(e),
+                _ => {}
+            }
+            Ok(())
+        })
+        .await;
+
+    if let Some(e) = rejected {
+        anyhow::bail!(e.prefixed("Failed to subscribe to events"));
+    }
+    ignore_broken_pipe(result)
+}
+
+/// Streams piped into something that stopped reading (e.g. `head`) end without an error
+fn ignore_broken_pipe(result: Result<()>) -> Result<()> {
+    match result {
+        Err(e)
+            if e.downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Print the daemon's log buffer, then with `follow` new lines until the daemon goes away.
+/// With `json`, each line is printed as a JSON string
+async fn print_logs(client: &IpcClient, follow: bool, json: bool) -> Result<()> {
+    let mut rejected = None;
+    let result = client
+        .subscribe(IpcRequest::Logs { follow }, |response| {
+            match response {
+                IpcResponse::Data(lines) => {
+                    let lines: Vec<String> = serde_json::from_value(lines)?;
+                    let mut stdout = io::stdout().lock();
+                    for line in lines {
+                        if json {
+                            writeln!(stdout, "{}", serde_json::Value::String(line))?;
+                        } else {
+                            writeln!(stdout, "{}", line)?;
+                        }
+                    }
+                    stdout.flush()?;
+                }
+                IpcResponse::Error(e) => rejected = Some(e),
+                _ => {}
+            }
+            Ok(())
+        })
+        .await;
+
+    if let Some(e) = rejected {
+        anyhow::bail!(e.prefixed("Failed to get logs"));
+    }
+    ignore_broken_pipe(result)
+}
+
+/// The IPC request a command line in a batch stands for
+fn batch_request(command: Commands) -> Result<IpcRequest> {
+    let request = match command {
+        Commands::Scratchpads { name, action } => match action {
+            ScratchpadAction::Toggle => IpcRequest::ScratchpadToggle { name },
+            ScratchpadAction::Add {
+                direction,
+                swallow_to_focus,
+            } => IpcRequest::ScratchpadAdd {
+                name,
+                direction,
+                swallow_to_focus,
+            },
+        },
+        Commands::Singleton { name, action } => match (action, name) {
+            (Some(SingletonAction::List), _) => IpcRequest::SingletonList,
+            (_, Some(name)) => IpcRequest::SingletonToggle { name },
+            (_, None) => anyhow::bail!("Singleton name is required for toggle"),
+        },
+        Commands::WindowOrder {
+            action: WindowOrderAction::Toggle,
+        } => IpcRequest::WindowOrderToggle,
+        Commands::Autofill {
+            action: AutofillAction::Toggle,
+        } => IpcRequest::AutofillToggle,
+        Commands::Focus { action } => match action {
+            FocusAction::Last(args) => IpcRequest::FocusLast {
+                scope: args.scope(),
+            },
+            FocusAction::Prev(args) => IpcRequest::FocusPrev {
+                scope: args.scope(),
+            },
+            FocusAction::Next(args) => IpcRequest::FocusNext {
+                scope: args.scope(),
+            },
+        },
+        Commands::Mark { action } => match action {
+            MarkAction::Set { name } => IpcRequest::MarkSet { name },
+            MarkAction::Goto { name } => IpcRequest::MarkGoto { name },
+            MarkAction::List => IpcRequest::MarkList,
+        },
+        Commands::Session { action } => match action {
+            SessionAction::Save { name } => IpcRequest::SessionSave { name },
+            SessionAction::Restore { name } => IpcRequest::SessionRestore { name },
+        },
+        Commands::Group { action } => match action {
+            GroupAction::Add { name } => IpcRequest::GroupAdd { name },
+            GroupAction::Remove => IpcRequest::GroupRemove,
+            GroupAction::Next { name } => IpcRequest::GroupNext { name },
+            GroupAction::Prev { name } => IpcRequest::GroupPrev { name },
+            GroupAction::List => IpcRequest::GroupList,
+        },
+        Commands::Column { action } => match action {
+            ColumnAction::Rotate { backward } => IpcRequest::ColumnRotate { backward },
+            ColumnAction::SwapMaster => IpcRequest::ColumnSwapMaster,
+            ColumnAction::Reverse => IpcRequest::ColumnReverse,
+        },
+        Commands::Pin { action } => match action {
+            PinAction::Override { workspace, output } => {
+                IpcRequest::WorkspacePinOverride { workspace, output }
+            }
+            PinAction::Restore { workspace } => IpcRequest::WorkspacePinRestore { workspace },
+        },
+        Commands::Dnd { action } => match action {
+            DndAction::Toggle => IpcRequest::DndSet { enabled: None },
+            DndAction::On => IpcRequest::DndSet {
+                enabled: Some(true),
+            },
+            DndAction::Off => IpcRequest::DndSet {
+                enabled: Some(false),
+            },
+            DndAction::Status => IpcRequest::DndStatus,
+        },
+        Commands::Stash { action } => match action {
+            None => IpcRequest::Stash,
+            Some(StashAction::List) => IpcRequest::StashList,
+        },
+        Commands::Unstash { window_id, here } => IpcRequest::Unstash { window_id, here },
+        Commands::Sendto {
+            target,
+            output,
+            follow,
+        } => IpcRequest::SendTo {
+            target,
+            output,
+            follow,
+        },
+        Commands::Present { name, stop } => match name {
+            Some(name) if !stop => IpcRequest::PresentStart { name },
+            _ => IpcRequest::PresentStop,
+        },
+        Commands::Workspace { action } => match action {
+            WorkspaceAction::Focus { query } => IpcRequest::WorkspaceFocus { query },
+            WorkspaceAction::List => IpcRequest::WorkspaceList,
+        },
+        Commands::Window { action } => action.into_request(),
+        Commands::Config { action } => match action {
+            ConfigAction::Init { .. } | ConfigAction::Migrate { .. } => {
+                anyhow::bail!("Only commands sent to the daemon are supported here")
+            }
+            ConfigAction::Get { path } => IpcRequest::ConfigGet { path },
+            ConfigAction::Show => IpcRequest::ConfigShow,
+            ConfigAction::Set { path, value, write } => {
+                IpcRequest::ConfigSet { path, value, write }
+            }
+        },
+        Commands::Profile {
+            action: ProfileAction::Set { name },
+        } => IpcRequest::ProfileSet { name: Some(name) },
+        Commands::Profile {
+            action: ProfileAction::Clear,
+        } => IpcRequest::ProfileSet { name: None },
+        Commands::Reload { plugin } => match plugin {
+            Some(name) => IpcRequest::ReloadPlugin { name },
+            None => IpcRequest::Reload,
+        },
+        Commands::Logs { follow } => IpcRequest::Logs { follow },
+        Commands::Status => IpcRequest::Status,
+        Commands::Version => IpcRequest::Version,
+        Commands::Metrics => IpcRequest::Metrics,
+        Commands::Ping { .. } => IpcRequest::Ping,
+        Commands::Wait(args) => IpcRequest::Wait {
+            condition: args.condition(),
+            timeout_ms: ipc::IpcClient::new(None).timeout().as_millis() as u64,
+        },
+        Commands::Stop { force: false } => IpcRequest::Shutdown,
+        Commands::Restart => IpcRequest::Restart,
+        Commands::LogLevel { level, .. } => IpcRequest::SetLogLevel { level },
+        _ => anyhow::bail!("Only commands sent to the daemon are supported here"),
+    };
+    Ok(request)
+}
+
+/// Have the daemon execute itself again and wait until the new one answers
+async fn restart(client: &IpcClient, json: bool) -> Result<()> {
+    let restarts = |response: Result<IpcResponse>| match response {
+        Ok(IpcResponse::Pong(info)) => Some(info.restarts),
+        _ => None,
+    };
+    let before = restarts(client.send_request(IpcRequest::Ping).await).unwrap_or_default();
+    match client.send_request(IpcRequest::Restart).await? {
+        IpcResponse::Success => {}
+        IpcResponse::Error(e) => anyhow::bail!(e.prefixed("Failed to restart daemon")),
+        response => anyhow::bail!("Unexpected response: {:?}", response),
+    }
+
+    // The old daemon may still answer for a moment, the new one counts one more restart
+    for _ in 0..50 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        if restarts(client.send_request(IpcRequest::Ping).await) == Some(before + 1) {
+            return handle_ipc_response(Ok(IpcResponse::Success), "Daemon restarted", "", json);
+        }
+    }
+    anyhow::bail!("The daemon didn't come back after restarting, see its log")
+}
+
+/// Terminate the daemon recorded in the pidfile (killing it if SIGTERM isn't enough), then
+/// remove the socket and pidfile it may have left behind
+async fn force_stop(json: bool) -> Result<()> {
+    let pid = std::fs::read_to_string(ipc::get_pid_path())
+        .ok()
+        .and_then(|pid| pid.trim().parse::<i32>().ok())
+        .filter(|pid| is_piri_process(*pid));
+
+    let stopped = if let Some(pid) = pid {
+        unsafe { libc::kill(pid, libc::SIGTERM) };
+        for _ in 0..20 {
+            if !is_piri_process(pid) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        if is_piri_process(pid) {
+            log::warn!("Daemon (PID {}) ignored SIGTERM, killing it", pid);
+            unsafe { libc::kill(pid, libc::SIGKILL) };
+        }
+        Some(format!("Daemon (PID {}) stopped", pid))
+    } else {
+        None
+    };
+
+    let removed = ipc::remove_stale_files();
+    let message = match (stopped, removed) {
+        (Some(message), _) => message,
+        (None, true) => "Daemon was not running, removed its stale socket".to_string(),
+        (None, false) => anyhow::bail!(ipc::IpcError::new(
+            ipc::ErrorKind::DaemonNotRunning,
+            "Daemon is not running"
+        )),
+    };
+    handle_ipc_response(Ok(IpcResponse::Success), &message, "", json)
+}
+
+/// Whether the process exists and has the same name as this one, so that a PID reused
+/// by another program is left alone
+fn is_piri_process(pid: i32) -> bool {
+    let comm = |pid: &str| std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok();
+    match comm(&pid.to_string()) {
+        Some(name) => Some(name) == comm("self"),
+        None => false,
+    }
+}
+
+/// The IPC request for a command line without the leading `piri`, as in batch files
+fn line_request(line: &str, config: &str) -> Result<IpcRequest> {
+    let words = utils::split_words(line).context("invalid command")?;
+    let args = std::iter::once("piri".to_string()).chain(words).collect();
+    let args = replace_alias(args, aliases(config))?;
+    let cli = Cli::try_parse_from(args).map_err(|e| {
+        // Only the first line, without the usage text clap adds for a terminal
+        let message = match e.kind() {
+            ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand => "missing arguments".to_string(),
+            _ => e.to_string().lines().next().unwrap_or_default().to_string(),
+        };
+        anyhow::anyhow!("{}", message.trim_start_matches("error: "))
+    })?;
+    batch_request(cli.command)
+}
+
+/// Parse all commands first so that a typo doesn't leave the batch half done, then send
+/// them in one go. Prints the data returned by queries and the errors of failed commands
+async fn run_batch(client: &IpcClient, file: &Path, config: &str, json: bool) -> Result<()> {
+    let content = if file == Path::new("-") {
+        io::read_to_string(io::stdin())?
+    } else {
+        std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read batch file: {:?}", file))?
+    };
+
+    let mut lines = Vec::new();
+    let mut requests = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let request = line_request(line, config)
+            .map_err(|e| anyhow::anyhow!("Line {}: {}: {:#}", number + 1, line, e))?;
+        lines.push(line);
+        requests.push(request);
+    }
+    if requests.is_empty() {
+        return Ok(());
+    }
+
+    let responses = client.send_batch(&requests).await?;
+    let mut failed = 0;
+    for (line, response) in lines.iter().zip(responses) {
+        if let IpcResponse::Error(_) = response {
+            failed += 1;
+        }
+        match response {
+            // One line per command
+            IpcResponse::Data(data) if json => println!(
+                "{}",
+                serde_json::json!({ "command": line, "success": true, "data": data })
+            ),
+            IpcResponse::Error(e) if json => println!(
+                "{}",
+                serde_json::json!({
+                    "command": line,
+                    "success": false,
+                    "error": e.message,
+                    "kind": e.kind,
+                    "trace_id": e.trace_id,
+                })
+            ),
+            _ if json => println!(
+                "{}",
+                serde_json::json!({ "command": line, "success": true })
+            ),
+            IpcResponse::Data(data) => println!("{}", data),
+            IpcResponse::Error(e) => eprintln!("{}: {}", line, e),
+            IpcResponse::Pong(info) => println!("{}", serde_json::to_value(info)?),
+            IpcResponse::Success => {}
+        }
+    }
+    if failed > 0 {
+        send_notification("piri", &format!("{} batched commands failed", failed));
+        anyhow::bail!("{} of {} commands failed", failed, lines.len());
+    }
+    Ok(())
+}
+
+/// Print singleton status from the daemon; without a running daemon, list the configured
+/// singletons as not running
+async fn list_singletons(client: &IpcClient, config: &str, json: bool) -> Result<()> {
+    let statuses: Vec<SingletonStatus> = match client.send_request(IpcRequest::SingletonList).await
+    {
+        Ok(IpcResponse::Data(data)) => serde_json::from_value(data)?,
+        Ok(IpcResponse::Error(e)) => anyhow::bail!(e.prefixed("Failed to list singletons")),
+        Ok(_) => anyhow::bail!("Unexpected response to singleton list request"),
+        Err(_) => {
+            let config = load_config_if_exists(config)?;
+            let mut statuses: Vec<SingletonStatus> = config
+                .singleton
+                .into_iter()
+                .map(|(name, singleton)| SingletonStatus {
+                    name,
+                    command: singleton.command.to_string(),
+                    running: false,
+                    window_id: None,
+                    workspace: None,
+                })
+                .collect();
+            statuses.sort_by(|a, b| a.name.cmp(&b.name));
+            statuses
+        }
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&statuses)?);
+        return Ok(());
+    }
+
+    let width = statuses.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    for status in statuses {
+        let state = match (status.window_id, status.workspace) {
+            (Some(id), Some(ws)) => format!("running (window {}, workspace {})", id, ws),
+            (Some(id), None) => format!("running (window {})", id),
+            _ => "not running".to_string(),
+        };
+        println!("{:width$}  {}", status.name, state, width = width);
+    }
+    Ok(())
+}
+
+/// Print one line per window for a picker; the line starts with the window ID
+/// Format seconds as e.g. "2h 5m 3s"
+fn format_duration(secs: u64) -> String {
+    let (days, hours, minutes, secs) = (
+        secs / 86400,
+        secs % 86400 / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+    );
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{}s", secs),
+        (0, 0, _) => format!("{}m {}s", minutes, secs),
+        (0, _, _) => format!("{}h {}m {}s", hours, minutes, secs),
+        _ => format!("{}d {}h {}m", days, hours, minutes),
+    }
+}
+
+/// Print how long the daemon and niri take to answer; fails when niri doesn't answer, or
+/// takes longer than `max_ms`, so that monitoring scripts only need the exit code
+async fn ping(client: &IpcClient, max_ms: Option<f64>, json: bool) -> Result<()> {
+    let start = std::time::Instant::now();
+    let info: PingInfo = match client.send_request(IpcRequest::Ping).await? {
+        IpcResponse::Pong(info) => info,
+        IpcResponse::Error(e) => anyhow::bail!(e.prefixed("Ping failed")),
+        response => anyhow::bail!("Unexpected response: {:?}", response),
+    };
+    let daemon_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    let niri_ms = match (info.niri_ms, info.niri_error) {
+        (Some(niri_ms), _) => niri_ms,
+        (None, error) => anyhow::bail!(ipc::IpcError::new(
+            ipc::ErrorKind::NiriUnreachable,
+            format!("niri doesn't answer: {}", error.unwrap_or_default())
+        )),
+    };
+    if let Some(max_ms) = max_ms.filter(|max_ms| niri_ms > *max_ms) {
+        anyhow::bail!(ipc::IpcError::new(
+            ipc::ErrorKind::Timeout,
+            format!(
+                "niri answered in {:.1} ms, more than {} ms",
+                niri_ms, max_ms
+            )
+        ));
+    }
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "daemon_ms": daemon_ms,
+                "niri_ms": niri_ms,
+                "uptime_secs": info.uptime_secs,
+            })
+        );
+    } else {
+        println!(
+            "daemon: {:.1} ms, up {}",
+            daemon_ms,
+            format_duration(info.uptime_secs)
+        );
+        println!("niri: {:.1} ms", niri_ms);
+    }
+    Ok(())
+}
+
+/// Requests change between releases, and a daemon left running after an upgrade can't
+/// read the ones it doesn't know: compare versions so that users know to restart it
+async fn print_version(client: &IpcClient, json: bool) -> Result<()> {
+    let version = env!("CARGO_PKG_VERSION");
+    let daemon: std::result::Result<VersionInfo, String> =
+        match client.send_request(IpcRequest::Version).await {
+            Ok(IpcResponse::Data(data)) => Ok(serde_json::from_value(data)?),
+            // Daemons from before `piri version` don't know the request
+            Ok(_) => Err("an older version, restart it".to_string()),
+            Err(e) if ipc::ErrorKind::of(&e) == ipc::ErrorKind::DaemonNotRunning => {
+                Err("not running".to_string())
+            }
+            Err(e) => Err(format!("{:#}", e)),
+        };
+    let mismatch = daemon.as_ref().is_ok_and(|daemon| daemon.version != version);
+
+    if json {
+        let (daemon, error) = match daemon {
+            Ok(daemon) => (Some(daemon), None),
+            Err(e) => (None, Some(e)),
+        };
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "client": version,
+                "daemon": daemon,
+                "daemon_error": error,
+                "mismatch": mismatch,
+            }))?
+        );
+        return Ok(());
+    }
+
+    println!("Client: piri {}", version);
+    match daemon {
+        Ok(daemon) => {
+            println!("Daemon: piri {}", daemon.version);
+            if daemon.plugins.is_empty() {
+                println!("Plugins: none");
+            } else {
+                println!("Plugins: {}", daemon.plugins.join(", "));
+            }
+            if mismatch {
+                eprintln!(
+                    "Warning: the daemon runs another version, restart it so that it understands all commands"
+                );
+            }
+        }
+        Err(e) => println!("Daemon: {}", e),
+    }
+    Ok(())
+}
+
+async fn print_status(client: &IpcClient, json: bool) -> Result<()> {
+    let status: DaemonStatus = match client.send_request(IpcRequest::Status).await? {
+        IpcResponse::Data(data) => serde_json::from_value(data)?,
+        IpcResponse::Error(e) => anyhow::bail!(e.prefixed("Failed to get daemon status")),
+        _ => anyhow::bail!("Unexpected response to status request"),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+
+    println!(
+        "piri {}, up {}",
+        status.version,
+        format_duration(status.uptime_secs)
+    );
+    match status.profile {
+        Some(profile) => println!(
+            "Config:      {} (profile: {})",
+            status.config_path.display(),
+            profile
+        ),
+        None => println!("Config:      {}", status.config_path.display()),
+    }
+    let last_reload = status.last_reload.map(|t| {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(t);
+        format!("{} ago", format_duration(now.saturating_sub(t)))
+    });
+    println!(
+        "Last reload: {}",
+        last_reload.unwrap_or_else(|| "never".to_string())
+    );
+    match (
+        status.niri.connected,
+        status.niri.version,
+        status.niri.error,
+    ) {
+        (true, Some(version), _) => println!("niri:        connected (version {})", version),
+        (true, None, _) => println!("niri:        connected"),
+        (false, _, error) => println!("niri:        unreachable ({})", error.unwrap_or_default()),
+    }
+    println!(
+        "Scratchpads: {}, singletons: {}",
+        status.scratchpads, status.singletons
+    );
+
+    let names = |f: &dyn Fn(&plugins::PluginState) -> bool| {
+        let names: Vec<&str> =
+            status.plugins.iter().filter(|p| f(p)).map(|p| p.name.as_str()).collect();
+        if names.is_empty() {
+            "-".to_string()
+        } else {
+            names.join(", ")
+        }
+    };
+    println!("Plugins:");
+    println!("  enabled:   {}", names(&|p| p.enabled && !p.suspended));
+    println!("  suspended: {}", names(&|p| p.enabled && p.suspended));
+    if status.plugins.iter().any(|p| p.crashed) {
+        println!(
+            "  crashed:   {} (until the next reload)",
+            names(&|p| p.crashed)
+        );
+    }
+    println!("  disabled:  {}", names(&|p| !p.enabled && !p.crashed));
+    Ok(())
+}
+
+async fn list_pickable(client: &IpcClient, json: bool) -> Result<()> {
+    let windows: Vec<WindowInfo> = match client.send_request(IpcRequest::WindowList).await? {
+        IpcResponse::Data(data) => serde_json::from_value(data)?,
+        IpcResponse::Error(e) => anyhow::bail!(e.prefixed("Failed to list windows")),
+        _ => anyhow::bail!("Unexpected response to window list request"),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&windows)?);
+        return Ok(());
+    }
+
+    for window in windows {
+        println!(
+            "{}: {}  {}  [{}]",
+            window.id,
+            window.app_id.unwrap_or_default(),
+            window.title,
+            window.workspace.unwrap_or_else(|| "-".to_string())
+        );
+    }
+    Ok(())
+}
+
+async fn list_workspaces(client: &IpcClient, json: bool) -> Result<()> {
+    let workspaces: Vec<WorkspaceInfo> =
+        match client.send_request(IpcRequest::WorkspaceList).await? {
+            IpcResponse::Data(data) => serde_json::from_value(data)?,
+            IpcResponse::Error(e) => anyhow::bail!(e.prefixed("Failed to list workspaces")),
+            _ => anyhow::bail!("Unexpected response to workspace list request"),
+        };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&workspaces)?);
+        return Ok(());
+    }
+
+    // Each line can be passed back to `piri workspace focus`
+    for workspace in workspaces {
+        println!(
+            "{}",
+            workspace.name.unwrap_or_else(|| workspace.idx.to_string())
+        );
+    }
+    Ok(())
+}
+
+/// Read a line printed by `list_pickable` from stdin and focus (or bring) its window.
+/// An empty selection (the picker was cancelled) does nothing
+async fn focus_picked(client: &IpcClient, bring: bool, json: bool) -> Result<()> {
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    let line = line.trim();
+    if line.is_empty() {
+        if json {
+            println!(
+                "{}",
+                serde_json::json!({ "success": true, "message": "Nothing picked" })
+            );
+        }
+        return Ok(());
+    }
+    let id: u64 = line
+        .split(':')
+        .next()
+        .and_then(|id| id.trim().parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("No window ID in selection '{}'", line))?;
+
+    match client.send_request(IpcRequest::WindowFocus { id, bring }).await? {
+        IpcResponse::Success => {
+            if json {
+                println!(
+                    "{}",
+                    serde_json::json!({ "success": true, "message": "Window focused" })
+                );
+            }
+            Ok(())
+        }
+        IpcResponse::Error(e) => {
+            send_notification("piri", &e.message);
+            anyhow::bail!(e.prefixed(&format!("Failed to focus window {}", id)))
+        }
+        _ => anyhow::bail!("Unexpected response to window focus request"),
+    }
+}
+
+/// The config file path with `~` and variables expanded. A missing `.toml` file falls back
+/// to a `.kdl` file next to it
+fn expand_config_path(config: &str) -> PathBuf {
+    let path = shellexpand::full(config)
+        .map(|s| PathBuf::from(s.as_ref()))
+        .unwrap_or_else(|_| PathBuf::from(config));
+    let kdl = path.with_extension("kdl");
+    if !path.exists() && path.extension().is_some_and(|ext| ext == "toml") && kdl.exists() {
+        return kdl;
+    }
+    path
+}
+
+/// The CLI definition, with the values only known from the config filled in
+fn cli_command(config: &str) -> clap::Command {
+    let mut cmd = Cli::command();
+
+    // Aliases show up as subcommands of their own
+    let mut aliases: Vec<(&String, &String)> = aliases(config).iter().collect();
+    aliases.sort();
+    for (name, expansion) in aliases {
+        if cmd.find_subcommand(name).is_none() {
+            cmd = cmd.subcommand(
+                clap::Command::new(name.clone()).about(format!("Alias for `piri {}`", expansion)),
+            );
+        }
+    }
+
+    // Offer the configured singleton and profile names as completions
+    let config = load_config_if_exists(config).unwrap_or_default();
+    let mut profiles: Vec<String> = config.profiles.into_keys().collect();
+    if !profiles.is_empty() {
+        profiles.sort();
+        cmd = cmd.mut_subcommand("profile", |sub| {
+            sub.mut_subcommand("set", |set| {
+                set.mut_arg("name", |arg| {
+                    arg.value_parser(PossibleValuesParser::new(profiles))
+                })
+            })
+        });
+    }
+    let mut singletons: Vec<String> = config.singleton.into_keys().collect();
+    if !singletons.is_empty() {
+        singletons.sort();
+        cmd = cmd.mut_subcommand("singleton", |sub| {
+            sub.mut_arg("name", |arg| {
+                arg.value_parser(PossibleValuesParser::new(singletons))
+            })
+        });
+    }
+    cmd
+}
+
+/// `[aliases]` from the config file, loaded on first use
+static ALIASES: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+fn aliases(config: &str) -> &'static HashMap<String, String> {
+    ALIASES.get_or_init(|| {
+        load_config_if_exists(config).map(|config| config.aliases).unwrap_or_default()
+    })
+}
+
+/// Index of the subcommand in a command line, skipping the options before it, and the
+/// config file given among them
+fn find_subcommand(args: &[String]) -> (usize, Option<String>) {
+    let cmd = Cli::command();
+    let takes_value = |arg: &str| {
+        cmd.get_arguments().any(|a| {
+            a.get_action().takes_values()
+                && (a.get_long().is_some_and(|long| arg.strip_prefix("--") == Some(long))
+                    || a.get_short().is_some_and(|short| arg == format!("-{}", short)))
+        })
+    };
+    let mut config = None;
+    let mut index = 1;
+    while let Some(arg) = args.get(index) {
+        if !arg.starts_with('-') {
+            break;
+        }
+        if let Some(value) = arg.strip_prefix("--config=") {
+            config = Some(value.to_string());
+        } else if takes_value(arg) {
+            if matches!(arg.as_str(), "-c" | "--config") {
+                config = args.get(index + 1).cloned();
+            }
+            index += 1;
+        }
+        index += 1;
+    }
+    (index, config)
+}
+
+/// Replace the subcommand by the command line it stands for if it's an alias. Built-in
+/// subcommands can't be overridden, and aliases don't expand further
+fn replace_alias(mut args: Vec<String>, aliases: &HashMap<String, String>) -> Result<Vec<String>> {
+    let (index, _) = find_subcommand(&args);
+    let Some(name) = args.get(index) else {
+        return Ok(args);
+    };
+    if Cli::command().find_subcommand(name).is_some() {
+        return Ok(args);
+    }
+    if let Some(expansion) = aliases.get(name) {
+        let words =
+            utils::split_words(expansion).with_context(|| format!("Invalid alias '{}'", name))?;
+        args.splice(index..=index, words);
+    }
+    Ok(args)
+}
+
+/// Expand an alias in piri's own command line; the config is only read when the
+/// subcommand isn't a built-in one
+fn expand_alias(args: Vec<String>) -> Vec<String> {
+    let (index, config) = find_subcommand(&args);
+    match args.get(index) {
+        Some(name) if Cli::command().find_subcommand(name).is_none() => {}
+        _ => return args,
+    }
+    let default_config = Cli::command()
+        .get_arguments()
+        .find(|arg| arg.get_id() == "config")
+        .and_then(|arg| arg.get_default_values().first())
+        .map(|value| value.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let config = config.unwrap_or(default_config);
+    match replace_alias(args.clone(), aliases(&config)) {
+        Ok(expanded) => expanded,
+        Err(e) => {
+            eprintln!("Warning: {:#}", e);
+            args
+        }
+    }
+}
+
+/// Write the config template to the config path, which must not exist unless `force`
+fn write_config_template(config: &str, force: bool, minimal: bool) -> Result<PathBuf> {
+    let path = shellexpand::full(config)
+        .map(|s| PathBuf::from(s.as_ref()))
+        .unwrap_or_else(|_| PathBuf::from(config));
+    if config::is_kdl(&path) {
+        anyhow::bail!("The config template is only available in TOML");
+    }
+    if path.exists() && !force {
+        anyhow::bail!(
+            "{} already exists, add --force to overwrite it",
+            path.display()
+        );
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {:?}", parent))?;
+    }
+    std::fs::write(&path, config::template(minimal))
+        .with_context(|| format!("Failed to write {:?}", path))?;
+    Ok(path)
+}
+
+/// Rewrite the deprecated settings of the config file, showing the diff first
+fn migrate_config(config: &str, dry_run: bool, json: bool) -> Result<()> {
+    let path = expand_config_path(config);
+    if config::is_kdl(&path) {
+        anyhow::bail!("Only TOML config files can be migrated");
+    }
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read config file: {:?}", path))?;
+    let migration = migrate::migrate(&content)
+        .with_context(|| format!("Failed to parse config file: {:?}", path))?;
+    let written = !dry_run && !migration.notices.is_empty();
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "path": path,
+                "changes": migration.notices,
+                "diff": migrate::diff(&content, &migration.content, &path.display().to_string()),
+                "written": written,
+            })
+        );
+    } else if migration.notices.is_empty() {
+        println!("{} has no deprecated settings", path.display());
+    } else {
+        for notice in &migration.notices {
+            eprintln!("{}", notice);
+        }
+        print!(
+            "{}",
+            migrate::diff(&content, &migration.content, &path.display().to_string())
+        );
+    }
+    if written {
+        std::fs::write(&path, &migration.content)
+            .with_context(|| format!("Failed to write {:?}", path))?;
+        if !json {
+            println!("Config written to {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Load the config file like the daemon would and report what's wrong with it. Errors stop
+/// the daemon from starting, warnings are about deprecated settings and profiles that fail
+/// once switched to
+async fn check_config(config: &str, simulate: Option<&str>, json: bool) -> Result<()> {
+    let config_path = expand_config_path(config);
+    if !config_path.exists() {
+        anyhow::bail!(ipc::IpcError::not_found(format!(
+            "Config file {:?} does not exist",
+            config_path
+        )));
+    }
+    let config = Config::load(&config_path)?;
+    let mut warnings = config.deprecations(&config_path);
+    warnings.extend(config.profile_warnings(&config_path));
+    warnings.extend(config.host_warnings());
+    let simulation = match simulate {
+        Some(spec) => {
+            let simulation: simulate::Simulation = spec.parse()?;
+            Some((simulation.describe(), simulation.run(&config).await?))
+        }
+        None => None,
+    };
+    if json {
+        let mut output = serde_json::json!({ "path": config_path, "warnings": warnings });
+        if let Some((event, outcomes)) = simulation {
+            output["simulation"] = serde_json::json!({ "event": event, "outcomes": outcomes });
+        }
+        println!("{}", output);
+    } else {
+        for warning in &warnings {
+            eprintln!("Warning: {}", warning);
+        }
+        println!("{} is valid", config_path.display());
+        if let Some((event, outcomes)) = simulation {
+            println!("\nSimulating: {}", event);
+            for outcome in &outcomes {
+                let mark = if outcome.fires { "fires" } else { "note" };
+                println!("  [{}] {}: {}", mark, outcome.plugin, outcome.message);
+            }
+            if !outcomes.iter().any(|outcome| outcome.fires) {
+                println!("  No rules would fire");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Load the config for client-side commands, without creating a default file like the daemon does
+fn load_config_if_exists(config: &str) -> Result<Config> {
+    let config_path = expand_config_path(config);
+    if config_path.exists() {
+        Config::load(&config_path)
+    } else {
+        Ok(Config::default())
+    }
+}
+
+fn handle_ipc_response(
+    result: Result<IpcResponse>,
+    success_msg: &str,
+    error_prefix: &str,
+    json: bool,
+) -> Result<()> {
+    match result {
+        Ok(IpcResponse::Success) if json => {
+            println!(
+                "{}",
+                serde_json::json!({ "success": true, "message": success_msg })
+            );
+            Ok(())
+        }
+        Ok(IpcResponse::Success) => {
+            println!("{}", success_msg);
+            Ok(())
+        }
+        Ok(IpcResponse::Error(e)) => {
+            send_notification("piri", &e.message);
+            anyhow::bail!(e.prefixed(error_prefix));
+        }
+        Ok(IpcResponse::Pong(_)) => {
+            println!("Pong");
+            Ok(())
+        }
+        Ok(IpcResponse::Data(data)) => {
+            println!("{}", serde_json::to_string_pretty(&data)?);
             Ok(())
         }
         Err(e) => {
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Name, type and help text of each metric, in the order they are printed
+const METRICS: &[(&str, &str, &str)] = &[
+    (
+        "piri_events_total",
+        "counter",
+        "niri events handled, per plugin",
+    ),
+    (
+        "piri_niri_request_duration_seconds",
+        "histogram",
+        "Time niri took to answer a request",
+    ),
+    (
+        "piri_ipc_requests_total",
+        "counter",
+        "Requests handled by the daemon, per request",
+    ),
+    ("piri_swallows_total", "counter", "Windows swallowed"),
+    (
+        "piri_scratchpad_toggles_total",
+        "counter",
+        "Scratchpad toggles, per scratchpad",
+    ),
+];
+
+/// Upper bounds of the histogram buckets, in seconds
+const BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+type Labels = Vec<(&'static str, String)>;
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+struct Registry {
+    counters: BTreeMap<(&'static str, Labels), u64>,
+    histograms: BTreeMap<(&'static str, Labels), Histogram>,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    counters: BTreeMap::new(),
+    histograms: BTreeMap::new(),
+});
+
+fn labels(labels: &[(&'static str, &str)]) -> Labels {
+    labels.iter().map(|(name, value)| (*name, value.to_string())).collect()
+}
+
+/// Add one to a counter
+pub fn count(name: &'static str, with: &[(&'static str, &str)]) {
+    let mut registry = REGISTRY.lock().unwrap();
+    *registry.counters.entry((name, labels(with))).or_default() += 1;
+}
+
+/// Record how long something took in a histogram
+pub fn observe(name: &'static str, with: &[(&'static str, &str)], duration: Duration) {
+    let seconds = duration.as_secs_f64();
+    let mut registry = REGISTRY.lock().unwrap();
+    let histogram = registry.histograms.entry((name, labels(with))).or_default();
+    if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+        histogram.buckets[bucket] += 1;
+    }
+    histogram.sum += seconds;
+    histogram.count += 1;
+}
+
+/// One line of the Prometheus text format: a histogram is several, for its buckets, sum
+/// and count
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sample {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+}
+
+fn sample(name: String, labels: &Labels, extra: Option<(&str, String)>, value: f64) -> Sample {
+    let mut labels: BTreeMap<String, String> =
+        labels.iter().map(|(name, value)| (name.to_string(), value.clone())).collect();
+    if let Some((name, value)) = extra {
+        labels.insert(name.to_string(), value);
+    }
+    Sample {
+        name,
+        labels,
+        value,
+    }
+}
+
+/// Every sample recorded so far, for `piri metrics`
+pub fn snapshot() -> Vec<Sample> {
+    let registry = REGISTRY.lock().unwrap();
+    let mut samples = Vec::new();
+    for ((name, labels), value) in &registry.counters {
+        samples.push(sample(name.to_string(), labels, None, *value as f64));
+    }
+    for ((name, labels), histogram) in &registry.histograms {
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+            cumulative += count;
+            let le = Some(("le", bound.to_string()));
+            samples.push(sample(
+                format!("{}_bucket", name),
+                labels,
+                le,
+                cumulative as f64,
+            ));
+        }
+        let le = Some(("le", "+Inf".to_string()));
+        samples.push(sample(
+            format!("{}_bucket", name),
+            labels,
+            le,
+            histogram.count as f64,
+        ));
+        samples.push(sample(format!("{}_sum", name), labels, None, histogram.sum));
+        samples.push(sample(
+            format!("{}_count", name),
+            labels,
+            None,
+            histogram.count as f64,
+        ));
+    }
+    samples
+}
+
+/// Samples in the Prometheus text format, e.g. for node_exporter's textfile collector
+pub fn render(samples: &[Sample]) -> String {
+    let mut out = String::new();
+    for (name, kind, help) in METRICS {
+        let of_metric: Vec<&Sample> = samples
+            .iter()
+            .filter(|sample| {
+                sample.name == *name
+                    || (*kind == "histogram"
+                        && sample
+                            .name
+                            .strip_prefix(name)
+                            .is_some_and(|suffix| matches!(suffix, "_bucket" | "_sum" | "_count")))
+            })
+            .collect();
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} {}\n",
+            name, help, name, kind
+        ));
+        for sample in of_metric {
+            out.push_str(&sample.name);
+            if !sample.labels.is_empty() {
+                let labels: Vec<String> = sample
+                    .labels
+                    .iter()
+                    .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+                    .collect();
+                out.push_str(&format!("{{{}}}", labels.join(",")));
+            }
+            out.push_str(&format!(" {}\n", sample.value));
+        }
+    }
+    out
+}
+
+/// Label values with backslashes, quotes and newlines escaped
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,237 @@
+use anyhow::{Context, Result};
+use toml_edit::{DocumentMut, Item, TableLike};
+
+/// A config file with its deprecated settings rewritten in the current format
+pub struct Migration {
+    pub content: String,
+    /// One line per deprecated setting found, saying what replaces it
+    pub notices: Vec<String>,
+}
+
+/// Rewrite the deprecated settings of a TOML config file. Comments and formatting are kept,
+/// and the content is returned unchanged if nothing is deprecated
+pub fn migrate(content: &str) -> Result<Migration> {
+    let mut doc: DocumentMut = content.parse()?;
+    let mut notices = Vec::new();
+    migrate_empty(&mut doc, &mut notices)?;
+    migrate_scratchpads(&mut doc, &mut notices);
+
+    let content = if notices.is_empty() {
+        content.to_string()
+    } else {
+        doc.to_string()
+    };
+    Ok(Migration { content, notices })
+}
+
+/// `[piri.plugins.empty_config.workspaces]` and `[piri.plugins.empty.workspaces]` become
+/// `[empty.<workspace>]` tables, the latter also turning `empty` back into a switch
+fn migrate_empty(doc: &mut DocumentMut, notices: &mut Vec<String>) -> Result<()> {
+    let Some(plugins) = doc
+        .get_mut("piri")
+        .and_then(|piri| piri.get_mut("plugins"))
+        .and_then(Item::as_table_like_mut)
+    else {
+        return Ok(());
+    };
+
+    let mut workspaces = Vec::new();
+    if let Some(item) = plugins.remove("empty_config") {
+        notices.push(
+            "`piri.plugins.empty_config.workspaces` is deprecated, use \
+             `[empty.<workspace>] command = \"...\"`"
+                .to_string(),
+        );
+        workspaces.extend(workspace_commands(&item, "piri.plugins.empty_config")?);
+    }
+    if let Some(item) = plugins.get_mut("empty").filter(|item| item.is_table_like()) {
+        notices.push(
+            "`piri.plugins.empty.workspaces` is deprecated, use `empty = true` and \
+             `[empty.<workspace>] command = \"...\"`"
+                .to_string(),
+        );
+        workspaces.extend(workspace_commands(item, "piri.plugins.empty")?);
+        *item = toml_edit::value(true);
+    }
+    if workspaces.is_empty() {
+        return Ok(());
+    }
+
+    let empty = doc.entry("empty").or_insert_with(|| {
+        let mut table = toml_edit::Table::new();
+        table.set_implicit(true);
+        Item::Table(table)
+    });
+    let empty = empty.as_table_like_mut().context("`empty` must be a table")?;
+    for (workspace, command) in workspaces {
+        // A workspace already set up the current way keeps its command
+        if empty.contains_key(&workspace) {
+            continue;
+        }
+        let mut table = toml_edit::Table::new();
+        table.insert("command", toml_edit::value(command));
+        empty.insert(&workspace, Item::Table(table));
+    }
+    Ok(())
+}
+
+/// The `workspace = "command"` entries of a legacy `workspaces` table
+fn workspace_commands(item: &Item, path: &str) -> Result<Vec<(String, String)>> {
+    let Some(workspaces) = item.get("workspaces").and_then(Item::as_table_like) else {
+        return Ok(Vec::new());
+    };
+    workspaces
+        .iter()
+        .map(|(workspace, command)| {
+            let command = command
+                .as_str()
+                .with_context(|| format!("Invalid value of '{}.workspaces.{}'", path, workspace))?;
+            Ok((workspace.to_string(), command.to_string()))
+        })
+        .collect()
+}
+
+/// Old names of the scratchpad settings, and directions written in other cases
+fn migrate_scratchpads(doc: &mut DocumentMut, notices: &mut Vec<String>) {
+    if let Some(defaults) = doc
+        .get_mut("piri")
+        .and_then(|piri| piri.get_mut("scratchpad"))
+        .and_then(Item::as_table_like_mut)
+    {
+        rename_key(defaults, "piri.scratchpad", "size", "default_size", notices);
+        rename_key(
+            defaults,
+            "piri.scratchpad",
+            "margin",
+            "default_margin",
+            notices,
+        );
+    }
+
+    let Some(scratchpads) = doc.get_mut("scratchpads").and_then(Item::as_table_like_mut) else {
+        return;
+    };
+    for (name, scratchpad) in scratchpads.iter_mut() {
+        let Some(scratchpad) = scratchpad.as_table_like_mut() else {
+            continue;
+        };
+        let path = format!("scratchpads.{}", name.get());
+        rename_key(scratchpad, &path, "class", "app_id", notices);
+        rename_key(scratchpad, &path, "swallow", "swallow_to_focus", notices);
+
+        let Some(direction) = scratchpad.get_mut("direction").and_then(Item::as_value_mut) else {
+            continue;
+        };
+        let Some(old) = direction.as_str().map(str::to_string) else {
+            continue;
+        };
+        if let Some(new) = canonical_direction(&old).filter(|new| *new != old) {
+            notices.push(format!(
+                "`{}.direction = \"{}\"` is deprecated, use `\"{}\"`",
+                path, old, new
+            ));
+            let decor = direction.decor().clone();
+            *direction = new.into();
+            *direction.decor_mut() = decor;
+        }
+    }
+}
+
+/// Move `old` to `new` within a table; if both are set, `new` wins
+fn rename_key(
+    table: &mut dyn TableLike,
+    path: &str,
+    old: &str,
+    new: &str,
+    notices: &mut Vec<String>,
+) {
+    let Some(item) = table.remove(old) else {
+        return;
+    };
+    notices.push(format!(
+        "`{}.{}` is deprecated, use `{}.{}`",
+        path, old, path, new
+    ));
+    if !table.contains_key(new) {
+        table.insert(new, item);
+    }
+}
+
+/// `fromTop` for `top`, `from_top`, `FROM-TOP` and the like
+fn canonical_direction(direction: &str) -> Option<&'static str> {
+    let normalized = direction.to_lowercase().replace(['_', '-'], "");
+    match normalized.trim_start_matches("from") {
+        "top" => Some("fromTop"),
+        "bottom" => Some("fromBottom"),
+        "left" => Some("fromLeft"),
+        "right" => Some("fromRight"),
+        _ => None,
+    }
+}
+
+/// Number of unchanged lines shown around each change
+const CONTEXT: usize = 3;
+
+/// Line diff of two versions of a file, in the unified format of `diff -u`
+pub fn diff(old: &str, new: &str, name: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Longest common subsequence of the lines from each position to the end
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    // (prefix, old line index, new line index, line)
+    let mut ops: Vec<(char, usize, usize, &str)> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ops.push((' ', i, j, old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(('-', i, j, old[i]));
+            i += 1;
+        } else {
+            ops.push(('+', i, j, new[j]));
+            j += 1;
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", name, name);
+    let changes: Vec<usize> = (0..ops.len()).filter(|&k| ops[k].0 != ' ').collect();
+    let mut k = 0;
+    while k < changes.len() {
+        // Changes closer together than twice the context share a hunk
+        let start = changes[k].saturating_sub(CONTEXT);
+        let mut end = changes[k];
+        while k + 1 < changes.len() && changes[k + 1] <= end + 2 * CONTEXT + 1 {
+            k += 1;
+            end = changes[k];
+        }
+        let end = (end + CONTEXT + 1).min(ops.len());
+        k += 1;
+
+        let hunk = &ops[start..end];
+        let old_count = hunk.iter().filter(|op| op.0 != '+').count();
+        let new_count = hunk.iter().filter(|op| op.0 != '-').count();
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            hunk[0].1 + usize::from(old_count > 0),
+            old_count,
+            hunk[0].2 + usize::from(new_count > 0),
+            new_count
+        ));
+        for (prefix, _, _, line) in hunk {
+            out.push_str(&format!("{}{}\n", prefix, line));
+        }
+    }
+    out
+}
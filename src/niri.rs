@@ -6,9 +6,37 @@ use niri_ipc::{
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use crate::ipc::{ErrorKind, IpcError};
 use crate::utils::send_notification;
 
+/// Longest pause between two attempts to reach niri
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// The socket of a niri that was restarted in the same session, for when the one
+/// `$NIRI_SOCKET` points to can't be reached. Its name has niri's PID in it, e.g.
+/// `niri.wayland-1.1234.sock`, and a niri that crashed may leave the old one behind
+fn restarted_niri_socket() -> Option<PathBuf> {
+    let current = PathBuf::from(std::env::var_os(niri_ipc::socket::SOCKET_PATH_ENV)?);
+    let name = current.file_name()?.to_str()?;
+    // niri.<display>.<pid>.sock
+    let prefix = name.rsplitn(3, '.').nth(2)?;
+    let prefix = format!("{}.", prefix);
+    std::fs::read_dir(current.parent()?)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with(&prefix) && name.ends_with(".sock")
+        })
+        .filter(|entry| entry.path() != current)
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .max()
+        .map(|(_, path)| path)
+}
+
 /// Wrapper for niri IPC communication
 #[derive(Clone)]
 pub struct NiriIpc {
@@ -40,6 +68,9 @@ pub struct Window {
     pub layout: Option<WindowLayout>,
     #[serde(default)]
     pub pid: Option<u32>,
+    /// When the window was last focused (monotonic, only meaningful for ordering)
+    #[serde(default)]
+    pub focus_timestamp: Option<Duration>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -111,18 +142,59 @@ impl NiriIpc {
     fn connect_internal(&self) -> Result<Socket> {
         let path_guard =
             self.inner.socket_path.lock().map_err(|_| anyhow::anyhow!("Mutex poisoned"))?;
+        let unreachable = || {
+            IpcError::new(
+                ErrorKind::NiriUnreachable,
+                "Failed to connect to niri socket",
+            )
+        };
         let socket = if let Some(ref path) = *path_guard {
-            Socket::connect_to(path).context("Failed to connect to niri socket")?
+            Socket::connect_to(path).with_context(unreachable)?
         } else {
-            Socket::connect().context("Failed to connect to niri socket")?
+            match (Socket::connect(), restarted_niri_socket()) {
+                (Ok(socket), _) => socket,
+                (Err(_), Some(path)) => {
+                    let socket = Socket::connect_to(&path).with_context(unreachable)?;
+                    log::info!("niri restarted, now listening on {:?}", path);
+                    // Also for `niri msg` in the commands piri runs
+                    std::env::set_var(niri_ipc::socket::SOCKET_PATH_ENV, &path);
+                    socket
+                }
+                (Err(e), None) => return Err(e).with_context(unreachable),
+            }
         };
         Ok(socket)
     }
 
+    /// Wait until niri answers, retrying with a growing delay. piri may be started before
+    /// niri, e.g. by systemd, or niri may be restarting
+    pub async fn wait_until_ready(&self) {
+        let mut delay = Duration::from_millis(100);
+        let mut waiting = false;
+        loop {
+            match self.send_request(Request::Version).await {
+                Ok(_) => {
+                    if waiting {
+                        log::info!("niri is up");
+                    }
+                    return;
+                }
+                Err(e) if !waiting => {
+                    log::info!("Waiting for niri: {:#}", e);
+                    waiting = true;
+                }
+                Err(e) => log::debug!("niri is not up yet: {:#}", e),
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+        }
+    }
+
     /// Helper to send a request and get a response
     pub async fn send_request(&self, request: Request) -> Result<Response> {
         let niri = self.clone();
-        tokio::task::spawn_blocking(move || -> Result<Response> {
+        let start = Instant::now();
+        let response = tokio::task::spawn_blocking(move || -> Result<Response> {
             let mut guard =
                 niri.inner.socket.lock().map_err(|_| anyhow::anyhow!("Mutex poisoned"))?;
             if guard.is_none() {
@@ -147,7 +219,9 @@ impl NiriIpc {
             }
         })
         .await
-        .context("Task join error")?
+        .context("Task join error")?;
+        crate::metrics::observe("piri_niri_request_duration_seconds", &[], start.elapsed());
+        response
     }
 
     /// Helper to send an action and expect Ok
@@ -225,6 +299,9 @@ impl NiriIpc {
                                 pos_in_scrolling_layout: w.layout.pos_in_scrolling_layout,
                             }),
                             pid: w.pid.map(|p| p as u32),
+                            focus_timestamp: w
+                                .focus_timestamp
+                                .map(|ts| Duration::new(ts.secs, ts.nanos)),
                         }
                     })
                     .collect();
@@ -268,6 +345,7 @@ impl NiriIpc {
                 pos_in_scrolling_layout: niri_window.layout.pos_in_scrolling_layout,
             }),
             pid: niri_window.pid.map(|p| p as u32),
+            focus_timestamp: niri_window.focus_timestamp.map(|ts| Duration::new(ts.secs, ts.nanos)),
         })
     }
 
@@ -298,6 +376,26 @@ impl NiriIpc {
         }
     }
 
+    /// Get all connected outputs
+    pub async fn get_outputs(&self) -> Result<Vec<Output>> {
+        match self.send_request(Request::Outputs).await? {
+            Response::Outputs(outputs) => Ok(outputs
+                .into_values()
+                .map(|niri_output| Output {
+                    name: niri_output.name,
+                    focused: false,
+                    logical: niri_output.logical.map(|l| OutputLogical {
+                        width: l.width,
+                        height: l.height,
+                        x: l.x,
+                        y: l.y,
+                    }),
+                })
+                .collect()),
+            _ => anyhow::bail!("Unexpected response type for Outputs request"),
+        }
+    }
+
     /// Get focused workspace
     pub async fn get_focused_workspace(&self) -> Result<Workspace> {
         match self.send_request(Request::Workspaces).await? {
@@ -378,6 +476,11 @@ impl NiriIpc {
     /// Move floating window to focused output and workspace
     /// This moves the window to the current focused workspace and monitor
     pub async fn move_floating_window(&self, window_id: u64) -> Result<()> {
+        self.move_window_to_focused_workspace(window_id).await
+    }
+
+    /// Move any window (tiled or floating) to the focused output and workspace
+    pub async fn move_window_to_focused_workspace(&self, window_id: u64) -> Result<()> {
         // First, move window to the focused monitor
         self.move_window_to_monitor(window_id).await?;
 
@@ -426,6 +529,20 @@ impl NiriIpc {
         .await
     }
 
+    /// Move window to a workspace by its unique id (unlike idx, valid across outputs)
+    pub async fn move_window_to_workspace_id(
+        &self,
+        window_id: u64,
+        workspace_id: u64,
+    ) -> Result<()> {
+        self.send_action(Action::MoveWindowToWorkspace {
+            window_id: Some(window_id),
+            reference: WorkspaceReferenceArg::Id(workspace_id),
+            focus: false,
+        })
+        .await
+    }
+
     /// Set window to floating
     pub async fn set_window_floating(&self, window_id: u64, floating: bool) -> Result<()> {
         let action = if floating {
@@ -451,6 +568,16 @@ impl NiriIpc {
         .await
     }
 
+    /// Move floating window to an absolute position in the workspace view
+    pub async fn move_floating_window_to(&self, window_id: u64, x: f64, y: f64) -> Result<()> {
+        self.send_action(Action::MoveFloatingWindow {
+            id: Some(window_id),
+            x: PositionChange::SetFixed(x),
+            y: PositionChange::SetFixed(y),
+        })
+        .await
+    }
+
     /// Resize floating window using set-window-width and set-window-height
     pub async fn resize_floating_window(
         &self,
@@ -0,0 +1,184 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info};
+use niri_ipc::{Action, Event, Window, Workspace};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::config::{AutoFullscreenRule, Config, FullscreenMode};
+use crate::niri::NiriIpc;
+use crate::plugins::window_utils::{WindowMatcher, WindowMatcherCache};
+use crate::plugins::FromConfig;
+
+/// Auto fullscreen plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AutoFullscreenPluginConfig {
+    pub rules: Vec<AutoFullscreenRule>,
+}
+
+impl FromConfig for AutoFullscreenPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            rules: config.auto_fullscreen.clone(),
+        })
+    }
+}
+
+/// Fullscreens or maximizes matching windows while they are on the configured
+/// workspaces/outputs, and undoes it once they are moved elsewhere.
+pub struct AutoFullscreenPlugin {
+    niri: NiriIpc,
+    config: AutoFullscreenPluginConfig,
+    matcher_cache: WindowMatcherCache,
+    workspaces: HashMap<u64, Workspace>,
+    /// Mode applied by this plugin to every known window (None: left alone)
+    applied: HashMap<u64, Option<FullscreenMode>>,
+}
+
+impl AutoFullscreenPlugin {
+    fn rule_location_matches(rule: &AutoFullscreenRule, workspace: Option<&Workspace>) -> bool {
+        if let Some(ref names) = rule.workspace {
+            let Some(workspace) = workspace else {
+                return false;
+            };
+            let idx = workspace.idx.to_string();
+            if !names.iter().any(|n| Some(n) == workspace.name.as_ref() || *n == idx) {
+                return false;
+            }
+        }
+        if let Some(ref outputs) = rule.output {
+            let Some(output) = workspace.and_then(|ws| ws.output.as_ref()) else {
+                return false;
+            };
+            if !outputs.contains(output) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Mode the first matching rule asks for, given where the window is now
+    async fn desired_mode(&self, window: &Window) -> Result<Option<FullscreenMode>> {
+        let workspace = window.workspace_id.and_then(|id| self.workspaces.get(&id));
+        for rule in &self.config.rules {
+            if !Self::rule_location_matches(rule, workspace) {
+                continue;
+            }
+            let matcher = WindowMatcher::new(rule.app_id.clone(), rule.title.clone());
+            if self
+                .matcher_cache
+                .matches(window.app_id.as_ref(), window.title.as_ref(), &matcher)
+                .await?
+            {
+                return Ok(Some(rule.mode));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Toggle a mode on a window; applying and undoing are the same action
+    async fn toggle(&self, window_id: u64, mode: FullscreenMode) -> Result<()> {
+        match mode {
+            FullscreenMode::Fullscreen => {
+                self.niri
+                    .send_action(Action::FullscreenWindow {
+                        id: Some(window_id),
+                    })
+                    .await
+            }
+            FullscreenMode::Maximize => {
+                // MaximizeColumn acts on the focused column
+                let focused = self.niri.get_focused_window_id().await?;
+                if focused != Some(window_id) {
+                    self.niri.focus_window(window_id).await?;
+                }
+                self.niri.send_action(Action::MaximizeColumn {}).await?;
+                match focused {
+                    Some(previous) if previous != window_id => {
+                        self.niri.focus_window(previous).await
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+
+    async fn window_changed(&mut self, window: &Window) -> Result<()> {
+        let desired = self.desired_mode(window).await?;
+        let applied = self.applied.get(&window.id).copied().flatten();
+        if desired == applied {
+            self.applied.insert(window.id, desired);
+            return Ok(());
+        }
+
+        if let Some(mode) = applied {
+            debug!("Undoing {:?} of window {}", mode, window.id);
+            self.toggle(window.id, mode).await?;
+        }
+        if let Some(mode) = desired {
+            debug!("Applying {:?} to window {}", mode, window.id);
+            self.toggle(window.id, mode).await?;
+        }
+        self.applied.insert(window.id, desired);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for AutoFullscreenPlugin {
+    type Config = AutoFullscreenPluginConfig;
+
+    fn new(niri: NiriIpc, config: AutoFullscreenPluginConfig) -> Self {
+        info!(
+            "Auto fullscreen plugin initialized with {} rules",
+            config.rules.len()
+        );
+        Self {
+            niri,
+            config,
+            matcher_cache: WindowMatcherCache::new(),
+            workspaces: HashMap::new(),
+            applied: HashMap::new(),
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        match event {
+            Event::WorkspacesChanged { workspaces } => {
+                self.workspaces = workspaces.iter().map(|ws| (ws.id, ws.clone())).collect();
+            }
+            // Sent on (re)connect: assume existing windows are already as the rules want,
+            // since fullscreen can only be toggled
+            Event::WindowsChanged { windows } => {
+                self.applied.clear();
+                for window in windows {
+                    let desired = self.desired_mode(window).await?;
+                    self.applied.insert(window.id, desired);
+                }
+            }
+            Event::WindowOpenedOrChanged { window } => self.window_changed(window).await?,
+            Event::WindowClosed { id } => {
+                self.applied.remove(id);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(
+            event,
+            Event::WorkspacesChanged { .. }
+                | Event::WindowsChanged { .. }
+                | Event::WindowOpenedOrChanged { .. }
+                | Event::WindowClosed { .. }
+        )
+    }
+
+    async fn update_config(&mut self, config: AutoFullscreenPluginConfig) -> Result<()> {
+        info!("Updating auto fullscreen plugin configuration");
+        self.config = config;
+        self.matcher_cache.clear_cache().await;
+        Ok(())
+    }
+}
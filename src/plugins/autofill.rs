@@ -1,21 +1,429 @@
 use anyhow::Result;
 use log::{debug, info, warn};
-use niri_ipc::{Action, Event, Reply, Request};
+use niri_ipc::{Action, Event, Reply, Request, SizeChange};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
 
+use crate::config::{AutofillAlign, Config, ExcludeSection};
+use crate::ipc::IpcRequest;
 use crate::niri::NiriIpc;
+use crate::plugins::window_utils::{self, WindowMatcher, WindowMatcherCache};
+use crate::plugins::FromConfig;
 use crate::utils::send_notification;
 
-pub struct AutofillPlugin;
+/// Autofill plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AutofillPluginConfig {
+    /// Alignment mode
+    pub align: AutofillAlign,
+    /// Equalize column widths after a window closes
+    pub balance_columns: bool,
+    /// Only act when the workspace has at least this many columns
+    pub min_columns: usize,
+    /// Workspaces (name or idx) to align (empty = all workspaces)
+    pub workspaces: Vec<String>,
+    /// Outputs to align (empty = all outputs)
+    pub outputs: Vec<String>,
+    /// Regex pattern(s) of app_ids that never trigger alignment
+    pub exclude_app_id: Option<Vec<String>>,
+    /// `[piri.exclude]`, windows that never trigger alignment either
+    #[serde(default)]
+    pub global_exclude: ExcludeSection,
+    /// Maintain a master/stack arrangement instead of aligning columns
+    pub master_stack: bool,
+    /// Width of the master column in percent
+    pub master_width: f64,
+    /// Regex pattern(s) of app_ids that take the master column
+    pub master_app_id: Option<Vec<String>>,
+    /// Center the largest floating window once no tiled windows remain
+    pub center_floating: bool,
+}
+
+impl FromConfig for AutofillPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        let section = &config.piri.autofill;
+        Some(Self {
+            align: section.align,
+            balance_columns: section.balance_columns,
+            min_columns: section.min_columns,
+            workspaces: section.workspaces.clone(),
+            outputs: section.outputs.clone(),
+            exclude_app_id: section.exclude_app_id.clone(),
+            global_exclude: config.piri.exclude.clone(),
+            master_stack: section.master_stack,
+            master_width: section.master_width,
+            master_app_id: section.master_app_id.clone(),
+            center_floating: section.center_floating,
+        })
+    }
+}
+
+pub struct AutofillPlugin {
+    config: AutofillPluginConfig,
+    matcher_cache: Arc<WindowMatcherCache>,
+    /// Paused at runtime via IPC (kept across config reloads)
+    paused: bool,
+    /// Window IDs in the order they were opened, newest last (master-stack mode)
+    window_order: Vec<u64>,
+}
 
 impl AutofillPlugin {
-    async fn handle_event_internal(&self, _event: &Event, niri: &NiriIpc) -> Result<()> {
-        if let Err(e) = Self::check_and_align_last_column(niri).await {
+    async fn handle_event_internal(&self, event: &Event, niri: &NiriIpc) -> Result<()> {
+        if !self.should_align(event, niri).await? {
+            return Ok(());
+        }
+
+        let columns = Self::focused_workspace_columns(niri).await?;
+        if columns.is_empty() {
+            // Nothing to align on a floating-only (or empty) workspace
+            if self.config.center_floating && matches!(event, Event::WindowClosed { .. }) {
+                if let Err(e) = Self::center_largest_floating(niri).await {
+                    warn!("Autofill floating centering failed: {}", e);
+                }
+            } else {
+                debug!("No tiled windows on focused workspace, skipping autofill");
+            }
+            return Ok(());
+        }
+        if columns.len() < self.config.min_columns {
+            debug!(
+                "Workspace has {} columns (min_columns = {}), skipping autofill",
+                columns.len(),
+                self.config.min_columns
+            );
+            return Ok(());
+        }
+
+        if self.config.master_stack {
+            if let Err(e) = self.enforce_master_stack(niri).await {
+                warn!("Autofill master-stack arrangement failed: {}", e);
+                send_notification("piri", &format!("Autofill master-stack failed: {}", e));
+            }
+            return Ok(());
+        }
+
+        if self.config.balance_columns && matches!(event, Event::WindowClosed { .. }) {
+            if let Err(e) = Self::balance_columns(niri, columns).await {
+                warn!("Autofill column balancing failed: {}", e);
+            }
+        }
+
+        let result = match self.config.align {
+            AutofillAlign::Right => Self::check_and_align_last_column(niri).await,
+            AutofillAlign::Center => Self::center_focused_column(niri).await,
+        };
+        if let Err(e) = result {
             warn!("Autofill alignment failed: {}", e);
             send_notification("piri", &format!("Autofill alignment failed: {}", e));
         }
         Ok(())
     }
 
+    /// Whether a window matches `exclude_app_id` or `[piri.exclude]`
+    async fn is_excluded(&self, window: &crate::niri::Window) -> Result<bool> {
+        if let Some(ref exclude_app_id) = self.config.exclude_app_id {
+            let matcher = WindowMatcher::new(Some(exclude_app_id.clone()), None);
+            if self.matcher_cache.matches(window.app_id.as_ref(), None, &matcher).await? {
+                return Ok(true);
+            }
+        }
+        window_utils::is_excluded(
+            &self.config.global_exclude,
+            window.app_id.as_ref(),
+            Some(&window.title),
+            &self.matcher_cache,
+        )
+        .await
+    }
+
+    /// Check the workspace/output filters and the excluded app_ids against the current state
+    async fn should_align(&self, event: &Event, niri: &NiriIpc) -> Result<bool> {
+        let workspaces = niri.get_workspaces().await?;
+        let Some(focused_ws) = workspaces.iter().find(|ws| ws.is_focused) else {
+            return Ok(false);
+        };
+
+        if !window_utils::workspace_in_list(focused_ws, &self.config.workspaces) {
+            debug!(
+                "Workspace {} is not in autofill workspaces, skipping",
+                focused_ws.idx
+            );
+            return Ok(false);
+        }
+
+        if !self.config.outputs.is_empty() {
+            let on_output = focused_ws
+                .output
+                .as_ref()
+                .map(|output| self.config.outputs.contains(output))
+                .unwrap_or(false);
+            if !on_output {
+                debug!(
+                    "Output {:?} is not in autofill outputs, skipping",
+                    focused_ws.output
+                );
+                return Ok(false);
+            }
+        }
+
+        if self.config.exclude_app_id.is_none()
+            && self.config.global_exclude.app_id.is_none()
+            && self.config.global_exclude.title.is_none()
+        {
+            return Ok(true);
+        }
+        let windows = niri.get_windows().await?;
+
+        // Never move focus away from an excluded window
+        let focused_id = niri.get_focused_window_id().await?;
+        if let Some(window) = windows.iter().find(|w| Some(w.id) == focused_id) {
+            if self.is_excluded(window).await? {
+                debug!(
+                    "Focused window {} (app_id={:?}) is excluded from autofill",
+                    window.id, window.app_id
+                );
+                return Ok(false);
+            }
+        }
+
+        // Layout changes caused only by excluded windows don't trigger alignment
+        if let Event::WindowLayoutsChanged { changes } = event {
+            for (id, _) in changes {
+                let Some(window) = windows.iter().find(|w| w.id == *id) else {
+                    return Ok(true);
+                };
+                if !self.is_excluded(window).await? {
+                    return Ok(true);
+                }
+            }
+            debug!("Only excluded windows changed layout, skipping autofill");
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// Get one representative tiled window per column on the focused workspace,
+    /// ordered by column index
+    async fn focused_workspace_columns(niri: &NiriIpc) -> Result<Vec<u64>> {
+        let workspaces = niri.get_workspaces().await?;
+        let Some(workspace_id) = workspaces.iter().find(|ws| ws.is_focused).map(|ws| ws.id) else {
+            return Ok(Vec::new());
+        };
+
+        let mut columns: BTreeMap<usize, u64> = BTreeMap::new();
+        for window in niri.get_windows().await? {
+            if window.floating || window.workspace_id != Some(workspace_id) {
+                continue;
+            }
+            if let Some((col, _)) = window.layout.as_ref().and_then(|l| l.pos_in_scrolling_layout) {
+                columns.entry(col).or_insert(window.id);
+            }
+        }
+        Ok(columns.into_values().collect())
+    }
+
+    /// Give every tiled column on the focused workspace the same proportional width
+    async fn balance_columns(niri: &NiriIpc, column_windows: Vec<u64>) -> Result<()> {
+        if column_windows.len() < 2 {
+            return Ok(());
+        }
+
+        let proportion = 100.0 / column_windows.len() as f64;
+        debug!(
+            "Balancing {} columns to {:.1}% each",
+            column_windows.len(),
+            proportion
+        );
+
+        niri.execute_batch(move |socket| {
+            let reply = socket.send(Request::FocusedWindow)?;
+            let focused_window_id = match reply {
+                Reply::Ok(niri_ipc::Response::FocusedWindow(Some(w))) => Some(w.id),
+                _ => None,
+            };
+
+            for &window_id in &column_windows {
+                let _ = socket.send(Request::Action(Action::FocusWindow { id: window_id }))?;
+                let _ = socket.send(Request::Action(Action::SetColumnWidth {
+                    change: SizeChange::SetProportion(proportion),
+                }))?;
+            }
+
+            if let Some(window_id) = focused_window_id {
+                let _ = socket.send(Request::Action(Action::FocusWindow { id: window_id }))?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Center the floating window with the largest area on the focused workspace
+    async fn center_largest_floating(niri: &NiriIpc) -> Result<()> {
+        let workspaces = niri.get_workspaces().await?;
+        let Some(workspace_id) = workspaces.iter().find(|ws| ws.is_focused).map(|ws| ws.id) else {
+            return Ok(());
+        };
+
+        let largest = niri
+            .get_windows()
+            .await?
+            .into_iter()
+            .filter(|w| w.floating && w.workspace_id == Some(workspace_id))
+            .max_by_key(|w| {
+                w.layout
+                    .as_ref()
+                    .and_then(|l| l.window_size)
+                    .map(|[width, height]| width as u64 * height as u64)
+                    .unwrap_or(0)
+            });
+        let Some(window) = largest else {
+            return Ok(());
+        };
+
+        debug!(
+            "Only floating windows remain, centering window {}",
+            window.id
+        );
+        niri.send_action(Action::CenterWindow {
+            id: Some(window.id),
+        })
+        .await
+    }
+
+    /// Tiled windows on the focused workspace with their (column, tile) position
+    async fn focused_workspace_tiles(niri: &NiriIpc) -> Result<Vec<(u64, usize, usize)>> {
+        let workspaces = niri.get_workspaces().await?;
+        let Some(workspace_id) = workspaces.iter().find(|ws| ws.is_focused).map(|ws| ws.id) else {
+            return Ok(Vec::new());
+        };
+
+        let mut tiles: Vec<(u64, usize, usize)> = niri
+            .get_windows()
+            .await?
+            .into_iter()
+            .filter(|w| !w.floating && w.workspace_id == Some(workspace_id))
+            .filter_map(|w| {
+                let (col, tile) = w.layout.as_ref()?.pos_in_scrolling_layout?;
+                Some((w.id, col, tile))
+            })
+            .collect();
+        tiles.sort_by_key(|&(_, col, tile)| (col, tile));
+        Ok(tiles)
+    }
+
+    /// Pick the master window: the first one matching `master_app_id`, otherwise the
+    /// most recently opened one, otherwise whatever is in the first column
+    async fn pick_master(&self, niri: &NiriIpc, tiles: &[(u64, usize, usize)]) -> Result<u64> {
+        if let Some(ref master_app_id) = self.config.master_app_id {
+            let matcher = WindowMatcher::new(Some(master_app_id.clone()), None);
+            let windows = niri.get_windows().await?;
+            for &(id, _, _) in tiles {
+                let Some(window) = windows.iter().find(|w| w.id == id) else {
+                    continue;
+                };
+                if self.matcher_cache.matches(window.app_id.as_ref(), None, &matcher).await? {
+                    return Ok(id);
+                }
+            }
+        }
+
+        let newest = self
+            .window_order
+            .iter()
+            .rev()
+            .find(|id| tiles.iter().any(|&(tile_id, _, _)| tile_id == **id));
+        Ok(newest.copied().unwrap_or(tiles[0].0))
+    }
+
+    /// Move the master window into its own first column and consume every other tiled
+    /// window into a single stack column right next to it
+    async fn enforce_master_stack(&self, niri: &NiriIpc) -> Result<()> {
+        let tiles = Self::focused_workspace_tiles(niri).await?;
+        if tiles.len() < 2 {
+            return Ok(());
+        }
+
+        let master = self.pick_master(niri, &tiles).await?;
+        let master_col = tiles.iter().find(|&&(id, _, _)| id == master).map(|&(_, col, _)| col);
+        let master_alone =
+            tiles.iter().filter(|&&(_, col, _)| Some(col) == master_col).count() == 1;
+        let arranged = master_col == Some(1)
+            && master_alone
+            && tiles.iter().all(|&(id, col, _)| id == master || col == 2);
+        if arranged {
+            debug!("Master-stack arrangement already in place");
+            return Ok(());
+        }
+
+        debug!("Arranging master-stack with master window {}", master);
+        let focused_window_id = niri.get_focused_window_id().await?;
+        let width = self.config.master_width;
+
+        // 1. Give the master window its own first column
+        niri.execute_batch(move |socket| {
+            let _ = socket.send(Request::Action(Action::FocusWindow { id: master }))?;
+            if !master_alone {
+                let _ = socket.send(Request::Action(Action::ExpelWindowFromColumn {}))?;
+            }
+            let _ = socket.send(Request::Action(Action::MoveColumnToFirst {}))?;
+            let _ = socket.send(Request::Action(Action::SetColumnWidth {
+                change: SizeChange::SetProportion(width),
+            }))?;
+            Ok(())
+        })
+        .await?;
+
+        // 2. Consume everything right of the second column into it
+        let tiles = Self::focused_workspace_tiles(niri).await?;
+        let stack = tiles.iter().find(|&&(_, col, _)| col == 2).map(|&(id, _, _)| id);
+        let stragglers = tiles.iter().filter(|&&(_, col, _)| col > 2).count();
+
+        niri.execute_batch(move |socket| {
+            if let Some(stack) = stack {
+                let _ = socket.send(Request::Action(Action::FocusWindow { id: stack }))?;
+                for _ in 0..stragglers {
+                    let _ = socket.send(Request::Action(Action::ConsumeWindowIntoColumn {}))?;
+                }
+            }
+            if let Some(window_id) = focused_window_id {
+                let _ = socket.send(Request::Action(Action::FocusWindow { id: window_id }))?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Track window open/close order; returns false for events that don't change
+    /// the set of windows (e.g. title updates of known windows)
+    fn track_window_order(&mut self, event: &Event) -> bool {
+        match event {
+            Event::WindowsChanged { windows } => {
+                // Initial snapshot: keep known order, append anything new
+                self.window_order.retain(|id| windows.iter().any(|w| w.id == *id));
+                for window in windows {
+                    if !self.window_order.contains(&window.id) {
+                        self.window_order.push(window.id);
+                    }
+                }
+                false
+            }
+            Event::WindowOpenedOrChanged { window } => {
+                if self.window_order.contains(&window.id) {
+                    return false;
+                }
+                self.window_order.push(window.id);
+                !window.is_floating
+            }
+            Event::WindowClosed { id } => {
+                self.window_order.retain(|window_id| window_id != id);
+                true
+            }
+            _ => true,
+        }
+    }
+
     async fn check_and_align_last_column(niri: &NiriIpc) -> Result<()> {
         debug!("Aligning columns in current workspace (batched original logic)");
 
@@ -41,25 +449,85 @@ impl AutofillPlugin {
         })
         .await
     }
+
+    async fn center_focused_column(niri: &NiriIpc) -> Result<()> {
+        debug!("Centering focused column in current workspace");
+
+        niri.execute_batch(|socket| {
+            // Floating windows are not part of the scrolling layout, so there is nothing to center
+            let reply = socket.send(Request::FocusedWindow)?;
+            if let Reply::Ok(niri_ipc::Response::FocusedWindow(Some(w))) = reply {
+                if !w.is_floating {
+                    let _ =
+                        socket.send(Request::Action(Action::CenterWindow { id: Some(w.id) }))?;
+                }
+            }
+            Ok(())
+        })
+        .await
+    }
 }
 
 #[async_trait::async_trait]
 impl crate::plugins::Plugin for AutofillPlugin {
-    type Config = ();
+    type Config = AutofillPluginConfig;
 
-    fn new(_niri: NiriIpc, _config: ()) -> Self {
+    fn new(_niri: NiriIpc, config: AutofillPluginConfig) -> Self {
         info!("Autofill plugin initialized");
-        Self
+        Self {
+            config,
+            matcher_cache: Arc::new(WindowMatcherCache::new()),
+            paused: false,
+            window_order: Vec::new(),
+        }
     }
 
     async fn handle_event(&mut self, event: &Event, niri: &NiriIpc) -> Result<()> {
+        if !self.track_window_order(event) {
+            return Ok(());
+        }
         self.handle_event_internal(event, niri).await
     }
 
+    async fn on_niri_reconnected(&mut self, _niri: &NiriIpc) -> Result<()> {
+        self.window_order.clear();
+        Ok(())
+    }
+
     fn is_interested_in_event(&self, event: &Event) -> bool {
-        matches!(
-            event,
-            Event::WindowClosed { .. } | Event::WindowLayoutsChanged { .. }
-        )
+        // While paused, the plugin manager skips autofill entirely
+        if self.paused {
+            return false;
+        }
+        // Master-stack re-arranges on open/close only; reacting to layout changes
+        // would fight the arrangement it just made
+        match event {
+            Event::WindowClosed { .. } => true,
+            Event::WindowLayoutsChanged { .. } => !self.config.master_stack,
+            Event::WindowsChanged { .. } | Event::WindowOpenedOrChanged { .. } => {
+                self.config.master_stack
+            }
+            _ => false,
+        }
+    }
+
+    async fn update_config(&mut self, config: AutofillPluginConfig) -> Result<()> {
+        info!("Updating autofill plugin configuration");
+        self.config = config;
+        self.matcher_cache.clear_cache().await;
+        Ok(())
+    }
+
+    async fn handle_ipc_request(&mut self, request: &IpcRequest) -> Result<Option<Result<()>>> {
+        match request {
+            IpcRequest::AutofillToggle => {
+                self.paused = !self.paused;
+                let state = if self.paused { "paused" } else { "resumed" };
+                info!("Autofill {}", state);
+                send_notification("piri", &format!("Autofill {}", state));
+                Ok(Some(Ok(())))
+            }
+            _ => Ok(None),
+        }
     }
 }
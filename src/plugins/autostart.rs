@@ -0,0 +1,166 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::config::{AutostartEntry, Config};
+use crate::niri::NiriIpc;
+use crate::plugins::window_utils::{self, WindowMatcher, WindowMatcherCache};
+use crate::plugins::FromConfig;
+use crate::utils::send_notification;
+
+/// Autostart plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AutostartPluginConfig {
+    pub entries: Vec<AutostartEntry>,
+}
+
+impl FromConfig for AutostartPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            entries: config.autostart.clone(),
+        })
+    }
+}
+
+/// Launches the `[[autostart]]` entries one after another when the daemon starts, waiting
+/// for each entry's window and moving it to its workspace/output.
+pub struct AutostartPlugin;
+
+/// Wait for a window matching `matcher` that wasn't open before the launch
+async fn wait_for_new_window(
+    niri: &NiriIpc,
+    matcher: &WindowMatcher,
+    matcher_cache: &WindowMatcherCache,
+    existing: &HashSet<u64>,
+    timeout: Duration,
+) -> Result<Option<u64>> {
+    for _ in 0..(timeout.as_millis() / 100).max(1) {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        for window in niri.get_windows().await? {
+            if existing.contains(&window.id) {
+                continue;
+            }
+            if matcher_cache.matches(window.app_id.as_ref(), None, matcher).await? {
+                return Ok(Some(window.id));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Move a window to the entry's workspace (by name, then idx) and/or output
+async fn place_window(niri: &NiriIpc, window_id: u64, entry: &AutostartEntry) -> Result<()> {
+    let workspaces = niri.get_workspaces().await?;
+    let on_output = |ws: &&niri_ipc::Workspace| match entry.output {
+        Some(ref output) => ws.output.as_ref() == Some(output),
+        None => true,
+    };
+    let target = match (&entry.workspace, &entry.output) {
+        (Some(workspace), _) => workspaces
+            .iter()
+            .filter(on_output)
+            .find(|ws| ws.name.as_ref() == Some(workspace))
+            .or_else(|| {
+                workspaces.iter().filter(on_output).find(|ws| ws.idx.to_string() == *workspace)
+            }),
+        (None, Some(_)) => workspaces.iter().filter(on_output).find(|ws| ws.is_active),
+        (None, None) => return Ok(()),
+    };
+
+    match target {
+        Some(workspace) => {
+            debug!("Moving window {} to workspace {}", window_id, workspace.id);
+            niri.move_window_to_workspace_id(window_id, workspace.id).await
+        }
+        None => {
+            warn!(
+                "No workspace {:?} on output {:?}, leaving window {} in place",
+                entry.workspace, entry.output, window_id
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Launch all entries in order. Returns the number of entries whose window didn't show up
+async fn run_autostart(niri: &NiriIpc, entries: &[AutostartEntry]) -> Result<usize> {
+    let matcher_cache = WindowMatcherCache::new();
+    let mut failed = 0;
+
+    for entry in entries {
+        let Some(ref app_id) = entry.app_id else {
+            info!("Autostart: launching {}", entry.command);
+            window_utils::execute(&entry.command)?;
+            continue;
+        };
+        let matcher = WindowMatcher::new(Some(app_id.clone()), None);
+        let app_id = app_id.join(", ");
+
+        let windows = niri.get_windows().await?;
+        let mut existing = HashSet::new();
+        let mut running = false;
+        for window in &windows {
+            existing.insert(window.id);
+            if matcher_cache.matches(window.app_id.as_ref(), None, &matcher).await? {
+                running = true;
+            }
+        }
+        // The daemon may have been restarted, don't launch apps twice
+        if running {
+            info!("Autostart: {} is already running, skipping", app_id);
+            continue;
+        }
+
+        info!("Autostart: launching {}", entry.command);
+        window_utils::execute(&entry.command)?;
+        match wait_for_new_window(niri, &matcher, &matcher_cache, &existing, entry.timeout.0)
+            .await?
+        {
+            Some(window_id) => {
+                if let Err(e) = place_window(niri, window_id, entry).await {
+                    warn!("Failed to place window {} ({}): {}", window_id, app_id, e);
+                }
+            }
+            None => {
+                warn!(
+                    "Autostart: no {} window after {}, continuing",
+                    app_id, entry.timeout
+                );
+                failed += 1;
+            }
+        }
+    }
+    Ok(failed)
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for AutostartPlugin {
+    type Config = AutostartPluginConfig;
+
+    fn new(niri: NiriIpc, config: AutostartPluginConfig) -> Self {
+        info!(
+            "Autostart plugin initialized with {} entries",
+            config.entries.len()
+        );
+        // Only runs once per plugin instance; config reloads don't launch anything
+        if !config.entries.is_empty() {
+            tokio::spawn(async move {
+                match run_autostart(&niri, &config.entries).await {
+                    Ok(0) => info!("Autostart finished"),
+                    Ok(failed) => send_notification(
+                        "piri",
+                        &format!("Autostart: {} apps didn't open a window", failed),
+                    ),
+                    Err(e) => {
+                        warn!("Autostart failed: {}", e);
+                        send_notification("piri", &format!("Autostart failed: {}", e));
+                    }
+                }
+            });
+        }
+        Self
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::info;
+use niri_ipc::{Event, Workspace};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::config::Config;
+use crate::niri::NiriIpc;
+use crate::plugins::FromConfig;
+
+/// Bar plugin config (for internal use)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BarPluginConfig {
+    /// Format of the `text` field
+    pub format: String,
+}
+
+impl FromConfig for BarPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            format: config.piri.bar.format.clone(),
+        })
+    }
+}
+
+/// Focused workspace as reported to status bars
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceStatus {
+    pub id: u64,
+    pub idx: u8,
+    pub name: Option<String>,
+    pub output: Option<String>,
+}
+
+impl WorkspaceStatus {
+    fn label(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.idx.to_string())
+    }
+}
+
+/// Status bar plugin: tracks the focused workspace and renders the combined
+/// plugin status into waybar-compatible JSON for `piri bar`
+pub struct BarPlugin {
+    config: BarPluginConfig,
+    workspaces: Vec<Workspace>,
+}
+
+impl BarPlugin {
+    fn focused_workspace(&self) -> Option<WorkspaceStatus> {
+        self.workspaces.iter().find(|ws| ws.is_focused).map(|ws| WorkspaceStatus {
+            id: ws.id,
+            idx: ws.idx,
+            name: ws.name.clone(),
+            output: ws.output.clone(),
+        })
+    }
+
+    /// Build a waybar custom module object (`text`, `alt`, `tooltip`, `class`)
+    /// from the status sections of all plugins. The sections are kept in the
+    /// output so scripts can use the raw state as well.
+    pub fn render(&self, mut sections: Map<String, Value>) -> Value {
+        let workspace = self.focused_workspace();
+        let workspace_label = workspace.as_ref().map(|ws| ws.label()).unwrap_or_default();
+        let output = workspace.as_ref().and_then(|ws| ws.output.clone()).unwrap_or_default();
+
+        // scratchpads: { name: { visible, window_id } }
+        let visible_scratchpads: Vec<&str> = sections
+            .get("scratchpads")
+            .and_then(Value::as_object)
+            .map(|scratchpads| {
+                scratchpads
+                    .iter()
+                    .filter(|(_, s)| s["visible"].as_bool().unwrap_or(false))
+                    .map(|(name, _)| name.as_str())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // singleton: [ { name, running, ... } ]
+        let running_singletons: Vec<&str> = sections
+            .get("singleton")
+            .and_then(Value::as_array)
+            .map(|singletons| {
+                singletons
+                    .iter()
+                    .filter(|s| s["running"].as_bool().unwrap_or(false))
+                    .filter_map(|s| s["name"].as_str())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let text = self
+            .config
+            .format
+            .replace("{workspace}", &workspace_label)
+            .replace("{output}", &output)
+            .replace("{scratchpads}", &visible_scratchpads.join(","))
+            .replace("{singletons}", &running_singletons.join(","));
+
+        let mut tooltip = vec![format!("Workspace: {} ({})", workspace_label, output)];
+        if !visible_scratchpads.is_empty() {
+            tooltip.push(format!("Scratchpads: {}", visible_scratchpads.join(", ")));
+        }
+        if !running_singletons.is_empty() {
+            tooltip.push(format!("Singletons: {}", running_singletons.join(", ")));
+        }
+
+        let mut class = Vec::new();
+        if !visible_scratchpads.is_empty() {
+            class.push("scratchpad-visible");
+        }
+
+        let mut status = json!({
+            // Empty placeholders would otherwise leave stray spaces behind
+            "text": text.split_whitespace().collect::<Vec<_>>().join(" "),
+            "alt": workspace_label,
+            "tooltip": tooltip.join("\n"),
+            "class": class,
+            "workspace": workspace,
+        });
+        if let Some(object) = status.as_object_mut() {
+            object.append(&mut sections);
+        }
+        status
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for BarPlugin {
+    type Config = BarPluginConfig;
+
+    fn new(_niri: NiriIpc, config: BarPluginConfig) -> Self {
+        info!("Bar plugin initialized");
+        Self {
+            config,
+            workspaces: Vec::new(),
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        match event {
+            Event::WorkspacesChanged { workspaces } => {
+                self.workspaces = workspaces.clone();
+            }
+            Event::WorkspaceActivated { id, focused } if *focused => {
+                for workspace in &mut self.workspaces {
+                    workspace.is_focused = workspace.id == *id;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(
+            event,
+            Event::WorkspacesChanged { .. } | Event::WorkspaceActivated { .. }
+        )
+    }
+
+    async fn update_config(&mut self, config: BarPluginConfig) -> Result<()> {
+        info!("Updating bar plugin configuration");
+        self.config = config;
+        Ok(())
+    }
+}
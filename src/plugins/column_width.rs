@@ -0,0 +1,154 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use niri_ipc::{Action, Event, SizeChange, Window};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::config::{ColumnWidthRule, Config};
+use crate::niri::NiriIpc;
+use crate::plugins::window_utils::{WindowMatcher, WindowMatcherCache};
+use crate::plugins::FromConfig;
+
+/// Column width plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ColumnWidthPluginConfig {
+    pub rules: Vec<ColumnWidthRule>,
+}
+
+impl FromConfig for ColumnWidthPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            rules: config.column_width.clone(),
+        })
+    }
+}
+
+/// Sets the column width of matching windows whenever they become tiled, either by
+/// opening tiled or by being switched from floating to tiled.
+pub struct ColumnWidthPlugin {
+    niri: NiriIpc,
+    config: ColumnWidthPluginConfig,
+    matcher_cache: WindowMatcherCache,
+    /// Window ID -> whether the window is tiled
+    tiled: HashMap<u64, bool>,
+}
+
+impl ColumnWidthPlugin {
+    /// Parse a width ("one-third", "half", "two-thirds", "40%" or "800" pixels)
+    fn parse_width(width: &str) -> Result<SizeChange> {
+        let width = width.trim();
+        let change = match width {
+            "one-third" => SizeChange::SetProportion(100.0 / 3.0),
+            "half" => SizeChange::SetProportion(50.0),
+            "two-thirds" => SizeChange::SetProportion(200.0 / 3.0),
+            _ => match width.strip_suffix('%') {
+                Some(percent) => SizeChange::SetProportion(
+                    percent
+                        .trim()
+                        .parse::<f64>()
+                        .map_err(|_| anyhow::anyhow!("Invalid column width '{}'", width))?,
+                ),
+                None => SizeChange::SetFixed(
+                    width
+                        .parse::<i32>()
+                        .map_err(|_| anyhow::anyhow!("Invalid column width '{}'", width))?,
+                ),
+            },
+        };
+        Ok(change)
+    }
+
+    /// Width of the first rule matching the window
+    async fn desired_width(&self, window: &Window) -> Result<Option<&ColumnWidthRule>> {
+        for rule in &self.config.rules {
+            let matcher = WindowMatcher::new(rule.app_id.clone(), None);
+            if self.matcher_cache.matches(window.app_id.as_ref(), None, &matcher).await? {
+                return Ok(Some(rule));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn apply(&self, window: &Window) -> Result<()> {
+        let Some(rule) = self.desired_width(window).await? else {
+            return Ok(());
+        };
+        let change = match Self::parse_width(&rule.width) {
+            Ok(change) => change,
+            Err(e) => {
+                warn!("{}", e);
+                return Ok(());
+            }
+        };
+        debug!(
+            "Setting column width of window {} ({:?}) to {}",
+            window.id, window.app_id, rule.width
+        );
+
+        // SetColumnWidth acts on the focused column
+        let focused = self.niri.get_focused_window_id().await?;
+        if focused != Some(window.id) {
+            self.niri.focus_window(window.id).await?;
+        }
+        self.niri.send_action(Action::SetColumnWidth { change }).await?;
+        match focused {
+            Some(previous) if previous != window.id => self.niri.focus_window(previous).await,
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for ColumnWidthPlugin {
+    type Config = ColumnWidthPluginConfig;
+
+    fn new(niri: NiriIpc, config: ColumnWidthPluginConfig) -> Self {
+        info!(
+            "Column width plugin initialized with {} rules",
+            config.rules.len()
+        );
+        Self {
+            niri,
+            config,
+            matcher_cache: WindowMatcherCache::new(),
+            tiled: HashMap::new(),
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        match event {
+            // Sent on (re)connect: existing windows keep whatever width they have
+            Event::WindowsChanged { windows } => {
+                self.tiled = windows.iter().map(|w| (w.id, !w.is_floating)).collect();
+            }
+            Event::WindowOpenedOrChanged { window } => {
+                let was_tiled = self.tiled.insert(window.id, !window.is_floating);
+                if !window.is_floating && was_tiled != Some(true) {
+                    self.apply(window).await?;
+                }
+            }
+            Event::WindowClosed { id } => {
+                self.tiled.remove(id);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(
+            event,
+            Event::WindowsChanged { .. }
+                | Event::WindowOpenedOrChanged { .. }
+                | Event::WindowClosed { .. }
+        )
+    }
+
+    async fn update_config(&mut self, config: ColumnWidthPluginConfig) -> Result<()> {
+        info!("Updating column width plugin configuration");
+        self.config = config;
+        self.matcher_cache.clear_cache().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info};
+use niri_ipc::Action;
+
+use crate::ipc::IpcRequest;
+use crate::niri::NiriIpc;
+
+/// A tiled window and its position in the scrolling layout (1-based, like niri)
+#[derive(Debug, Clone, Copy)]
+struct Tile {
+    id: u64,
+    column: usize,
+    tile: usize,
+}
+
+/// Layout operations built from niri's column/window move actions: rotating the windows
+/// of the focused column, swapping the focused window with the master (leftmost) column
+/// and reversing the column order of the workspace.
+pub struct ColumnsPlugin {
+    niri: NiriIpc,
+}
+
+impl ColumnsPlugin {
+    /// The focused window and the tiled windows of its workspace, sorted by position
+    async fn focused_workspace_tiles(&self) -> Result<(Tile, Vec<Tile>)> {
+        let windows = self.niri.get_windows().await?;
+        let focused_id = self
+            .niri
+            .get_focused_window_id()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("No focused window"))?;
+        let focused_window = windows
+            .iter()
+            .find(|w| w.id == focused_id)
+            .ok_or_else(|| anyhow::anyhow!("Focused window {} not found", focused_id))?;
+        if focused_window.floating {
+            anyhow::bail!("The focused window is floating");
+        }
+        let workspace_id = focused_window.workspace_id;
+
+        let mut tiles: Vec<Tile> = windows
+            .iter()
+            .filter(|w| !w.floating && w.workspace_id == workspace_id)
+            .filter_map(|w| {
+                let (column, tile) = w.layout.as_ref()?.pos_in_scrolling_layout?;
+                Some(Tile {
+                    id: w.id,
+                    column,
+                    tile,
+                })
+            })
+            .collect();
+        tiles.sort_by_key(|t| (t.column, t.tile));
+
+        let focused = tiles
+            .iter()
+            .find(|t| t.id == focused_id)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("Focused window {} is not tiled", focused_id))?;
+        Ok((focused, tiles))
+    }
+
+    /// Rotate the windows of the focused column by one: down (the bottom window wraps to
+    /// the top) or, with `backward`, up
+    async fn rotate(&self, backward: bool) -> Result<()> {
+        let (focused, tiles) = self.focused_workspace_tiles().await?;
+        let column: Vec<Tile> = tiles.into_iter().filter(|t| t.column == focused.column).collect();
+        if column.len() < 2 {
+            debug!(
+                "Column {} has a single window, nothing to rotate",
+                focused.column
+            );
+            return Ok(());
+        }
+
+        // Walk the window at one end of the column over all the others
+        let (moving, action) = if backward {
+            (column[0], Action::MoveWindowDown {})
+        } else {
+            (column[column.len() - 1], Action::MoveWindowUp {})
+        };
+        info!(
+            "Rotating column {} ({} windows)",
+            focused.column,
+            column.len()
+        );
+        self.niri.focus_window(moving.id).await?;
+        for _ in 1..column.len() {
+            self.niri.send_action(action.clone()).await?;
+        }
+        self.niri.focus_window(focused.id).await
+    }
+
+    /// Swap the focused window with the master column. A window sharing its column is
+    /// expelled first; if it already is in the master column, the first two columns swap
+    async fn swap_master(&self) -> Result<()> {
+        let (focused, tiles) = self.focused_workspace_tiles().await?;
+        let columns = tiles.last().map_or(0, |t| t.column);
+        if focused.column == 1 {
+            if columns < 2 {
+                debug!("Only the master column on the workspace, nothing to swap");
+                return Ok(());
+            }
+            info!("Swapping the master column with the second column");
+            return self.niri.send_action(Action::MoveColumnRight {}).await;
+        }
+
+        // Any window of the master column, to move it to the focused window's place later
+        let master = tiles[0];
+        let alone = tiles.iter().filter(|t| t.column == focused.column).count() == 1;
+        let mut target = focused.column;
+        if !alone {
+            // The expelled window gets its own column right of its old one
+            self.niri.send_action(Action::ExpelWindowFromColumn {}).await?;
+            target += 1;
+        }
+
+        info!(
+            "Swapping window {} (column {}) with the master column",
+            focused.id, focused.column
+        );
+        self.niri.send_action(Action::MoveColumnToIndex { index: 1 }).await?;
+        self.niri.focus_window(master.id).await?;
+        self.niri.send_action(Action::MoveColumnToIndex { index: target }).await?;
+        self.niri.focus_window(focused.id).await
+    }
+
+    /// Reverse the column order of the focused window's workspace
+    async fn reverse(&self) -> Result<()> {
+        let (focused, tiles) = self.focused_workspace_tiles().await?;
+        // One window per column, leftmost first
+        let mut columns: Vec<Tile> = Vec::new();
+        for tile in tiles {
+            if columns.last().map(|c| c.column) != Some(tile.column) {
+                columns.push(tile);
+            }
+        }
+        if columns.len() < 2 {
+            debug!("Fewer than two columns, nothing to reverse");
+            return Ok(());
+        }
+
+        info!("Reversing {} columns", columns.len());
+        // Moving the original columns from right to left to the front, one index further
+        // each time, leaves them in reverse order; the first one ends up last by itself
+        for (i, column) in columns.iter().rev().take(columns.len() - 1).enumerate() {
+            self.niri.focus_window(column.id).await?;
+            self.niri.send_action(Action::MoveColumnToIndex { index: i + 1 }).await?;
+        }
+        self.niri.focus_window(focused.id).await
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for ColumnsPlugin {
+    type Config = ();
+
+    fn new(niri: NiriIpc, _config: ()) -> Self {
+        info!("Columns plugin initialized");
+        Self { niri }
+    }
+
+    async fn handle_ipc_request(&mut self, request: &IpcRequest) -> Result<Option<Result<()>>> {
+        match request {
+            IpcRequest::ColumnRotate { backward } => Ok(Some(self.rotate(*backward).await)),
+            IpcRequest::ColumnSwapMaster => Ok(Some(self.swap_master().await)),
+            IpcRequest::ColumnReverse => Ok(Some(self.reverse().await)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn update_config(&mut self, _config: ()) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{CommandLine, Config};
+use crate::ipc::IpcRequest;
+use crate::niri::NiriIpc;
+use crate::plugins::{request_suspensions, window_utils, FromConfig};
+use crate::utils::set_do_not_disturb;
+
+/// Do-not-disturb plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DndPluginConfig {
+    /// Plugins suspended while do-not-disturb is on
+    pub suspend: Vec<String>,
+    /// Command run when do-not-disturb is turned on
+    pub on_enter: Option<CommandLine>,
+    /// Command run when do-not-disturb is turned off
+    pub on_exit: Option<CommandLine>,
+}
+
+impl FromConfig for DndPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        let section = &config.piri.dnd;
+        Some(Self {
+            suspend: section.suspend.clone(),
+            on_enter: section.on_enter.clone(),
+            on_exit: section.on_exit.clone(),
+        })
+    }
+}
+
+/// Do-not-disturb mode: while on, piri's own notifications are dropped, the configured
+/// plugins are suspended and the on_enter hook has run.
+pub struct DndPlugin {
+    config: DndPluginConfig,
+    enabled: bool,
+}
+
+impl DndPlugin {
+    fn run_hook(command: &Option<CommandLine>) {
+        if let Some(command) = command {
+            debug!("Running do-not-disturb hook: {}", command);
+            if let Err(e) = window_utils::execute(command) {
+                warn!("Failed to run do-not-disturb hook: {}", e);
+            }
+        }
+    }
+
+    /// Turn do-not-disturb on or off (None: toggle)
+    fn set(&mut self, enabled: Option<bool>) {
+        let enabled = enabled.unwrap_or(!self.enabled);
+        if enabled == self.enabled {
+            return;
+        }
+        info!("Do not disturb {}", if enabled { "on" } else { "off" });
+        self.enabled = enabled;
+        set_do_not_disturb(enabled);
+        request_suspensions("dnd", vec![(self.config.suspend.clone(), enabled)]);
+        Self::run_hook(if enabled {
+            &self.config.on_enter
+        } else {
+            &self.config.on_exit
+        });
+    }
+}
+
+impl Drop for DndPlugin {
+    fn drop(&mut self) {
+        // The plugin manager lifts suspensions itself once no plugin can do it anymore
+        if self.enabled {
+            set_do_not_disturb(false);
+        }
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for DndPlugin {
+    type Config = DndPluginConfig;
+
+    fn new(_niri: NiriIpc, config: DndPluginConfig) -> Self {
+        info!("Do-not-disturb plugin initialized");
+        Self {
+            config,
+            enabled: false,
+        }
+    }
+
+    async fn handle_ipc_query(
+        &mut self,
+        request: &IpcRequest,
+    ) -> Result<Option<serde_json::Value>> {
+        match request {
+            IpcRequest::DndStatus => Ok(self.status().await?),
+            _ => Ok(None),
+        }
+    }
+
+    async fn handle_ipc_request(&mut self, request: &IpcRequest) -> Result<Option<Result<()>>> {
+        match request {
+            IpcRequest::DndSet { enabled } => {
+                self.set(*enabled);
+                Ok(Some(Ok(())))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn status(&mut self) -> Result<Option<serde_json::Value>> {
+        Ok(Some(serde_json::json!({
+            "enabled": self.enabled,
+        })))
+    }
+
+    async fn update_config(&mut self, config: DndPluginConfig) -> Result<()> {
+        info!("Updating do-not-disturb plugin configuration");
+        if self.enabled && config.suspend != self.config.suspend {
+            request_suspensions(
+                "dnd",
+                vec![
+                    (self.config.suspend.clone(), false),
+                    (config.suspend.clone(), true),
+                ],
+            );
+        }
+        self.config = config;
+        Ok(())
+    }
+}
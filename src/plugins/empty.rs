@@ -5,41 +5,62 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
-use crate::config::Config;
+use crate::config::{CommandLine, Config};
 use crate::niri::NiriIpc;
 use crate::plugins::{window_utils, FromConfig};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct EmptyPluginConfig {
-    pub workspaces: HashMap<String, String>,
+    pub workspaces: HashMap<String, Vec<CommandLine>>,
+    /// Output -> workspace -> commands, before `workspaces` on that output
+    pub outputs: HashMap<String, HashMap<String, Vec<CommandLine>>>,
 }
 
 impl FromConfig for EmptyPluginConfig {
     fn from_config(config: &Config) -> Option<Self> {
-        let workspaces = if !config.empty.is_empty() {
-            let mut workspaces = HashMap::new();
-            for (workspace, cfg) in &config.empty {
-                workspaces.insert(workspace.clone(), cfg.command.clone());
-            }
-            workspaces
-        } else {
-            config
-                .piri
-                .plugins
-                .empty_config
-                .clone()
-                .map(|c| c.workspaces)
-                .unwrap_or_default()
-        };
-
-        if workspaces.is_empty() {
+        let workspaces: HashMap<String, Vec<CommandLine>> = config
+            .empty
+            .iter()
+            .map(|(workspace, cfg)| (workspace.clone(), cfg.command.clone()))
+            .collect();
+        let outputs: HashMap<String, HashMap<String, Vec<CommandLine>>> = config
+            .outputs
+            .iter()
+            .filter(|(_, output)| !output.empty.is_empty())
+            .map(|(name, output)| {
+                let rules = output
+                    .empty
+                    .iter()
+                    .map(|(workspace, cfg)| (workspace.clone(), cfg.command.clone()))
+                    .collect();
+                (name.clone(), rules)
+            })
+            .collect();
+
+        if workspaces.is_empty() && outputs.is_empty() {
             None
         } else {
-            Some(EmptyPluginConfig { workspaces })
+            Some(EmptyPluginConfig {
+                workspaces,
+                outputs,
+            })
         }
     }
 }
 
+impl EmptyPluginConfig {
+    /// The rules for the workspaces on an output
+    fn rules_on(
+        &self,
+        output: Option<&str>,
+    ) -> impl Iterator<Item = &HashMap<String, Vec<CommandLine>>> {
+        output
+            .and_then(|output| self.outputs.get(output))
+            .into_iter()
+            .chain(std::iter::once(&self.workspaces))
+    }
+}
+
 pub struct EmptyPlugin {
     niri: NiriIpc,
     config: EmptyPluginConfig,
@@ -63,18 +84,21 @@ impl EmptyPlugin {
             let is_empty = window_utils::is_workspace_empty(&self.niri, focused_ws.id).await?;
 
             if is_empty {
-                let command_opt = focused_ws
-                    .name
-                    .as_ref()
-                    .and_then(|name| self.config.workspaces.get(name))
-                    .or_else(|| self.config.workspaces.get(&workspace_key));
-
-                if let Some(cmd) = command_opt {
+                let commands =
+                    self.config.rules_on(focused_ws.output.as_deref()).find_map(|rules| {
+                        focused_ws
+                            .name
+                            .as_ref()
+                            .and_then(|name| rules.get(name))
+                            .or_else(|| rules.get(&workspace_key))
+                    });
+
+                for cmd in commands.into_iter().flatten() {
                     info!(
                         "Workspace {} matches empty rule, executing: {}",
                         workspace_key, cmd
                     );
-                    window_utils::execute_command(cmd)?;
+                    window_utils::execute(cmd)?;
                 }
             }
         }
@@ -0,0 +1,238 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use niri_ipc::{Event, Window, WindowLayout};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::config::Config;
+use crate::niri::NiriIpc;
+use crate::plugins::window_utils::{WindowMatcher, WindowMatcherCache};
+use crate::plugins::FromConfig;
+use crate::utils::{load_state, save_state};
+
+/// State file (in the state directory) that geometries are persisted to
+const GEOMETRY_FILE: &str = "floating_geometry.json";
+
+/// Floating geometry plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FloatingGeometryPluginConfig {
+    /// Only remember windows whose app_id matches (None: all)
+    pub app_id: Option<Vec<String>>,
+    /// Title patterns that get their own geometry per app
+    pub titles: Vec<String>,
+    /// Restore the position as well as the size
+    pub restore_position: bool,
+}
+
+impl FromConfig for FloatingGeometryPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        let section = &config.piri.floating_geometry;
+        Some(Self {
+            app_id: section.app_id.clone(),
+            titles: section.titles.clone(),
+            restore_position: section.restore_position,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Geometry {
+    x: f64,
+    y: f64,
+    width: u32,
+    height: u32,
+}
+
+impl Geometry {
+    fn from_layout(layout: &WindowLayout) -> Option<Self> {
+        let (x, y) = layout.tile_pos_in_workspace_view?;
+        Some(Self {
+            x,
+            y,
+            width: layout.window_size.0.max(0) as u32,
+            height: layout.window_size.1.max(0) as u32,
+        })
+    }
+}
+
+/// An open window being tracked
+struct Tracked {
+    /// Key its geometry is remembered under
+    key: String,
+    floating: bool,
+    geometry: Option<Geometry>,
+}
+
+/// Remembers the size and position of floating windows when they close and restores them
+/// when a floating window of the same app (and title pattern) opens again.
+pub struct FloatingGeometryPlugin {
+    niri: NiriIpc,
+    config: FloatingGeometryPluginConfig,
+    matcher_cache: WindowMatcherCache,
+    /// Key -> last geometry
+    saved: BTreeMap<String, Geometry>,
+    windows: HashMap<u64, Tracked>,
+}
+
+impl FloatingGeometryPlugin {
+    /// Key for a window: its app_id plus the first title pattern it matches, or None if
+    /// the window isn't remembered
+    async fn key_for(&self, window: &Window) -> Result<Option<String>> {
+        let Some(ref app_id) = window.app_id else {
+            return Ok(None);
+        };
+        if self.config.app_id.is_some() {
+            let matcher = WindowMatcher::new(self.config.app_id.clone(), None);
+            if !self.matcher_cache.matches(Some(app_id), None, &matcher).await? {
+                return Ok(None);
+            }
+        }
+        for pattern in &self.config.titles {
+            let matcher = WindowMatcher::new(None, Some(vec![pattern.clone()]));
+            if self.matcher_cache.matches(None, window.title.as_ref(), &matcher).await? {
+                return Ok(Some(format!("{}:{}", app_id, pattern)));
+            }
+        }
+        Ok(Some(app_id.clone()))
+    }
+
+    fn save(&self) {
+        if let Err(e) = save_state(GEOMETRY_FILE, &self.saved) {
+            warn!("Failed to save floating geometry: {}", e);
+        }
+    }
+
+    async fn restore(&self, window_id: u64, key: &str) -> Result<()> {
+        let Some(geometry) = self.saved.get(key) else {
+            return Ok(());
+        };
+        debug!(
+            "Restoring geometry of window {} ({}): {:?}",
+            window_id, key, geometry
+        );
+        self.niri
+            .resize_floating_window(window_id, geometry.width, geometry.height)
+            .await?;
+        if self.config.restore_position {
+            self.niri.move_floating_window_to(window_id, geometry.x, geometry.y).await?;
+        }
+        Ok(())
+    }
+
+    /// Remember the geometry of a window that is going away
+    fn remember(&mut self, window_id: u64) {
+        let Some(tracked) = self.windows.remove(&window_id) else {
+            return;
+        };
+        if let (true, Some(geometry)) = (tracked.floating, tracked.geometry) {
+            let previous = self.saved.insert(tracked.key.clone(), geometry);
+            if previous != Some(geometry) {
+                debug!("Remembering geometry of {}: {:?}", tracked.key, geometry);
+                self.save();
+            }
+        }
+    }
+
+    async fn window_changed(&mut self, window: &Window) -> Result<()> {
+        let geometry = Geometry::from_layout(&window.layout);
+        if let Some(tracked) = self.windows.get_mut(&window.id) {
+            tracked.floating = window.is_floating;
+            tracked.geometry = geometry;
+            return Ok(());
+        }
+
+        // A new window: restore if it opened floating
+
+        let Some(key) = self.key_for(window).await? else {
+            return Ok(());
+        };
+        if window.is_floating {
+            self.restore(window.id, &key).await?;
+        }
+        self.windows.insert(
+            window.id,
+            Tracked {
+                key,
+                floating: window.is_floating,
+                geometry,
+            },
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for FloatingGeometryPlugin {
+    type Config = FloatingGeometryPluginConfig;
+
+    fn new(niri: NiriIpc, config: FloatingGeometryPluginConfig) -> Self {
+        let saved: BTreeMap<String, Geometry> = match load_state(GEOMETRY_FILE) {
+            Ok(saved) => saved.unwrap_or_default(),
+            Err(e) => {
+                warn!("Failed to load floating geometry: {}", e);
+                BTreeMap::new()
+            }
+        };
+        info!(
+            "Floating geometry plugin initialized with {} saved geometries",
+            saved.len()
+        );
+        Self {
+            niri,
+            config,
+            matcher_cache: WindowMatcherCache::new(),
+            saved,
+            windows: HashMap::new(),
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        match event {
+            // Sent on (re)connect: track existing windows without touching them
+            Event::WindowsChanged { windows } => {
+                self.windows.clear();
+                for window in windows {
+                    if let Some(key) = self.key_for(window).await? {
+                        self.windows.insert(
+                            window.id,
+                            Tracked {
+                                key,
+                                floating: window.is_floating,
+                                geometry: Geometry::from_layout(&window.layout),
+                            },
+                        );
+                    }
+                }
+            }
+            Event::WindowOpenedOrChanged { window } => self.window_changed(window).await?,
+            Event::WindowLayoutsChanged { changes } => {
+                for (id, layout) in changes {
+                    if let Some(tracked) = self.windows.get_mut(id) {
+                        tracked.geometry = Geometry::from_layout(layout);
+                    }
+                }
+            }
+            Event::WindowClosed { id } => self.remember(*id),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(
+            event,
+            Event::WindowsChanged { .. }
+                | Event::WindowOpenedOrChanged { .. }
+                | Event::WindowLayoutsChanged { .. }
+                | Event::WindowClosed { .. }
+        )
+    }
+
+    async fn update_config(&mut self, config: FloatingGeometryPluginConfig) -> Result<()> {
+        info!("Updating floating geometry plugin configuration");
+        self.config = config;
+        self.matcher_cache.clear_cache().await;
+        Ok(())
+    }
+}
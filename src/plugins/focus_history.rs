@@ -0,0 +1,218 @@
+use anyhow::Result;
+use log::{debug, info};
+use niri_ipc::Event;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::config::{Config, FocusScope};
+use crate::ipc::IpcRequest;
+use crate::niri::NiriIpc;
+use crate::plugins::FromConfig;
+
+/// Focus history plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FocusHistoryPluginConfig {
+    /// Scope used when a request doesn't specify one
+    pub scope: FocusScope,
+}
+
+impl FromConfig for FocusHistoryPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            scope: config.piri.focus_history.scope,
+        })
+    }
+}
+
+/// In-progress prev/next walk through a frozen copy of the history
+struct Navigation {
+    windows: Vec<u64>,
+    index: usize,
+}
+
+pub struct FocusHistoryPlugin {
+    niri: NiriIpc,
+    config: FocusHistoryPluginConfig,
+    /// Window IDs, most recently focused first
+    history: Vec<u64>,
+    /// Workspace of every known window
+    window_workspaces: HashMap<u64, Option<u64>>,
+    navigation: Option<Navigation>,
+    /// Window focused by the plugin itself, so its focus event doesn't end the navigation
+    pending_focus: Option<u64>,
+}
+
+impl FocusHistoryPlugin {
+    fn record_focus(&mut self, id: u64) {
+        if self.pending_focus == Some(id) {
+            self.pending_focus = None;
+        } else {
+            self.navigation = None;
+        }
+        self.history.retain(|&window_id| window_id != id);
+        self.history.insert(0, id);
+    }
+
+    fn remove_window(&mut self, id: u64) {
+        self.history.retain(|&window_id| window_id != id);
+        self.window_workspaces.remove(&id);
+        if let Some(ref mut navigation) = self.navigation {
+            navigation.windows.retain(|&window_id| window_id != id);
+            if navigation.windows.is_empty() {
+                self.navigation = None;
+            } else if navigation.index >= navigation.windows.len() {
+                navigation.index = 0;
+            }
+        }
+    }
+
+    /// History restricted to the requested scope
+    async fn candidates(&self, scope: Option<FocusScope>) -> Result<Vec<u64>> {
+        match scope.unwrap_or(self.config.scope) {
+            FocusScope::Global => Ok(self.history.clone()),
+            FocusScope::Workspace => {
+                let workspaces = self.niri.get_workspaces().await?;
+                let focused_ws = workspaces.iter().find(|ws| ws.is_focused).map(|ws| ws.id);
+                Ok(self
+                    .history
+                    .iter()
+                    .copied()
+                    .filter(|id| {
+                        focused_ws.is_some()
+                            && self.window_workspaces.get(id).copied().flatten() == focused_ws
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    /// Focus the most recent window other than the focused one
+    async fn focus_last(&mut self, scope: Option<FocusScope>) -> Result<()> {
+        let focused_id = self.niri.get_focused_window_id().await?;
+        let candidates = self.candidates(scope).await?;
+        let Some(&target) = candidates.iter().find(|&&id| Some(id) != focused_id) else {
+            debug!("No previous window in focus history");
+            return Ok(());
+        };
+
+        self.navigation = None;
+        debug!("Focusing last window {}", target);
+        self.niri.focus_window(target).await
+    }
+
+    /// Step through the history; `older` walks back in time
+    async fn step(&mut self, scope: Option<FocusScope>, older: bool) -> Result<()> {
+        if self.navigation.is_none() {
+            let candidates = self.candidates(scope).await?;
+            if candidates.is_empty() {
+                debug!("Focus history is empty");
+                return Ok(());
+            }
+            let focused_id = self.niri.get_focused_window_id().await?;
+            let index = candidates.iter().position(|&id| Some(id) == focused_id).unwrap_or(0);
+            self.navigation = Some(Navigation {
+                windows: candidates,
+                index,
+            });
+        }
+
+        let Some(ref mut navigation) = self.navigation else {
+            return Ok(());
+        };
+        let len = navigation.windows.len();
+        navigation.index = if older {
+            (navigation.index + 1) % len
+        } else {
+            (navigation.index + len - 1) % len
+        };
+        let target = navigation.windows[navigation.index];
+
+        debug!(
+            "Focus history step to window {} ({}/{})",
+            target,
+            navigation.index + 1,
+            len
+        );
+        self.pending_focus = Some(target);
+        self.niri.focus_window(target).await
+    }
+}
+
+#[async_trait::async_trait]
+impl crate::plugins::Plugin for FocusHistoryPlugin {
+    type Config = FocusHistoryPluginConfig;
+
+    fn new(niri: NiriIpc, config: FocusHistoryPluginConfig) -> Self {
+        info!("Focus history plugin initialized");
+        Self {
+            niri,
+            config,
+            history: Vec::new(),
+            window_workspaces: HashMap::new(),
+            navigation: None,
+            pending_focus: None,
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        match event {
+            Event::WindowsChanged { windows } => {
+                // Seed the history from niri's focus timestamps
+                let mut windows: Vec<_> = windows
+                    .iter()
+                    .map(|w| {
+                        let timestamp =
+                            w.focus_timestamp.map(|ts| Duration::new(ts.secs, ts.nanos));
+                        (w.id, w.workspace_id, timestamp)
+                    })
+                    .collect();
+                windows.sort_by_key(|&(_, _, timestamp)| std::cmp::Reverse(timestamp));
+                self.history = windows.iter().map(|&(id, _, _)| id).collect();
+                self.window_workspaces =
+                    windows.iter().map(|&(id, workspace_id, _)| (id, workspace_id)).collect();
+                self.navigation = None;
+            }
+            Event::WindowOpenedOrChanged { window } => {
+                self.window_workspaces.insert(window.id, window.workspace_id);
+            }
+            Event::WindowClosed { id } => self.remove_window(*id),
+            Event::WindowFocusTimestampChanged { id, .. } => self.record_focus(*id),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    async fn on_niri_reconnected(&mut self, _niri: &NiriIpc) -> Result<()> {
+        self.history.clear();
+        self.window_workspaces.clear();
+        self.navigation = None;
+        self.pending_focus = None;
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(
+            event,
+            Event::WindowsChanged { .. }
+                | Event::WindowOpenedOrChanged { .. }
+                | Event::WindowClosed { .. }
+                | Event::WindowFocusTimestampChanged { .. }
+        )
+    }
+
+    async fn update_config(&mut self, config: FocusHistoryPluginConfig) -> Result<()> {
+        info!("Updating focus history plugin configuration");
+        self.config = config;
+        Ok(())
+    }
+
+    async fn handle_ipc_request(&mut self, request: &IpcRequest) -> Result<Option<Result<()>>> {
+        match request {
+            IpcRequest::FocusLast { scope } => Ok(Some(self.focus_last(*scope).await)),
+            IpcRequest::FocusPrev { scope } => Ok(Some(self.step(*scope, true).await)),
+            IpcRequest::FocusNext { scope } => Ok(Some(self.step(*scope, false).await)),
+            _ => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use niri_ipc::Event;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::config::{CommandLine, Config};
+use crate::niri::NiriIpc;
+use crate::plugins::window_utils::{self, WindowMatcher, WindowMatcherCache};
+use crate::plugins::{request_suspensions, FromConfig};
+
+/// Gaming plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GamingPluginConfig {
+    /// Regex patterns of game app_ids
+    pub app_id: Vec<String>,
+    /// Plugins that stop receiving events while a game runs
+    pub suspend: Vec<String>,
+    /// Command run when a game goes fullscreen
+    pub on_start: Option<CommandLine>,
+    /// Command run when the last fullscreen game is gone
+    pub on_stop: Option<CommandLine>,
+}
+
+impl FromConfig for GamingPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        let section = &config.piri.gaming;
+        Some(Self {
+            app_id: section.app_id.clone().unwrap_or_default(),
+            suspend: section.suspend.clone(),
+            on_start: section.on_start.clone(),
+            on_stop: section.on_stop.clone(),
+        })
+    }
+}
+
+struct GameWindow {
+    app_id: Option<String>,
+    workspace_id: Option<u64>,
+    size: (i32, i32),
+}
+
+/// Gaming mode: while a window of a configured app is fullscreen, other plugins are
+/// suspended and the on_start hook has run; everything is restored once it is gone.
+pub struct GamingPlugin {
+    niri: NiriIpc,
+    config: GamingPluginConfig,
+    matcher_cache: WindowMatcherCache,
+    windows: HashMap<u64, GameWindow>,
+    /// Output of every workspace
+    workspace_outputs: HashMap<u64, Option<String>>,
+    /// The fullscreen game window while gaming mode is on
+    active: Option<u64>,
+}
+
+impl GamingPlugin {
+    async fn is_game(&self, window: &GameWindow) -> Result<bool> {
+        if self.config.app_id.is_empty() {
+            return Ok(false);
+        }
+        let matcher = WindowMatcher::new(Some(self.config.app_id.clone()), None);
+        self.matcher_cache.matches(window.app_id.as_ref(), None, &matcher).await
+    }
+
+    /// A fullscreen game window, if any. niri doesn't report fullscreen state, so a window
+    /// counts as fullscreen when it covers its whole output
+    async fn find_fullscreen_game(&self) -> Result<Option<u64>> {
+        let mut games = Vec::new();
+        for (&id, window) in &self.windows {
+            if self.is_game(window).await? {
+                games.push(id);
+            }
+        }
+        if games.is_empty() {
+            return Ok(None);
+        }
+
+        let outputs = self.niri.get_outputs().await?;
+        for id in games {
+            let window = &self.windows[&id];
+            let output = window
+                .workspace_id
+                .and_then(|ws| self.workspace_outputs.get(&ws))
+                .and_then(|name| outputs.iter().find(|o| Some(&o.name) == name.as_ref()));
+            let Some(logical) = output.and_then(|o| o.logical.as_ref()) else {
+                continue;
+            };
+            if window.size.0 >= logical.width as i32 && window.size.1 >= logical.height as i32 {
+                return Ok(Some(id));
+            }
+        }
+        Ok(None)
+    }
+
+    fn run_hook(command: &Option<CommandLine>) {
+        if let Some(command) = command {
+            debug!("Running gaming hook: {}", command);
+            if let Err(e) = window_utils::execute(command) {
+                warn!("Failed to run gaming hook: {}", e);
+            }
+        }
+    }
+
+    async fn update(&mut self) -> Result<()> {
+        let game = self.find_fullscreen_game().await?;
+        match (game, self.active) {
+            (Some(id), None) => {
+                info!("Game window {} is fullscreen, entering gaming mode", id);
+                request_suspensions("gaming", vec![(self.config.suspend.clone(), true)]);
+                Self::run_hook(&self.config.on_start);
+            }
+            (None, Some(id)) => {
+                info!("Game window {} is gone, leaving gaming mode", id);
+                request_suspensions("gaming", vec![(self.config.suspend.clone(), false)]);
+                Self::run_hook(&self.config.on_stop);
+            }
+            _ => {}
+        }
+        self.active = game;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for GamingPlugin {
+    type Config = GamingPluginConfig;
+
+    fn new(niri: NiriIpc, config: GamingPluginConfig) -> Self {
+        info!(
+            "Gaming plugin initialized with {} game patterns",
+            config.app_id.len()
+        );
+        Self {
+            niri,
+            config,
+            matcher_cache: WindowMatcherCache::new(),
+            windows: HashMap::new(),
+            workspace_outputs: HashMap::new(),
+            active: None,
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        match event {
+            Event::WorkspacesChanged { workspaces } => {
+                self.workspace_outputs =
+                    workspaces.iter().map(|ws| (ws.id, ws.output.clone())).collect();
+                return Ok(());
+            }
+            Event::WindowsChanged { windows } => {
+                self.windows = windows
+                    .iter()
+                    .map(|w| {
+                        (
+                            w.id,
+                            GameWindow {
+                                app_id: w.app_id.clone(),
+                                workspace_id: w.workspace_id,
+                                size: w.layout.window_size,
+                            },
+                        )
+                    })
+                    .collect();
+            }
+            Event::WindowOpenedOrChanged { window } => {
+                self.windows.insert(
+                    window.id,
+                    GameWindow {
+                        app_id: window.app_id.clone(),
+                        workspace_id: window.workspace_id,
+                        size: window.layout.window_size,
+                    },
+                );
+            }
+            Event::WindowLayoutsChanged { changes } => {
+                for (id, layout) in changes {
+                    if let Some(window) = self.windows.get_mut(id) {
+                        window.size = layout.window_size;
+                    }
+                }
+            }
+            Event::WindowClosed { id } => {
+                self.windows.remove(id);
+            }
+            _ => {}
+        }
+        self.update().await
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(
+            event,
+            Event::WorkspacesChanged { .. }
+                | Event::WindowsChanged { .. }
+                | Event::WindowOpenedOrChanged { .. }
+                | Event::WindowLayoutsChanged { .. }
+                | Event::WindowClosed { .. }
+        )
+    }
+
+    async fn status(&mut self) -> Result<Option<serde_json::Value>> {
+        Ok(Some(serde_json::json!({
+            "active": self.active.is_some(),
+            "window_id": self.active,
+        })))
+    }
+
+    async fn update_config(&mut self, config: GamingPluginConfig) -> Result<()> {
+        info!("Updating gaming plugin configuration");
+        if self.active.is_some() && config.suspend != self.config.suspend {
+            request_suspensions(
+                "gaming",
+                vec![
+                    (self.config.suspend.clone(), false),
+                    (config.suspend.clone(), true),
+                ],
+            );
+        }
+        self.config = config;
+        self.matcher_cache.clear_cache().await;
+        Ok(())
+    }
+}
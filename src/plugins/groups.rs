@@ -0,0 +1,429 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use niri_ipc::{Action, Event};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::config::Config;
+use crate::ipc::{IpcError, IpcRequest};
+use crate::niri::{NiriIpc, Window};
+use crate::plugins::FromConfig;
+
+/// Groups plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GroupsPluginConfig {
+    /// Named workspace hidden group members are parked on
+    pub park_workspace: String,
+}
+
+impl FromConfig for GroupsPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            park_workspace: config.piri.groups.park_workspace.clone(),
+        })
+    }
+}
+
+/// A group member, as returned by `GroupList`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupMemberInfo {
+    pub window_id: u64,
+    pub app_id: Option<String>,
+    pub title: String,
+}
+
+/// A group and its members, as returned by `GroupList`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupInfo {
+    pub name: String,
+    /// The visible member
+    pub active: u64,
+    pub members: Vec<GroupMemberInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Group {
+    /// Window IDs in the order they were added
+    members: Vec<u64>,
+    /// The visible member, every other member is parked
+    active: u64,
+    /// Workspace of the visible member, to show the next one there if it is closed
+    workspace_id: Option<u64>,
+    /// Whether the visible member is floating
+    floating: bool,
+}
+
+/// Emulates tabbed containers: only the active member of a group is shown, the others
+/// are parked on a hidden workspace and swapped into its place when cycling.
+pub struct GroupsPlugin {
+    niri: NiriIpc,
+    config: GroupsPluginConfig,
+    groups: BTreeMap<String, Group>,
+}
+
+impl GroupsPlugin {
+    fn group_of(&self, window_id: u64) -> Option<&str> {
+        self.groups
+            .iter()
+            .find(|(_, group)| group.members.contains(&window_id))
+            .map(|(name, _)| name.as_str())
+    }
+
+    async fn focused_window(&self) -> Result<Window> {
+        let focused = self
+            .niri
+            .get_focused_window_id()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("No focused window"))?;
+        self.niri
+            .get_windows()
+            .await?
+            .into_iter()
+            .find(|w| w.id == focused)
+            .ok_or_else(|| anyhow::anyhow!("Focused window {} not found", focused))
+    }
+
+    async fn park(&self, window: &Window) -> Result<()> {
+        let workspaces = self.niri.get_workspaces().await?;
+        if !workspaces
+            .iter()
+            .any(|ws| ws.name.as_deref() == Some(self.config.park_workspace.as_str()))
+        {
+            anyhow::bail!(
+                "Park workspace '{}' does not exist, declare it in the niri config with `workspace \"{}\"`",
+                self.config.park_workspace,
+                self.config.park_workspace
+            );
+        }
+        debug!(
+            "Parking window {} on workspace '{}'",
+            window.id, self.config.park_workspace
+        );
+        self.niri
+            .move_window_to_workspace(window.id, &self.config.park_workspace)
+            .await?;
+        crate::journal::record("groups", window.id, window.app_id.as_deref(), None, false);
+        Ok(())
+    }
+
+    /// Bring the parked window `shown` into the place of the visible window `replaced`
+    /// (joining its column, or taking over its floating geometry), then park `replaced`
+    /// unless `keep_replaced` is set.
+    async fn swap(&self, replaced: &Window, shown: u64, keep_replaced: bool) -> Result<()> {
+        let workspace_id = replaced
+            .workspace_id
+            .ok_or_else(|| anyhow::anyhow!("Window {} is not on a workspace", replaced.id))?;
+
+        // New windows land next to the active column, so focus the replaced window first
+        self.niri.focus_window(replaced.id).await?;
+        self.niri.move_window_to_workspace_id(shown, workspace_id).await?;
+        crate::journal::forget(shown);
+        self.niri.set_window_floating(shown, replaced.floating).await?;
+        if replaced.floating {
+            if let Some((x, y, width, height)) =
+                self.niri.get_window_position_async(replaced.id).await?
+            {
+                self.niri.resize_floating_window(shown, width, height).await?;
+                self.niri.move_floating_window_to(shown, x as f64, y as f64).await?;
+            }
+        } else {
+            // Alone in its new column right of the replaced window, so this joins that column
+            self.niri
+                .send_action(Action::ConsumeOrExpelWindowLeft { id: Some(shown) })
+                .await?;
+        }
+
+        if !keep_replaced {
+            self.park(replaced).await?;
+        }
+        self.niri.focus_window(shown).await
+    }
+
+    async fn add(&mut self, name: &str) -> Result<()> {
+        let window = self.focused_window().await?;
+        if self.group_of(window.id) == Some(name) {
+            anyhow::bail!("Window {} is already in group '{}'", window.id, name);
+        }
+        if self.group_of(window.id).is_some() {
+            self.remove().await?;
+        }
+
+        let Some(group) = self.groups.get(name) else {
+            info!("Creating group '{}' with window {}", name, window.id);
+            self.groups.insert(
+                name.to_string(),
+                Group {
+                    members: vec![window.id],
+                    active: window.id,
+                    workspace_id: window.workspace_id,
+                    floating: window.floating,
+                },
+            );
+            return Ok(());
+        };
+
+        let active = self
+            .niri
+            .get_windows()
+            .await?
+            .into_iter()
+            .find(|w| w.id == group.active)
+            .ok_or_else(|| anyhow::anyhow!("Active window of group '{}' is gone", name))?;
+
+        info!("Adding window {} to group '{}'", window.id, name);
+        // Park first so the window is shown the same way wherever it came from
+        self.park(&window).await?;
+        self.swap(&active, window.id, false).await?;
+
+        let group = self.groups.get_mut(name).expect("group exists");
+        group.members.push(window.id);
+        group.active = window.id;
+        group.workspace_id = active.workspace_id;
+        group.floating = active.floating;
+        Ok(())
+    }
+
+    /// Take the focused window out of its group, leaving it where it is
+    async fn remove(&mut self) -> Result<()> {
+        let window = self.focused_window().await?;
+        let name = self
+            .group_of(window.id)
+            .ok_or_else(|| anyhow::anyhow!("Focused window is not in a group"))?
+            .to_string();
+        let group = self.groups.get_mut(&name).expect("group exists");
+        group.members.retain(|&id| id != window.id);
+        info!("Removed window {} from group '{}'", window.id, name);
+
+        if group.members.is_empty() {
+            self.groups.remove(&name);
+            return Ok(());
+        }
+        if group.active == window.id {
+            // Show the next member next to the removed window so the group stays visible
+            let next = group.members[0];
+            group.active = next;
+            self.swap(&window, next, true).await?;
+        }
+        Ok(())
+    }
+
+    async fn cycle(&mut self, name: Option<&str>, forward: bool) -> Result<()> {
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => {
+                let focused = self.focused_window().await?;
+                self.group_of(focused.id)
+                    .ok_or_else(|| anyhow::anyhow!("Focused window is not in a group"))?
+                    .to_string()
+            }
+        };
+        let group = self
+            .groups
+            .get(&name)
+            .ok_or_else(|| IpcError::not_found(format!("Group '{}' does not exist", name)))?;
+        if group.members.len() < 2 {
+            debug!("Group '{}' has a single member, nothing to cycle", name);
+            return Ok(());
+        }
+
+        let len = group.members.len();
+        let index = group.members.iter().position(|&id| id == group.active).unwrap_or(0);
+        let next = if forward {
+            group.members[(index + 1) % len]
+        } else {
+            group.members[(index + len - 1) % len]
+        };
+
+        let active = self
+            .niri
+            .get_windows()
+            .await?
+            .into_iter()
+            .find(|w| w.id == group.active)
+            .ok_or_else(|| anyhow::anyhow!("Active window of group '{}' is gone", name))?;
+
+        debug!(
+            "Group '{}': showing window {} instead of {}",
+            name, next, active.id
+        );
+        self.swap(&active, next, false).await?;
+        self.groups.get_mut(&name).expect("group exists").active = next;
+        Ok(())
+    }
+
+    /// Show the next member where the closed active member was
+    async fn replace_closed(&mut self, name: &str) -> Result<()> {
+        let Some(group) = self.groups.get(name) else {
+            return Ok(());
+        };
+        let next = group.active;
+        let floating = group.floating;
+        if let Some(workspace_id) = group.workspace_id {
+            self.niri.move_window_to_workspace_id(next, workspace_id).await?;
+        }
+        crate::journal::forget(next);
+        self.niri.set_window_floating(next, floating).await?;
+        self.niri.focus_window(next).await
+    }
+
+    async fn list(&self) -> Result<Vec<GroupInfo>> {
+        let windows = self.niri.get_windows().await?;
+        Ok(self
+            .groups
+            .iter()
+            .map(|(name, group)| GroupInfo {
+                name: name.clone(),
+                active: group.active,
+                members: group
+                    .members
+                    .iter()
+                    .filter_map(|id| windows.iter().find(|w| w.id == *id))
+                    .map(|w| GroupMemberInfo {
+                        window_id: w.id,
+                        app_id: w.app_id.clone(),
+                        title: w.title.clone(),
+                    })
+                    .collect(),
+            })
+            .collect())
+    }
+
+    /// Forget closed windows; returns groups whose active member was closed
+    fn prune(&mut self, is_open: impl Fn(u64) -> bool) -> Vec<String> {
+        let mut orphaned = Vec::new();
+        for (name, group) in self.groups.iter_mut() {
+            let index = group.members.iter().position(|&id| id == group.active).unwrap_or(0);
+            group.members.retain(|&id| is_open(id));
+            if !group.members.is_empty() && !is_open(group.active) {
+                group.active = group.members[index % group.members.len()];
+                orphaned.push(name.clone());
+            }
+        }
+        self.groups.retain(|name, group| {
+            if group.members.is_empty() {
+                debug!("Group '{}' has no windows left, removing it", name);
+            }
+            !group.members.is_empty()
+        });
+        orphaned
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for GroupsPlugin {
+    type Config = GroupsPluginConfig;
+
+    fn new(niri: NiriIpc, config: GroupsPluginConfig) -> Self {
+        info!(
+            "Groups plugin initialized, parking on workspace '{}'",
+            config.park_workspace
+        );
+        Self {
+            niri,
+            config,
+            groups: BTreeMap::new(),
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        let orphaned = match event {
+            Event::WindowsChanged { windows } => {
+                self.prune(|id| windows.iter().any(|w| w.id == id))
+            }
+            Event::WindowClosed { id } => self.prune(|window_id| window_id != *id),
+            Event::WindowOpenedOrChanged { window } => {
+                // Follow the visible member when the user moves it
+                for group in self.groups.values_mut() {
+                    if group.active == window.id {
+                        group.workspace_id = window.workspace_id;
+                        group.floating = window.is_floating;
+                    }
+                }
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+
+        for name in orphaned {
+            if let Err(e) = self.replace_closed(&name).await {
+                warn!("Failed to show next window of group '{}': {}", name, e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn export_state(&mut self) -> Result<Option<serde_json::Value>> {
+        Ok(Some(serde_json::to_value(&self.groups)?))
+    }
+
+    async fn import_state(&mut self, state: serde_json::Value) -> Result<()> {
+        self.groups = serde_json::from_value(state)?;
+        Ok(())
+    }
+
+    async fn on_niri_reconnected(&mut self, _niri: &NiriIpc) -> Result<()> {
+        if !self.groups.is_empty() {
+            info!(
+                "Dropping {} groups of windows from before niri restarted",
+                self.groups.len()
+            );
+            self.groups.clear();
+        }
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(
+            event,
+            Event::WindowsChanged { .. }
+                | Event::WindowOpenedOrChanged { .. }
+                | Event::WindowClosed { .. }
+        )
+    }
+
+    async fn handle_ipc_query(
+        &mut self,
+        request: &IpcRequest,
+    ) -> Result<Option<serde_json::Value>> {
+        match request {
+            IpcRequest::GroupList => Ok(Some(serde_json::to_value(self.list().await?)?)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn handle_ipc_request(&mut self, request: &IpcRequest) -> Result<Option<Result<()>>> {
+        match request {
+            IpcRequest::GroupAdd { name } => Ok(Some(self.add(name).await)),
+            IpcRequest::GroupRemove => Ok(Some(self.remove().await)),
+            IpcRequest::GroupNext { name } => Ok(Some(self.cycle(name.as_deref(), true).await)),
+            IpcRequest::GroupPrev { name } => Ok(Some(self.cycle(name.as_deref(), false).await)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn status(&mut self) -> Result<Option<serde_json::Value>> {
+        let status: BTreeMap<&str, serde_json::Value> = self
+            .groups
+            .iter()
+            .map(|(name, group)| {
+                let index = group.members.iter().position(|&id| id == group.active);
+                (
+                    name.as_str(),
+                    serde_json::json!({
+                        "active": group.active,
+                        "index": index.map_or(0, |i| i + 1),
+                        "count": group.members.len(),
+                    }),
+                )
+            })
+            .collect();
+        Ok(Some(serde_json::to_value(status)?))
+    }
+
+    async fn update_config(&mut self, config: GroupsPluginConfig) -> Result<()> {
+        info!("Updating groups plugin configuration");
+        self.config = config;
+        Ok(())
+    }
+}
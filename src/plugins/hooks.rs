@@ -0,0 +1,200 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use niri_ipc::Event;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::config::{Config, HookRule};
+use crate::niri::NiriIpc;
+use crate::plugins::window_utils::{self, WindowMatcher, WindowMatcherCache};
+use crate::plugins::FromConfig;
+use crate::utils::shell_quote;
+
+/// Hooks plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HooksPluginConfig {
+    pub hooks: Vec<HookRule>,
+}
+
+impl FromConfig for HooksPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            hooks: config.hooks.clone(),
+        })
+    }
+}
+
+/// Split a serialized niri event (`{"EventName": {...}}`) into its name and fields
+fn event_parts(event: &Event) -> Option<(String, serde_json::Map<String, Value>)> {
+    match serde_json::to_value(event).ok()? {
+        Value::Object(map) => {
+            let (name, payload) = map.into_iter().next()?;
+            let fields = match payload {
+                Value::Object(fields) => fields,
+                _ => serde_json::Map::new(),
+            };
+            Some((name, fields))
+        }
+        // Events without fields serialize as a plain string
+        Value::String(name) => Some((name, serde_json::Map::new())),
+        _ => None,
+    }
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Runs user commands on niri events, with {field} placeholders taken from the event
+pub struct HooksPlugin {
+    config: HooksPluginConfig,
+    matcher_cache: WindowMatcherCache,
+    placeholder: Regex,
+    /// app_id and title of every open window, so events that only carry an id can be matched
+    windows: HashMap<u64, (Option<String>, Option<String>)>,
+}
+
+impl HooksPlugin {
+    /// Placeholder values for an event. Fields of nested objects (e.g. `window`) are
+    /// available both as {window.title} and as {title}
+    fn placeholders(
+        &self,
+        name: &str,
+        fields: &serde_json::Map<String, Value>,
+        window_id: Option<u64>,
+    ) -> HashMap<String, String> {
+        let mut values = HashMap::new();
+        values.insert("event".to_string(), name.to_string());
+        for (key, value) in fields {
+            if let Value::Object(inner) = value {
+                for (inner_key, inner_value) in inner {
+                    values.insert(format!("{}.{}", key, inner_key), value_text(inner_value));
+                }
+            }
+            values.insert(key.clone(), value_text(value));
+        }
+        // Top-level fields win over nested ones of the same name
+        for value in fields.values() {
+            if let Value::Object(inner) = value {
+                for (inner_key, inner_value) in inner {
+                    values.entry(inner_key.clone()).or_insert_with(|| value_text(inner_value));
+                }
+            }
+        }
+        // Events like WindowClosed only carry the id
+        if let Some((app_id, title)) = window_id.and_then(|id| self.windows.get(&id)) {
+            values
+                .entry("app_id".to_string())
+                .or_insert_with(|| app_id.clone().unwrap_or_default());
+            values
+                .entry("title".to_string())
+                .or_insert_with(|| title.clone().unwrap_or_default());
+        }
+        values
+    }
+
+    async fn hook_matches(&self, hook: &HookRule, window_id: Option<u64>) -> Result<bool> {
+        if hook.match_app_id.is_none() && hook.match_title.is_none() {
+            return Ok(true);
+        }
+        let Some((app_id, title)) = window_id.and_then(|id| self.windows.get(&id)) else {
+            return Ok(false);
+        };
+        let matcher = WindowMatcher::new(hook.match_app_id.clone(), hook.match_title.clone());
+        self.matcher_cache.matches(app_id.as_ref(), title.as_ref(), &matcher).await
+    }
+
+    async fn run_hooks(&self, event: &Event) -> Result<()> {
+        let Some((name, fields)) = event_parts(event) else {
+            return Ok(());
+        };
+        if !self.config.hooks.iter().any(|hook| hook.event == name) {
+            return Ok(());
+        }
+
+        // The window an event is about: `window.id` for WindowOpenedOrChanged, `id` for
+        // the other window events
+        let window_id = fields
+            .get("window")
+            .and_then(|window| window.get("id"))
+            .or_else(|| fields.get("id").filter(|_| name.starts_with("Window")))
+            .and_then(Value::as_u64);
+        let values = self.placeholders(&name, &fields, window_id);
+        let env = HashMap::from([("PIRI_EVENT".to_string(), serde_json::to_string(event)?)]);
+
+        for hook in self.config.hooks.iter().filter(|hook| hook.event == name) {
+            if !self.hook_matches(hook, window_id).await? {
+                continue;
+            }
+            let command = hook.exec.expand(|text, quote| {
+                self.placeholder
+                    .replace_all(text, |caps: &Captures| match values.get(&caps[1]) {
+                        Some(value) if quote => shell_quote(value),
+                        Some(value) => value.clone(),
+                        // Leave unknown placeholders alone, they may be shell syntax
+                        None => caps[0].to_string(),
+                    })
+                    .into_owned()
+            });
+            debug!("Running {} hook: {}", name, command);
+            if let Err(e) = window_utils::execute_with(&command, &env, None) {
+                warn!("Failed to run {} hook: {}", name, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for HooksPlugin {
+    type Config = HooksPluginConfig;
+
+    fn new(_niri: NiriIpc, config: HooksPluginConfig) -> Self {
+        info!("Hooks plugin initialized with {} hooks", config.hooks.len());
+        Self {
+            config,
+            matcher_cache: WindowMatcherCache::new(),
+            placeholder: Regex::new(r"\{([A-Za-z0-9_.]+)\}").expect("valid placeholder regex"),
+            windows: HashMap::new(),
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        match event {
+            Event::WindowsChanged { windows } => {
+                self.windows =
+                    windows.iter().map(|w| (w.id, (w.app_id.clone(), w.title.clone()))).collect();
+            }
+            Event::WindowOpenedOrChanged { window } => {
+                self.windows.insert(window.id, (window.app_id.clone(), window.title.clone()));
+            }
+            _ => {}
+        }
+
+        self.run_hooks(event).await?;
+
+        if let Event::WindowClosed { id } = event {
+            self.windows.remove(id);
+        }
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, _event: &Event) -> bool {
+        // Hooks can be on any event type, and window events keep the window cache current
+        !self.config.hooks.is_empty()
+    }
+
+    async fn update_config(&mut self, config: HooksPluginConfig) -> Result<()> {
+        info!("Updating hooks plugin configuration");
+        self.config = config;
+        self.matcher_cache.clear_cache().await;
+        Ok(())
+    }
+}
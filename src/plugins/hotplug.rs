@@ -0,0 +1,180 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use niri_ipc::{Action, Event, WorkspaceReferenceArg};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+use crate::config::{CommandLine, Config};
+use crate::ipc::{IpcClient, IpcRequest};
+use crate::niri::NiriIpc;
+use crate::plugins::{window_utils, FromConfig};
+
+/// Hotplug plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HotplugPluginConfig {
+    /// Outputs to move orphaned workspaces to, in order of preference
+    pub fallback_outputs: Vec<String>,
+    /// Command run when an output is connected ({output} placeholder)
+    pub on_added: Option<CommandLine>,
+    /// Command run when an output is disconnected ({output} placeholder)
+    pub on_removed: Option<CommandLine>,
+    /// Re-hide hidden scratchpads for the new output layout
+    pub rehome_scratchpads: bool,
+}
+
+impl FromConfig for HotplugPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        let section = &config.piri.hotplug;
+        Some(Self {
+            fallback_outputs: section.fallback_outputs.clone(),
+            on_added: section.on_added.clone(),
+            on_removed: section.on_removed.clone(),
+            rehome_scratchpads: section.rehome_scratchpads,
+        })
+    }
+}
+
+pub struct HotplugPlugin {
+    niri: NiriIpc,
+    config: HotplugPluginConfig,
+    /// Enabled outputs, None until the first check
+    outputs: Option<BTreeSet<String>>,
+    /// Output of every workspace as of the last check
+    workspace_outputs: HashMap<u64, Option<String>>,
+}
+
+impl HotplugPlugin {
+    fn run_hook(command: &Option<CommandLine>, output: &str) {
+        if let Some(command) = command {
+            let command = command.expand(|text, _| text.replace("{output}", output));
+            debug!("Running output hook: {}", command);
+            if let Err(e) = window_utils::execute(&command) {
+                warn!("Failed to run output hook: {}", e);
+            }
+        }
+    }
+
+    /// Compare outputs with the last check and react to connected/disconnected ones
+    async fn check_outputs(&mut self, workspaces: &[niri_ipc::Workspace]) -> Result<()> {
+        let outputs: BTreeSet<String> = self
+            .niri
+            .get_outputs()
+            .await?
+            .into_iter()
+            .filter(|output| output.logical.is_some())
+            .map(|output| output.name)
+            .collect();
+        let previous_workspaces = std::mem::replace(
+            &mut self.workspace_outputs,
+            workspaces.iter().map(|ws| (ws.id, ws.output.clone())).collect(),
+        );
+        let Some(previous) = self.outputs.replace(outputs.clone()) else {
+            info!("Outputs: {:?}", outputs);
+            return Ok(());
+        };
+
+        let added: Vec<&String> = outputs.difference(&previous).collect();
+        let removed: Vec<&String> = previous.difference(&outputs).collect();
+        if added.is_empty() && removed.is_empty() {
+            return Ok(());
+        }
+
+        for output in &removed {
+            info!("Output disconnected: {}", output);
+            self.move_orphaned_workspaces(output, &previous_workspaces, &outputs).await?;
+            Self::run_hook(&self.config.on_removed, output);
+        }
+        for output in &added {
+            info!("Output connected: {}", output);
+            Self::run_hook(&self.config.on_added, output);
+        }
+
+        if self.config.rehome_scratchpads {
+            // Goes through the daemon's IPC like a user command, so it has to run
+            // after this event is handled; give niri a moment to settle the layout
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                let client = IpcClient::new(None);
+                if let Err(e) = client.send_request(IpcRequest::ScratchpadRehome).await {
+                    warn!("Failed to rehome scratchpads: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// niri moves the workspaces of a disconnected output to another output on its own;
+    /// move them on to the first connected fallback output instead
+    async fn move_orphaned_workspaces(
+        &self,
+        removed: &str,
+        previous_workspaces: &HashMap<u64, Option<String>>,
+        outputs: &BTreeSet<String>,
+    ) -> Result<()> {
+        let Some(fallback) = self.config.fallback_outputs.iter().find(|o| outputs.contains(*o))
+        else {
+            return Ok(());
+        };
+
+        let mut orphaned: Vec<u64> = previous_workspaces
+            .iter()
+            .filter(|(_, output)| output.as_deref() == Some(removed))
+            .map(|(&id, _)| id)
+            .collect();
+        orphaned.sort_unstable();
+
+        for workspace_id in orphaned {
+            if !self.workspace_outputs.contains_key(&workspace_id) {
+                // Empty workspaces are removed along with the output
+                continue;
+            }
+            if self.workspace_outputs[&workspace_id].as_deref() == Some(fallback.as_str()) {
+                continue;
+            }
+            debug!("Moving workspace {} to {}", workspace_id, fallback);
+            self.niri
+                .send_action(Action::MoveWorkspaceToMonitor {
+                    output: fallback.clone(),
+                    reference: Some(WorkspaceReferenceArg::Id(workspace_id)),
+                })
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for HotplugPlugin {
+    type Config = HotplugPluginConfig;
+
+    fn new(niri: NiriIpc, config: HotplugPluginConfig) -> Self {
+        info!("Hotplug plugin initialized");
+        Self {
+            niri,
+            config,
+            outputs: None,
+            workspace_outputs: HashMap::new(),
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        // niri has no output events, but connecting or disconnecting an output always
+        // creates, removes or moves workspaces
+        if let Event::WorkspacesChanged { workspaces } = event {
+            self.check_outputs(workspaces).await?;
+        }
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(event, Event::WorkspacesChanged { .. })
+    }
+
+    async fn update_config(&mut self, config: HotplugPluginConfig) -> Result<()> {
+        info!("Updating hotplug plugin configuration");
+        self.config = config;
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use niri_ipc::Event;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Stdio};
+
+use crate::config::{CommandLine, Config, IdleInhibitRule, InhibitWhen};
+use crate::niri::NiriIpc;
+use crate::plugins::window_utils::{self, WindowMatcher, WindowMatcherCache};
+use crate::plugins::FromConfig;
+
+/// Idle inhibit plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IdleInhibitPluginConfig {
+    /// Command that keeps the session from going idle while it runs
+    pub command: CommandLine,
+    pub rules: Vec<IdleInhibitRule>,
+}
+
+impl FromConfig for IdleInhibitPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            command: config.piri.idle_inhibit.command.clone(),
+            rules: config.idle_inhibit.clone(),
+        })
+    }
+}
+
+pub struct IdleInhibitPlugin {
+    config: IdleInhibitPluginConfig,
+    matcher_cache: WindowMatcherCache,
+    /// app_id and title of every open window
+    windows: HashMap<u64, (Option<String>, Option<String>)>,
+    focused: Option<u64>,
+    /// Running inhibit command, if inhibiting
+    inhibitor: Option<Child>,
+}
+
+impl IdleInhibitPlugin {
+    async fn window_matches(&self, window_id: u64, rule: &IdleInhibitRule) -> Result<bool> {
+        let Some((app_id, title)) = self.windows.get(&window_id) else {
+            return Ok(false);
+        };
+        let matcher = WindowMatcher::new(rule.app_id.clone(), rule.title.clone());
+        self.matcher_cache.matches(app_id.as_ref(), title.as_ref(), &matcher).await
+    }
+
+    /// Check whether any rule currently asks for idle to be inhibited
+    async fn should_inhibit(&self) -> Result<bool> {
+        for rule in &self.config.rules {
+            let matched = match rule.when {
+                InhibitWhen::Exists => {
+                    let mut any = false;
+                    for &window_id in self.windows.keys() {
+                        if self.window_matches(window_id, rule).await? {
+                            any = true;
+                            break;
+                        }
+                    }
+                    any
+                }
+                InhibitWhen::Focused => match self.focused {
+                    Some(window_id) => self.window_matches(window_id, rule).await?,
+                    None => false,
+                },
+            };
+            if matched {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    async fn update(&mut self) -> Result<()> {
+        // Reap an inhibitor that exited on its own so it gets restarted
+        if let Some(ref mut child) = self.inhibitor {
+            if let Ok(Some(status)) = child.try_wait() {
+                warn!("Idle inhibit command exited unexpectedly: {}", status);
+                self.inhibitor = None;
+            }
+        }
+
+        let inhibit = self.should_inhibit().await?;
+        match (inhibit, self.inhibitor.is_some()) {
+            (true, false) => self.start_inhibitor()?,
+            (false, true) => self.stop_inhibitor(),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn start_inhibitor(&mut self) -> Result<()> {
+        info!("Inhibiting idle");
+        let child = window_utils::command_process(&self.config.command)?
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            // Own process group, so stopping also ends whatever the shell started
+            .process_group(0)
+            .spawn()
+            .with_context(|| {
+                format!(
+                    "Failed to execute idle inhibit command: {}",
+                    self.config.command
+                )
+            })?;
+        self.inhibitor = Some(child);
+        Ok(())
+    }
+
+    fn stop_inhibitor(&mut self) {
+        let Some(mut child) = self.inhibitor.take() else {
+            return;
+        };
+        info!("Releasing idle inhibit");
+        // Signal the whole process group started by start_inhibitor
+        unsafe {
+            libc::kill(-(child.id() as i32), libc::SIGTERM);
+        }
+        // Reap in the background so a slow exit doesn't block event handling
+        std::thread::spawn(move || {
+            if let Err(e) = child.wait() {
+                debug!("Failed to wait for idle inhibit command: {}", e);
+            }
+        });
+    }
+}
+
+impl Drop for IdleInhibitPlugin {
+    fn drop(&mut self) {
+        self.stop_inhibitor();
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for IdleInhibitPlugin {
+    type Config = IdleInhibitPluginConfig;
+
+    fn new(_niri: NiriIpc, config: IdleInhibitPluginConfig) -> Self {
+        info!(
+            "Idle inhibit plugin initialized with {} rules",
+            config.rules.len()
+        );
+        Self {
+            config,
+            matcher_cache: WindowMatcherCache::new(),
+            windows: HashMap::new(),
+            focused: None,
+            inhibitor: None,
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        match event {
+            Event::WindowsChanged { windows } => {
+                self.windows =
+                    windows.iter().map(|w| (w.id, (w.app_id.clone(), w.title.clone()))).collect();
+                self.focused = windows.iter().find(|w| w.is_focused).map(|w| w.id);
+            }
+            Event::WindowOpenedOrChanged { window } => {
+                self.windows.insert(window.id, (window.app_id.clone(), window.title.clone()));
+                if window.is_focused {
+                    self.focused = Some(window.id);
+                }
+            }
+            Event::WindowClosed { id } => {
+                self.windows.remove(id);
+                if self.focused == Some(*id) {
+                    self.focused = None;
+                }
+            }
+            Event::WindowFocusChanged { id } => {
+                self.focused = *id;
+            }
+            _ => {}
+        }
+        self.update().await
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(
+            event,
+            Event::WindowsChanged { .. }
+                | Event::WindowOpenedOrChanged { .. }
+                | Event::WindowClosed { .. }
+                | Event::WindowFocusChanged { .. }
+        )
+    }
+
+    async fn status(&mut self) -> Result<Option<serde_json::Value>> {
+        Ok(Some(
+            serde_json::json!({ "inhibited": self.inhibitor.is_some() }),
+        ))
+    }
+
+    async fn update_config(&mut self, config: IdleInhibitPluginConfig) -> Result<()> {
+        info!("Updating idle inhibit plugin configuration");
+        // Restart a running inhibitor if its command changed
+        if config.command != self.config.command {
+            self.stop_inhibitor();
+        }
+        self.config = config;
+        self.matcher_cache.clear_cache().await;
+        self.update().await
+    }
+}
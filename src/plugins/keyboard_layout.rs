@@ -0,0 +1,164 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use niri_ipc::{Action, Event, LayoutSwitchTarget};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::config::Config;
+use crate::niri::NiriIpc;
+use crate::plugins::FromConfig;
+
+/// Keyboard layout plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeyboardLayoutPluginConfig {
+    /// Layout name for windows without a remembered layout
+    pub default: Option<String>,
+}
+
+impl FromConfig for KeyboardLayoutPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            default: config.piri.keyboard_layout.default.clone(),
+        })
+    }
+}
+
+/// Remembers the keyboard layout used in each window and switches back to it whenever
+/// the window gets focused again.
+pub struct KeyboardLayoutPlugin {
+    niri: NiriIpc,
+    config: KeyboardLayoutPluginConfig,
+    /// Layout names, as configured in niri
+    names: Vec<String>,
+    /// Index of the active layout
+    current: Option<u8>,
+    focused: Option<u64>,
+    /// Window ID -> layout index
+    layouts: HashMap<u64, u8>,
+}
+
+impl KeyboardLayoutPlugin {
+    fn default_layout(&self) -> Option<u8> {
+        let name = self.config.default.as_ref()?;
+        let index = self.names.iter().position(|n| n == name);
+        if index.is_none() {
+            warn!(
+                "Default keyboard layout '{}' is not one of {:?}",
+                name, self.names
+            );
+        }
+        index.and_then(|i| u8::try_from(i).ok())
+    }
+
+    async fn focus_changed(&mut self, window_id: u64) -> Result<()> {
+        let target = match self.layouts.get(&window_id) {
+            Some(&index) => Some(index),
+            None => self.default_layout(),
+        };
+        match target {
+            Some(index) if Some(index) != self.current => {
+                debug!(
+                    "Switching keyboard layout to {:?} for window {}",
+                    self.names.get(index as usize),
+                    window_id
+                );
+                self.niri
+                    .send_action(Action::SwitchLayout {
+                        layout: LayoutSwitchTarget::Index(index),
+                    })
+                    .await?;
+                self.current = Some(index);
+                self.layouts.insert(window_id, index);
+            }
+            // Windows seen for the first time keep the layout that is active
+            _ => {
+                if let Some(current) = self.current {
+                    self.layouts.entry(window_id).or_insert(current);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for KeyboardLayoutPlugin {
+    type Config = KeyboardLayoutPluginConfig;
+
+    fn new(niri: NiriIpc, config: KeyboardLayoutPluginConfig) -> Self {
+        info!("Keyboard layout plugin initialized");
+        Self {
+            niri,
+            config,
+            names: Vec::new(),
+            current: None,
+            focused: None,
+            layouts: HashMap::new(),
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        match event {
+            Event::KeyboardLayoutsChanged { keyboard_layouts } => {
+                // Remembered indices mean nothing once the list of layouts changed
+                if keyboard_layouts.names != self.names {
+                    self.layouts.clear();
+                    self.names = keyboard_layouts.names.clone();
+                }
+                self.current = Some(keyboard_layouts.current_idx);
+            }
+            Event::KeyboardLayoutSwitched { idx } => {
+                self.current = Some(*idx);
+                if let Some(focused) = self.focused {
+                    self.layouts.insert(focused, *idx);
+                }
+            }
+            Event::WindowsChanged { windows } => {
+                self.focused = windows.iter().find(|w| w.is_focused).map(|w| w.id);
+                self.layouts.retain(|id, _| windows.iter().any(|w| w.id == *id));
+            }
+            Event::WindowFocusChanged { id } => {
+                self.focused = *id;
+                if let Some(id) = id {
+                    self.focus_changed(*id).await?;
+                }
+            }
+            Event::WindowClosed { id } => {
+                self.layouts.remove(id);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    async fn on_niri_reconnected(&mut self, _niri: &NiriIpc) -> Result<()> {
+        self.layouts.clear();
+        self.focused = None;
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(
+            event,
+            Event::KeyboardLayoutsChanged { .. }
+                | Event::KeyboardLayoutSwitched { .. }
+                | Event::WindowsChanged { .. }
+                | Event::WindowFocusChanged { .. }
+                | Event::WindowClosed { .. }
+        )
+    }
+
+    async fn status(&mut self) -> Result<Option<serde_json::Value>> {
+        let current = self.current.and_then(|i| self.names.get(i as usize));
+        Ok(Some(serde_json::json!({
+            "layout": current,
+        })))
+    }
+
+    async fn update_config(&mut self, config: KeyboardLayoutPluginConfig) -> Result<()> {
+        info!("Updating keyboard layout plugin configuration");
+        self.config = config;
+        Ok(())
+    }
+}
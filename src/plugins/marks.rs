@@ -0,0 +1,229 @@
+use anyhow::Result;
+use log::{debug, info, warn};
+use niri_ipc::Event;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::ipc::{IpcError, IpcRequest};
+use crate::niri::{NiriIpc, Window};
+use crate::utils::{load_state, save_state};
+
+/// State file (in the state directory) that marks are persisted to
+const MARKS_FILE: &str = "marks.json";
+
+/// A mark and the window it points to, as returned by `MarkList`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarkInfo {
+    pub mark: String,
+    pub window_id: u64,
+    pub app_id: Option<String>,
+    pub title: String,
+    /// Workspace name, or idx if unnamed
+    pub workspace: Option<String>,
+}
+
+/// A marked window. niri reuses window IDs after it restarts, so the window is only taken
+/// for the marked one while it has the same app_id, or title if it has none
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Mark {
+    window_id: u64,
+    app_id: Option<String>,
+    title: String,
+}
+
+impl Mark {
+    fn matches(&self, window_id: u64, app_id: Option<&str>, title: Option<&str>) -> bool {
+        window_id == self.window_id
+            && match self.app_id {
+                Some(ref marked) => app_id == Some(marked.as_str()),
+                None => app_id.is_none() && title == Some(self.title.as_str()),
+            }
+    }
+
+    fn matches_window(&self, window: &Window) -> bool {
+        self.matches(window.id, window.app_id.as_deref(), Some(&window.title))
+    }
+}
+
+pub struct MarksPlugin {
+    niri: NiriIpc,
+    /// Mark name -> marked window
+    marks: BTreeMap<String, Mark>,
+}
+
+impl MarksPlugin {
+    fn validate_mark(name: &str) -> Result<()> {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_alphanumeric() => Ok(()),
+            _ => anyhow::bail!(
+                "Invalid mark '{}': marks are a single letter or digit",
+                name
+            ),
+        }
+    }
+
+    fn save(&self) {
+        if let Err(e) = save_state(MARKS_FILE, &self.marks) {
+            warn!("Failed to save marks: {}", e);
+        }
+    }
+
+    async fn set(&mut self, name: &str) -> Result<()> {
+        Self::validate_mark(name)?;
+        let window_id = self
+            .niri
+            .get_focused_window_id()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("No focused window to mark"))?;
+        let window = self
+            .niri
+            .get_windows()
+            .await?
+            .into_iter()
+            .find(|w| w.id == window_id)
+            .ok_or_else(|| anyhow::anyhow!("The focused window closed"))?;
+
+        info!("Setting mark '{}' on window {}", name, window_id);
+        self.marks.insert(
+            name.to_string(),
+            Mark {
+                window_id,
+                app_id: window.app_id,
+                title: window.title,
+            },
+        );
+        self.save();
+        Ok(())
+    }
+
+    async fn goto(&mut self, name: &str) -> Result<()> {
+        let mark = self
+            .marks
+            .get(name)
+            .ok_or_else(|| IpcError::not_found(format!("Mark '{}' is not set", name)))?;
+        let window_id = mark.window_id;
+
+        let exists = self.niri.get_windows().await?.iter().any(|w| mark.matches_window(w));
+        if !exists {
+            self.marks.remove(name);
+            self.save();
+            anyhow::bail!(IpcError::not_found(format!(
+                "Mark '{}' pointed to a window that no longer exists",
+                name
+            )));
+        }
+
+        debug!("Jumping to mark '{}' (window {})", name, window_id);
+        // Focusing a window on another workspace or output switches there
+        self.niri.focus_window(window_id).await
+    }
+
+    async fn list(&self) -> Result<Vec<MarkInfo>> {
+        let windows = self.niri.get_windows().await?;
+        let workspaces = self.niri.get_workspaces().await?;
+
+        Ok(self
+            .marks
+            .iter()
+            .filter_map(|(name, mark)| {
+                let window = windows.iter().find(|w| mark.matches_window(w))?;
+                let workspace = window.workspace_id.and_then(|id| {
+                    workspaces
+                        .iter()
+                        .find(|ws| ws.id == id)
+                        .map(|ws| ws.name.clone().unwrap_or_else(|| ws.idx.to_string()))
+                });
+                Some(MarkInfo {
+                    mark: name.clone(),
+                    window_id: mark.window_id,
+                    app_id: window.app_id.clone(),
+                    title: window.title.clone(),
+                    workspace,
+                })
+            })
+            .collect())
+    }
+}
+
+#[async_trait::async_trait]
+impl crate::plugins::Plugin for MarksPlugin {
+    type Config = ();
+
+    fn new(niri: NiriIpc, _config: ()) -> Self {
+        let marks: BTreeMap<String, Mark> = match load_state(MARKS_FILE) {
+            Ok(marks) => marks.unwrap_or_default(),
+            Err(e) => {
+                warn!("Failed to load marks: {}", e);
+                BTreeMap::new()
+            }
+        };
+        info!("Marks plugin initialized with {} saved marks", marks.len());
+        Self { niri, marks }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        let before = self.marks.len();
+        match event {
+            // Drop saved marks whose windows are gone, or whose IDs now belong to other
+            // windows (e.g. after niri restarted)
+            Event::WindowsChanged { windows } => {
+                self.marks.retain(|_, mark| {
+                    windows
+                        .iter()
+                        .any(|w| mark.matches(w.id, w.app_id.as_deref(), w.title.as_deref()))
+                });
+            }
+            Event::WindowClosed { id } => {
+                self.marks.retain(|_, mark| mark.window_id != *id);
+            }
+            _ => {}
+        }
+        if self.marks.len() != before {
+            debug!(
+                "Removed {} marks of closed windows",
+                before - self.marks.len()
+            );
+            self.save();
+        }
+        Ok(())
+    }
+
+    async fn on_niri_reconnected(&mut self, _niri: &NiriIpc) -> Result<()> {
+        // Window IDs start over, so the marked windows can't be told apart from new ones
+        if !self.marks.is_empty() {
+            info!(
+                "Dropping {} marks of windows from before niri restarted",
+                self.marks.len()
+            );
+            self.marks.clear();
+            self.save();
+        }
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(
+            event,
+            Event::WindowsChanged { .. } | Event::WindowClosed { .. }
+        )
+    }
+
+    async fn handle_ipc_query(
+        &mut self,
+        request: &IpcRequest,
+    ) -> Result<Option<serde_json::Value>> {
+        match request {
+            IpcRequest::MarkList => Ok(Some(serde_json::to_value(self.list().await?)?)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn handle_ipc_request(&mut self, request: &IpcRequest) -> Result<Option<Result<()>>> {
+        match request {
+            IpcRequest::MarkSet { name } => Ok(Some(self.set(name).await)),
+            IpcRequest::MarkGoto { name } => Ok(Some(self.goto(name).await)),
+            _ => Ok(None),
+        }
+    }
+}
@@ -1,28 +1,89 @@
+pub mod auto_fullscreen;
 pub mod autofill;
+pub mod autostart;
+pub mod bar;
+pub mod column_width;
+pub mod columns;
+pub mod dnd;
 pub mod empty;
+pub mod floating_geometry;
+pub mod focus_history;
+pub mod gaming;
+pub mod groups;
+pub mod hooks;
+pub mod hotplug;
+pub mod idle_inhibit;
+pub mod keyboard_layout;
+pub mod marks;
+pub mod power;
+pub mod present;
+pub mod schedule;
 pub mod scratchpads;
+pub mod sendto;
+pub mod session;
 pub mod singleton;
+pub mod stash;
 pub mod swallow;
+pub mod title_watch;
+pub mod wallpaper;
 pub mod window_order;
 pub mod window_rule;
 pub mod window_utils;
+pub mod workspace_lifecycle;
+pub mod workspace_names;
+pub mod workspace_osd;
+pub mod workspace_pin;
 
 use anyhow::Result;
 use async_trait::async_trait;
-use log::{debug, info, warn};
+use futures::FutureExt;
+use log::{debug, error, info, warn};
 use niri_ipc::Event;
-use tokio::sync::mpsc;
-use tokio::time::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use tokio::sync::{mpsc, watch};
 
 use crate::config::Config;
-use crate::ipc::IpcRequest;
+use crate::ipc::{IpcClient, IpcRequest};
 use crate::niri::NiriIpc;
-use crate::utils::send_notification;
+use crate::utils::{merge_json, send_notification};
+
+/// What the niri event listener passes on to the daemon
+pub enum NiriMessage {
+    Event(Event),
+    /// The event stream is back after niri went away (see `Plugin::on_niri_reconnected`)
+    Reconnected,
+}
+
+/// Whether a plugin is running, as reported by `piri status`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginState {
+    pub name: String,
+    pub enabled: bool,
+    pub suspended: bool,
+    /// Disabled after it panicked, until the next reload
+    #[serde(default)]
+    pub crashed: bool,
+}
+
+/// Run a plugin call, turning a panic into an error with its message, so that it takes
+/// down only that plugin and not the daemon loop
+async fn guarded<T>(call: impl Future<Output = T>) -> std::result::Result<T, String> {
+    AssertUnwindSafe(call).catch_unwind().await.map_err(|payload| {
+        payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string())
+    })
+}
 
 /// Plugin trait that all plugins must implement
 #[async_trait]
 pub trait Plugin: Send + Sync {
-    type Config: Clone + Send + Sync + FromConfig;
+    type Config: Clone + Send + Sync + FromConfig + Serialize;
 
     /// Create a new instance of the plugin
     fn new(niri: NiriIpc, config: Self::Config) -> Self
@@ -33,10 +94,49 @@ pub trait Plugin: Send + Sync {
         Ok(None)
     }
 
+    /// Handle an IPC request that returns data (e.g. status listings)
+    async fn handle_ipc_query(
+        &mut self,
+        _request: &IpcRequest,
+    ) -> Result<Option<serde_json::Value>> {
+        Ok(None)
+    }
+
+    /// Runtime state shown by status bars (see the bar plugin), keyed by plugin name
+    async fn status(&mut self) -> Result<Option<serde_json::Value>> {
+        Ok(None)
+    }
+
+    /// Config entries created at runtime (e.g. with `piri scratchpads add`), as a table to
+    /// merge into the config shown by `piri config show`
+    async fn runtime_config(&mut self) -> Result<Option<serde_json::Value>> {
+        Ok(None)
+    }
+
+    /// Runtime state to hand over to the daemon `piri restart` starts, such as the windows
+    /// found for scratchpads. None if the plugin has nothing worth keeping
+    async fn export_state(&mut self) -> Result<Option<serde_json::Value>> {
+        Ok(None)
+    }
+
+    /// Take over the state the previous daemon exported, right after the plugin was created
+    /// and before the first events. The windows it refers to may have closed meanwhile
+    async fn import_state(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
     async fn handle_event(&mut self, _event: &Event, _niri: &NiriIpc) -> Result<()> {
         Ok(())
     }
 
+    /// Called when the event stream connects again after niri went away, e.g. because the
+    /// compositor restarted. Window and workspace IDs from before are no longer valid and
+    /// may be reused for other windows; the window and workspace lists of the new stream
+    /// arrive right after. Suspended plugins are called too
+    async fn on_niri_reconnected(&mut self, _niri: &NiriIpc) -> Result<()> {
+        Ok(())
+    }
+
     /// Check if plugin is interested in a specific event type
     /// This is used by PluginManager for event filtering to avoid calling plugins that don't care about the event.
     /// Only events that pass this filter will be passed to handle_event().
@@ -66,12 +166,38 @@ impl FromConfig for () {
     }
 }
 
+/// Suspend or resume other plugins, in order; `owner` itself is never suspended. Goes
+/// through the daemon's IPC like a user command, so it runs after the current event or
+/// request is handled
+pub(crate) fn request_suspensions(owner: &'static str, changes: Vec<(Vec<String>, bool)>) {
+    tokio::spawn(async move {
+        let client = IpcClient::new(None);
+        for (plugins, suspend) in changes {
+            let plugins: Vec<String> = plugins.into_iter().filter(|name| name != owner).collect();
+            if plugins.is_empty() {
+                continue;
+            }
+            let request = IpcRequest::PluginSuspend {
+                owner: owner.to_string(),
+                plugins,
+                suspend,
+            };
+            if let Err(e) = client.send_request(request).await {
+                warn!("Failed to change plugin suspension: {}", e);
+            }
+        }
+    });
+}
+
 macro_rules! register_plugins {
     ($($name:expr => $variant:ident($module:ident::$struct:ident)),* $(,)?) => {
         pub enum PluginEnum {
             $($variant($module::$struct),)*
         }
 
+        /// Names of all plugins, in registration order
+        pub const PLUGIN_NAMES: &[&str] = &[$($name,)*];
+
         impl PluginEnum {
             pub fn name(&self) -> &str {
                 match self {
@@ -85,6 +211,12 @@ macro_rules! register_plugins {
                 }
             }
 
+            async fn on_niri_reconnected(&mut self, niri: &NiriIpc) -> Result<()> {
+                match self {
+                    $(PluginEnum::$variant(p) => p.on_niri_reconnected(niri).await,)*
+                }
+            }
+
             fn is_interested_in_event(&self, event: &Event) -> bool {
                 match self {
                     $(PluginEnum::$variant(p) => p.is_interested_in_event(event),)*
@@ -97,6 +229,36 @@ macro_rules! register_plugins {
                 }
             }
 
+            async fn handle_ipc_query(&mut self, request: &IpcRequest) -> Result<Option<serde_json::Value>> {
+                match self {
+                    $(PluginEnum::$variant(p) => p.handle_ipc_query(request).await,)*
+                }
+            }
+
+            async fn status(&mut self) -> Result<Option<serde_json::Value>> {
+                match self {
+                    $(PluginEnum::$variant(p) => p.status().await,)*
+                }
+            }
+
+            async fn runtime_config(&mut self) -> Result<Option<serde_json::Value>> {
+                match self {
+                    $(PluginEnum::$variant(p) => p.runtime_config().await,)*
+                }
+            }
+
+            async fn export_state(&mut self) -> Result<Option<serde_json::Value>> {
+                match self {
+                    $(PluginEnum::$variant(p) => p.export_state().await,)*
+                }
+            }
+
+            async fn import_state(&mut self, state: serde_json::Value) -> Result<()> {
+                match self {
+                    $(PluginEnum::$variant(p) => p.import_state(state).await,)*
+                }
+            }
+
             async fn update_config(&mut self, config: &Config) -> Result<()> {
                 match self {
                     $(PluginEnum::$variant(p) => {
@@ -113,40 +275,103 @@ macro_rules! register_plugins {
             }
         }
 
+        /// The settings a plugin gets from the config, to tell whether a reload changes them
+        fn plugin_settings(name: &str, config: &Config) -> Option<serde_json::Value> {
+            match name {
+                $($name => {
+                    let plugin_config = <<$module::$struct as Plugin>::Config as FromConfig>::from_config(config)?;
+                    serde_json::to_value(plugin_config).ok()
+                })*
+                _ => None,
+            }
+        }
+
         impl PluginManager {
-            pub async fn init(&mut self, niri: NiriIpc, config: &Config) -> Result<()> {
-                let p = &config.piri.plugins;
-                $(
-                    let plugin_config = <<$module::$struct as Plugin>::Config as FromConfig>::from_config(config);
-                    let enabled = p.is_enabled($name) && plugin_config.is_some();
-
-                    self.init_or_update_plugin($name, enabled, niri.clone(), config, || {
-                        PluginEnum::$variant(<$module::$struct as Plugin>::new(
-                            niri.clone(),
-                            plugin_config.unwrap(),
-                        ))
-                    }).await?;
-                )*
-                Ok(())
+            /// Create, update or remove a single plugin according to the config
+            async fn init_plugin(&mut self, name: &str, niri: NiriIpc, config: &Config) -> Result<()> {
+                match name {
+                    $($name => {
+                        let plugin_config = <<$module::$struct as Plugin>::Config as FromConfig>::from_config(config);
+                        let enabled = config.plugin_runs($name) && plugin_config.is_some();
+
+                        self.init_or_update_plugin($name, enabled, niri.clone(), config, || {
+                            PluginEnum::$variant(<$module::$struct as Plugin>::new(
+                                niri.clone(),
+                                plugin_config.unwrap(),
+                            ))
+                        }).await
+                    })*
+                    _ => anyhow::bail!("Unknown plugin '{}'", name),
+                }
             }
         }
     };
 }
 
 register_plugins! {
-    "empty"        => Empty(empty::EmptyPlugin),
-    "window_rule"  => WindowRule(window_rule::WindowRulePlugin),
-    "scratchpads"  => Scratchpads(scratchpads::ScratchpadsPlugin),
-    "singleton"    => Singleton(singleton::SingletonPlugin),
-    "window_order" => WindowOrder(window_order::WindowOrderPlugin),
-    "autofill"     => Autofill(autofill::AutofillPlugin),
-    "swallow"      => Swallow(swallow::SwallowPlugin),
+    "empty"               => Empty(empty::EmptyPlugin),
+    "window_rule"         => WindowRule(window_rule::WindowRulePlugin),
+    "scratchpads"         => Scratchpads(scratchpads::ScratchpadsPlugin),
+    "singleton"           => Singleton(singleton::SingletonPlugin),
+    "window_order"        => WindowOrder(window_order::WindowOrderPlugin),
+    "autofill"            => Autofill(autofill::AutofillPlugin),
+    "swallow"             => Swallow(swallow::SwallowPlugin),
+    "focus_history"       => FocusHistory(focus_history::FocusHistoryPlugin),
+    "marks"               => Marks(marks::MarksPlugin),
+    "session"             => Session(session::SessionPlugin),
+    "bar"                 => Bar(bar::BarPlugin),
+    "workspace_names"     => WorkspaceNames(workspace_names::WorkspaceNamesPlugin),
+    "idle_inhibit"        => IdleInhibit(idle_inhibit::IdleInhibitPlugin),
+    "wallpaper"           => Wallpaper(wallpaper::WallpaperPlugin),
+    "hotplug"             => Hotplug(hotplug::HotplugPlugin),
+    "workspace_lifecycle" => WorkspaceLifecycle(workspace_lifecycle::WorkspaceLifecyclePlugin),
+    "groups"              => Groups(groups::GroupsPlugin),
+    "hooks"               => Hooks(hooks::HooksPlugin),
+    "floating_geometry"   => FloatingGeometry(floating_geometry::FloatingGeometryPlugin),
+    "auto_fullscreen"     => AutoFullscreen(auto_fullscreen::AutoFullscreenPlugin),
+    "gaming"              => Gaming(gaming::GamingPlugin),
+    "power"               => Power(power::PowerPlugin),
+    "autostart"           => Autostart(autostart::AutostartPlugin),
+    "columns"             => Columns(columns::ColumnsPlugin),
+    "workspace_pin"       => WorkspacePin(workspace_pin::WorkspacePinPlugin),
+    "workspace_osd"       => WorkspaceOsd(workspace_osd::WorkspaceOsdPlugin),
+    "title_watch"         => TitleWatch(title_watch::TitleWatchPlugin),
+    "stash"               => Stash(stash::StashPlugin),
+    "dnd"                 => Dnd(dnd::DndPlugin),
+    "column_width"        => ColumnWidth(column_width::ColumnWidthPlugin),
+    "schedule"            => Schedule(schedule::SchedulePlugin),
+    "present"             => Present(present::PresentPlugin),
+    "keyboard_layout"     => KeyboardLayout(keyboard_layout::KeyboardLayoutPlugin),
+    "sendto"              => SendTo(sendto::SendToPlugin),
+}
+
+/// The output of the window or workspace an event is about, if niri still knows it
+async fn event_output(event: &Event, niri: &NiriIpc) -> Option<String> {
+    let workspace_id = match event {
+        Event::WindowOpenedOrChanged { window } => window.workspace_id?,
+        Event::WorkspaceActivated { id, .. } => *id,
+        Event::WindowFocusChanged { id: Some(id) } => {
+            let windows = niri.get_windows().await.ok()?;
+            return windows.into_iter().find(|window| window.id == *id)?.output;
+        }
+        _ => return None,
+    };
+    let workspaces = niri.get_workspaces().await.ok()?;
+    workspaces.into_iter().find(|ws| ws.id == workspace_id)?.output
 }
 
 pub struct PluginManager {
     plugins: Vec<PluginEnum>,
     event_listener_handle: Option<tokio::task::JoinHandle<()>>,
-    event_sender: Option<mpsc::UnboundedSender<Event>>,
+    event_sender: Option<mpsc::UnboundedSender<NiriMessage>>,
+    status_tx: watch::Sender<serde_json::Value>,
+    /// Plugins that don't receive events for now, with the plugins that suspended them (see
+    /// the gaming, dnd and present plugins)
+    suspended: HashMap<String, HashSet<String>>,
+    /// Plugins taken out after they panicked, until the next reload
+    crashed: HashSet<String>,
+    /// The config the plugins were last set up with, for the plugins switched per output
+    config: Config,
 }
 
 impl PluginManager {
@@ -155,13 +380,17 @@ impl PluginManager {
             plugins: Vec::new(),
             event_listener_handle: None,
             event_sender: None,
+            status_tx: watch::channel(serde_json::Value::Null).0,
+            suspended: HashMap::new(),
+            crashed: HashSet::new(),
+            config: Config::default(),
         }
     }
 
     pub async fn start_event_listener(
         &mut self,
         niri: NiriIpc,
-    ) -> Result<mpsc::UnboundedReceiver<Event>> {
+    ) -> Result<mpsc::UnboundedReceiver<NiriMessage>> {
         let (tx, rx) = mpsc::unbounded_channel();
         let tx_clone = tx.clone();
         self.event_sender = Some(tx);
@@ -176,7 +405,7 @@ impl PluginManager {
         Ok(rx)
     }
 
-    async fn event_listener_loop(niri: NiriIpc, event_tx: mpsc::UnboundedSender<Event>) {
+    async fn event_listener_loop(niri: NiriIpc, event_tx: mpsc::UnboundedSender<NiriMessage>) {
         info!("Plugin manager event listener started");
 
         let mut is_first_connection = true;
@@ -186,8 +415,8 @@ impl PluginManager {
             let socket = match niri.create_event_stream_socket() {
                 Ok(s) => s,
                 Err(e) => {
-                    warn!("Failed to create event stream: {}, retrying in 1s", e);
-                    tokio::time::sleep(Duration::from_millis(1000)).await;
+                    warn!("Failed to create event stream: {}", e);
+                    niri.wait_until_ready().await;
                     continue;
                 }
             };
@@ -202,13 +431,16 @@ impl PluginManager {
                     "Started successfully, socket connection established",
                 );
                 is_first_connection = false;
+            } else if event_tx.send(NiriMessage::Reconnected).is_err() {
+                warn!("Event channel closed, stopping event listener");
+                return;
             }
 
             while let Ok(event) = read_event() {
                 debug!("Raw event received: {:?}", event);
 
                 // Send event to channel for distribution
-                if event_tx.send(event).is_err() {
+                if event_tx.send(NiriMessage::Event(event)).is_err() {
                     warn!("Event channel closed, stopping event listener");
                     return;
                 }
@@ -216,20 +448,133 @@ impl PluginManager {
 
             // Connection closed or error - will reconnect in outer loop
             warn!("Event stream closed, reconnecting...");
-            tokio::time::sleep(Duration::from_millis(1000)).await;
+            niri.wait_until_ready().await;
         }
     }
 
     /// Distribute event to all plugins (called from daemon loop)
     /// Only plugins that are interested in the event type will receive it
     pub async fn distribute_event(&mut self, event: &Event, niri: &NiriIpc) {
+        // `piri events` clients see every event, suspended plugins or not
+        crate::events::publish_niri(event);
+        if let Event::WindowClosed { id } = event {
+            crate::journal::forget(*id);
+        }
+        let switched_per_output = self
+            .config
+            .outputs
+            .values()
+            .any(|output| PLUGIN_NAMES.iter().any(|name| output.plugins.get(name).is_some()));
+        let output = if switched_per_output {
+            event_output(event, niri).await
+        } else {
+            None
+        };
+        let mut crashed = Vec::new();
         for plugin in &mut self.plugins {
+            if self.suspended.contains_key(plugin.name()) {
+                continue;
+            }
+            // Events that can't be told apart by output, such as closed windows, go to all
+            if let Some(output) = &output {
+                if !self.config.plugin_enabled_on(plugin.name(), output) {
+                    continue;
+                }
+            }
             // Check if plugin is interested in this event type
             if plugin.is_interested_in_event(event) {
-                if let Err(e) = plugin.handle_event(event, niri).await {
-                    log::warn!("Plugin {} error: {}", plugin.name(), e);
-                    send_notification("piri", &format!("Plugin {} error", plugin.name()));
+                crate::metrics::count("piri_events_total", &[("plugin", plugin.name())]);
+                match guarded(plugin.handle_event(event, niri)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => {
+                        log::warn!("Plugin {} error: {}", plugin.name(), e);
+                        send_notification("piri", &format!("Plugin {} error", plugin.name()));
+                    }
+                    Err(panic) => crashed.push((plugin.name().to_string(), panic)),
+                }
+            }
+        }
+        for (name, panic) in crashed {
+            self.disable_crashed(&name, &panic);
+        }
+        self.publish_status().await;
+    }
+
+    /// Take a plugin that panicked out of service until the next reload
+    fn disable_crashed(&mut self, name: &str, panic: &str) {
+        error!(
+            "Plugin {} panicked and is disabled until the next reload: {}",
+            name, panic
+        );
+        send_notification(
+            "piri",
+            &format!("Plugin {} crashed and was disabled: {}", name, panic),
+        );
+        self.plugins.retain(|p| p.name() != name);
+        self.crashed.insert(name.to_string());
+    }
+
+    /// Let every plugin, suspended or not, drop what it knows about windows and workspaces
+    /// from before niri went away
+    pub async fn niri_reconnected(&mut self, niri: &NiriIpc) {
+        info!("Reconnected to niri, resynchronizing plugins");
+        crate::journal::clear();
+        for plugin in &mut self.plugins {
+            if let Err(e) = plugin.on_niri_reconnected(niri).await {
+                warn!("Plugin {} failed to resynchronize: {}", plugin.name(), e);
+            }
+        }
+        self.publish_status().await;
+    }
+
+    /// Subscribe to status updates for `piri bar`, if the bar plugin is enabled
+    pub async fn subscribe_status(&mut self) -> Option<watch::Receiver<serde_json::Value>> {
+        if !self.plugins.iter().any(|p| matches!(p, PluginEnum::Bar(_))) {
+            return None;
+        }
+        let receiver = self.status_tx.subscribe();
+        // Status isn't computed while nobody listens, so refresh it for the new subscriber
+        self.publish_status().await;
+        Some(receiver)
+    }
+
+    /// Recompute the combined plugin status and notify subscribers if it changed
+    async fn publish_status(&mut self) {
+        if self.status_tx.receiver_count() == 0 {
+            return;
+        }
+
+        let mut sections = serde_json::Map::new();
+        let names: Vec<&str> = self.plugins.iter().map(|p| p.name()).collect();
+        sections.insert("plugins".to_string(), serde_json::json!(names));
+        for plugin in &mut self.plugins {
+            match plugin.status().await {
+                Ok(Some(status)) => {
+                    sections.insert(plugin.name().to_string(), status);
                 }
+                Ok(None) => {}
+                Err(e) => debug!("Failed to get status of plugin {}: {}", plugin.name(), e),
+            }
+        }
+
+        let bar = self.plugins.iter().find_map(|p| match p {
+            PluginEnum::Bar(bar) => Some(bar),
+            _ => None,
+        });
+        match bar {
+            Some(bar) => {
+                let status = bar.render(sections);
+                self.status_tx.send_if_modified(|current| {
+                    if *current == status {
+                        return false;
+                    }
+                    *current = status;
+                    true
+                });
+            }
+            None => {
+                // Bar plugin was disabled: drop the channel so open `piri bar` streams end
+                self.status_tx = watch::channel(serde_json::Value::Null).0;
             }
         }
     }
@@ -248,6 +593,8 @@ impl PluginManager {
     where
         F: FnOnce() -> PluginEnum,
     {
+        // Setting a plugin up again gives one that crashed another chance
+        self.crashed.remove(name);
         let existing_plugin = self.plugins.iter_mut().find(|p| p.name() == name);
 
         if enabled {
@@ -273,14 +620,202 @@ impl PluginManager {
         Ok(())
     }
 
+    pub async fn init(&mut self, niri: NiriIpc, config: &Config) -> Result<()> {
+        self.config = config.clone();
+        for name in PLUGIN_NAMES {
+            self.init_plugin(name, niri.clone(), config).await?;
+        }
+        self.finish_init().await;
+        Ok(())
+    }
+
+    /// Apply a changed config to the plugins whose settings differ from the ones they were
+    /// set up with, or that are switched on or off. The other plugins are left alone and
+    /// keep their state, such as queued windows or scratchpads added at runtime
+    pub async fn reload(&mut self, niri: NiriIpc, config: &Config) -> Result<()> {
+        let mut changed = Vec::new();
+        for name in PLUGIN_NAMES {
+            let settings = plugin_settings(name, config);
+            let running = self.plugins.iter().any(|p| p.name() == *name);
+            let enabled = config.plugin_runs(name) && settings.is_some();
+            if running == enabled && (!enabled || settings == plugin_settings(name, &self.config)) {
+                continue;
+            }
+            self.init_plugin(name, niri.clone(), config).await?;
+            changed.push(*name);
+        }
+        if changed.is_empty() {
+            info!("No plugin settings changed");
+        } else {
+            info!("Updated plugins: {}", changed.join(", "));
+        }
+        self.config = config.clone();
+        self.finish_init().await;
+        Ok(())
+    }
+
+    /// Apply the config to one plugin only, leaving the state of the others untouched
+    pub async fn reload_plugin(
+        &mut self,
+        name: &str,
+        niri: NiriIpc,
+        config: &Config,
+    ) -> Result<()> {
+        self.init_plugin(name, niri, config).await?;
+        self.config = config.clone();
+        self.finish_init().await;
+        Ok(())
+    }
+
+    async fn finish_init(&mut self) {
+        // Nothing can lift a suspension once the plugin that asked for it is gone
+        let running: HashSet<&str> = self.plugins.iter().map(|p| p.name()).collect();
+        self.suspended.retain(|_, owners| {
+            owners.retain(|owner| running.contains(owner.as_str()));
+            !owners.is_empty()
+        });
+        self.publish_status().await;
+    }
+
+    /// Config entries the running plugins created at runtime, merged into one table
+    pub async fn runtime_config(&mut self) -> serde_json::Value {
+        let mut merged = serde_json::json!({});
+        for plugin in &mut self.plugins {
+            match plugin.runtime_config().await {
+                Ok(Some(table)) => merge_json(&mut merged, table),
+                Ok(None) => {}
+                Err(e) => debug!(
+                    "Failed to get runtime config of plugin {}: {}",
+                    plugin.name(),
+                    e
+                ),
+            }
+        }
+        merged
+    }
+
+    /// Runtime state of the running plugins that have some, by plugin name
+    pub async fn export_states(&mut self) -> BTreeMap<String, serde_json::Value> {
+        let mut states = BTreeMap::new();
+        for plugin in &mut self.plugins {
+            match plugin.export_state().await {
+                Ok(Some(state)) => {
+                    states.insert(plugin.name().to_string(), state);
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Failed to export state of plugin {}: {}", plugin.name(), e),
+            }
+        }
+        states
+    }
+
+    /// Hand the state exported by the previous daemon to the plugins that are running again
+    pub async fn import_states(&mut self, states: BTreeMap<String, serde_json::Value>) {
+        for (name, state) in states {
+            let Some(plugin) = self.plugins.iter_mut().find(|p| p.name() == name) else {
+                debug!("Plugin {} is no longer running, dropping its state", name);
+                continue;
+            };
+            match plugin.import_state(state).await {
+                Ok(()) => info!("Plugin {} took over its previous state", name),
+                Err(e) => warn!("Failed to import state of plugin {}: {}", name, e),
+            }
+        }
+        self.publish_status().await;
+    }
+
+    /// Status of a running plugin, None if it isn't running or has none
+    pub async fn plugin_status(&mut self, name: &str) -> Option<serde_json::Value> {
+        let plugin = self.plugins.iter_mut().find(|p| p.name() == name)?;
+        plugin.status().await.ok().flatten()
+    }
+
+    /// State of every known plugin, in registration order
+    pub fn plugin_states(&self) -> Vec<PluginState> {
+        PLUGIN_NAMES
+            .iter()
+            .map(|&name| PluginState {
+                name: name.to_string(),
+                enabled: self.plugins.iter().any(|p| p.name() == name),
+                suspended: self.suspended.contains_key(name),
+                crashed: self.crashed.contains(name),
+            })
+            .collect()
+    }
+
     /// Handle IPC request through plugins
     pub async fn handle_ipc_request(&mut self, request: &IpcRequest) -> Result<Option<Result<()>>> {
+        if let IpcRequest::PluginSuspend {
+            owner,
+            plugins,
+            suspend,
+        } = request
+        {
+            for name in plugins {
+                if *suspend {
+                    let owners = self.suspended.entry(name.clone()).or_default();
+                    if owners.is_empty() {
+                        info!("Suspending plugin: {} (for {})", name, owner);
+                    }
+                    owners.insert(owner.clone());
+                } else if let Some(owners) = self.suspended.get_mut(name) {
+                    owners.remove(owner);
+                    if owners.is_empty() {
+                        self.suspended.remove(name);
+                        info!("Resuming plugin: {}", name);
+                    }
+                }
+            }
+            return Ok(Some(Ok(())));
+        }
+
+        let mut crashed = None;
         for plugin in &mut self.plugins {
-            match plugin.handle_ipc_request(request).await? {
-                Some(result) => return Ok(Some(result)),
-                None => continue,
+            match guarded(plugin.handle_ipc_request(request)).await {
+                Ok(result) => {
+                    if let Some(result) = result? {
+                        self.publish_status().await;
+                        return Ok(Some(result));
+                    }
+                }
+                Err(panic) => {
+                    crashed = Some((plugin.name().to_string(), panic));
+                    break;
+                }
             }
         }
+        self.crashed_while_handling(crashed)?;
         Ok(None)
     }
+
+    pub async fn handle_ipc_query(
+        &mut self,
+        request: &IpcRequest,
+    ) -> Result<Option<serde_json::Value>> {
+        let mut crashed = None;
+        for plugin in &mut self.plugins {
+            match guarded(plugin.handle_ipc_query(request)).await {
+                Ok(result) => {
+                    if let Some(data) = result? {
+                        return Ok(Some(data));
+                    }
+                }
+                Err(panic) => {
+                    crashed = Some((plugin.name().to_string(), panic));
+                    break;
+                }
+            }
+        }
+        self.crashed_while_handling(crashed)?;
+        Ok(None)
+    }
+
+    /// Disable the plugin that panicked while handling a request, failing the request
+    fn crashed_while_handling(&mut self, crashed: Option<(String, String)>) -> Result<()> {
+        let Some((name, panic)) = crashed else {
+            return Ok(());
+        };
+        self.disable_crashed(&name, &panic);
+        anyhow::bail!("Plugin {} crashed and was disabled: {}", name, panic)
+    }
 }
@@ -0,0 +1,174 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+use crate::config::{CommandLine, Config, Timespan};
+use crate::ipc::{IpcClient, IpcRequest};
+use crate::niri::NiriIpc;
+use crate::plugins::{window_utils, FromConfig};
+
+/// Where the kernel lists power supplies
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+/// Power plugin config (for internal use)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PowerPluginConfig {
+    /// Profile to switch to on battery
+    pub battery_profile: Option<String>,
+    /// Profile to switch to on AC (None: the base config)
+    pub ac_profile: Option<String>,
+    /// Command run when switching to battery
+    pub on_battery: Option<CommandLine>,
+    /// Command run when switching to AC
+    pub on_ac: Option<CommandLine>,
+    /// Time between power supply checks
+    pub poll_interval: Timespan,
+}
+
+impl FromConfig for PowerPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        let section = &config.piri.power;
+        Some(Self {
+            battery_profile: section.battery_profile.clone(),
+            ac_profile: section.ac_profile.clone(),
+            on_battery: section.on_battery.clone(),
+            on_ac: section.on_ac.clone(),
+            poll_interval: section.poll_interval,
+        })
+    }
+}
+
+/// Whether the machine runs on battery: true if there are external power supplies
+/// (mains or USB chargers) and none of them is online. Machines without any external
+/// supply listed (desktops) count as on AC
+fn on_battery() -> bool {
+    let Ok(entries) = std::fs::read_dir(POWER_SUPPLY_DIR) else {
+        return false;
+    };
+    let read = |dir: &Path, file: &str| {
+        std::fs::read_to_string(dir.join(file)).map(|s| s.trim().to_string()).ok()
+    };
+
+    let mut has_external = false;
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        let Some(kind) = read(&dir, "type") else {
+            continue;
+        };
+        if kind != "Mains" && !kind.starts_with("USB") {
+            continue;
+        }
+        has_external = true;
+        if read(&dir, "online").as_deref() == Some("1") {
+            return false;
+        }
+    }
+    has_external
+}
+
+/// Switches config profiles and runs hooks when the machine goes on battery or AC
+pub struct PowerPlugin {
+    config: PowerPluginConfig,
+    battery: Arc<AtomicBool>,
+    watcher: Option<JoinHandle<()>>,
+}
+
+impl PowerPlugin {
+    async fn apply(config: &PowerPluginConfig, battery: bool) {
+        let (hook, profile) = if battery {
+            (&config.on_battery, &config.battery_profile)
+        } else {
+            (&config.on_ac, &config.ac_profile)
+        };
+
+        if let Some(command) = hook {
+            debug!("Running power hook: {}", command);
+            if let Err(e) = window_utils::execute(command) {
+                warn!("Failed to run power hook: {}", e);
+            }
+        }
+
+        // Goes through the daemon's IPC like a user command, since switching profiles
+        // reinitializes all plugins
+        let client = IpcClient::new(None);
+        let request = IpcRequest::ProfileSet {
+            name: profile.clone(),
+        };
+        if let Err(e) = client.send_request(request).await {
+            warn!("Failed to switch to profile {:?}: {}", profile, e);
+        }
+    }
+
+    fn start_watcher(&mut self) {
+        let config = self.config.clone();
+        let battery = self.battery.clone();
+        self.watcher = Some(tokio::spawn(async move {
+            let mut last = None;
+            loop {
+                let current = on_battery();
+                if last != Some(current) {
+                    info!("Power source: {}", if current { "battery" } else { "AC" });
+                    battery.store(current, Ordering::Relaxed);
+                    Self::apply(&config, current).await;
+                    last = Some(current);
+                }
+                tokio::time::sleep(config.poll_interval.0).await;
+            }
+        }));
+    }
+
+    fn stop_watcher(&mut self) {
+        if let Some(handle) = self.watcher.take() {
+            handle.abort();
+        }
+    }
+}
+
+impl Drop for PowerPlugin {
+    fn drop(&mut self) {
+        self.stop_watcher();
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for PowerPlugin {
+    type Config = PowerPluginConfig;
+
+    fn new(_niri: NiriIpc, config: PowerPluginConfig) -> Self {
+        info!(
+            "Power plugin initialized, checking every {}",
+            config.poll_interval
+        );
+        let mut plugin = Self {
+            config,
+            battery: Arc::new(AtomicBool::new(false)),
+            watcher: None,
+        };
+        plugin.start_watcher();
+        plugin
+    }
+
+    async fn status(&mut self) -> Result<Option<serde_json::Value>> {
+        Ok(Some(serde_json::json!({
+            "battery": self.battery.load(Ordering::Relaxed),
+        })))
+    }
+
+    async fn update_config(&mut self, config: PowerPluginConfig) -> Result<()> {
+        // Profile switches reinitialize plugins with an unchanged power config; keep the
+        // watcher then, restarting it would switch profiles again
+        if config == self.config {
+            return Ok(());
+        }
+        info!("Updating power plugin configuration");
+        self.stop_watcher();
+        self.config = config;
+        self.start_watcher();
+        Ok(())
+    }
+}
@@ -0,0 +1,336 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use niri_ipc::{Action, WorkspaceReferenceArg};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::config::{Config, PresentConfig};
+use crate::ipc::{IpcClient, IpcError, IpcRequest};
+use crate::niri::NiriIpc;
+use crate::plugins::window_utils::{WindowMatcher, WindowMatcherCache};
+use crate::plugins::{request_suspensions, FromConfig};
+
+/// Present plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PresentPluginConfig {
+    pub presentations: HashMap<String, PresentConfig>,
+}
+
+impl FromConfig for PresentPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            presentations: config.present.clone(),
+        })
+    }
+}
+
+/// Where a presented window was before
+#[derive(Debug, Clone)]
+struct MovedWindow {
+    window_id: u64,
+    workspace_id: Option<u64>,
+    floating: bool,
+    /// Column in the scrolling layout (1-based), for tiled windows
+    column: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Presentation {
+    name: String,
+    workspace_id: u64,
+    /// Plugins suspended for this presentation
+    suspended: Vec<String>,
+    windows: Vec<MovedWindow>,
+    previous_workspace_id: Option<u64>,
+    previous_focused: Option<u64>,
+}
+
+/// Presentation mode for screen sharing: `piri present <name>` moves the configured
+/// windows to a clean workspace, hides scratchpads and suspends plugins that would
+/// rearrange them; stopping puts everything back.
+pub struct PresentPlugin {
+    niri: NiriIpc,
+    config: PresentPluginConfig,
+    matcher_cache: WindowMatcherCache,
+    active: Option<Presentation>,
+}
+
+impl PresentPlugin {
+    async fn start(&mut self, name: &str) -> Result<()> {
+        if let Some(ref active) = self.active {
+            anyhow::bail!(
+                "Already presenting '{}', stop it first with `piri present --stop`",
+                active.name
+            );
+        }
+        let presentation = self.config.presentations.get(name).ok_or_else(|| {
+            IpcError::not_found(format!("Presentation '{}' not found in config", name))
+        })?;
+        // A matcher without patterns would match every window
+        if presentation.app_id.is_none() && presentation.title.is_none() {
+            anyhow::bail!("Presentation '{}' needs app_id or title patterns", name);
+        }
+
+        let workspaces = self.niri.get_workspaces().await?;
+        let workspace_id = workspaces
+            .iter()
+            .find(|ws| ws.name.as_deref() == Some(presentation.workspace.as_str()))
+            .map(|ws| ws.id)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Presentation workspace '{}' does not exist, declare it in the niri config with `workspace \"{}\"`",
+                    presentation.workspace,
+                    presentation.workspace
+                )
+            })?;
+
+        let windows = self.niri.get_windows().await?;
+        let matcher = WindowMatcher::new(presentation.app_id.clone(), presentation.title.clone());
+        let mut presented = Vec::new();
+        for window in &windows {
+            if self
+                .matcher_cache
+                .matches(window.app_id.as_ref(), Some(&window.title), &matcher)
+                .await?
+            {
+                presented.push(window);
+            } else if window.workspace_id == Some(workspace_id) {
+                anyhow::bail!(
+                    "Presentation workspace '{}' is not empty, window {} ({:?}) is on it",
+                    presentation.workspace,
+                    window.id,
+                    window.app_id
+                );
+            }
+        }
+        // Keep the windows' left-to-right order on the presentation workspace
+        presented.sort_by_key(|w| {
+            (
+                w.workspace_id,
+                w.layout.as_ref().and_then(|l| l.pos_in_scrolling_layout),
+            )
+        });
+
+        info!(
+            "Starting presentation '{}' with {} windows on workspace '{}'",
+            name,
+            presented.len(),
+            presentation.workspace
+        );
+        let previous_focused = self.niri.get_focused_window_id().await?;
+        Self::hide_scratchpads();
+        request_suspensions("present", vec![(presentation.suspend.clone(), true)]);
+
+        // Active from here on, so that `piri present --stop` can undo a start that failed
+        // halfway
+        let active = self.active.insert(Presentation {
+            name: name.to_string(),
+            workspace_id,
+            suspended: presentation.suspend.clone(),
+            windows: Vec::new(),
+            previous_workspace_id: workspaces.iter().find(|ws| ws.is_focused).map(|ws| ws.id),
+            previous_focused,
+        });
+        for window in presented {
+            active.windows.push(MovedWindow {
+                window_id: window.id,
+                workspace_id: window.workspace_id,
+                floating: window.floating,
+                column: window
+                    .layout
+                    .as_ref()
+                    .and_then(|l| l.pos_in_scrolling_layout)
+                    .map(|(c, _)| c),
+            });
+            if window.workspace_id != Some(workspace_id) {
+                self.niri
+                    .move_window_to_workspace_id(window.id, workspace_id)
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "Failed to move window {} to the presentation, `piri present --stop` puts back the windows moved so far",
+                            window.id
+                        )
+                    })?;
+            }
+        }
+
+        match active.windows.first() {
+            Some(window) => self.niri.focus_window(window.window_id).await?,
+            None => {
+                self.niri
+                    .send_action(Action::FocusWorkspace {
+                        reference: WorkspaceReferenceArg::Id(workspace_id),
+                    })
+                    .await?
+            }
+        }
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        let active =
+            self.active.take().ok_or_else(|| anyhow::anyhow!("Not presenting anything"))?;
+        info!("Stopping presentation '{}'", active.name);
+        let result = self.restore(&active).await;
+        request_suspensions("present", vec![(active.suspended, false)]);
+        result
+    }
+
+    /// Put the windows of a presentation back, skipping the steps niri fails to do
+    async fn restore(&self, active: &Presentation) -> Result<()> {
+        let windows = self.niri.get_windows().await?;
+        let workspaces = self.niri.get_workspaces().await?;
+        let fallback = active
+            .previous_workspace_id
+            .filter(|id| workspaces.iter().any(|ws| ws.id == *id));
+
+        // Windows moved off the presentation workspace by hand stay where they are
+        let mut restored: Vec<&MovedWindow> = active
+            .windows
+            .iter()
+            .filter(|moved| {
+                windows
+                    .iter()
+                    .any(|w| w.id == moved.window_id && w.workspace_id == Some(active.workspace_id))
+            })
+            .collect();
+        for moved in &restored {
+            let target = moved
+                .workspace_id
+                .filter(|id| workspaces.iter().any(|ws| ws.id == *id))
+                .or(fallback);
+            match target {
+                Some(target) if target != active.workspace_id => {
+                    debug!(
+                        "Moving window {} back to workspace {}",
+                        moved.window_id, target
+                    );
+                    if let Err(e) =
+                        self.niri.move_window_to_workspace_id(moved.window_id, target).await
+                    {
+                        warn!(
+                            "Failed to move window {} back to workspace {}: {}",
+                            moved.window_id, target, e
+                        );
+                    }
+                }
+                _ => {}
+            }
+            if let Err(e) = self.niri.set_window_floating(moved.window_id, moved.floating).await {
+                warn!(
+                    "Failed to restore the floating state of window {}: {}",
+                    moved.window_id, e
+                );
+            }
+        }
+
+        // Put tiled windows back into their columns, leftmost first so that the indices of
+        // columns already restored stay valid
+        restored.retain(|moved| !moved.floating && moved.column.is_some());
+        restored.sort_by_key(|moved| moved.column);
+        for moved in restored {
+            let Some(index) = moved.column else {
+                continue;
+            };
+            let result = match self.niri.focus_window(moved.window_id).await {
+                Ok(()) => self.niri.send_action(Action::MoveColumnToIndex { index }).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                warn!(
+                    "Failed to move window {} back to column {}: {}",
+                    moved.window_id, index, e
+                );
+            }
+        }
+
+        if let Some(workspace_id) = fallback {
+            self.niri
+                .send_action(Action::FocusWorkspace {
+                    reference: WorkspaceReferenceArg::Id(workspace_id),
+                })
+                .await?;
+        }
+        if let Some(window_id) = active.previous_focused {
+            if windows.iter().any(|w| w.id == window_id) {
+                self.niri.focus_window(window_id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Goes through the daemon's IPC, since the scratchpads are another plugin's
+    fn hide_scratchpads() {
+        tokio::spawn(async move {
+            let client = IpcClient::new(None);
+            if let Err(e) = client.send_request(IpcRequest::ScratchpadHideAll).await {
+                debug!("Failed to hide scratchpads: {}", e);
+            }
+        });
+    }
+}
+
+impl Drop for PresentPlugin {
+    fn drop(&mut self) {
+        if let Some(ref active) = self.active {
+            warn!(
+                "Present plugin stopped while presenting '{}', windows stay on the presentation workspace",
+                active.name
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for PresentPlugin {
+    type Config = PresentPluginConfig;
+
+    fn new(niri: NiriIpc, config: PresentPluginConfig) -> Self {
+        info!(
+            "Present plugin initialized with {} presentations",
+            config.presentations.len()
+        );
+        Self {
+            niri,
+            config,
+            matcher_cache: WindowMatcherCache::new(),
+            active: None,
+        }
+    }
+
+    async fn handle_ipc_request(&mut self, request: &IpcRequest) -> Result<Option<Result<()>>> {
+        match request {
+            IpcRequest::PresentStart { name } => Ok(Some(self.start(name).await)),
+            IpcRequest::PresentStop => Ok(Some(self.stop().await)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn on_niri_reconnected(&mut self, _niri: &NiriIpc) -> Result<()> {
+        // The windows it moved are gone, only the suspended plugins are left to restore
+        if let Some(active) = self.active.take() {
+            info!(
+                "Presentation '{}' ended with niri, resuming its plugins",
+                active.name
+            );
+            request_suspensions("present", vec![(active.suspended, false)]);
+        }
+        Ok(())
+    }
+
+    async fn status(&mut self) -> Result<Option<serde_json::Value>> {
+        Ok(Some(serde_json::json!({
+            "active": self.active.as_ref().map(|p| &p.name),
+        })))
+    }
+
+    async fn update_config(&mut self, config: PresentPluginConfig) -> Result<()> {
+        info!("Updating present plugin configuration");
+        // A running presentation keeps the windows and suspensions it started with
+        self.config = config;
+        self.matcher_cache.clear_cache().await;
+        Ok(())
+    }
+}
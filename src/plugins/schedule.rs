@@ -0,0 +1,155 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+use crate::config::{Config, ScheduleEntry};
+use crate::ipc::{IpcClient, IpcRequest};
+use crate::niri::NiriIpc;
+use crate::plugins::{window_utils, FromConfig};
+use crate::scheduler::{self, CronExpr, LocalTime};
+
+/// Schedule plugin config (for internal use)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SchedulePluginConfig {
+    pub entries: Vec<ScheduleEntry>,
+}
+
+impl FromConfig for SchedulePluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            entries: config.schedule.clone(),
+        })
+    }
+}
+
+/// Switches config profiles and runs commands (e.g. to arrange a layout) at the times
+/// given by cron expressions.
+pub struct SchedulePlugin {
+    config: SchedulePluginConfig,
+    /// Cron expression of each entry that parsed, in config order
+    entries: Arc<Vec<(CronExpr, ScheduleEntry)>>,
+    /// Local time of the last minute an entry ran
+    last_run: Arc<Mutex<Option<LocalTime>>>,
+    scheduler: Option<JoinHandle<()>>,
+}
+
+impl SchedulePlugin {
+    fn parse_entries(config: &SchedulePluginConfig) -> Vec<(CronExpr, ScheduleEntry)> {
+        config
+            .entries
+            .iter()
+            .filter_map(|entry| match entry.cron.parse::<CronExpr>() {
+                Ok(cron) => Some((cron, entry.clone())),
+                Err(e) => {
+                    warn!("Skipping schedule entry '{}': {}", entry.cron, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    async fn run(entry: &ScheduleEntry) {
+        if let Some(ref command) = entry.exec {
+            info!("Schedule '{}': running {}", entry.cron, command);
+            if let Err(e) = window_utils::execute(command) {
+                warn!("Failed to run scheduled command: {}", e);
+            }
+        }
+        if let Some(ref profile) = entry.profile {
+            info!(
+                "Schedule '{}': switching to profile '{}'",
+                entry.cron, profile
+            );
+            // Goes through the daemon's IPC like a user command, since switching profiles
+            // reinitializes all plugins
+            let client = IpcClient::new(None);
+            let request = IpcRequest::ProfileSet {
+                name: Some(profile.clone()).filter(|p| !p.is_empty()),
+            };
+            if let Err(e) = client.send_request(request).await {
+                warn!("Failed to switch to profile '{}': {}", profile, e);
+            }
+        }
+    }
+
+    fn start_scheduler(&mut self) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let entries = self.entries.clone();
+        let last_run = self.last_run.clone();
+        self.scheduler = Some(scheduler::spawn_minutely(move |time| {
+            let due: Vec<ScheduleEntry> = entries
+                .iter()
+                .filter(|(cron, _)| cron.matches(&time))
+                .map(|(_, entry)| entry.clone())
+                .collect();
+            if due.is_empty() {
+                return;
+            }
+            *last_run.lock().unwrap() = Some(time);
+            // Run due entries in config order, each one after the previous finished
+            tokio::spawn(async move {
+                for entry in &due {
+                    Self::run(entry).await;
+                }
+            });
+        }));
+    }
+
+    fn stop_scheduler(&mut self) {
+        if let Some(handle) = self.scheduler.take() {
+            handle.abort();
+        }
+    }
+}
+
+impl Drop for SchedulePlugin {
+    fn drop(&mut self) {
+        self.stop_scheduler();
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for SchedulePlugin {
+    type Config = SchedulePluginConfig;
+
+    fn new(_niri: NiriIpc, config: SchedulePluginConfig) -> Self {
+        let entries = Self::parse_entries(&config);
+        info!("Schedule plugin initialized with {} entries", entries.len());
+        let mut plugin = Self {
+            config,
+            entries: Arc::new(entries),
+            last_run: Arc::new(Mutex::new(None)),
+            scheduler: None,
+        };
+        plugin.start_scheduler();
+        plugin
+    }
+
+    async fn status(&mut self) -> Result<Option<serde_json::Value>> {
+        let last_run =
+            self.last_run.lock().unwrap().map(|t| format!("{:02}:{:02}", t.hour, t.minute));
+        Ok(Some(serde_json::json!({
+            "entries": self.entries.len(),
+            "last_run": last_run,
+        })))
+    }
+
+    async fn update_config(&mut self, config: SchedulePluginConfig) -> Result<()> {
+        // Profile switches, including the ones made by this plugin, reinitialize plugins
+        // with an unchanged schedule; keep the scheduler running then
+        if config == self.config {
+            return Ok(());
+        }
+        info!("Updating schedule plugin configuration");
+        self.stop_scheduler();
+        self.entries = Arc::new(Self::parse_entries(&config));
+        self.config = config;
+        self.start_scheduler();
+        Ok(())
+    }
+}
@@ -1,14 +1,16 @@
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use log::{debug, info, warn};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 use tokio::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
-use crate::config::{Config, Direction, ScratchpadConfig};
-use crate::ipc::IpcRequest;
+use crate::config::{
+    CommandLine, Config, Direction, ScratchpadConfig, ScratchpadDefaults, ScratchpadOverrides, Size,
+};
+use crate::ipc::{IpcError, IpcRequest};
 use crate::niri::NiriIpc;
 use crate::plugins::window_utils::{
     self, get_focused_window, perform_swallow, WindowMatcher, WindowMatcherCache,
@@ -19,18 +21,36 @@ use crate::utils::send_notification;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScratchpadsPluginConfig {
     pub scratchpads: HashMap<String, ScratchpadConfig>,
-    pub default_size: String,
+    pub default_size: Size,
     pub default_margin: u32,
     pub move_to_workspace: Option<String>,
+    /// Output -> the defaults above that differ while it is focused
+    pub outputs: HashMap<String, ScratchpadOverrides>,
 }
 
 impl Default for ScratchpadsPluginConfig {
     fn default() -> Self {
         Self {
             scratchpads: HashMap::new(),
-            default_size: "75% 60%".to_string(),
+            default_size: ScratchpadDefaults::default().default_size,
             default_margin: 50,
             move_to_workspace: None,
+            outputs: HashMap::new(),
+        }
+    }
+}
+
+impl ScratchpadsPluginConfig {
+    /// The defaults on an output, with its overrides applied
+    fn defaults_on(&self, output: Option<&str>) -> ScratchpadDefaults {
+        let overrides = output.and_then(|output| self.outputs.get(output));
+        let overrides = overrides.cloned().unwrap_or_default();
+        ScratchpadDefaults {
+            default_size: overrides.default_size.unwrap_or(self.default_size),
+            default_margin: overrides.default_margin.unwrap_or(self.default_margin),
+            move_to_workspace: overrides
+                .move_to_workspace
+                .or_else(|| self.move_to_workspace.clone()),
         }
     }
 }
@@ -41,14 +61,19 @@ impl FromConfig for ScratchpadsPluginConfig {
         // because it can be used dynamically via IPC even without initial config.
         Some(Self {
             scratchpads: config.scratchpads.clone(),
-            default_size: config.piri.scratchpad.default_size.clone(),
+            default_size: config.piri.scratchpad.default_size,
             default_margin: config.piri.scratchpad.default_margin,
             move_to_workspace: config.piri.scratchpad.move_to_workspace.clone(),
+            outputs: config
+                .outputs
+                .iter()
+                .map(|(name, output)| (name.clone(), output.scratchpad.clone()))
+                .collect(),
         })
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ScratchpadState {
     window_id: Option<u64>,
     is_visible: bool,
@@ -109,7 +134,7 @@ impl ScratchpadManager {
         is_visible: bool,
     ) -> Result<(i32, i32, u32, u32)> {
         let (output_width, output_height) = self.niri.get_output_size().await?;
-        let (width_ratio, height_ratio) = config.parse_size()?;
+        let (width_ratio, height_ratio) = config.size.ratios();
         let window_width = (output_width as f64 * width_ratio) as u32;
         let window_height = (output_height as f64 * height_ratio) as u32;
 
@@ -262,6 +287,20 @@ impl ScratchpadManager {
             &self.niri, window_id, current_x, current_y, target_x, target_y,
         )
         .await?;
+        // Configured scratchpads are found again by their app_id, the others only by the
+        // daemon that added them
+        if is_visible {
+            crate::journal::forget(window_id);
+        } else if is_dynamic {
+            let app_id = self
+                .niri
+                .get_windows()
+                .await?
+                .into_iter()
+                .find(|w| w.id == window_id)
+                .and_then(|w| w.app_id);
+            crate::journal::record("scratchpads", window_id, app_id.as_deref(), None, true);
+        }
 
         if is_visible {
             window_utils::focus_window(self.niri.clone(), window_id).await?;
@@ -295,6 +334,14 @@ impl ScratchpadManager {
             }
         }
 
+        crate::events::publish(
+            if is_visible {
+                "ScratchpadShown"
+            } else {
+                "ScratchpadHidden"
+            },
+            serde_json::json!({ "name": name, "window_id": window_id }),
+        );
         Ok(())
     }
 
@@ -322,7 +369,7 @@ impl ScratchpadManager {
 
         info!("Finding or launching window for scratchpad {}", name);
         let config = state.config.clone();
-        let matcher = WindowMatcher::new(Some(vec![config.app_id.clone()]), None);
+        let matcher = WindowMatcher::new(Some(config.app_id.clone()), None);
 
         let window_id = if let Some(window) =
             window_utils::find_window_by_matcher(self.niri.clone(), &matcher, &self.matcher_cache)
@@ -331,9 +378,9 @@ impl ScratchpadManager {
             window.id
         } else {
             window_utils::launch_application(&config.command).await?;
-            let window = window_utils::wait_for_window(
+            let window = window_utils::wait_for_window_matcher(
                 self.niri.clone(),
-                &config.app_id,
+                &matcher,
                 name,
                 50,
                 &self.matcher_cache,
@@ -350,6 +397,37 @@ impl ScratchpadManager {
         Ok(window_id)
     }
 
+    /// After niri restarted: drop the window IDs from before, and with them the scratchpads
+    /// added at runtime, then take the windows that match the configured patterns again.
+    /// They are left where they are until toggled
+    async fn reregister(&mut self) -> Result<()> {
+        self.states.retain(|name, state| {
+            if state.is_dynamic {
+                info!("Dropping scratchpad '{}', its window is gone", name);
+            }
+            !state.is_dynamic
+        });
+        let windows = self.niri.get_windows().await?;
+        let mut claimed = HashSet::new();
+        for (name, state) in &mut self.states {
+            state.window_id = None;
+            state.is_visible = false;
+            state.previous_focused_window = None;
+            let matcher = WindowMatcher::new(Some(state.config.app_id.clone()), None);
+            for window in &windows {
+                if !claimed.contains(&window.id)
+                    && self.matcher_cache.matches(window.app_id.as_ref(), None, &matcher).await?
+                {
+                    claimed.insert(window.id);
+                    debug!("Scratchpad '{}' is window {} again", name, window.id);
+                    state.window_id = Some(window.id);
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
     async fn toggle(
         &mut self,
         name: &str,
@@ -358,7 +436,9 @@ impl ScratchpadManager {
     ) -> Result<()> {
         // 1. Ensure state exists
         if !self.states.contains_key(name) {
-            let config = config.context("No config provided for new scratchpad")?;
+            let config = config.with_context(|| {
+                IpcError::not_found(format!("Scratchpad '{}' is not configured", name))
+            })?;
             self.states.insert(
                 name.to_string(),
                 ScratchpadState {
@@ -421,11 +501,70 @@ impl ScratchpadManager {
         self.sync_state(name, move_to_workspace).await
     }
 
+    /// Move hidden scratchpads back off-screen after the output layout changed
+    async fn rehome_hidden(&mut self) -> Result<()> {
+        let hidden: Vec<(String, u64, ScratchpadConfig, bool)> = self
+            .states
+            .iter()
+            .filter(|(_, state)| !state.is_visible)
+            .filter_map(|(name, state)| {
+                state
+                    .window_id
+                    .map(|id| (name.clone(), id, state.config.clone(), state.is_dynamic))
+            })
+            .collect();
+
+        for (name, window_id, config, is_dynamic) in hidden {
+            let Some((current_x, current_y, current_width, current_height)) =
+                self.niri.get_window_position_async(window_id).await?
+            else {
+                continue;
+            };
+            let (target_x, target_y) = if is_dynamic {
+                self.get_target_position(&config, current_width, current_height, false).await?
+            } else {
+                let (x, y, _, _) = self.get_target_geometry(&config, false).await?;
+                (x, y)
+            };
+            debug!("Rehoming hidden scratchpad '{}'", name);
+            window_utils::move_window_to_position(
+                &self.niri, window_id, current_x, current_y, target_x, target_y,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Hide every visible scratchpad without giving focus back to the windows focused
+    /// before they were shown
+    async fn hide_all(&mut self, move_to_workspace: Option<String>) -> Result<()> {
+        let visible: Vec<(String, u64)> = self
+            .states
+            .iter()
+            .filter(|(_, state)| state.is_visible)
+            .filter_map(|(name, state)| state.window_id.map(|id| (name.clone(), id)))
+            .collect();
+
+        for (name, window_id) in visible {
+            // Closed windows are cleaned up on the next toggle
+            if !window_utils::window_exists(&self.niri, window_id).await? {
+                continue;
+            }
+            debug!("Hiding scratchpad '{}'", name);
+            if let Some(state) = self.states.get_mut(&name) {
+                state.is_visible = false;
+                state.previous_focused_window = None;
+            }
+            self.sync_state(&name, move_to_workspace.clone()).await?;
+        }
+        Ok(())
+    }
+
     async fn add_current_window(
         &mut self,
         name: &str,
         direction: Direction,
-        default_size: &str,
+        default_size: Size,
         default_margin: u32,
         swallow_to_focus: bool,
     ) -> Result<()> {
@@ -451,9 +590,9 @@ impl ScratchpadManager {
 
         let config = ScratchpadConfig {
             direction,
-            command: format!("# Window {} added dynamically", window.id),
-            app_id,
-            size: default_size.to_string(),
+            command: CommandLine::Shell(format!("# Window {} added dynamically", window.id)),
+            app_id: vec![app_id],
+            size: default_size,
             margin: default_margin,
             swallow_to_focus,
         };
@@ -481,6 +620,24 @@ pub struct ScratchpadsPlugin {
     config: ScratchpadsPluginConfig,
 }
 
+impl ScratchpadsPlugin {
+    /// The defaults on the focused output, where scratchpads are shown and added
+    async fn focused_defaults(&self) -> ScratchpadDefaults {
+        let output = if self.config.outputs.is_empty() {
+            None
+        } else {
+            match self.manager.niri.get_focused_output().await {
+                Ok(output) => Some(output.name),
+                Err(e) => {
+                    debug!("Failed to get focused output: {}", e);
+                    None
+                }
+            }
+        };
+        self.config.defaults_on(output.as_deref())
+    }
+}
+
 #[async_trait]
 impl crate::plugins::Plugin for ScratchpadsPlugin {
     type Config = ScratchpadsPluginConfig;
@@ -506,6 +663,66 @@ impl crate::plugins::Plugin for ScratchpadsPlugin {
         Self { manager, config }
     }
 
+    async fn status(&mut self) -> Result<Option<serde_json::Value>> {
+        let status: BTreeMap<&String, serde_json::Value> = self
+            .manager
+            .states
+            .iter()
+            .map(|(name, state)| {
+                (
+                    name,
+                    serde_json::json!({
+                        "visible": state.is_visible,
+                        "window_id": state.window_id,
+                    }),
+                )
+            })
+            .collect();
+        Ok(Some(serde_json::to_value(status)?))
+    }
+
+    async fn runtime_config(&mut self) -> Result<Option<serde_json::Value>> {
+        let mut dynamic = BTreeMap::new();
+        for (name, state) in &self.manager.states {
+            if state.is_dynamic {
+                // Through TOML, which leaves out unset fields instead of making them null
+                let config = toml::Value::try_from(&state.config)?;
+                dynamic.insert(name, serde_json::to_value(config)?);
+            }
+        }
+        if dynamic.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::json!({ "scratchpads": dynamic })))
+    }
+
+    async fn export_state(&mut self) -> Result<Option<serde_json::Value>> {
+        Ok(Some(serde_json::to_value(&self.manager.states)?))
+    }
+
+    async fn import_state(&mut self, state: serde_json::Value) -> Result<()> {
+        let states: HashMap<String, ScratchpadState> = serde_json::from_value(state)?;
+        for (name, previous) in states {
+            match self.manager.states.get_mut(&name) {
+                // The config may have changed in between, the one just loaded wins
+                Some(state) => {
+                    state.window_id = previous.window_id;
+                    state.is_visible = previous.is_visible;
+                    state.previous_focused_window = previous.previous_focused_window;
+                }
+                None if previous.is_dynamic => {
+                    self.manager.states.insert(name, previous);
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn on_niri_reconnected(&mut self, _niri: &NiriIpc) -> Result<()> {
+        self.manager.reregister().await
+    }
+
     async fn update_config(&mut self, config: ScratchpadsPluginConfig) -> Result<()> {
         info!("Updating scratchpads plugin configuration");
 
@@ -545,10 +762,11 @@ impl crate::plugins::Plugin for ScratchpadsPlugin {
         match request {
             IpcRequest::ScratchpadToggle { name } => {
                 info!("Handling scratchpad toggle for: {}", name);
+                crate::metrics::count("piri_scratchpad_toggles_total", &[("scratchpad", name)]);
 
                 let config = self.config.scratchpads.get(name).cloned();
-                match self.manager.toggle(name, config, self.config.move_to_workspace.clone()).await
-                {
+                let defaults = self.focused_defaults().await;
+                match self.manager.toggle(name, config, defaults.move_to_workspace).await {
                     Ok(_) => Ok(Some(Ok(()))),
                     Err(e) => {
                         let error_msg = format!("Scratchpad '{}' error: {}", name, e);
@@ -567,21 +785,30 @@ impl crate::plugins::Plugin for ScratchpadsPlugin {
                     name, direction, swallow_to_focus
                 );
 
-                let direction = Direction::from_str(direction)
-                    .map_err(|e| anyhow::anyhow!("Invalid direction: {}", e))?;
-
+                let defaults = self.focused_defaults().await;
                 self.manager
                     .add_current_window(
                         name,
-                        direction,
-                        &self.config.default_size,
-                        self.config.default_margin,
+                        *direction,
+                        defaults.default_size,
+                        defaults.default_margin,
                         *swallow_to_focus,
                     )
                     .await?;
 
                 Ok(Some(Ok(())))
             }
+            IpcRequest::ScratchpadRehome => {
+                info!("Repositioning hidden scratchpads");
+                self.manager.rehome_hidden().await?;
+                Ok(Some(Ok(())))
+            }
+            IpcRequest::ScratchpadHideAll => {
+                info!("Hiding all scratchpads");
+                let defaults = self.focused_defaults().await;
+                self.manager.hide_all(defaults.move_to_workspace).await?;
+                Ok(Some(Ok(())))
+            }
             _ => Ok(None), // Not handled by this plugin
         }
     }
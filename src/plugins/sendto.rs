@@ -0,0 +1,124 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::info;
+use niri_ipc::{Action, WorkspaceReferenceArg};
+
+use crate::ipc::{IpcError, IpcRequest};
+use crate::niri::NiriIpc;
+use crate::plugins::window_utils;
+
+/// Sends the focused window to a workspace (resolved by name, index or a fuzzy name
+/// match) or to an output, optionally following it there.
+pub struct SendToPlugin {
+    niri: NiriIpc,
+}
+
+impl SendToPlugin {
+    /// Resolve an output by exact name, then case-insensitive prefix
+    async fn resolve_output(&self, target: &str) -> Result<Option<String>> {
+        let names: Vec<String> = self
+            .niri
+            .get_outputs()
+            .await?
+            .into_iter()
+            .filter(|output| output.logical.is_some())
+            .map(|output| output.name)
+            .collect();
+        if names.iter().any(|name| name == target) {
+            return Ok(Some(target.to_string()));
+        }
+        let target = target.to_lowercase();
+        let found: Vec<&String> =
+            names.iter().filter(|name| name.to_lowercase().starts_with(&target)).collect();
+        match found.as_slice() {
+            [] => Ok(None),
+            [name] => Ok(Some(name.to_string())),
+            _ => anyhow::bail!(
+                "Output '{}' is ambiguous: {}",
+                target,
+                found.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+
+    async fn send_to(&self, target: &str, output: bool, follow: bool) -> Result<()> {
+        let window_id = self
+            .niri
+            .get_focused_window_id()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("No focused window"))?;
+
+        let workspaces = self.niri.get_workspaces().await?;
+        // Workspaces take precedence, outputs are only tried if no workspace matches
+        if !output {
+            if let Some(workspace) = window_utils::resolve_workspace(target, &workspaces)? {
+                info!(
+                    "Sending window {} to workspace {}",
+                    window_id,
+                    workspace.name.clone().unwrap_or_else(|| workspace.idx.to_string())
+                );
+                return self
+                    .niri
+                    .send_action(Action::MoveWindowToWorkspace {
+                        window_id: Some(window_id),
+                        reference: WorkspaceReferenceArg::Id(workspace.id),
+                        focus: follow,
+                    })
+                    .await;
+            }
+        }
+
+        let Some(name) = self.resolve_output(target).await? else {
+            if output {
+                anyhow::bail!(IpcError::not_found(format!(
+                    "No output matches '{}'",
+                    target
+                )));
+            }
+            anyhow::bail!(IpcError::not_found(format!(
+                "No workspace or output matches '{}'",
+                target
+            )));
+        };
+        info!("Sending window {} to output {}", window_id, name);
+        self.niri
+            .send_action(Action::MoveWindowToMonitor {
+                id: Some(window_id),
+                output: name,
+            })
+            .await?;
+        if follow {
+            self.niri.focus_window(window_id).await
+        } else if let Some(workspace) = workspaces.iter().find(|ws| ws.is_focused) {
+            // niri may move focus along with the window, go back to where we were
+            self.niri
+                .send_action(Action::FocusWorkspace {
+                    reference: WorkspaceReferenceArg::Id(workspace.id),
+                })
+                .await
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for SendToPlugin {
+    type Config = ();
+
+    fn new(niri: NiriIpc, _config: ()) -> Self {
+        info!("Send to plugin initialized");
+        Self { niri }
+    }
+
+    async fn handle_ipc_request(&mut self, request: &IpcRequest) -> Result<Option<Result<()>>> {
+        match request {
+            IpcRequest::SendTo {
+                target,
+                output,
+                follow,
+            } => Ok(Some(self.send_to(target, *output, *follow).await)),
+            _ => Ok(None),
+        }
+    }
+}
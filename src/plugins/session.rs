@@ -0,0 +1,364 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use niri_ipc::Action;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::{CommandLine, Config, Timespan};
+use crate::ipc::{IpcError, IpcRequest};
+use crate::niri::NiriIpc;
+use crate::plugins::{window_utils, FromConfig};
+use crate::utils::{load_state, save_state, send_notification};
+
+/// Session plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionPluginConfig {
+    /// app_id -> command used to relaunch windows of that app
+    pub commands: HashMap<String, CommandLine>,
+    /// How long to wait for each relaunched window
+    pub launch_timeout: Timespan,
+}
+
+impl FromConfig for SessionPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        let section = &config.piri.session;
+        Some(Self {
+            commands: section.commands.clone(),
+            launch_timeout: section.launch_timeout,
+        })
+    }
+}
+
+/// A window as recorded in a session snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionWindow {
+    pub app_id: Option<String>,
+    pub title: String,
+    pub workspace_name: Option<String>,
+    pub workspace_idx: Option<u8>,
+    pub output: Option<String>,
+    /// (column, tile) in the scrolling layout, 1-based
+    pub position: Option<(usize, usize)>,
+    pub floating: bool,
+    /// Position of a floating window in the workspace view
+    pub floating_pos: Option<(f64, f64)>,
+    pub size: Option<(u32, u32)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    /// Unix timestamp of when the session was saved
+    pub saved_at: u64,
+    pub windows: Vec<SessionWindow>,
+}
+
+/// File of a session in the state directory. Names can't be empty or contain `/` or `..`,
+/// so that they stay inside it
+fn session_file(name: Option<&str>) -> Result<String> {
+    match name {
+        Some(name) if name.is_empty() || name.contains('/') || name.contains("..") => {
+            anyhow::bail!(
+                "Invalid session name '{}': it can't be empty or contain '/' or '..'",
+                name
+            )
+        }
+        Some(name) => Ok(format!("session-{}.json", name)),
+        None => Ok("session.json".to_string()),
+    }
+}
+
+pub struct SessionPlugin {
+    niri: NiriIpc,
+    config: SessionPluginConfig,
+}
+
+impl SessionPlugin {
+    async fn save(&self, name: Option<&str>) -> Result<()> {
+        let file = session_file(name)?;
+        let workspaces = self.niri.get_workspaces().await?;
+        let mut windows: Vec<SessionWindow> = self
+            .niri
+            .get_windows()
+            .await?
+            .into_iter()
+            .map(|w| {
+                let workspace =
+                    w.workspace_id.and_then(|id| workspaces.iter().find(|ws| ws.id == id));
+                let layout = w.layout.as_ref();
+                SessionWindow {
+                    app_id: w.app_id.clone(),
+                    title: w.title.clone(),
+                    workspace_name: workspace.and_then(|ws| ws.name.clone()),
+                    workspace_idx: workspace.map(|ws| ws.idx),
+                    output: workspace.and_then(|ws| ws.output.clone()),
+                    position: layout.and_then(|l| l.pos_in_scrolling_layout),
+                    floating: w.floating,
+                    floating_pos: layout.and_then(|l| l.tile_pos).map(|[x, y]| (x, y)),
+                    size: layout.and_then(|l| l.window_size).map(|[w, h]| (w, h)),
+                }
+            })
+            .collect();
+
+        // Stable order: by output, workspace, then layout position
+        windows.sort_by(|a, b| {
+            (&a.output, a.workspace_idx, a.position).cmp(&(&b.output, b.workspace_idx, b.position))
+        });
+
+        let session = Session {
+            saved_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            windows,
+        };
+        save_state(&file, &session)?;
+        info!(
+            "Saved session with {} windows to {}",
+            session.windows.len(),
+            file
+        );
+        Ok(())
+    }
+
+    fn restore(&self, name: Option<&str>) -> Result<()> {
+        let file = session_file(name)?;
+        let session: Session = load_state(&file)?
+            .with_context(|| IpcError::not_found(format!("No saved session found ({})", file)))?;
+        info!(
+            "Restoring session from {} ({} windows)",
+            file,
+            session.windows.len()
+        );
+
+        // Launching apps can take a while; run in the background so the IPC call returns
+        let niri = self.niri.clone();
+        let config = self.config.clone();
+        tokio::spawn(async move {
+            match restore_session(&niri, &config, &session).await {
+                Ok((restored, skipped)) => {
+                    info!(
+                        "Session restored: {} windows, {} skipped",
+                        restored, skipped
+                    );
+                    send_notification(
+                        "piri",
+                        &format!(
+                            "Session restored: {} windows, {} skipped",
+                            restored, skipped
+                        ),
+                    );
+                }
+                Err(e) => {
+                    warn!("Session restore failed: {}", e);
+                    send_notification("piri", &format!("Session restore failed: {}", e));
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Relaunch (or reuse) the windows of a session and put them back in place.
+/// Returns the number of restored and skipped windows.
+async fn restore_session(
+    niri: &NiriIpc,
+    config: &SessionPluginConfig,
+    session: &Session,
+) -> Result<(usize, usize)> {
+    let focused_id = niri.get_focused_window_id().await?;
+    let mut claimed: HashSet<u64> = HashSet::new();
+    let mut placed: Vec<(u64, &SessionWindow)> = Vec::new();
+    let mut skipped = 0;
+
+    for saved in &session.windows {
+        let Some(ref app_id) = saved.app_id else {
+            skipped += 1;
+            continue;
+        };
+
+        // Reuse a running window of the same app before launching a new one
+        let existing = niri
+            .get_windows()
+            .await?
+            .into_iter()
+            .find(|w| w.app_id.as_ref() == Some(app_id) && !claimed.contains(&w.id));
+        let window_id = match existing {
+            Some(window) => window.id,
+            None => {
+                let Some(command) = config.commands.get(app_id) else {
+                    debug!("No command configured for app_id {}, skipping", app_id);
+                    skipped += 1;
+                    continue;
+                };
+                if let Err(e) = window_utils::execute(command) {
+                    warn!("Failed to launch {} for app_id {}: {}", command, app_id, e);
+                    skipped += 1;
+                    continue;
+                }
+                match wait_for_new_window(niri, app_id, &claimed, config.launch_timeout.0).await? {
+                    Some(window_id) => window_id,
+                    None => {
+                        warn!("Timeout waiting for {} to open a window", app_id);
+                        skipped += 1;
+                        continue;
+                    }
+                }
+            }
+        };
+
+        claimed.insert(window_id);
+        if let Err(e) = place_window(niri, window_id, saved).await {
+            warn!("Failed to place window {} ({}): {}", window_id, app_id, e);
+        }
+        placed.push((window_id, saved));
+    }
+
+    arrange_columns(niri, &placed).await?;
+
+    if let Some(window_id) = focused_id {
+        let _ = niri.focus_window(window_id).await;
+    }
+    Ok((placed.len(), skipped))
+}
+
+/// Wait for a window of the app that isn't already part of the restored session
+async fn wait_for_new_window(
+    niri: &NiriIpc,
+    app_id: &str,
+    claimed: &HashSet<u64>,
+    timeout: Duration,
+) -> Result<Option<u64>> {
+    for _ in 0..(timeout.as_millis() / 100).max(1) {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let window = niri
+            .get_windows()
+            .await?
+            .into_iter()
+            .find(|w| w.app_id.as_deref() == Some(app_id) && !claimed.contains(&w.id));
+        if let Some(window) = window {
+            return Ok(Some(window.id));
+        }
+    }
+    Ok(None)
+}
+
+/// Move a window to its saved workspace and restore floating state and geometry
+async fn place_window(niri: &NiriIpc, window_id: u64, saved: &SessionWindow) -> Result<()> {
+    let workspaces = niri.get_workspaces().await?;
+    let target = match saved.workspace_name {
+        Some(ref name) => workspaces.iter().find(|ws| ws.name.as_ref() == Some(name)),
+        None => workspaces
+            .iter()
+            .find(|ws| Some(ws.idx) == saved.workspace_idx && ws.output == saved.output)
+            .or_else(|| workspaces.iter().find(|ws| Some(ws.idx) == saved.workspace_idx)),
+    };
+    match target {
+        Some(workspace) => niri.move_window_to_workspace_id(window_id, workspace.id).await?,
+        None => warn!(
+            "Workspace {:?}/{:?} of {:?} no longer exists, leaving window in place",
+            saved.workspace_name, saved.workspace_idx, saved.app_id
+        ),
+    }
+
+    niri.set_window_floating(window_id, saved.floating).await?;
+    if saved.floating {
+        if let Some((width, height)) = saved.size {
+            niri.resize_floating_window(window_id, width, height).await?;
+        }
+        if let Some((x, y)) = saved.floating_pos {
+            niri.move_floating_window_to(window_id, x, y).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Recreate the saved column order of tiled windows on each workspace
+async fn arrange_columns(niri: &NiriIpc, placed: &[(u64, &SessionWindow)]) -> Result<()> {
+    // (output, workspace) -> column -> windows ordered by tile
+    let mut workspaces: BTreeMap<_, BTreeMap<usize, Vec<(usize, u64)>>> = BTreeMap::new();
+    for &(window_id, saved) in placed {
+        let (Some((column, tile)), false) = (saved.position, saved.floating) else {
+            continue;
+        };
+        let key = (
+            saved.output.clone(),
+            saved.workspace_name.clone(),
+            saved.workspace_idx,
+        );
+        workspaces
+            .entry(key)
+            .or_default()
+            .entry(column)
+            .or_default()
+            .push((tile, window_id));
+    }
+
+    for columns in workspaces.into_values() {
+        // Gaps left by windows that weren't restored collapse, so number columns densely
+        for (index, mut tiles) in columns.into_values().enumerate() {
+            tiles.sort();
+            let index = index + 1;
+            for (i, &(_, window_id)) in tiles.iter().enumerate() {
+                niri.focus_window(window_id).await?;
+                if i == 0 {
+                    niri.send_action(Action::MoveColumnToIndex { index }).await?;
+                } else {
+                    // Put the window right after the column, then consume it into the column
+                    niri.send_action(Action::MoveColumnToIndex { index: index + 1 }).await?;
+                    niri.send_action(Action::ConsumeOrExpelWindowLeft { id: None }).await?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl crate::plugins::Plugin for SessionPlugin {
+    type Config = SessionPluginConfig;
+
+    fn new(niri: NiriIpc, config: SessionPluginConfig) -> Self {
+        info!(
+            "Session plugin initialized with {} launch commands",
+            config.commands.len()
+        );
+        Self { niri, config }
+    }
+
+    async fn update_config(&mut self, config: SessionPluginConfig) -> Result<()> {
+        info!("Updating session plugin configuration");
+        self.config = config;
+        Ok(())
+    }
+
+    async fn handle_ipc_request(&mut self, request: &IpcRequest) -> Result<Option<Result<()>>> {
+        match request {
+            IpcRequest::SessionSave { name } => Ok(Some(self.save(name.as_deref()).await)),
+            IpcRequest::SessionRestore { name } => Ok(Some(self.restore(name.as_deref()))),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_file_names() {
+        assert_eq!(session_file(None).unwrap(), "session.json");
+        assert_eq!(session_file(Some("work")).unwrap(), "session-work.json");
+        assert_eq!(
+            session_file(Some("2024.01")).unwrap(),
+            "session-2024.01.json"
+        );
+        for name in ["", "a/b", "/etc", "..", "../x", "x/../../y"] {
+            assert!(
+                session_file(Some(name)).is_err(),
+                "{:?} should be rejected",
+                name
+            );
+        }
+    }
+}
@@ -1,16 +1,19 @@
 use anyhow::{Context, Result};
 use async_trait::async_trait;
-use log::{debug, info};
+use log::{debug, info, warn};
+use niri_ipc::Event;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
-use crate::config::{Config, SingletonConfig};
-use crate::ipc::IpcRequest;
+use crate::config::{CommandLine, Config, SingletonConfig};
+use crate::ipc::{IpcError, IpcRequest};
 use crate::niri::NiriIpc;
 use crate::plugins::window_utils::{self, WindowMatcher, WindowMatcherCache};
 use crate::plugins::FromConfig;
+use crate::utils::send_notification;
 
 /// Singleton plugin config (for internal use)
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +46,74 @@ impl FromConfig for SingletonPluginConfig {
 struct SingletonState {
     window_id: Option<u64>,
     config: SingletonConfig,
+    /// Matching windows in the order repeated toggles cycle through them
+    cycle: Vec<u64>,
+    /// Window that was focused before the singleton was shown
+    previous_focus: Option<u64>,
+    /// (window, workspace id) of the last summoned window, to send it back when hidden
+    summoned_from: Option<(u64, u64)>,
+}
+
+impl SingletonState {
+    fn new(config: SingletonConfig) -> Self {
+        Self {
+            window_id: None,
+            config,
+            cycle: Vec::new(),
+            previous_focus: None,
+            summoned_from: None,
+        }
+    }
+}
+
+/// What `piri restart` hands over of a singleton, its config being loaded again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SavedSingleton {
+    window_id: Option<u64>,
+    cycle: Vec<u64>,
+    previous_focus: Option<u64>,
+    summoned_from: Option<(u64, u64)>,
+}
+
+/// Status of a configured singleton, as returned by `SingletonList`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SingletonStatus {
+    pub name: String,
+    pub command: String,
+    pub running: bool,
+    pub window_id: Option<u64>,
+    /// Workspace name, or idx if unnamed
+    pub workspace: Option<String>,
+}
+
+/// Outcome of picking the next matching window on toggle
+enum CycleStep {
+    /// No matching window was focused: start a new cycle at this window
+    Enter(u64),
+    /// A matching window was focused: move on to this one
+    Next(u64),
+    /// The focused window was the last in the cycle
+    Wrapped { focused: u64, next: u64 },
+}
+
+/// Window properties the singleton plugin matches on, kept up to date from niri events
+#[derive(Debug, Clone)]
+struct TrackedWindow {
+    app_id: Option<String>,
+    title: String,
+    workspace_id: Option<u64>,
+    focus_timestamp: Option<Duration>,
+}
+
+impl From<&niri_ipc::Window> for TrackedWindow {
+    fn from(window: &niri_ipc::Window) -> Self {
+        Self {
+            app_id: window.app_id.clone(),
+            title: window.title.clone().unwrap_or_default(),
+            workspace_id: window.workspace_id,
+            focus_timestamp: window.focus_timestamp.map(|ts| Duration::new(ts.secs, ts.nanos)),
+        }
+    }
 }
 
 /// Manages singleton windows (windows that should only have one instance)
@@ -50,6 +121,8 @@ struct SingletonManager {
     niri: NiriIpc,
     states: HashMap<String, SingletonState>,
     matcher_cache: Arc<WindowMatcherCache>,
+    /// All windows by ID; None until the event stream delivered the initial window list
+    windows: Option<HashMap<u64, TrackedWindow>>,
 }
 
 impl SingletonManager {
@@ -58,56 +131,199 @@ impl SingletonManager {
             niri,
             states: HashMap::new(),
             matcher_cache: Arc::new(WindowMatcherCache::new()),
+            windows: None,
+        }
+    }
+
+    /// Drop every window ID; they are found again by their patterns when needed
+    fn forget_windows(&mut self) {
+        for state in self.states.values_mut() {
+            state.window_id = None;
+            state.cycle.clear();
+            state.previous_focus = None;
+            state.summoned_from = None;
+        }
+        self.windows = None;
+    }
+
+    /// Keep the window map and the tracked singleton windows in sync with niri
+    fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::WindowsChanged { windows } => {
+                let windows: HashMap<u64, TrackedWindow> =
+                    windows.iter().map(|w| (w.id, TrackedWindow::from(w))).collect();
+                for state in self.states.values_mut() {
+                    if state.window_id.is_some_and(|id| !windows.contains_key(&id)) {
+                        state.window_id = None;
+                    }
+                    state.cycle.retain(|id| windows.contains_key(id));
+                }
+                debug!(
+                    "Singleton window map initialized with {} windows",
+                    windows.len()
+                );
+                self.windows = Some(windows);
+            }
+            Event::WindowOpenedOrChanged { window } => {
+                if let Some(ref mut windows) = self.windows {
+                    windows.insert(window.id, TrackedWindow::from(window));
+                }
+            }
+            Event::WindowClosed { id } => {
+                if let Some(ref mut windows) = self.windows {
+                    windows.remove(id);
+                }
+                for (name, state) in self.states.iter_mut() {
+                    if state.window_id == Some(*id) {
+                        debug!("Singleton window {} (name: {}) closed", id, name);
+                        state.window_id = None;
+                    }
+                    state.cycle.retain(|window_id| window_id != id);
+                }
+            }
+            Event::WindowFocusTimestampChanged {
+                id,
+                focus_timestamp,
+            } => {
+                if let Some(window) = self.windows.as_mut().and_then(|w| w.get_mut(id)) {
+                    window.focus_timestamp =
+                        focus_timestamp.map(|ts| Duration::new(ts.secs, ts.nanos));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// IDs of all windows matching the singleton, most recently focused first.
+    /// Uses the event-tracked window map, falling back to querying niri before it is ready.
+    async fn matching_windows(&self, matcher: &WindowMatcher) -> Result<Vec<u64>> {
+        let Some(ref windows) = self.windows else {
+            return Ok(window_utils::find_windows_by_matcher(
+                self.niri.clone(),
+                matcher,
+                &self.matcher_cache,
+            )
+            .await?
+            .into_iter()
+            .map(|w| w.id)
+            .collect());
+        };
+
+        let mut matched = Vec::new();
+        for (id, window) in windows {
+            if self
+                .matcher_cache
+                .matches(window.app_id.as_ref(), Some(&window.title), matcher)
+                .await?
+            {
+                matched.push((*id, window.focus_timestamp));
+            }
         }
+        matched.sort_by_key(|&(_, focus_timestamp)| std::cmp::Reverse(focus_timestamp));
+        Ok(matched.into_iter().map(|(id, _)| id).collect())
     }
 
-    fn extract_app_id_from_command(command: &str) -> String {
-        let cmd = command.split_whitespace().next().unwrap_or(command);
+    async fn window_exists(&self, window_id: u64) -> Result<bool> {
+        match self.windows {
+            Some(ref windows) => Ok(windows.contains_key(&window_id)),
+            None => window_utils::window_exists(&self.niri, window_id).await,
+        }
+    }
+
+    async fn window_workspace_id(&self, window_id: u64) -> Result<Option<u64>> {
+        if let Some(ref windows) = self.windows {
+            return Ok(windows.get(&window_id).and_then(|w| w.workspace_id));
+        }
+        Ok(self
+            .niri
+            .get_windows()
+            .await?
+            .into_iter()
+            .find(|w| w.id == window_id)
+            .and_then(|w| w.workspace_id))
+    }
+
+    fn extract_app_id_from_command(command: &CommandLine) -> String {
+        let cmd = command.program().unwrap_or_default();
         cmd.split('/').last().unwrap_or(cmd).to_string()
     }
 
-    fn get_window_match_pattern(config: &SingletonConfig) -> String {
-        config
-            .app_id
-            .clone()
-            .unwrap_or_else(|| Self::extract_app_id_from_command(&config.command))
+    fn get_window_matcher(config: &SingletonConfig) -> WindowMatcher {
+        if config.app_id.is_none() && config.title.is_none() {
+            let app_id = Self::extract_app_id_from_command(&config.command);
+            return WindowMatcher::new(Some(vec![app_id]), None);
+        }
+        WindowMatcher::new(config.app_id.clone(), config.title.clone())
     }
 
     async fn ensure_window_id(&mut self, name: &str) -> Result<u64> {
-        let state = self.states.get_mut(name).context("Singleton state not found")?;
+        let state = self.states.get(name).context("Singleton state not found")?;
+        let config = state.config.clone();
 
         if let Some(window_id) = state.window_id {
-            if window_utils::window_exists(&self.niri, window_id).await? {
+            if self.window_exists(window_id).await? {
                 return Ok(window_id);
             }
             debug!(
                 "Singleton window {} (name: {}) no longer exists, clearing ID",
                 window_id, name
             );
-            state.window_id = None;
+            if let Some(state) = self.states.get_mut(name) {
+                state.window_id = None;
+            }
         }
 
-        let config = state.config.clone();
-        let window_match = Self::get_window_match_pattern(&config);
-        let matcher = WindowMatcher::new(Some(vec![window_match.clone()]), None);
+        let matcher = Self::get_window_matcher(&config);
 
-        let window_id = if let Some(window) =
-            window_utils::find_window_by_matcher(self.niri.clone(), &matcher, &self.matcher_cache)
-                .await?
-        {
-            window.id
+        let window_id = if let Some(&window_id) = self.matching_windows(&matcher).await?.first() {
+            window_id
         } else {
-            info!("Launching application for singleton {}", name);
-            window_utils::launch_application(&config.command).await?;
-            let window = window_utils::wait_for_window(
-                self.niri.clone(),
-                &window_match,
-                name,
-                50,
-                &self.matcher_cache,
-            )
-            .await?
-            .context("Failed to launch/find singleton window")?;
+            let command = match config.args_when_new {
+                Some(ref args) => config.command.with_args(args),
+                None => config.command.clone(),
+            };
+            info!("Launching application for singleton {}: {}", name, command);
+            window_utils::execute_with(&command, &config.env, config.cwd.as_deref())?;
+            let window = match self.wait_for_singleton(name, &matcher).await {
+                Ok(window) => window,
+                Err(e) => {
+                    let Some(ref fallback_command) = config.fallback_command else {
+                        return Err(e);
+                    };
+                    warn!(
+                        "Singleton {} did not appear ({}), running fallback_command: {}",
+                        name, e, fallback_command
+                    );
+                    send_notification(
+                        "piri",
+                        &format!(
+                            "Singleton {}: no window from '{}', trying fallback '{}'",
+                            name, command, fallback_command
+                        ),
+                    );
+                    window_utils::execute_with(
+                        fallback_command,
+                        &config.env,
+                        config.cwd.as_deref(),
+                    )?;
+                    let window = self.wait_for_singleton(name, &matcher).await?;
+                    info!("Singleton {} launched via fallback_command", name);
+                    send_notification(
+                        "piri",
+                        &format!("Singleton {} launched via fallback command", name),
+                    );
+                    window
+                }
+            };
+
+            // Place the new window before it gets focused (summon keeps it where the user is)
+            if let (Some(ref workspace), false) = (&config.workspace, config.summon) {
+                info!(
+                    "Moving singleton {} (window {}) to workspace {}",
+                    name, window.id, workspace
+                );
+                self.niri.move_window_to_workspace(window.id, workspace).await?;
+            }
 
             // Execute on_created_command if specified (only when window is newly created)
             if let Some(ref on_created_command) = config.on_created_command {
@@ -115,7 +331,7 @@ impl SingletonManager {
                     "Executing on_created_command for singleton {}: {}",
                     name, on_created_command
                 );
-                window_utils::execute_command(on_created_command).with_context(|| {
+                window_utils::execute(on_created_command).with_context(|| {
                     format!(
                         "Failed to execute on_created_command: {}",
                         on_created_command
@@ -131,13 +347,203 @@ impl SingletonManager {
         Ok(window_id)
     }
 
+    async fn wait_for_singleton(
+        &self,
+        name: &str,
+        matcher: &WindowMatcher,
+    ) -> Result<crate::niri::Window> {
+        window_utils::wait_for_window_matcher(
+            self.niri.clone(),
+            matcher,
+            name,
+            50,
+            &self.matcher_cache,
+        )
+        .await?
+        .context("Failed to launch/find singleton window")
+    }
+
     async fn toggle(&mut self, name: &str) -> Result<()> {
         info!("Toggling singleton: {}", name);
-        let window_id = self.ensure_window_id(name).await?;
+        let config = self
+            .states
+            .get(name)
+            .with_context(|| {
+                IpcError::not_found(format!("Singleton '{}' is not configured", name))
+            })?
+            .config
+            .clone();
+        let focused_id = self.niri.get_focused_window_id().await?;
+
+        let window_id = match self.next_in_cycle(name, focused_id).await? {
+            Some(CycleStep::Wrapped { focused, .. }) if config.hide_on_refocus => {
+                return self.hide(name, focused).await;
+            }
+            Some(CycleStep::Wrapped { next, .. }) | Some(CycleStep::Next(next)) => next,
+            Some(CycleStep::Enter(window_id)) => {
+                self.set_previous_focus(name, focused_id);
+                window_id
+            }
+            None => {
+                self.set_previous_focus(name, focused_id);
+                self.ensure_window_id(name).await?
+            }
+        };
+
+        if config.summon {
+            self.summon_window(name, window_id).await?;
+        }
+
         window_utils::focus_window(self.niri.clone(), window_id).await?;
         Ok(())
     }
 
+    fn set_previous_focus(&mut self, name: &str, focused_id: Option<u64>) {
+        if let Some(state) = self.states.get_mut(name) {
+            state.previous_focus = focused_id;
+        }
+    }
+
+    /// Hide the focused singleton window: move it to the stash workspace, or back to where
+    /// it was summoned from, then return focus to the window focused before it was shown
+    async fn hide(&mut self, name: &str, window_id: u64) -> Result<()> {
+        let state = self.states.get_mut(name).context("Singleton state not found")?;
+        let summoned_from = state.summoned_from.take().filter(|(id, _)| *id == window_id);
+        let previous_focus = state.previous_focus.take();
+
+        if let Some(ref stash) = state.config.stash_workspace {
+            info!(
+                "Hiding singleton {} (window {}) to workspace {}",
+                name, window_id, stash
+            );
+            self.niri.move_window_to_workspace(window_id, stash).await?;
+        } else if let Some((_, workspace_id)) = summoned_from {
+            info!(
+                "Sending singleton {} (window {}) back to workspace id {}",
+                name, window_id, workspace_id
+            );
+            self.niri.move_window_to_workspace_id(window_id, workspace_id).await?;
+        }
+
+        if let Some(previous) = previous_focus {
+            if previous != window_id && self.window_exists(previous).await? {
+                window_utils::focus_window(self.niri.clone(), previous).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the window to the focused workspace unless it is already there
+    async fn summon_window(&mut self, name: &str, window_id: u64) -> Result<()> {
+        let workspaces = self.niri.get_workspaces().await?;
+        let focused_workspace_id = workspaces.iter().find(|ws| ws.is_focused).map(|ws| ws.id);
+        let window_workspace_id = self.window_workspace_id(window_id).await?;
+
+        if focused_workspace_id.is_some() && window_workspace_id != focused_workspace_id {
+            info!(
+                "Summoning singleton {} (window {}) to focused workspace",
+                name, window_id
+            );
+            self.niri.move_window_to_focused_workspace(window_id).await?;
+            if let (Some(state), Some(workspace_id)) =
+                (self.states.get_mut(name), window_workspace_id)
+            {
+                state.summoned_from = Some((window_id, workspace_id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Pick the window to focus when several windows match the singleton.
+    /// The first toggle focuses the most recently used match; toggling again while a
+    /// match is focused moves on to the next one, in the MRU order captured at the start.
+    async fn next_in_cycle(
+        &mut self,
+        name: &str,
+        focused_id: Option<u64>,
+    ) -> Result<Option<CycleStep>> {
+        let config = self.states.get(name).context("Singleton state not found")?.config.clone();
+        let matcher = Self::get_window_matcher(&config);
+        let matches = self.matching_windows(&matcher).await?;
+        if matches.is_empty() {
+            return Ok(None);
+        }
+
+        let state = self.states.get_mut(name).context("Singleton state not found")?;
+
+        // Drop closed windows and append newly opened ones without reordering the cycle
+        state.cycle.retain(|id| matches.contains(id));
+        for id in &matches {
+            if !state.cycle.contains(id) {
+                state.cycle.push(*id);
+            }
+        }
+
+        let step = match focused_id.and_then(|f| state.cycle.iter().position(|&id| id == f)) {
+            Some(pos) if pos + 1 == state.cycle.len() => CycleStep::Wrapped {
+                focused: state.cycle[pos],
+                next: state.cycle[0],
+            },
+            Some(pos) => CycleStep::Next(state.cycle[pos + 1]),
+            None => {
+                state.cycle = matches;
+                CycleStep::Enter(state.cycle[0])
+            }
+        };
+        let window_id = match step {
+            CycleStep::Enter(id) | CycleStep::Next(id) | CycleStep::Wrapped { next: id, .. } => id,
+        };
+        debug!(
+            "Singleton {} cycling to window {} ({} matches)",
+            name,
+            window_id,
+            state.cycle.len()
+        );
+        state.window_id = Some(window_id);
+        Ok(Some(step))
+    }
+
+    /// Status of every configured singleton, sorted by name
+    async fn list(&self) -> Result<Vec<SingletonStatus>> {
+        let workspaces = self.niri.get_workspaces().await?;
+        let mut statuses = Vec::new();
+
+        for (name, state) in &self.states {
+            let tracked = match state.window_id {
+                Some(window_id) if self.window_exists(window_id).await? => Some(window_id),
+                _ => None,
+            };
+            let window_id = match tracked {
+                Some(window_id) => Some(window_id),
+                None => {
+                    let matcher = Self::get_window_matcher(&state.config);
+                    self.matching_windows(&matcher).await?.first().copied()
+                }
+            };
+
+            let workspace = match window_id {
+                Some(window_id) => self.window_workspace_id(window_id).await?.and_then(|id| {
+                    workspaces
+                        .iter()
+                        .find(|ws| ws.id == id)
+                        .map(|ws| ws.name.clone().unwrap_or_else(|| ws.idx.to_string()))
+                }),
+                None => None,
+            };
+
+            statuses.push(SingletonStatus {
+                name: name.clone(),
+                command: state.config.command.to_string(),
+                running: window_id.is_some(),
+                window_id,
+                workspace,
+            });
+        }
+
+        statuses.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(statuses)
+    }
+
     async fn clear_cache(&self) {
         self.matcher_cache.clear_cache().await;
     }
@@ -159,13 +565,7 @@ impl crate::plugins::Plugin for SingletonPlugin {
 
         let mut manager = SingletonManager::new(niri);
         for (name, s_config) in &config.singletons {
-            manager.states.insert(
-                name.clone(),
-                SingletonState {
-                    window_id: None,
-                    config: s_config.clone(),
-                },
-            );
+            manager.states.insert(name.clone(), SingletonState::new(s_config.clone()));
         }
 
         Self { manager, config }
@@ -178,13 +578,7 @@ impl crate::plugins::Plugin for SingletonPlugin {
             if let Some(state) = self.manager.states.get_mut(name) {
                 state.config = s_config.clone();
             } else {
-                self.manager.states.insert(
-                    name.clone(),
-                    SingletonState {
-                        window_id: None,
-                        config: s_config.clone(),
-                    },
-                );
+                self.manager.states.insert(name.clone(), SingletonState::new(s_config.clone()));
             }
         }
 
@@ -196,6 +590,77 @@ impl crate::plugins::Plugin for SingletonPlugin {
         Ok(())
     }
 
+    async fn export_state(&mut self) -> Result<Option<serde_json::Value>> {
+        let saved: HashMap<&String, SavedSingleton> = self
+            .manager
+            .states
+            .iter()
+            .map(|(name, state)| {
+                (
+                    name,
+                    SavedSingleton {
+                        window_id: state.window_id,
+                        cycle: state.cycle.clone(),
+                        previous_focus: state.previous_focus,
+                        summoned_from: state.summoned_from,
+                    },
+                )
+            })
+            .collect();
+        Ok(Some(serde_json::to_value(saved)?))
+    }
+
+    async fn import_state(&mut self, state: serde_json::Value) -> Result<()> {
+        let saved: HashMap<String, SavedSingleton> = serde_json::from_value(state)?;
+        for (name, saved) in saved {
+            if let Some(state) = self.manager.states.get_mut(&name) {
+                state.window_id = saved.window_id;
+                state.cycle = saved.cycle;
+                state.previous_focus = saved.previous_focus;
+                state.summoned_from = saved.summoned_from;
+            }
+        }
+        Ok(())
+    }
+
+    async fn on_niri_reconnected(&mut self, _niri: &NiriIpc) -> Result<()> {
+        self.manager.forget_windows();
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(
+            event,
+            Event::WindowsChanged { .. }
+                | Event::WindowOpenedOrChanged { .. }
+                | Event::WindowClosed { .. }
+                | Event::WindowFocusTimestampChanged { .. }
+        )
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        self.manager.handle_event(event);
+        Ok(())
+    }
+
+    async fn handle_ipc_query(
+        &mut self,
+        request: &IpcRequest,
+    ) -> Result<Option<serde_json::Value>> {
+        match request {
+            IpcRequest::SingletonList => {
+                let statuses = self.manager.list().await?;
+                Ok(Some(serde_json::to_value(statuses)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn status(&mut self) -> Result<Option<serde_json::Value>> {
+        let statuses = self.manager.list().await?;
+        Ok(Some(serde_json::to_value(statuses)?))
+    }
+
     async fn handle_ipc_request(&mut self, request: &IpcRequest) -> Result<Option<Result<()>>> {
         match request {
             IpcRequest::SingletonToggle { name } => {
@@ -0,0 +1,287 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info};
+use niri_ipc::Event;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::ipc::{IpcError, IpcRequest};
+use crate::niri::NiriIpc;
+use crate::plugins::FromConfig;
+
+/// Stash plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StashPluginConfig {
+    /// Named workspace stashed windows are moved to
+    pub workspace: String,
+}
+
+impl FromConfig for StashPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            workspace: config.piri.stash.workspace.clone(),
+        })
+    }
+}
+
+/// A stashed window, as returned by `StashList`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StashedWindowInfo {
+    pub window_id: u64,
+    pub app_id: Option<String>,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Stashed {
+    window_id: u64,
+    /// Workspace the window was stashed from (None: unknown, e.g. after a daemon restart)
+    workspace_id: Option<u64>,
+    floating: bool,
+}
+
+/// Emulates minimizing: stashed windows are moved to a hidden workspace and brought back,
+/// most recent first or by ID, to where they came from.
+pub struct StashPlugin {
+    niri: NiriIpc,
+    config: StashPluginConfig,
+    /// Stashed windows, most recent last
+    stack: Vec<Stashed>,
+    /// ID of the stash workspace, once seen
+    stash_workspace_id: Option<u64>,
+}
+
+impl StashPlugin {
+    async fn find_stash_workspace(&self) -> Result<u64> {
+        self.niri
+            .get_workspaces()
+            .await?
+            .iter()
+            .find(|ws| ws.name.as_deref() == Some(self.config.workspace.as_str()))
+            .map(|ws| ws.id)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Stash workspace '{}' does not exist, declare it in the niri config with `workspace \"{}\"`",
+                    self.config.workspace,
+                    self.config.workspace
+                )
+            })
+    }
+
+    async fn stash(&mut self) -> Result<()> {
+        let stash_workspace_id = self.find_stash_workspace().await?;
+        let focused = self
+            .niri
+            .get_focused_window_id()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("No focused window to stash"))?;
+        let window = self
+            .niri
+            .get_windows()
+            .await?
+            .into_iter()
+            .find(|w| w.id == focused)
+            .ok_or_else(|| anyhow::anyhow!("Focused window {} not found", focused))?;
+        if window.workspace_id == Some(stash_workspace_id) {
+            anyhow::bail!("Window {} is already stashed", window.id);
+        }
+
+        info!("Stashing window {}", window.id);
+        self.stack.push(Stashed {
+            window_id: window.id,
+            workspace_id: window.workspace_id,
+            floating: window.floating,
+        });
+        self.niri.move_window_to_workspace_id(window.id, stash_workspace_id).await?;
+        crate::journal::record(
+            "stash",
+            window.id,
+            window.app_id.as_deref(),
+            window.workspace_id,
+            false,
+        );
+        Ok(())
+    }
+
+    /// Bring back a stashed window (None: the most recent one) to the workspace it was
+    /// stashed from, or to the focused workspace if `here` is set or that workspace is gone
+    async fn unstash(&mut self, window_id: Option<u64>, here: bool) -> Result<()> {
+        let index = match window_id {
+            Some(id) => self
+                .stack
+                .iter()
+                .position(|s| s.window_id == id)
+                .ok_or_else(|| IpcError::not_found(format!("Window {} is not stashed", id)))?,
+            None => self
+                .stack
+                .len()
+                .checked_sub(1)
+                .ok_or_else(|| anyhow::anyhow!("No stashed windows"))?,
+        };
+        let stashed = self.stack[index].clone();
+
+        let workspaces = self.niri.get_workspaces().await?;
+        let original = stashed
+            .workspace_id
+            .filter(|id| !here && workspaces.iter().any(|ws| ws.id == *id));
+        let target = match original {
+            Some(id) => id,
+            None => workspaces
+                .iter()
+                .find(|ws| ws.is_focused)
+                .map(|ws| ws.id)
+                .ok_or_else(|| anyhow::anyhow!("No focused workspace"))?,
+        };
+
+        info!(
+            "Unstashing window {} to workspace {}",
+            stashed.window_id, target
+        );
+        self.niri.move_window_to_workspace_id(stashed.window_id, target).await?;
+        crate::journal::forget(stashed.window_id);
+        self.niri.set_window_floating(stashed.window_id, stashed.floating).await?;
+        self.niri.focus_window(stashed.window_id).await?;
+        self.stack.remove(index);
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<StashedWindowInfo>> {
+        let windows = self.niri.get_windows().await?;
+        Ok(self
+            .stack
+            .iter()
+            .rev()
+            .filter_map(|s| windows.iter().find(|w| w.id == s.window_id))
+            .map(|w| StashedWindowInfo {
+                window_id: w.id,
+                app_id: w.app_id.clone(),
+                title: w.title.clone(),
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for StashPlugin {
+    type Config = StashPluginConfig;
+
+    fn new(niri: NiriIpc, config: StashPluginConfig) -> Self {
+        info!(
+            "Stash plugin initialized, stashing on workspace '{}'",
+            config.workspace
+        );
+        Self {
+            niri,
+            config,
+            stack: Vec::new(),
+            stash_workspace_id: None,
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        match event {
+            Event::WorkspacesChanged { workspaces } => {
+                self.stash_workspace_id = workspaces
+                    .iter()
+                    .find(|ws| ws.name.as_deref() == Some(self.config.workspace.as_str()))
+                    .map(|ws| ws.id);
+            }
+            // Sent on (re)connect: windows already on the stash workspace (e.g. from before
+            // a daemon restart) count as stashed
+            Event::WindowsChanged { windows } => {
+                self.stack.retain(|s| windows.iter().any(|w| w.id == s.window_id));
+                for window in windows {
+                    if window.workspace_id.is_some()
+                        && window.workspace_id == self.stash_workspace_id
+                        && !self.stack.iter().any(|s| s.window_id == window.id)
+                    {
+                        debug!("Adopting window {} on the stash workspace", window.id);
+                        self.stack.push(Stashed {
+                            window_id: window.id,
+                            workspace_id: None,
+                            floating: window.is_floating,
+                        });
+                    }
+                }
+            }
+            // A stashed window moved off the stash workspace by hand isn't stashed anymore
+            Event::WindowOpenedOrChanged { window } => {
+                if let Some(stash_workspace_id) = self.stash_workspace_id {
+                    self.stack.retain(|s| {
+                        s.window_id != window.id || window.workspace_id == Some(stash_workspace_id)
+                    });
+                }
+            }
+            Event::WindowClosed { id } => {
+                self.stack.retain(|s| s.window_id != *id);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    async fn export_state(&mut self) -> Result<Option<serde_json::Value>> {
+        Ok(Some(serde_json::to_value(&self.stack)?))
+    }
+
+    // Unlike the windows adopted from the stash workspace, these know where they came from
+    async fn import_state(&mut self, state: serde_json::Value) -> Result<()> {
+        self.stack = serde_json::from_value(state)?;
+        Ok(())
+    }
+
+    async fn on_niri_reconnected(&mut self, _niri: &NiriIpc) -> Result<()> {
+        if !self.stack.is_empty() {
+            info!(
+                "Dropping {} stashed windows from before niri restarted",
+                self.stack.len()
+            );
+            self.stack.clear();
+        }
+        self.stash_workspace_id = None;
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(
+            event,
+            Event::WorkspacesChanged { .. }
+                | Event::WindowsChanged { .. }
+                | Event::WindowOpenedOrChanged { .. }
+                | Event::WindowClosed { .. }
+        )
+    }
+
+    async fn handle_ipc_query(
+        &mut self,
+        request: &IpcRequest,
+    ) -> Result<Option<serde_json::Value>> {
+        match request {
+            IpcRequest::StashList => Ok(Some(serde_json::to_value(self.list().await?)?)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn handle_ipc_request(&mut self, request: &IpcRequest) -> Result<Option<Result<()>>> {
+        match request {
+            IpcRequest::Stash => Ok(Some(self.stash().await)),
+            IpcRequest::Unstash { window_id, here } => {
+                Ok(Some(self.unstash(*window_id, *here).await))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn status(&mut self) -> Result<Option<serde_json::Value>> {
+        Ok(Some(serde_json::json!({
+            "count": self.stack.len(),
+        })))
+    }
+
+    async fn update_config(&mut self, config: StashPluginConfig) -> Result<()> {
+        info!("Updating stash plugin configuration");
+        self.config = config;
+        self.stash_workspace_id = self.find_stash_workspace().await.ok();
+        Ok(())
+    }
+}
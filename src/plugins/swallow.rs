@@ -7,15 +7,17 @@ use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use crate::config::{deserialize_string_or_vec, Config};
-use crate::niri::NiriIpc;
+use crate::config::{deserialize_string_or_vec, Config, ExcludeSection};
+use crate::niri::{NiriIpc, Window};
 use crate::plugins::window_utils::{
-    get_focused_window, matches_window, perform_swallow, try_pid_matching, WindowMatcherCache,
+    get_focused_window, is_excluded, matches_window, perform_swallow, try_pid_matching,
+    WindowMatcherCache,
 };
 use crate::plugins::FromConfig;
 use crate::utils::send_notification;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct SwallowExclude {
     #[serde(default, deserialize_with = "deserialize_string_or_vec")]
     pub app_id: Option<Vec<String>>,
@@ -24,6 +26,7 @@ pub struct SwallowExclude {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct SwallowRule {
     #[serde(default, deserialize_with = "deserialize_string_or_vec")]
     pub parent_app_id: Option<Vec<String>>,
@@ -35,6 +38,64 @@ pub struct SwallowRule {
     pub child_title: Option<Vec<String>>,
 }
 
+impl SwallowExclude {
+    /// Whether a window matches the exclude rule; no conditions exclude nothing
+    pub async fn matches(
+        &self,
+        window: &Window,
+        matcher_cache: &WindowMatcherCache,
+    ) -> Result<bool> {
+        if self.app_id.is_none() && self.title.is_none() {
+            return Ok(false);
+        }
+        matches_window(
+            window,
+            self.app_id.as_ref(),
+            self.title.as_ref(),
+            None,
+            None,
+            matcher_cache,
+        )
+        .await
+    }
+}
+
+impl SwallowRule {
+    /// Whether a newly opened window meets the child conditions; no conditions match any window
+    pub async fn matches_child(
+        &self,
+        window: &Window,
+        matcher_cache: &WindowMatcherCache,
+    ) -> Result<bool> {
+        matches_window(
+            window,
+            self.child_app_id.as_ref(),
+            self.child_title.as_ref(),
+            None,
+            None,
+            matcher_cache,
+        )
+        .await
+    }
+
+    /// Whether a window meets the parent conditions; no conditions match any window
+    pub async fn matches_parent(
+        &self,
+        window: &Window,
+        matcher_cache: &WindowMatcherCache,
+    ) -> Result<bool> {
+        matches_window(
+            window,
+            self.parent_app_id.as_ref(),
+            self.parent_title.as_ref(),
+            None,
+            None,
+            matcher_cache,
+        )
+        .await
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SwallowPluginConfig {
     pub rules: Vec<SwallowRule>,
@@ -42,6 +103,9 @@ pub struct SwallowPluginConfig {
     pub use_pid_matching: bool,
     #[serde(default)]
     pub exclude: Option<SwallowExclude>,
+    /// `[piri.exclude]`
+    #[serde(default)]
+    pub global_exclude: ExcludeSection,
 }
 
 fn default_true() -> bool {
@@ -54,6 +118,7 @@ impl Default for SwallowPluginConfig {
             rules: Vec::new(),
             use_pid_matching: true,
             exclude: None,
+            global_exclude: ExcludeSection::default(),
         }
     }
 }
@@ -65,6 +130,7 @@ impl FromConfig for SwallowPluginConfig {
             rules: config.swallow.clone(),
             use_pid_matching: config.piri.swallow.use_pid_matching,
             exclude: config.piri.swallow.exclude.clone(),
+            global_exclude: config.piri.exclude.clone(),
         })
     }
 }
@@ -127,33 +193,10 @@ impl SwallowPlugin {
         Ok(())
     }
 
-    /// Check if a window matches the exclude rule
-    async fn check_window_matches_exclude(
-        &self,
-        window: &crate::niri::Window,
-        exclude: &SwallowExclude,
-    ) -> Result<bool> {
-        // If no conditions specified, exclude nothing
-        if exclude.app_id.is_none() && exclude.title.is_none() {
-            return Ok(false);
-        }
-
-        // Check if window matches exclude app_id and title
-        matches_window(
-            window,
-            exclude.app_id.as_ref(),
-            exclude.title.as_ref(),
-            None,
-            None,
-            &self.matcher_cache,
-        )
-        .await
-    }
-
     /// Check if a child window matches a rule's child window conditions
     async fn check_child_window_matches_rule(
         &self,
-        child_window: &crate::niri::Window,
+        child_window: &Window,
         window_id: u64,
         rule: &SwallowRule,
     ) -> Result<bool> {
@@ -181,15 +224,7 @@ impl SwallowPlugin {
             "Checking child window against rule patterns: app_id={:?}, title={:?}",
             rule.child_app_id, rule.child_title
         );
-        let matches_window_criteria = matches_window(
-            child_window,
-            rule.child_app_id.as_ref(),
-            rule.child_title.as_ref(),
-            None,
-            None,
-            &self.matcher_cache,
-        )
-        .await?;
+        let matches_window_criteria = rule.matches_child(child_window, &self.matcher_cache).await?;
 
         if !matches_window_criteria {
             return Ok(false);
@@ -209,7 +244,7 @@ impl SwallowPlugin {
         &self,
         rule: &SwallowRule,
         child_window_id: u64,
-    ) -> Result<Option<crate::niri::Window>> {
+    ) -> Result<Option<Window>> {
         // Get currently focused window
         info!("Checking focused window for parent rule matching...");
         let focused_window = match get_focused_window(&self.niri).await {
@@ -259,15 +294,8 @@ impl SwallowPlugin {
                 }
 
                 // Check if this window matches parent criteria
-                let matches_window_criteria = matches_window(
-                    &prev_window,
-                    rule.parent_app_id.as_ref(),
-                    rule.parent_title.as_ref(),
-                    None,
-                    None,
-                    &self.matcher_cache,
-                )
-                .await?;
+                let matches_window_criteria =
+                    rule.matches_parent(&prev_window, &self.matcher_cache).await?;
 
                 if !matches_window_criteria {
                     debug!(
@@ -304,15 +332,8 @@ impl SwallowPlugin {
             "Checking if focused window {} matches parent criteria (app_id={:?}, title={:?})",
             focused_window.id, rule.parent_app_id, rule.parent_title
         );
-        let matches_window_criteria = matches_window(
-            &focused_window,
-            rule.parent_app_id.as_ref(),
-            rule.parent_title.as_ref(),
-            None,
-            None,
-            &self.matcher_cache,
-        )
-        .await?;
+        let matches_window_criteria =
+            rule.matches_parent(&focused_window, &self.matcher_cache).await?;
 
         if !matches_window_criteria {
             warn!(
@@ -383,7 +404,7 @@ impl SwallowPlugin {
 
         // Check if child window matches exclude rule
         if let Some(ref exclude) = self.config.exclude {
-            let matches_exclude = self.check_window_matches_exclude(&child_window, exclude).await?;
+            let matches_exclude = exclude.matches(&child_window, &self.matcher_cache).await?;
             if matches_exclude {
                 debug!(
                     "Child window {} (app_id={:?}, title={}) matches exclude rule, skipping swallow",
@@ -392,6 +413,20 @@ impl SwallowPlugin {
                 return Ok(());
             }
         }
+        if is_excluded(
+            &self.config.global_exclude,
+            child_window.app_id.as_ref(),
+            Some(&child_window.title),
+            &self.matcher_cache,
+        )
+        .await?
+        {
+            debug!(
+                "Child window {} (app_id={:?}, title={}) is in [piri.exclude], skipping swallow",
+                window_id, child_window.app_id, child_window.title
+            );
+            return Ok(());
+        }
 
         // Priority 1: Try PID matching first (if enabled)
         if self.config.use_pid_matching {
@@ -477,6 +512,12 @@ impl crate::plugins::Plugin for SwallowPlugin {
         Ok(())
     }
 
+    async fn on_niri_reconnected(&mut self, niri: &NiriIpc) -> Result<()> {
+        self.focused_window_queue.clear();
+        self.window_pid_map.lock().await.clear();
+        Self::perform_initial_scan(niri.clone(), self.window_pid_map.clone()).await
+    }
+
     fn is_interested_in_event(&self, event: &Event) -> bool {
         matches!(
             event,
@@ -0,0 +1,159 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use niri_ipc::{Event, Window};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::config::{Config, TitleWatchRule};
+use crate::niri::NiriIpc;
+use crate::plugins::window_utils::{self, WindowMatcher, WindowMatcherCache};
+use crate::plugins::FromConfig;
+use crate::utils::shell_quote;
+
+/// Title watch plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TitleWatchPluginConfig {
+    pub rules: Vec<TitleWatchRule>,
+}
+
+impl FromConfig for TitleWatchPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            rules: config.title_watch.clone(),
+        })
+    }
+}
+
+/// Runs a command when the title of a watched window changes so that it starts matching a
+/// rule's regex (e.g. a terminal title ending in "DONE"), at most once per cooldown.
+pub struct TitleWatchPlugin {
+    config: TitleWatchPluginConfig,
+    matcher_cache: WindowMatcherCache,
+    /// Window ID -> whether its title matches each rule
+    matched: HashMap<u64, Vec<bool>>,
+    /// When each rule last ran
+    last_run: Vec<Option<Instant>>,
+}
+
+impl TitleWatchPlugin {
+    /// Whether the window matches each rule right now
+    async fn match_rules(&self, window: &Window) -> Result<Vec<bool>> {
+        let mut matched = Vec::with_capacity(self.config.rules.len());
+        for rule in &self.config.rules {
+            if let Some(ref app_id) = rule.app_id {
+                let matcher = WindowMatcher::new(Some(app_id.clone()), None);
+                if !self.matcher_cache.matches(window.app_id.as_ref(), None, &matcher).await? {
+                    matched.push(false);
+                    continue;
+                }
+            }
+            let matcher = WindowMatcher::new(None, Some(vec![rule.title.clone()]));
+            matched.push(self.matcher_cache.matches(None, window.title.as_ref(), &matcher).await?);
+        }
+        Ok(matched)
+    }
+
+    fn run(&mut self, index: usize, window: &Window) {
+        let rule = &self.config.rules[index];
+        let now = Instant::now();
+        if let Some(last) = self.last_run[index] {
+            if now.duration_since(last) < rule.cooldown.0 {
+                debug!("Title watch rule {} is cooling down", index);
+                return;
+            }
+        }
+        self.last_run[index] = Some(now);
+
+        let app_id = window.app_id.as_deref().unwrap_or_default();
+        let title = window.title.as_deref().unwrap_or_default();
+        let command = rule.exec.expand(|text, quote| {
+            let text = text.replace("{id}", &window.id.to_string());
+            if quote {
+                text.replace("{app_id}", &shell_quote(app_id))
+                    .replace("{title}", &shell_quote(title))
+            } else {
+                text.replace("{app_id}", app_id).replace("{title}", title)
+            }
+        });
+        info!(
+            "Title of window {} matches /{}/, running: {}",
+            window.id, rule.title, command
+        );
+        if let Err(e) = window_utils::execute(&command) {
+            warn!("Failed to run title watch command: {}", e);
+        }
+    }
+
+    async fn window_changed(&mut self, window: &Window) -> Result<()> {
+        let matched = self.match_rules(window).await?;
+        // Only title changes count, not the title a window opens with
+        if let Some(previous) = self.matched.insert(window.id, matched.clone()) {
+            for (index, (&was, &is)) in previous.iter().zip(&matched).enumerate() {
+                if is && !was {
+                    self.run(index, window);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for TitleWatchPlugin {
+    type Config = TitleWatchPluginConfig;
+
+    fn new(_niri: NiriIpc, config: TitleWatchPluginConfig) -> Self {
+        info!(
+            "Title watch plugin initialized with {} rules",
+            config.rules.len()
+        );
+        let rules = config.rules.len();
+        Self {
+            config,
+            matcher_cache: WindowMatcherCache::new(),
+            matched: HashMap::new(),
+            last_run: vec![None; rules],
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        match event {
+            Event::WindowsChanged { windows } => {
+                self.matched.clear();
+                for window in windows {
+                    let matched = self.match_rules(window).await?;
+                    self.matched.insert(window.id, matched);
+                }
+            }
+            Event::WindowOpenedOrChanged { window } => self.window_changed(window).await?,
+            Event::WindowClosed { id } => {
+                self.matched.remove(id);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        !self.config.rules.is_empty()
+            && matches!(
+                event,
+                Event::WindowsChanged { .. }
+                    | Event::WindowOpenedOrChanged { .. }
+                    | Event::WindowClosed { .. }
+            )
+    }
+
+    async fn update_config(&mut self, config: TitleWatchPluginConfig) -> Result<()> {
+        info!("Updating title watch plugin configuration");
+        self.last_run = vec![None; config.rules.len()];
+        self.config = config;
+        self.matcher_cache.clear_cache().await;
+        // Windows are matched against the new rules on their next change, without running
+        // anything for titles that already matched
+        self.matched.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use niri_ipc::{Event, Workspace};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::config::{CommandLine, Config};
+use crate::niri::NiriIpc;
+use crate::plugins::{window_utils, FromConfig};
+use crate::utils::shell_quote;
+
+/// Wallpaper plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WallpaperPluginConfig {
+    /// Command template with {image}, {output} and {workspace} placeholders
+    pub command: CommandLine,
+    /// Image used when nothing more specific is configured
+    pub default: Option<String>,
+    /// Workspace ("OUTPUT:workspace", name or idx) -> image
+    pub workspaces: HashMap<String, String>,
+    /// Output -> image
+    pub outputs: HashMap<String, String>,
+}
+
+impl FromConfig for WallpaperPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        let section = &config.piri.wallpaper;
+        Some(Self {
+            command: section.command.clone(),
+            default: section.default.clone(),
+            workspaces: section.workspaces.clone(),
+            outputs: section.outputs.clone(),
+        })
+    }
+}
+
+pub struct WallpaperPlugin {
+    config: WallpaperPluginConfig,
+    workspaces: Vec<Workspace>,
+    /// Image currently shown on each output
+    current: HashMap<String, String>,
+}
+
+impl WallpaperPlugin {
+    /// Image for a workspace: per-workspace mapping, then per-output, then the default
+    fn image_for(&self, workspace: &Workspace) -> Option<&String> {
+        let idx = workspace.idx.to_string();
+        let mut keys = Vec::new();
+        if let Some(ref output) = workspace.output {
+            if let Some(ref name) = workspace.name {
+                keys.push(format!("{}:{}", output, name));
+            }
+            keys.push(format!("{}:{}", output, idx));
+        }
+        if let Some(ref name) = workspace.name {
+            keys.push(name.clone());
+        }
+        keys.push(idx);
+
+        keys.iter()
+            .find_map(|key| self.config.workspaces.get(key))
+            .or_else(|| {
+                workspace.output.as_ref().and_then(|output| self.config.outputs.get(output))
+            })
+            .or(self.config.default.as_ref())
+    }
+
+    /// Set the wallpaper of a workspace's output, unless it already shows the right image
+    fn apply(&mut self, workspace_id: u64) -> Result<()> {
+        let Some(workspace) = self.workspaces.iter().find(|ws| ws.id == workspace_id) else {
+            return Ok(());
+        };
+        let Some(ref output) = workspace.output else {
+            return Ok(());
+        };
+        let Some(image) = self.image_for(workspace) else {
+            debug!("No wallpaper configured for workspace {}", workspace_id);
+            return Ok(());
+        };
+        if self.current.get(output) == Some(image) {
+            return Ok(());
+        }
+
+        let expanded = shellexpand::full(image)
+            .map(|path| path.into_owned())
+            .unwrap_or_else(|_| image.clone());
+        let workspace_label = workspace.name.clone().unwrap_or_else(|| workspace.idx.to_string());
+        let values = [
+            ("{image}", expanded.as_str()),
+            ("{output}", output.as_str()),
+            ("{workspace}", workspace_label.as_str()),
+        ];
+        let command = self.config.command.expand(|text, quote| {
+            values.iter().fold(text.to_string(), |text, (placeholder, value)| {
+                if quote {
+                    text.replace(placeholder, &shell_quote(value))
+                } else {
+                    text.replace(placeholder, value)
+                }
+            })
+        });
+
+        debug!("Setting wallpaper of {} to {}", output, expanded);
+        self.current.insert(output.clone(), image.clone());
+        window_utils::execute(&command)
+    }
+
+    /// Apply wallpapers for the active workspace of every output
+    fn apply_all(&mut self) {
+        let active: Vec<u64> =
+            self.workspaces.iter().filter(|ws| ws.is_active).map(|ws| ws.id).collect();
+        for workspace_id in active {
+            if let Err(e) = self.apply(workspace_id) {
+                warn!("Failed to set wallpaper: {}", e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for WallpaperPlugin {
+    type Config = WallpaperPluginConfig;
+
+    fn new(_niri: NiriIpc, config: WallpaperPluginConfig) -> Self {
+        info!(
+            "Wallpaper plugin initialized with {} workspace and {} output wallpapers",
+            config.workspaces.len(),
+            config.outputs.len()
+        );
+        Self {
+            config,
+            workspaces: Vec::new(),
+            current: HashMap::new(),
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        match event {
+            Event::WorkspacesChanged { workspaces } => {
+                self.workspaces = workspaces.clone();
+                self.apply_all();
+            }
+            Event::WorkspaceActivated { id, focused } => {
+                // Activation is per output: the previously active workspace there is no longer active
+                let output =
+                    self.workspaces.iter().find(|ws| ws.id == *id).and_then(|ws| ws.output.clone());
+                for workspace in &mut self.workspaces {
+                    if workspace.output == output {
+                        workspace.is_active = workspace.id == *id;
+                    }
+                    if *focused {
+                        workspace.is_focused = workspace.id == *id;
+                    }
+                }
+                self.apply(*id)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(
+            event,
+            Event::WorkspacesChanged { .. } | Event::WorkspaceActivated { .. }
+        )
+    }
+
+    async fn update_config(&mut self, config: WallpaperPluginConfig) -> Result<()> {
+        info!("Updating wallpaper plugin configuration");
+        self.config = config;
+        self.current.clear();
+        self.apply_all();
+        Ok(())
+    }
+}
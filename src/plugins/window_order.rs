@@ -2,13 +2,15 @@ use anyhow::Result;
 use async_trait::async_trait;
 use log::{debug, info, warn};
 use niri_ipc::{Action, Event};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 
-use crate::config::Config;
+use crate::config::{Config, ExcludeSection};
 use crate::ipc::IpcRequest;
 use crate::niri::NiriIpc;
+use crate::plugins::window_utils::{self, WindowMatcherCache};
 use crate::plugins::FromConfig;
 
 /// Window order plugin config (for internal use)
@@ -22,6 +24,9 @@ pub struct WindowOrderPluginConfig {
     pub enable_event_listener: bool,
     /// List of workspaces to apply ordering to (empty = all workspaces)
     pub workspaces: Vec<String>,
+    /// `[piri.exclude]`, windows that keep their column
+    #[serde(default)]
+    pub global_exclude: ExcludeSection,
 }
 
 impl Default for WindowOrderPluginConfig {
@@ -31,6 +36,7 @@ impl Default for WindowOrderPluginConfig {
             default_weight: 0,
             enable_event_listener: false,
             workspaces: Vec::new(),
+            global_exclude: ExcludeSection::default(),
         }
     }
 }
@@ -45,6 +51,7 @@ impl FromConfig for WindowOrderPluginConfig {
                 default_weight: config.piri.window_order.default_weight,
                 enable_event_listener: config.piri.window_order.enable_event_listener,
                 workspaces: config.piri.window_order.workspaces.clone(),
+                global_exclude: config.piri.exclude.clone(),
             })
         }
     }
@@ -54,12 +61,13 @@ impl FromConfig for WindowOrderPluginConfig {
 pub struct WindowOrderPlugin {
     niri: NiriIpc,
     config: WindowOrderPluginConfig,
+    matcher_cache: Arc<WindowMatcherCache>,
 }
 
 impl WindowOrderPlugin {
     /// Get order value for a window based on its app_id
     /// Uses configured weight if exists, otherwise uses default_weight from config
-    fn get_window_order(
+    pub fn get_window_order(
         app_id: Option<&String>,
         window_order: &HashMap<String, u32>,
         default_weight: u32,
@@ -202,9 +210,30 @@ impl WindowOrderPlugin {
         let current_col_map: HashMap<u64, usize> =
             current_positions.iter().map(|(id, col, _)| (*id, *col)).collect();
 
+        // Windows in [piri.exclude] stay in their column, the others are sorted around them
+        let mut excluded = HashSet::new();
+        for w in &workspace_windows {
+            if window_utils::is_excluded(
+                &self.config.global_exclude,
+                w.app_id.as_ref(),
+                Some(&w.title),
+                &self.matcher_cache,
+            )
+            .await?
+            {
+                excluded.insert(w.id);
+            }
+        }
+        let pinned_cols: HashSet<usize> = current_positions
+            .iter()
+            .filter(|(id, _, _)| excluded.contains(id))
+            .map(|(_, col, _)| *col)
+            .collect();
+
         // Get window orders
         let mut windows_with_order: Vec<_> = workspace_windows
             .iter()
+            .filter(|w| !excluded.contains(&w.id))
             .map(|w| {
                 let order = Self::get_window_order(w.app_id.as_ref(), window_order, default_weight);
                 let current_col = current_col_map.get(&w.id).copied().unwrap_or(0);
@@ -225,20 +254,24 @@ impl WindowOrderPlugin {
             }
         });
 
-        // Assign target column indices (1-based: 1, 2, 3, ...)
-        let target_positions: Vec<_> = windows_with_order
+        // Assign target column indices (1-based: 1, 2, 3, ...), skipping pinned columns
+        let free_cols = (1..).filter(|col| !pinned_cols.contains(col));
+        let mut target_positions: Vec<_> = windows_with_order
             .iter()
-            .enumerate()
+            .zip(free_cols)
             .map(
-                |(idx, (window_id, order, _current_col, app_id)): (
-                    usize,
+                |((window_id, order, _current_col, app_id), target_col): (
                     &(u64, u32, usize, Option<String>),
-                )| {
-                    let target_col = idx + 1; // 1-based column index
-                    (*window_id, target_col, *order, app_id.clone())
-                },
+                    usize,
+                )| { (*window_id, target_col, *order, app_id.clone()) },
             )
             .collect();
+        target_positions.extend(
+            current_positions
+                .iter()
+                .filter(|(id, _, _)| excluded.contains(id))
+                .map(|(id, col, app_id)| (*id, *col, default_weight, app_id.clone())),
+        );
 
         info!(
             "Target window order (by order weight): {:?}",
@@ -521,7 +554,11 @@ impl crate::plugins::Plugin for WindowOrderPlugin {
             "WindowOrder plugin initialized with {} rules",
             config.window_order.len()
         );
-        Self { niri, config }
+        Self {
+            niri,
+            config,
+            matcher_cache: Arc::new(WindowMatcherCache::new()),
+        }
     }
 
     async fn update_config(&mut self, config: WindowOrderPluginConfig) -> Result<()> {
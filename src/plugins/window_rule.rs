@@ -7,7 +7,7 @@ use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
 
-use crate::config::{Config, WindowRuleConfig};
+use crate::config::{CommandLine, Config, ExcludeSection, WindowRuleConfig};
 use crate::niri::NiriIpc;
 use crate::plugins::window_utils::{self, WindowMatcher, WindowMatcherCache};
 use crate::plugins::FromConfig;
@@ -17,11 +17,16 @@ use crate::plugins::FromConfig;
 pub struct WindowRulePluginConfig {
     /// List of window rules
     pub rules: Vec<WindowRuleConfig>,
+    /// `[piri.exclude]`
+    pub global_exclude: ExcludeSection,
 }
 
 impl Default for WindowRulePluginConfig {
     fn default() -> Self {
-        Self { rules: Vec::new() }
+        Self {
+            rules: Vec::new(),
+            global_exclude: ExcludeSection::default(),
+        }
     }
 }
 
@@ -32,6 +37,7 @@ impl FromConfig for WindowRulePluginConfig {
         } else {
             Some(Self {
                 rules: config.window_rule.clone(),
+                global_exclude: config.piri.exclude.clone(),
             })
         }
     }
@@ -56,7 +62,7 @@ impl WindowRulePlugin {
     async fn execute_focus_rule(
         &mut self,
         window_id: u64,
-        focus_command: &str,
+        focus_command: &CommandLine,
         rule_index: usize,
         focus_once: bool,
     ) -> Result<()> {
@@ -78,7 +84,7 @@ impl WindowRulePlugin {
             "Executing focus_command for window {}: {}",
             window_id, focus_command
         );
-        window_utils::execute_command(focus_command)?;
+        window_utils::execute(focus_command)?;
 
         // Mark this rule as having executed focus_command if focus_once is true
         if focus_once {
@@ -90,6 +96,16 @@ impl WindowRulePlugin {
         Ok(())
     }
 
+    async fn is_excluded(&self, app_id: Option<&String>, title: Option<&String>) -> Result<bool> {
+        window_utils::is_excluded(
+            &self.config.global_exclude,
+            app_id,
+            title,
+            &self.matcher_cache,
+        )
+        .await
+    }
+
     /// Handle focus command execution for currently focused window
     async fn handle_focus_command(&mut self, window_id: u64) -> Result<()> {
         let windows = self.niri.get_windows().await?;
@@ -97,6 +113,9 @@ impl WindowRulePlugin {
             .into_iter()
             .find(|w| w.id == window_id)
             .ok_or_else(|| anyhow::anyhow!("Focused window {} not found", window_id))?;
+        if self.is_excluded(window.app_id.as_ref(), Some(&window.title)).await? {
+            return Ok(());
+        }
 
         let rules = self.config.rules.clone();
         for (rule_index, rule) in rules.iter().enumerate() {
@@ -123,6 +142,9 @@ impl WindowRulePlugin {
     }
 
     async fn handle_window_opened(&mut self, window: &niri_ipc::Window) -> Result<()> {
+        if self.is_excluded(window.app_id.as_ref(), window.title.as_ref()).await? {
+            return Ok(());
+        }
         let rules = self.config.rules.clone();
         for (rule_index, rule) in rules.iter().enumerate() {
             let matcher = WindowMatcher::new(rule.app_id.clone(), rule.title.clone());
@@ -207,6 +229,12 @@ impl crate::plugins::Plugin for WindowRulePlugin {
         Ok(())
     }
 
+    async fn on_niri_reconnected(&mut self, _niri: &NiriIpc) -> Result<()> {
+        self.last_focused_window = None;
+        self.last_execution_time = None;
+        Ok(())
+    }
+
     fn is_interested_in_event(&self, event: &Event) -> bool {
         matches!(
             event,
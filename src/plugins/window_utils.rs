@@ -4,21 +4,61 @@ use niri_ipc::{Action, ColumnDisplay, Reply, Request, WorkspaceReferenceArg};
 use regex::Regex;
 use std::collections::{HashMap, HashSet};
 use std::process::{Command, Stdio};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use tokio::sync::Mutex;
 use tokio::time::Duration;
 
-use crate::config::Direction;
+use crate::config::{CommandLine, CommandShell, Direction, ExcludeSection};
+use crate::ipc::{ErrorKind, IpcError};
 use crate::niri::NiriIpc;
 use crate::niri::Window;
 
-/// Execute a shell command (generic function for all plugins)
-/// This function spawns a command in the background without waiting for completion
-pub fn execute_command(command: &str) -> Result<()> {
-    Command::new("sh")
-        .arg("-c")
-        .arg(command)
-        .stdin(Stdio::null())
+static COMMAND_SHELL: RwLock<CommandShell> = RwLock::new(CommandShell::Sh);
+
+/// Run string commands with the configured `command_shell` from now on
+pub fn set_command_shell(shell: CommandShell) {
+    *COMMAND_SHELL.write().unwrap() = shell;
+}
+
+/// The process for a command: a string through `command_shell`, a list directly
+pub fn command_process(command: &CommandLine) -> Result<Command> {
+    let shell = *COMMAND_SHELL.read().unwrap();
+    let args = match (command, shell) {
+        (CommandLine::Exec(args), _) => args.clone(),
+        (CommandLine::Shell(command), CommandShell::Sh) => {
+            vec!["sh".to_string(), "-c".to_string(), command.clone()]
+        }
+        (CommandLine::Shell(command), CommandShell::Bash) => {
+            vec!["bash".to_string(), "-c".to_string(), command.clone()]
+        }
+        (CommandLine::Shell(command), CommandShell::None) => crate::utils::split_words(command)?,
+    };
+    let (program, args) = args.split_first().ok_or_else(|| anyhow::anyhow!("Empty command"))?;
+    let mut cmd = Command::new(program);
+    cmd.args(args);
+    Ok(cmd)
+}
+
+/// Execute a configured command, a string or a list of arguments (generic function for
+/// all plugins). This function spawns it in the background without waiting for completion
+pub fn execute(command: &CommandLine) -> Result<()> {
+    execute_with(command, &HashMap::new(), None)
+}
+
+/// Execute a command with extra environment variables and an optional working directory
+pub fn execute_with(
+    command: &CommandLine,
+    env: &HashMap<String, String>,
+    cwd: Option<&str>,
+) -> Result<()> {
+    let mut cmd = command_process(command)?;
+    cmd.envs(env);
+    if let Some(cwd) = cwd {
+        let cwd = shellexpand::full(cwd)
+            .with_context(|| format!("Failed to expand working directory: {}", cwd))?;
+        cmd.current_dir(cwd.as_ref());
+    }
+    cmd.stdin(Stdio::null())
         .stdout(Stdio::null())
         .stderr(Stdio::null())
         .spawn()
@@ -27,10 +67,10 @@ pub fn execute_command(command: &str) -> Result<()> {
 }
 
 /// Launch an application by executing a command
-/// This is a convenience wrapper around execute_command
-pub async fn launch_application(command: &str) -> Result<()> {
+/// This is a convenience wrapper around execute
+pub async fn launch_application(command: &CommandLine) -> Result<()> {
     debug!("Launching: {}", command);
-    execute_command(command)
+    execute(command)
 }
 
 /// Focus a window by ID
@@ -54,27 +94,18 @@ pub async fn window_exists(niri: &NiriIpc, window_id: u64) -> Result<bool> {
     Ok(windows.iter().any(|w| w.id == window_id))
 }
 
-/// Wait for a window to appear matching the given pattern
-/// Returns the window if found, or error on timeout
-pub async fn wait_for_window(
+/// Wait for a window matching a WindowMatcher (app_id and/or title patterns) to appear
+pub async fn wait_for_window_matcher(
     niri: NiriIpc,
-    window_match: &str,
+    matcher: &WindowMatcher,
     name: &str,
     max_attempts: u32,
     matcher_cache: &WindowMatcherCache,
 ) -> Result<Option<Window>> {
-    let pattern = if window_match.chars().any(|c| ".+*?[]()".contains(c)) {
-        window_match.to_string()
-    } else {
-        regex::escape(window_match)
-    };
-
-    let matcher = WindowMatcher::new(Some(vec![pattern]), None);
-
     for attempt in 1..=max_attempts {
         tokio::time::sleep(Duration::from_millis(100)).await;
 
-        if let Some(window) = find_window_by_matcher(niri.clone(), &matcher, matcher_cache).await? {
+        if let Some(window) = find_window_by_matcher(niri.clone(), matcher, matcher_cache).await? {
             return Ok(Some(window));
         }
 
@@ -87,7 +118,10 @@ pub async fn wait_for_window(
     }
 
     // Timeout: Log all available windows to help debug matching issues
-    warn!("Timeout waiting for {} (pattern: '{}')", name, window_match);
+    warn!(
+        "Timeout waiting for {} (app_id: {:?}, title: {:?})",
+        name, matcher.app_id, matcher.title
+    );
     if let Ok(windows) = niri.get_windows().await {
         debug!("Available windows at timeout:");
         for window in windows {
@@ -98,11 +132,13 @@ pub async fn wait_for_window(
         }
     }
 
-    anyhow::bail!(
-        "Timeout waiting for window to appear for {} (pattern: '{}')",
-        name,
-        window_match
-    );
+    anyhow::bail!(IpcError::new(
+        ErrorKind::Timeout,
+        format!(
+            "Timeout waiting for window to appear for {} (app_id: {:?}, title: {:?})",
+            name, matcher.app_id, matcher.title
+        )
+    ));
 }
 
 /// Window matcher configuration for matching windows by app_id and/or title
@@ -223,6 +259,26 @@ pub async fn find_window_by_matcher(
     Ok(None)
 }
 
+/// Find all windows matching a WindowMatcher, most recently focused first
+pub async fn find_windows_by_matcher(
+    niri: NiriIpc,
+    matcher: &WindowMatcher,
+    matcher_cache: &WindowMatcherCache,
+) -> Result<Vec<Window>> {
+    let mut matched = Vec::new();
+    for window in niri.get_windows().await? {
+        if matcher_cache
+            .matches(window.app_id.as_ref(), Some(&window.title), matcher)
+            .await?
+        {
+            matched.push(window);
+        }
+    }
+    // Never-focused windows (None) sort last
+    matched.sort_by_key(|w| std::cmp::Reverse(w.focus_timestamp));
+    Ok(matched)
+}
+
 pub async fn get_focused_workspace_from_event(
     niri: &NiriIpc,
     workspace_id: u64,
@@ -275,6 +331,68 @@ pub async fn match_workspace(target_workspace: &str, niri: NiriIpc) -> Result<Op
     Ok(None)
 }
 
+/// Resolve a workspace the way `match_workspace` does (exact name, then idx, preferring
+/// the focused output), falling back to case-insensitive name matches: whole name, then
+/// prefix, then substring. Errors if the best fuzzy match is ambiguous
+pub fn resolve_workspace<'a>(
+    target: &str,
+    workspaces: &'a [niri_ipc::Workspace],
+) -> Result<Option<&'a niri_ipc::Workspace>> {
+    if let Some(workspace) = workspaces.iter().find(|ws| ws.name.as_deref() == Some(target)) {
+        return Ok(Some(workspace));
+    }
+
+    if let Ok(target_idx) = target.parse::<u8>() {
+        let focused_output =
+            workspaces.iter().find(|ws| ws.is_focused).and_then(|ws| ws.output.as_ref());
+        let mut candidates = workspaces.iter().filter(|ws| ws.idx == target_idx);
+        let on_focused_output = candidates.clone().find(|ws| ws.output.as_ref() == focused_output);
+        if let Some(workspace) = on_focused_output.or_else(|| candidates.next()) {
+            return Ok(Some(workspace));
+        }
+    }
+
+    let target = target.to_lowercase();
+    let named: Vec<(&niri_ipc::Workspace, String)> = workspaces
+        .iter()
+        .filter_map(|ws| ws.name.as_ref().map(|name| (ws, name.to_lowercase())))
+        .collect();
+    let passes: [&dyn Fn(&str) -> bool; 3] = [
+        &|name| name == target,
+        &|name| name.starts_with(&target),
+        &|name| name.contains(&target),
+    ];
+    for matches in passes {
+        let found: Vec<&niri_ipc::Workspace> =
+            named.iter().filter(|(_, name)| matches(name)).map(|(ws, _)| *ws).collect();
+        match found.as_slice() {
+            [] => continue,
+            [workspace] => {
+                debug!(
+                    "Fuzzy matched workspace: {} -> {:?}",
+                    target, workspace.name
+                );
+                return Ok(Some(workspace));
+            }
+            _ => {
+                let names: Vec<&str> = found.iter().filter_map(|ws| ws.name.as_deref()).collect();
+                anyhow::bail!("Workspace '{}' is ambiguous: {}", target, names.join(", "));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Check if a workspace is selected by a list of workspace identifiers (name or idx)
+/// An empty list selects every workspace
+pub fn workspace_in_list(workspace: &niri_ipc::Workspace, workspaces: &[String]) -> bool {
+    if workspaces.is_empty() {
+        return true;
+    }
+    let idx = workspace.idx.to_string();
+    workspaces.iter().any(|ws| workspace.name.as_ref() == Some(ws) || *ws == idx)
+}
+
 /// Check if a window is in the current workspace
 pub fn is_window_in_workspace(window: &Window, workspace: &crate::niri::Workspace) -> bool {
     match (&window.workspace, &window.workspace_id) {
@@ -402,6 +520,20 @@ pub async fn move_window_to_position(
     Ok(())
 }
 
+/// Whether a window is in `[piri.exclude]`, i.e. matches any of its app_id or title patterns
+pub async fn is_excluded(
+    exclude: &ExcludeSection,
+    app_id: Option<&String>,
+    title: Option<&String>,
+    matcher_cache: &WindowMatcherCache,
+) -> Result<bool> {
+    if exclude.app_id.is_none() && exclude.title.is_none() {
+        return Ok(false);
+    }
+    let matcher = WindowMatcher::new(exclude.app_id.clone(), exclude.title.clone());
+    matcher_cache.matches(app_id, title, &matcher).await
+}
+
 /// Check if a window matches the given matcher (with optional exclude patterns)
 /// This is a generic window matching function that supports both include and exclude patterns
 pub async fn matches_window(
@@ -637,5 +769,10 @@ pub async fn perform_swallow(
     })
     .await?;
 
+    crate::events::publish(
+        "WindowSwallowed",
+        serde_json::json!({ "window_id": child_window_id, "parent_id": parent_window_id }),
+    );
+    crate::metrics::count("piri_swallows_total", &[]);
     Ok(())
 }
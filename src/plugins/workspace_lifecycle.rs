@@ -0,0 +1,166 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info};
+use niri_ipc::{Action, Event, WorkspaceReferenceArg};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::config::Config;
+use crate::niri::NiriIpc;
+use crate::plugins::FromConfig;
+
+/// Workspace lifecycle plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkspaceLifecyclePluginConfig {
+    /// Names handed out in order before falling back to `format`
+    pub names: Vec<String>,
+    /// Name format with an {n} placeholder
+    pub format: String,
+    /// Unname empty workspaces so niri removes them
+    pub remove_empty: bool,
+    /// Outputs to manage (None: all)
+    pub outputs: Option<Vec<String>>,
+}
+
+impl FromConfig for WorkspaceLifecyclePluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        let section = &config.piri.workspace_lifecycle;
+        Some(Self {
+            names: section.names.clone(),
+            format: section.format.clone(),
+            remove_empty: section.remove_empty,
+            outputs: section.outputs.clone(),
+        })
+    }
+}
+
+/// Gives every workspace that gets a window a name, and unnames workspaces again once
+/// they are empty. niri keeps an unnamed empty workspace at the end of each output and
+/// removes empty unnamed workspaces, so together this keeps "named while in use, one
+/// empty at the end".
+pub struct WorkspaceLifecyclePlugin {
+    niri: NiriIpc,
+    config: WorkspaceLifecyclePluginConfig,
+    /// Workspace of every window
+    windows: HashMap<u64, Option<u64>>,
+    /// Names set by this plugin; workspaces named any other way are left alone
+    managed: HashMap<u64, String>,
+}
+
+impl WorkspaceLifecyclePlugin {
+    fn next_name(&self, taken: &HashSet<String>) -> String {
+        if let Some(name) = self.config.names.iter().find(|name| !taken.contains(*name)) {
+            return name.clone();
+        }
+        (1..)
+            .map(|n| self.config.format.replace("{n}", &n.to_string()))
+            .find(|name| !taken.contains(name))
+            .unwrap_or_default()
+    }
+
+    async fn reconcile(&mut self) -> Result<()> {
+        let workspaces = self.niri.get_workspaces().await?;
+        self.managed.retain(|id, _| workspaces.iter().any(|ws| ws.id == *id));
+
+        let occupied: HashSet<u64> = self.windows.values().flatten().copied().collect();
+        let mut taken: HashSet<String> =
+            workspaces.iter().filter_map(|ws| ws.name.clone()).collect();
+
+        let mut actions = Vec::new();
+        for workspace in &workspaces {
+            if let Some(ref outputs) = self.config.outputs {
+                if !workspace.output.as_ref().is_some_and(|o| outputs.contains(o)) {
+                    continue;
+                }
+            }
+            let reference = Some(WorkspaceReferenceArg::Id(workspace.id));
+
+            match (&workspace.name, occupied.contains(&workspace.id)) {
+                (None, true) => {
+                    let name = self.next_name(&taken);
+                    debug!("Naming workspace {} '{}'", workspace.id, name);
+                    taken.insert(name.clone());
+                    self.managed.insert(workspace.id, name.clone());
+                    actions.push(Action::SetWorkspaceName {
+                        name,
+                        workspace: reference,
+                    });
+                }
+                // Wait until the user leaves the workspace, niri keeps active ones anyway
+                (Some(name), false)
+                    if self.config.remove_empty
+                        && !workspace.is_active
+                        && self.managed.get(&workspace.id) == Some(name) =>
+                {
+                    debug!("Unnaming empty workspace {} '{}'", workspace.id, name);
+                    self.managed.remove(&workspace.id);
+                    actions.push(Action::UnsetWorkspaceName { reference });
+                }
+                _ => {}
+            }
+        }
+
+        for action in actions {
+            self.niri.send_action(action).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for WorkspaceLifecyclePlugin {
+    type Config = WorkspaceLifecyclePluginConfig;
+
+    fn new(niri: NiriIpc, config: WorkspaceLifecyclePluginConfig) -> Self {
+        info!("Workspace lifecycle plugin initialized");
+        Self {
+            niri,
+            config,
+            windows: HashMap::new(),
+            managed: HashMap::new(),
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        match event {
+            Event::WindowsChanged { windows } => {
+                self.windows = windows.iter().map(|w| (w.id, w.workspace_id)).collect();
+            }
+            Event::WindowOpenedOrChanged { window } => {
+                // Only moves between workspaces matter
+                let previous = self.windows.insert(window.id, window.workspace_id);
+                if previous == Some(window.workspace_id) {
+                    return Ok(());
+                }
+            }
+            Event::WindowClosed { id } => {
+                self.windows.remove(id);
+            }
+            _ => {}
+        }
+        self.reconcile().await
+    }
+
+    async fn on_niri_reconnected(&mut self, _niri: &NiriIpc) -> Result<()> {
+        self.windows.clear();
+        self.managed.clear();
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(
+            event,
+            Event::WindowsChanged { .. }
+                | Event::WindowOpenedOrChanged { .. }
+                | Event::WindowClosed { .. }
+                | Event::WorkspacesChanged { .. }
+                | Event::WorkspaceActivated { .. }
+        )
+    }
+
+    async fn update_config(&mut self, config: WorkspaceLifecyclePluginConfig) -> Result<()> {
+        info!("Updating workspace lifecycle plugin configuration");
+        self.config = config;
+        self.reconcile().await
+    }
+}
@@ -0,0 +1,270 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use niri_ipc::{Action, Event, WorkspaceReferenceArg};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::config::Config;
+use crate::niri::NiriIpc;
+use crate::plugins::FromConfig;
+
+/// Workspace names plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkspaceNamesPluginConfig {
+    /// app_id (exact or regex) -> label or icon
+    pub labels: HashMap<String, String>,
+    /// Name format with {idx} and {apps} placeholders
+    pub format: String,
+    /// Maximum number of apps in a name
+    pub max_apps: usize,
+    /// Separator between app labels
+    pub separator: String,
+}
+
+impl FromConfig for WorkspaceNamesPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        let section = &config.piri.workspace_names;
+        Some(Self {
+            labels: section.labels.clone(),
+            format: section.format.clone(),
+            max_apps: section.max_apps,
+            separator: section.separator.clone(),
+        })
+    }
+}
+
+/// Labels compiled from the config: exact app_id matches take precedence over
+/// patterns, which are tried in alphabetical order
+struct LabelMap {
+    exact: HashMap<String, String>,
+    patterns: Vec<(Regex, String)>,
+}
+
+impl LabelMap {
+    fn new(labels: &HashMap<String, String>) -> Self {
+        let mut keys: Vec<&String> = labels.keys().collect();
+        keys.sort();
+        let patterns = keys
+            .into_iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some((regex, labels[pattern].clone())),
+                Err(e) => {
+                    warn!("Invalid workspace label pattern '{}': {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+        Self {
+            exact: labels.clone(),
+            patterns,
+        }
+    }
+
+    /// Label of an app, falling back to the app_id itself
+    fn label(&self, app_id: &str) -> String {
+        if let Some(label) = self.exact.get(app_id) {
+            return label.clone();
+        }
+        self.patterns
+            .iter()
+            .find(|(regex, _)| regex.is_match(app_id))
+            .map(|(_, label)| label.clone())
+            .unwrap_or_else(|| app_id.to_string())
+    }
+}
+
+/// What a window contributes to its workspace name
+#[derive(Debug, Clone, PartialEq)]
+struct WindowInfo {
+    app_id: Option<String>,
+    workspace_id: Option<u64>,
+    /// Column in the scrolling layout, used to break ties between apps
+    column: Option<usize>,
+}
+
+impl WindowInfo {
+    fn from_niri(window: &niri_ipc::Window) -> Self {
+        Self {
+            app_id: window.app_id.clone(),
+            workspace_id: window.workspace_id,
+            column: window.layout.pos_in_scrolling_layout.map(|(column, _)| column),
+        }
+    }
+}
+
+pub struct WorkspaceNamesPlugin {
+    niri: NiriIpc,
+    config: WorkspaceNamesPluginConfig,
+    labels: LabelMap,
+    windows: HashMap<u64, WindowInfo>,
+    /// Names set by this plugin; workspaces named any other way are left alone
+    managed: HashMap<u64, String>,
+}
+
+impl WorkspaceNamesPlugin {
+    /// Name for a workspace from the apps on it, None if it's empty
+    fn workspace_name(&self, workspace_id: u64, idx: u8) -> Option<String> {
+        // label -> (window count, leftmost column)
+        let mut apps: HashMap<String, (usize, usize)> = HashMap::new();
+        for window in self.windows.values() {
+            let Some(ref app_id) = window.app_id else {
+                continue;
+            };
+            if window.workspace_id != Some(workspace_id) {
+                continue;
+            }
+            let column = window.column.unwrap_or(usize::MAX);
+            let entry = apps.entry(self.labels.label(app_id)).or_insert((0, column));
+            entry.0 += 1;
+            entry.1 = entry.1.min(column);
+        }
+        if apps.is_empty() {
+            return None;
+        }
+
+        // Most windows first, then leftmost
+        let mut apps: Vec<(String, (usize, usize))> = apps.into_iter().collect();
+        apps.sort_by(
+            |(a_label, (a_count, a_column)), (b_label, (b_count, b_column))| {
+                b_count.cmp(a_count).then(a_column.cmp(b_column)).then(a_label.cmp(b_label))
+            },
+        );
+        let labels: Vec<String> = apps
+            .into_iter()
+            .take(self.config.max_apps.max(1))
+            .map(|(label, _)| label)
+            .collect();
+
+        Some(
+            self.config
+                .format
+                .replace("{idx}", &idx.to_string())
+                .replace("{apps}", &labels.join(&self.config.separator)),
+        )
+    }
+
+    /// Bring the names of all workspaces in line with their windows
+    async fn refresh(&mut self) -> Result<()> {
+        let workspaces = self.niri.get_workspaces().await?;
+        self.managed.retain(|id, _| workspaces.iter().any(|ws| ws.id == *id));
+
+        // niri moves a name to the last workspace it was set on, so names must be unique
+        let mut taken: HashSet<String> = workspaces
+            .iter()
+            .filter(|ws| ws.name.is_some() && ws.name.as_ref() != self.managed.get(&ws.id))
+            .filter_map(|ws| ws.name.clone())
+            .collect();
+
+        let mut actions = Vec::new();
+        for workspace in &workspaces {
+            if workspace.name.is_some()
+                && workspace.name.as_ref() != self.managed.get(&workspace.id)
+            {
+                continue;
+            }
+
+            let desired = self.workspace_name(workspace.id, workspace.idx).map(|name| {
+                let mut unique = name.clone();
+                let mut n = 2;
+                while taken.contains(&unique) {
+                    unique = format!("{} {}", name, n);
+                    n += 1;
+                }
+                unique
+            });
+            if let Some(ref name) = desired {
+                taken.insert(name.clone());
+            }
+            if desired == workspace.name {
+                continue;
+            }
+
+            let reference = Some(WorkspaceReferenceArg::Id(workspace.id));
+            match desired {
+                Some(name) => {
+                    debug!("Renaming workspace {} to '{}'", workspace.id, name);
+                    self.managed.insert(workspace.id, name.clone());
+                    actions.push(Action::SetWorkspaceName {
+                        name,
+                        workspace: reference,
+                    });
+                }
+                None => {
+                    // Named workspaces aren't removed by niri when empty, so drop the name
+                    debug!("Unnaming empty workspace {}", workspace.id);
+                    self.managed.remove(&workspace.id);
+                    actions.push(Action::UnsetWorkspaceName { reference });
+                }
+            }
+        }
+
+        for action in actions {
+            self.niri.send_action(action).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for WorkspaceNamesPlugin {
+    type Config = WorkspaceNamesPluginConfig;
+
+    fn new(niri: NiriIpc, config: WorkspaceNamesPluginConfig) -> Self {
+        info!(
+            "Workspace names plugin initialized with {} labels",
+            config.labels.len()
+        );
+        Self {
+            niri,
+            labels: LabelMap::new(&config.labels),
+            config,
+            windows: HashMap::new(),
+            managed: HashMap::new(),
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        match event {
+            Event::WindowsChanged { windows } => {
+                self.windows = windows.iter().map(|w| (w.id, WindowInfo::from_niri(w))).collect();
+            }
+            Event::WindowOpenedOrChanged { window } => {
+                // Title changes don't affect names
+                let info = WindowInfo::from_niri(window);
+                if self.windows.insert(window.id, info.clone()) == Some(info) {
+                    return Ok(());
+                }
+            }
+            Event::WindowClosed { id } => {
+                self.windows.remove(id);
+            }
+            _ => {}
+        }
+        self.refresh().await
+    }
+
+    async fn on_niri_reconnected(&mut self, _niri: &NiriIpc) -> Result<()> {
+        self.windows.clear();
+        self.managed.clear();
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(
+            event,
+            Event::WindowsChanged { .. }
+                | Event::WindowOpenedOrChanged { .. }
+                | Event::WindowClosed { .. }
+                | Event::WorkspacesChanged { .. }
+        )
+    }
+
+    async fn update_config(&mut self, config: WorkspaceNamesPluginConfig) -> Result<()> {
+        info!("Updating workspace names plugin configuration");
+        self.labels = LabelMap::new(&config.labels);
+        self.config = config;
+        self.refresh().await
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use niri_ipc::{Event, Workspace};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::config::{CommandLine, Config, Timespan, WorkspaceOsdOutput};
+use crate::niri::NiriIpc;
+use crate::plugins::{window_utils, FromConfig};
+use crate::utils::shell_quote;
+
+/// Notifications with this tag replace each other instead of stacking up
+const NOTIFICATION_TAG: &str = "string:x-canonical-private-synchronous:piri-workspace-osd";
+
+/// Workspace OSD plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkspaceOsdPluginConfig {
+    /// Notification text ({name}, {idx}, {output}, {windows} placeholders)
+    pub format: String,
+    /// Command run instead of a notification
+    pub command: Option<CommandLine>,
+    /// How long the notification shows
+    pub timeout: Timespan,
+    /// Per-output overrides
+    pub outputs: HashMap<String, WorkspaceOsdOutput>,
+}
+
+impl FromConfig for WorkspaceOsdPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        let section = &config.piri.workspace_osd;
+        Some(Self {
+            format: section.format.clone(),
+            command: section.command.clone(),
+            timeout: section.timeout,
+            outputs: section.outputs.clone(),
+        })
+    }
+}
+
+/// Shows a notification (or runs a command) with the workspace name and window count
+/// whenever a workspace is activated, since niri has no workspace indicator of its own.
+pub struct WorkspaceOsdPlugin {
+    config: WorkspaceOsdPluginConfig,
+    workspaces: HashMap<u64, Workspace>,
+    /// Window ID -> workspace ID
+    windows: HashMap<u64, Option<u64>>,
+}
+
+impl WorkspaceOsdPlugin {
+    fn expand(template: &str, values: &[(&str, String)], quote: bool) -> String {
+        values.iter().fold(template.to_string(), |text, (key, value)| {
+            let value = if quote {
+                shell_quote(value)
+            } else {
+                value.clone()
+            };
+            text.replace(&format!("{{{}}}", key), &value)
+        })
+    }
+
+    fn show(&self, workspace_id: u64) {
+        let Some(workspace) = self.workspaces.get(&workspace_id) else {
+            return;
+        };
+        let output = workspace.output.clone().unwrap_or_default();
+        let overrides = self.config.outputs.get(&output);
+        if overrides.is_some_and(|o| !o.enabled) {
+            return;
+        }
+
+        let windows = self.windows.values().filter(|&&ws| ws == Some(workspace_id)).count();
+        let values = [
+            (
+                "name",
+                workspace.name.clone().unwrap_or_else(|| workspace.idx.to_string()),
+            ),
+            ("idx", workspace.idx.to_string()),
+            ("output", output.clone()),
+            ("windows", windows.to_string()),
+        ];
+
+        let command = overrides.and_then(|o| o.command.as_ref()).or(self.config.command.as_ref());
+        if let Some(command) = command {
+            let command = command.expand(|text, quote| Self::expand(text, &values, quote));
+            debug!("Running workspace OSD command: {}", command);
+            if let Err(e) = window_utils::execute(&command) {
+                warn!("Failed to run workspace OSD command: {}", e);
+            }
+            return;
+        }
+
+        let format = overrides.and_then(|o| o.format.as_ref()).unwrap_or(&self.config.format);
+        let text = Self::expand(format, &values, false);
+        debug!("Showing workspace OSD: {}", text);
+        if let Err(e) = std::process::Command::new("notify-send")
+            .args(["-a", "piri", "-h", NOTIFICATION_TAG, "-t"])
+            .arg(self.config.timeout.0.as_millis().to_string())
+            .arg(text)
+            .spawn()
+        {
+            warn!("Failed to show workspace OSD: {}", e);
+        }
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for WorkspaceOsdPlugin {
+    type Config = WorkspaceOsdPluginConfig;
+
+    fn new(_niri: NiriIpc, config: WorkspaceOsdPluginConfig) -> Self {
+        info!("Workspace OSD plugin initialized");
+        Self {
+            config,
+            workspaces: HashMap::new(),
+            windows: HashMap::new(),
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        match event {
+            Event::WorkspacesChanged { workspaces } => {
+                self.workspaces = workspaces.iter().map(|ws| (ws.id, ws.clone())).collect();
+            }
+            Event::WindowsChanged { windows } => {
+                self.windows = windows.iter().map(|w| (w.id, w.workspace_id)).collect();
+            }
+            Event::WindowOpenedOrChanged { window } => {
+                self.windows.insert(window.id, window.workspace_id);
+            }
+            Event::WindowClosed { id } => {
+                self.windows.remove(id);
+            }
+            Event::WorkspaceActivated { id, .. } => self.show(*id),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(
+            event,
+            Event::WorkspacesChanged { .. }
+                | Event::WindowsChanged { .. }
+                | Event::WindowOpenedOrChanged { .. }
+                | Event::WindowClosed { .. }
+                | Event::WorkspaceActivated { .. }
+        )
+    }
+
+    async fn update_config(&mut self, config: WorkspaceOsdPluginConfig) -> Result<()> {
+        info!("Updating workspace OSD plugin configuration");
+        self.config = config;
+        Ok(())
+    }
+}
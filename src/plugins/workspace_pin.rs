@@ -0,0 +1,190 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, info};
+use niri_ipc::{Action, Event, Workspace, WorkspaceReferenceArg};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::config::Config;
+use crate::ipc::{IpcError, IpcRequest};
+use crate::niri::NiriIpc;
+use crate::plugins::FromConfig;
+
+/// Workspace pin plugin config (for internal use)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkspacePinPluginConfig {
+    /// Workspace name -> outputs, in order of preference
+    pub pins: HashMap<String, Vec<String>>,
+}
+
+impl FromConfig for WorkspacePinPluginConfig {
+    fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            pins: config.workspace_pin.clone(),
+        })
+    }
+}
+
+/// Keeps named workspaces on the outputs they are pinned to, moving them back whenever
+/// niri puts them somewhere else (e.g. after an output was reconnected).
+pub struct WorkspacePinPlugin {
+    niri: NiriIpc,
+    config: WorkspacePinPluginConfig,
+    /// Pinned workspaces that are left wherever they are for now
+    overrides: BTreeSet<String>,
+}
+
+impl WorkspacePinPlugin {
+    /// Move every pinned workspace that isn't on its output back to it
+    async fn enforce(&self, workspaces: &[Workspace]) -> Result<()> {
+        let misplaced: Vec<(&Workspace, &Vec<String>)> = workspaces
+            .iter()
+            .filter_map(|ws| {
+                let name = ws.name.as_ref()?;
+                if self.overrides.contains(name) {
+                    return None;
+                }
+                let outputs = self.config.pins.get(name)?;
+                // Already on its first choice, no need to ask niri for outputs
+                if outputs.first() == ws.output.as_ref() {
+                    return None;
+                }
+                Some((ws, outputs))
+            })
+            .collect();
+        if misplaced.is_empty() {
+            return Ok(());
+        }
+
+        let connected: Vec<String> = self
+            .niri
+            .get_outputs()
+            .await?
+            .into_iter()
+            .filter(|output| output.logical.is_some())
+            .map(|output| output.name)
+            .collect();
+        for (workspace, outputs) in misplaced {
+            let Some(target) = outputs.iter().find(|o| connected.contains(o)) else {
+                debug!(
+                    "No output of workspace {:?} is connected, leaving it on {:?}",
+                    workspace.name, workspace.output
+                );
+                continue;
+            };
+            if workspace.output.as_ref() == Some(target) {
+                continue;
+            }
+            info!(
+                "Moving pinned workspace {:?} from {:?} back to {}",
+                workspace.name, workspace.output, target
+            );
+            self.move_workspace(workspace.id, target).await?;
+        }
+        Ok(())
+    }
+
+    async fn move_workspace(&self, workspace_id: u64, output: &str) -> Result<()> {
+        self.niri
+            .send_action(Action::MoveWorkspaceToMonitor {
+                output: output.to_string(),
+                reference: Some(WorkspaceReferenceArg::Id(workspace_id)),
+            })
+            .await
+    }
+
+    /// Stop enforcing a workspace's pin, optionally moving it to another output right away
+    async fn set_override(&mut self, name: &str, output: Option<&str>) -> Result<()> {
+        if !self.config.pins.contains_key(name) {
+            anyhow::bail!("Workspace '{}' is not pinned", name);
+        }
+        info!("Overriding pin of workspace '{}'", name);
+        self.overrides.insert(name.to_string());
+
+        if let Some(output) = output {
+            let workspace = self
+                .niri
+                .get_workspaces()
+                .await?
+                .into_iter()
+                .find(|ws| ws.name.as_deref() == Some(name))
+                .ok_or_else(|| IpcError::not_found(format!("Workspace '{}' not found", name)))?;
+            self.move_workspace(workspace.id, output).await?;
+        }
+        Ok(())
+    }
+
+    /// Enforce a workspace's pin again (None: all overridden workspaces)
+    async fn restore(&mut self, name: Option<&str>) -> Result<()> {
+        match name {
+            Some(name) => {
+                if !self.overrides.remove(name) {
+                    anyhow::bail!("Workspace '{}' has no pin override", name);
+                }
+                info!("Restoring pin of workspace '{}'", name);
+            }
+            None => {
+                info!("Restoring pins of {} workspaces", self.overrides.len());
+                self.overrides.clear();
+            }
+        }
+        let workspaces = self.niri.get_workspaces().await?;
+        self.enforce(&workspaces).await
+    }
+}
+
+#[async_trait]
+impl crate::plugins::Plugin for WorkspacePinPlugin {
+    type Config = WorkspacePinPluginConfig;
+
+    fn new(niri: NiriIpc, config: WorkspacePinPluginConfig) -> Self {
+        info!(
+            "Workspace pin plugin initialized with {} pinned workspaces",
+            config.pins.len()
+        );
+        Self {
+            niri,
+            config,
+            overrides: BTreeSet::new(),
+        }
+    }
+
+    async fn handle_event(&mut self, event: &Event, _niri: &NiriIpc) -> Result<()> {
+        // Outputs being connected or disconnected always changes workspaces as well
+        if let Event::WorkspacesChanged { workspaces } = event {
+            self.enforce(workspaces).await?;
+        }
+        Ok(())
+    }
+
+    fn is_interested_in_event(&self, event: &Event) -> bool {
+        matches!(event, Event::WorkspacesChanged { .. })
+    }
+
+    async fn handle_ipc_request(&mut self, request: &IpcRequest) -> Result<Option<Result<()>>> {
+        match request {
+            IpcRequest::WorkspacePinOverride { workspace, output } => {
+                Ok(Some(self.set_override(workspace, output.as_deref()).await))
+            }
+            IpcRequest::WorkspacePinRestore { workspace } => {
+                Ok(Some(self.restore(workspace.as_deref()).await))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn status(&mut self) -> Result<Option<serde_json::Value>> {
+        Ok(Some(serde_json::json!({
+            "overrides": self.overrides,
+        })))
+    }
+
+    async fn update_config(&mut self, config: WorkspacePinPluginConfig) -> Result<()> {
+        info!("Updating workspace pin plugin configuration");
+        // Keep overrides across reloads, except for workspaces that aren't pinned anymore
+        self.overrides.retain(|name| config.pins.contains_key(name));
+        self.config = config;
+        let workspaces = self.niri.get_workspaces().await?;
+        self.enforce(&workspaces).await
+    }
+}
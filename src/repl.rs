@@ -0,0 +1,197 @@
+use anyhow::Result;
+use clap::error::ErrorKind;
+use clap::{Command, Parser};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use crate::ipc::{IpcClient, IpcResponse, IpcSession};
+use crate::{cli_command, line_request, utils, Cli};
+
+/// File in the state directory keeping the lines entered in earlier sessions
+const HISTORY_FILE: &str = "repl_history";
+
+/// Completes subcommands, long options and the values arguments accept
+struct ReplHelper {
+    cmd: Command,
+}
+
+impl ReplHelper {
+    fn candidates(&self, words: &[&str], partial: &str) -> Vec<String> {
+        // Follow the subcommands typed so far, skipping their arguments
+        let mut cmd = &self.cmd;
+        for word in words {
+            if let Some(sub) = cmd.find_subcommand(word) {
+                cmd = sub;
+            }
+        }
+
+        let mut candidates: Vec<String> = if partial.starts_with('-') {
+            cmd.get_arguments()
+                .filter(|arg| !arg.is_hide_set())
+                .filter_map(|arg| arg.get_long())
+                .map(|long| format!("--{}", long))
+                .collect()
+        } else {
+            let mut names: Vec<String> =
+                cmd.get_subcommands().map(|sub| sub.get_name().to_string()).collect();
+            names.extend(
+                cmd.get_positionals()
+                    .flat_map(|arg| arg.get_possible_values())
+                    .map(|value| value.get_name().to_string()),
+            );
+            if words.is_empty() {
+                names.extend(["exit".to_string(), "quit".to_string()]);
+            }
+            names
+        };
+        candidates.retain(|candidate| candidate.starts_with(partial));
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let words: Vec<&str> = line[..start].split_whitespace().collect();
+        Ok((start, self.candidates(&words, &line[start..])))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// Read commands line by line and send them over one connection, which is opened again
+/// if the daemon went away in between
+pub async fn run(client: &IpcClient, config: &str, json: bool) -> Result<()> {
+    let mut editor: Editor<ReplHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(ReplHelper {
+        cmd: cli_command(config),
+    }));
+    let history = utils::state_dir().join(HISTORY_FILE);
+    let _ = editor.load_history(&history);
+
+    let mut session: Option<IpcSession> = None;
+    loop {
+        let line = match tokio::task::block_in_place(|| editor.readline("piri> ")) {
+            Ok(line) => line,
+            // Ctrl-C only clears the line
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let _ = editor.add_history_entry(line);
+        if matches!(line, "exit" | "quit") {
+            break;
+        }
+        if show_help(line) {
+            continue;
+        }
+
+        let request = match line_request(line, config) {
+            Ok(request) => request,
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                continue;
+            }
+        };
+        if session.is_none() {
+            match client.session().await {
+                Ok(opened) => session = Some(opened),
+                Err(e) => {
+                    eprintln!("Error: {:#}", e);
+                    continue;
+                }
+            }
+        }
+        let Some(current) = session.as_mut() else {
+            continue;
+        };
+        match current.send_request(&request).await {
+            Ok(response) => print_response(response, json),
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                session = None;
+            }
+        }
+    }
+
+    if let Some(parent) = history.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Err(e) = editor.save_history(&history) {
+        log::warn!("Failed to save REPL history to {:?}: {}", history, e);
+    }
+    Ok(())
+}
+
+/// Print clap's help for `help`, `--help` and the like; false for any other line
+fn show_help(line: &str) -> bool {
+    let Ok(words) = utils::split_words(line) else {
+        return false;
+    };
+    match Cli::try_parse_from(std::iter::once("piri".to_string()).chain(words)) {
+        Err(e)
+            if matches!(
+                e.kind(),
+                ErrorKind::DisplayHelp
+                    | ErrorKind::DisplayVersion
+                    | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+            ) =>
+        {
+            let _ = e.print();
+            true
+        }
+        _ => false,
+    }
+}
+
+fn print_response(response: IpcResponse, json: bool) {
+    match response {
+        IpcResponse::Data(data) if json => println!("{}", data),
+        IpcResponse::Data(data) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&data).unwrap_or_default()
+            )
+        }
+        IpcResponse::Error(e) if json => println!(
+            "{}",
+            serde_json::json!({
+                "success": false,
+                "error": e.message,
+                "kind": e.kind,
+                "trace_id": e.trace_id,
+            })
+        ),
+        IpcResponse::Error(e) => eprintln!("Error: {}", e),
+        IpcResponse::Pong(info) => println!("{}", serde_json::to_value(info).unwrap_or_default()),
+        IpcResponse::Success if json => println!("{}", serde_json::json!({ "success": true })),
+        IpcResponse::Success => {}
+    }
+}
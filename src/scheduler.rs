@@ -0,0 +1,250 @@
+use anyhow::{Context, Result};
+use std::str::FromStr;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+
+/// Broken-down local time, down to the minute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+    pub minute: u32,
+    pub hour: u32,
+    /// Day of the month, 1-31
+    pub day: u32,
+    /// Month, 1-12
+    pub month: u32,
+    /// Day of the week, 0-6 starting on Sunday
+    pub weekday: u32,
+}
+
+impl LocalTime {
+    pub fn now() -> Self {
+        // localtime_r instead of localtime: the result isn't shared with other threads
+        let tm = unsafe {
+            let now = libc::time(std::ptr::null_mut());
+            let mut tm: libc::tm = std::mem::zeroed();
+            libc::localtime_r(&now, &mut tm);
+            tm
+        };
+        Self {
+            minute: tm.tm_min as u32,
+            hour: tm.tm_hour as u32,
+            day: tm.tm_mday as u32,
+            month: tm.tm_mon as u32 + 1,
+            weekday: tm.tm_wday as u32,
+        }
+    }
+}
+
+/// A cron expression: "minute hour day-of-month month day-of-week", each field being
+/// `*`, a value, a range (`1-5`), a step (`*/15`, `0-30/10`) or a comma-separated list
+/// of those. As in cron, if both day fields are restricted either one matching is enough
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+    /// One bit per allowed value of each field
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl CronExpr {
+    /// Parse one field into a bitmask of values within `min..=max`
+    fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+        let mut mask = 0u64;
+        for part in field.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (
+                    range,
+                    step.parse::<u32>()
+                        .ok()
+                        .filter(|&s| s > 0)
+                        .with_context(|| format!("Invalid step in '{}'", part))?,
+                ),
+                None => (part, 1),
+            };
+            let (start, end) = if range == "*" {
+                (min, max)
+            } else if let Some((start, end)) = range.split_once('-') {
+                (
+                    start.parse().with_context(|| format!("Invalid value in '{}'", part))?,
+                    end.parse().with_context(|| format!("Invalid value in '{}'", part))?,
+                )
+            } else {
+                let value =
+                    range.parse().with_context(|| format!("Invalid value in '{}'", part))?;
+                // "5/10" means from 5 to the end in steps of 10
+                (value, if step > 1 { max } else { value })
+            };
+            if start < min || end > max || start > end {
+                anyhow::bail!("'{}' is out of range {}-{}", part, min, max);
+            }
+            for value in (start..=end).step_by(step as usize) {
+                mask |= 1 << value;
+            }
+        }
+        Ok(mask)
+    }
+
+    pub fn matches(&self, time: &LocalTime) -> bool {
+        let has = |mask: u64, value: u32| mask & (1 << value) != 0;
+        let day = has(self.days, time.day);
+        let weekday = has(self.weekdays, time.weekday);
+        let day_matches = match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            _ => day && weekday,
+        };
+        has(self.minutes, time.minute)
+            && has(self.hours, time.hour)
+            && has(self.months, time.month)
+            && day_matches
+    }
+}
+
+impl FromStr for CronExpr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            anyhow::bail!(
+                "Cron expression must have 5 fields (minute hour day month weekday), got: {}",
+                s
+            );
+        };
+        let mut weekdays = Self::parse_field(weekday, 0, 7)?;
+        // Both 0 and 7 are Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            minutes: Self::parse_field(minute, 0, 59)?,
+            hours: Self::parse_field(hour, 0, 23)?,
+            days: Self::parse_field(day, 1, 31)?,
+            months: Self::parse_field(month, 1, 12)?,
+            weekdays,
+            days_restricted: !day.starts_with('*'),
+            weekdays_restricted: !weekday.starts_with('*'),
+        })
+    }
+}
+
+/// Call `tick` at the start of every minute with the local time, until the returned
+/// task is aborted. Minutes passed while the machine was suspended are skipped
+pub fn spawn_minutely<F>(mut tick: F) -> JoinHandle<()>
+where
+    F: FnMut(LocalTime) + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut last = None;
+        loop {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default();
+            // Wake up just past the minute boundary
+            let wait = 60_000 - (now.as_millis() % 60_000) as u64 + 50;
+            tokio::time::sleep(Duration::from_millis(wait)).await;
+
+            let time = LocalTime::now();
+            // Sleeping can end early after a clock change; never tick a minute twice
+            if last == Some(time) {
+                continue;
+            }
+            last = Some(time);
+            tick(time);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(minute: u32, hour: u32, day: u32, month: u32, weekday: u32) -> LocalTime {
+        LocalTime {
+            minute,
+            hour,
+            day,
+            month,
+            weekday,
+        }
+    }
+
+    fn cron(expr: &str) -> CronExpr {
+        expr.parse().unwrap()
+    }
+
+    #[test]
+    fn steps() {
+        let expr = cron("*/15 * * * *");
+        for minute in [0, 15, 30, 45] {
+            assert!(expr.matches(&at(minute, 3, 1, 1, 0)));
+        }
+        assert!(!expr.matches(&at(10, 3, 1, 1, 0)));
+        let expr = cron("0-30/10 5/6 * * *");
+        assert!(expr.matches(&at(20, 11, 1, 1, 0)));
+        assert!(!expr.matches(&at(40, 11, 1, 1, 0)));
+        assert!(!expr.matches(&at(20, 12, 1, 1, 0)));
+    }
+
+    #[test]
+    fn ranges_and_lists() {
+        let expr = cron("0,30 9 * * 1-5");
+        assert!(expr.matches(&at(0, 9, 14, 10, 1)));
+        assert!(expr.matches(&at(30, 9, 18, 10, 5)));
+        assert!(!expr.matches(&at(15, 9, 14, 10, 1)));
+        assert!(!expr.matches(&at(0, 9, 19, 10, 6)));
+        assert!(!expr.matches(&at(0, 10, 14, 10, 1)));
+        let expr = cron("0 0 1,15-16 1-3,12 *");
+        assert!(expr.matches(&at(0, 0, 16, 12, 3)));
+        assert!(!expr.matches(&at(0, 0, 16, 6, 3)));
+        assert!(!expr.matches(&at(0, 0, 2, 1, 3)));
+    }
+
+    #[test]
+    fn sunday_is_0_or_7() {
+        for expr in ["0 12 * * 0", "0 12 * * 7", "0 12 * * 6-7"] {
+            let expr = cron(expr);
+            assert!(expr.matches(&at(0, 12, 5, 1, 0)));
+            assert!(!expr.matches(&at(0, 12, 6, 1, 1)));
+        }
+    }
+
+    #[test]
+    fn either_day_field_matches_when_both_are_restricted() {
+        let expr = cron("0 0 1 * 1");
+        assert!(expr.matches(&at(0, 0, 1, 5, 3)));
+        assert!(expr.matches(&at(0, 0, 12, 5, 1)));
+        assert!(!expr.matches(&at(0, 0, 12, 5, 3)));
+        let expr = cron("0 0 1 * *");
+        assert!(!expr.matches(&at(0, 0, 12, 5, 1)));
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        for expr in [
+            "",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "5-1 * * * *",
+            "*/0 * * * *",
+            "*/x * * * *",
+            "a * * * *",
+            "1,,2 * * * *",
+            "1- * * * *",
+        ] {
+            assert!(
+                expr.parse::<CronExpr>().is_err(),
+                "{:?} should be rejected",
+                expr
+            );
+        }
+    }
+}
@@ -0,0 +1,389 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::niri::Window;
+use crate::plugins::window_order::WindowOrderPlugin;
+use crate::plugins::window_utils::{self, WindowMatcher, WindowMatcherCache};
+use crate::utils;
+
+/// Keys a simulated window can be described with
+const KEYS: &[&str] = &[
+    "app_id",
+    "title",
+    "parent",
+    "parent_title",
+    "workspace",
+    "output",
+];
+
+/// The niri event being simulated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EventKind {
+    Open,
+    Focus,
+}
+
+/// A synthetic event, parsed from `open app_id=mpv parent=foot` and the like
+pub struct Simulation {
+    kind: EventKind,
+    window: Window,
+    /// The window focused before, which swallow rules take as the parent
+    parent: Option<Window>,
+    output: Option<String>,
+}
+
+/// What a plugin would do with the simulated window
+#[derive(Debug, Clone, Serialize)]
+pub struct Outcome {
+    pub plugin: &'static str,
+    /// Position of the rule in the config file, counting from 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<usize>,
+    pub message: String,
+    /// Whether the plugin would act, as opposed to a note on why it wouldn't
+    pub fires: bool,
+}
+
+impl Outcome {
+    fn fires(plugin: &'static str, rule: Option<usize>, message: String) -> Self {
+        Self {
+            plugin,
+            rule,
+            message,
+            fires: true,
+        }
+    }
+
+    fn note(plugin: &'static str, rule: Option<usize>, message: String) -> Self {
+        Self {
+            plugin,
+            rule,
+            message,
+            fires: false,
+        }
+    }
+}
+
+fn window(app_id: Option<String>, title: Option<String>, workspace: Option<String>) -> Window {
+    Window {
+        id: 0,
+        title: title.unwrap_or_default(),
+        app_id,
+        class: None,
+        floating: false,
+        workspace_id: None,
+        workspace,
+        output: None,
+        layout: None,
+        pid: None,
+        focus_timestamp: None,
+    }
+}
+
+/// `app_id="mpv" title="..."`, leaving out what wasn't given
+fn describe_window(window: &Window) -> String {
+    let mut parts = Vec::new();
+    if let Some(ref app_id) = window.app_id {
+        parts.push(format!("app_id={:?}", app_id));
+    }
+    if !window.title.is_empty() {
+        parts.push(format!("title={:?}", window.title));
+    }
+    parts.join(" ")
+}
+
+impl std::str::FromStr for Simulation {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let words = utils::split_words(spec)?;
+        let Some((event, pairs)) = words.split_first() else {
+            anyhow::bail!("Nothing to simulate, expected e.g. 'open app_id=mpv parent=foot'");
+        };
+        let kind = match event.as_str() {
+            "open" => EventKind::Open,
+            "focus" => EventKind::Focus,
+            other => anyhow::bail!("Unknown event '{}', expected 'open' or 'focus'", other),
+        };
+
+        let (mut app_id, mut title, mut workspace, mut output) = (None, None, None, None);
+        let (mut parent_app_id, mut parent_title) = (None, None);
+        for pair in pairs {
+            let Some((key, value)) = pair.split_once('=') else {
+                anyhow::bail!("Expected key=value, got '{}'", pair);
+            };
+            let slot = match key {
+                "app_id" => &mut app_id,
+                "title" => &mut title,
+                "parent" => &mut parent_app_id,
+                "parent_title" => &mut parent_title,
+                "workspace" => &mut workspace,
+                "output" => &mut output,
+                other => anyhow::bail!(
+                    "Unknown key '{}', expected one of: {}",
+                    other,
+                    KEYS.join(", ")
+                ),
+            };
+            *slot = Some(value.to_string());
+        }
+        if app_id.is_none() && title.is_none() {
+            anyhow::bail!("The simulated window needs an app_id or a title");
+        }
+
+        let parent = (parent_app_id.is_some() || parent_title.is_some())
+            .then(|| window(parent_app_id, parent_title, None));
+        let mut window = window(app_id, title, workspace);
+        window.output = output.clone();
+        Ok(Self {
+            kind,
+            window,
+            parent,
+            output,
+        })
+    }
+}
+
+impl Simulation {
+    /// Human-readable description of the event
+    pub fn describe(&self) -> String {
+        let event = match self.kind {
+            EventKind::Open => "opened",
+            EventKind::Focus => "focused",
+        };
+        let mut description = format!("window {} ({})", event, describe_window(&self.window));
+        if let Some(ref parent) = self.parent {
+            description.push_str(&format!(" while ({}) is focused", describe_window(parent)));
+        }
+        description
+    }
+
+    fn plugin_runs(&self, config: &Config, name: &str) -> bool {
+        match self.output {
+            Some(ref output) => config.plugin_enabled_on(name, output),
+            None => config.plugin_runs(name),
+        }
+    }
+
+    /// Run the event through the matchers of the plugins it would reach, without talking
+    /// to niri, and report what each of them would do
+    pub async fn run(&self, config: &Config) -> Result<Vec<Outcome>> {
+        let cache = WindowMatcherCache::new();
+        let window = &self.window;
+        let mut outcomes = Vec::new();
+
+        if window_utils::is_excluded(
+            &config.piri.exclude,
+            window.app_id.as_ref(),
+            Some(&window.title),
+            &cache,
+        )
+        .await?
+        {
+            outcomes.push(Outcome::note(
+                "exclude",
+                None,
+                "matches [piri.exclude], so swallow, window_rule, window_order and autofill \
+                 leave it alone"
+                    .to_string(),
+            ));
+            return Ok(outcomes);
+        }
+
+        if self.kind == EventKind::Open && self.plugin_runs(config, "swallow") {
+            self.swallow(config, &cache, &mut outcomes).await?;
+        }
+        if self.plugin_runs(config, "window_rule") {
+            self.window_rule(config, &cache, &mut outcomes).await?;
+        }
+        if self.kind == EventKind::Open && self.plugin_runs(config, "window_order") {
+            self.window_order(config, &mut outcomes);
+        }
+        if self.kind == EventKind::Focus && self.plugin_runs(config, "autofill") {
+            if let Some(ref exclude_app_id) = config.piri.autofill.exclude_app_id {
+                let matcher = WindowMatcher::new(Some(exclude_app_id.clone()), None);
+                if cache.matches(window.app_id.as_ref(), None, &matcher).await? {
+                    outcomes.push(Outcome::note(
+                        "autofill",
+                        None,
+                        "matches exclude_app_id, focusing it never triggers alignment".to_string(),
+                    ));
+                }
+            }
+        }
+        Ok(outcomes)
+    }
+
+    async fn swallow(
+        &self,
+        config: &Config,
+        cache: &WindowMatcherCache,
+        outcomes: &mut Vec<Outcome>,
+    ) -> Result<()> {
+        let window = &self.window;
+        if let Some(ref exclude) = config.piri.swallow.exclude {
+            if exclude.matches(window, cache).await? {
+                outcomes.push(Outcome::note(
+                    "swallow",
+                    None,
+                    "matches [piri.swallow.exclude], not swallowed".to_string(),
+                ));
+                return Ok(());
+            }
+        }
+        if config.piri.swallow.use_pid_matching {
+            outcomes.push(Outcome::note(
+                "swallow",
+                None,
+                "PID matching is tried before the rules and can't be simulated; a parent \
+                 process with a window takes precedence"
+                    .to_string(),
+            ));
+        }
+
+        for (index, rule) in config.swallow.iter().enumerate() {
+            let number = Some(index + 1);
+            if !rule.matches_child(window, cache).await? {
+                continue;
+            }
+            let has_parent_conditions = rule.parent_app_id.is_some() || rule.parent_title.is_some();
+            match self.parent {
+                Some(ref parent) if rule.matches_parent(parent, cache).await? => {
+                    outcomes.push(Outcome::fires(
+                        "swallow",
+                        number,
+                        format!(
+                            "[[swallow]] #{} swallows it into the parent window",
+                            index + 1
+                        ),
+                    ));
+                    return Ok(());
+                }
+                Some(_) => outcomes.push(Outcome::note(
+                    "swallow",
+                    number,
+                    format!(
+                        "[[swallow]] #{} matches the window but not the parent \
+                         (parent_app_id={:?}, parent_title={:?})",
+                        index + 1,
+                        rule.parent_app_id,
+                        rule.parent_title
+                    ),
+                )),
+                None if has_parent_conditions => outcomes.push(Outcome::note(
+                    "swallow",
+                    number,
+                    format!(
+                        "[[swallow]] #{} matches the window; add parent=<app_id> to check \
+                         its parent conditions",
+                        index + 1
+                    ),
+                )),
+                None => {
+                    outcomes.push(Outcome::fires(
+                        "swallow",
+                        number,
+                        format!(
+                            "[[swallow]] #{} swallows it into whichever window is focused",
+                            index + 1
+                        ),
+                    ));
+                    return Ok(());
+                }
+            }
+        }
+        if !config.swallow.is_empty()
+            && !outcomes
+                .iter()
+                .any(|outcome| outcome.plugin == "swallow" && outcome.rule.is_some())
+        {
+            outcomes.push(Outcome::note(
+                "swallow",
+                None,
+                format!(
+                    "none of the {} [[swallow]] rules match the window",
+                    config.swallow.len()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    async fn window_rule(
+        &self,
+        config: &Config,
+        cache: &WindowMatcherCache,
+        outcomes: &mut Vec<Outcome>,
+    ) -> Result<()> {
+        let window = &self.window;
+        for (index, rule) in config.window_rule.iter().enumerate() {
+            // Focusing a window only runs the rules that have a focus_command
+            if self.kind == EventKind::Focus && rule.focus_command.is_none() {
+                continue;
+            }
+            let matcher = WindowMatcher::new(rule.app_id.clone(), rule.title.clone());
+            if !cache.matches(window.app_id.as_ref(), Some(&window.title), &matcher).await? {
+                continue;
+            }
+
+            let mut actions = Vec::new();
+            if self.kind == EventKind::Open {
+                if let Some(ref workspace) = rule.open_on_workspace {
+                    actions.push(format!("moves it to workspace {:?}", workspace));
+                }
+            }
+            if let Some(ref command) = rule.focus_command {
+                let once = if rule.focus_command_once {
+                    " (first time only)"
+                } else {
+                    ""
+                };
+                actions.push(format!("runs `{}`{}", command, once));
+            }
+            outcomes.push(Outcome::fires(
+                "window_rule",
+                Some(index + 1),
+                format!("[[window_rule]] #{} {}", index + 1, actions.join(" and ")),
+            ));
+            // Only the first matching rule applies
+            return Ok(());
+        }
+        Ok(())
+    }
+
+    fn window_order(&self, config: &Config, outcomes: &mut Vec<Outcome>) {
+        if config.window_order.is_empty() {
+            return;
+        }
+        let section = &config.piri.window_order;
+        if let Some(ref workspace) = self.window.workspace {
+            if !section.workspaces.is_empty() && !section.workspaces.contains(workspace) {
+                outcomes.push(Outcome::note(
+                    "window_order",
+                    None,
+                    format!(
+                        "workspace {:?} is not in [piri.window_order] workspaces",
+                        workspace
+                    ),
+                ));
+                return;
+            }
+        }
+        let weight = WindowOrderPlugin::get_window_order(
+            self.window.app_id.as_ref(),
+            &config.window_order,
+            section.default_weight,
+        );
+        let when = if section.enable_event_listener {
+            "sorted"
+        } else {
+            "sorted on `piri window-order toggle`"
+        };
+        outcomes.push(Outcome::fires(
+            "window_order",
+            None,
+            format!("{} with weight {}", when, weight),
+        ));
+    }
+}
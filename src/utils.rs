@@ -1,4 +1,30 @@
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set while do-not-disturb mode is on (see the dnd plugin)
+static DO_NOT_DISTURB: AtomicBool = AtomicBool::new(false);
+
+/// Turn do-not-disturb mode on or off; while on, `send_notification` does nothing
+pub fn set_do_not_disturb(enabled: bool) {
+    DO_NOT_DISTURB.store(enabled, Ordering::Relaxed);
+}
+
+pub fn do_not_disturb() -> bool {
+    DO_NOT_DISTURB.load(Ordering::Relaxed)
+}
+
 pub fn send_notification(summary: &str, body: &str) {
+    if do_not_disturb() {
+        log::debug!(
+            "Do not disturb, dropping notification: {}: {}",
+            summary,
+            body
+        );
+        return;
+    }
     let _ = std::process::Command::new("notify-send")
         .arg("-a")
         .arg("piri")
@@ -8,3 +34,167 @@ pub fn send_notification(summary: &str, body: &str) {
         .arg(body)
         .spawn();
 }
+
+/// Name of this machine, as printed by `hostname`
+pub fn hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    if unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) } != 0 {
+        return None;
+    }
+    let name = std::ffi::CStr::from_bytes_until_nul(&buffer).ok()?;
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// Quote a value for use in a `sh -c` command line
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Merge `value` into `target`: objects key by key, anything else replaces the target
+pub fn merge_json(target: &mut serde_json::Value, value: serde_json::Value) {
+    match (target, value) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(value)) => {
+            for (key, value) in value {
+                merge_json(target.entry(key).or_insert(serde_json::Value::Null), value);
+            }
+        }
+        (target, value) => *target = value,
+    }
+}
+
+/// Split a command line into words like a shell would, honoring single and double
+/// quotes and backslash escapes (no expansions)
+pub fn split_words(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(word) = word.take() {
+                    words.push(word);
+                }
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => anyhow::bail!("Unterminated quote in: {}", line),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => anyhow::bail!("Unterminated quote in: {}", line),
+                        },
+                        Some(c) => word.push(c),
+                        None => anyhow::bail!("Unterminated quote in: {}", line),
+                    }
+                }
+            }
+            '\\' => {
+                let escaped = chars.next().unwrap_or('\\');
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Directory for persistent runtime state ($XDG_STATE_HOME/piri or ~/.local/state/piri)
+pub fn state_dir() -> PathBuf {
+    if let Ok(state_home) = std::env::var("XDG_STATE_HOME") {
+        if !state_home.is_empty() {
+            return PathBuf::from(state_home).join("piri");
+        }
+    }
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    PathBuf::from(home).join(".local/state/piri")
+}
+
+/// Load a JSON state file from the state directory, returning None if it doesn't exist
+pub fn load_state<T: DeserializeOwned>(file: &str) -> Result<Option<T>> {
+    let path = state_dir().join(file);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read state file: {:?}", path))?;
+    let state = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse state file: {:?}", path))?;
+    Ok(Some(state))
+}
+
+/// Write a JSON state file to the state directory
+pub fn save_state<T: Serialize>(file: &str, state: &T) -> Result<()> {
+    let dir = state_dir();
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create state directory: {:?}", dir))?;
+    let path = dir.join(file);
+    let content = serde_json::to_string_pretty(state).context("Failed to serialize state")?;
+    std::fs::write(&path, content)
+        .with_context(|| format!("Failed to write state file: {:?}", path))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<String> {
+        split_words(line).unwrap()
+    }
+
+    #[test]
+    fn split_words_on_whitespace() {
+        assert_eq!(
+            words("  kitty  --class\tnotes \n"),
+            ["kitty", "--class", "notes"]
+        );
+        assert!(words("").is_empty());
+        assert!(words(" \t ").is_empty());
+    }
+
+    #[test]
+    fn split_words_quoted() {
+        assert_eq!(words("echo 'a b' \"c  d\""), ["echo", "a b", "c  d"]);
+        assert_eq!(words("'a'\"b\"c d"), ["abc", "d"]);
+        assert_eq!(words("'$HOME \\n' \"it's\""), ["$HOME \\n", "it's"]);
+    }
+
+    #[test]
+    fn split_words_escapes() {
+        assert_eq!(
+            words(r#""say \"hi\"" "a\\b" "\n""#),
+            ["say \"hi\"", "a\\b", "\\n"]
+        );
+        assert_eq!(words(r#"a\ b \'c\' \"d"#), ["a b", "'c'", "\"d"]);
+        assert_eq!(words(r"trailing\"), ["trailing\\"]);
+    }
+
+    #[test]
+    fn split_words_empty_strings() {
+        assert_eq!(words("a '' \"\" b"), ["a", "", "", "b"]);
+        assert_eq!(words("''"), [""]);
+    }
+
+    #[test]
+    fn split_words_rejects_unterminated_quotes() {
+        for line in ["echo 'a b", "echo \"a b", "\"a\\\"", "'", "a \"b\\"] {
+            assert!(split_words(line).is_err(), "{:?} should be rejected", line);
+        }
+    }
+}